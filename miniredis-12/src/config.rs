@@ -0,0 +1,242 @@
+use std::path::PathBuf;
+
+/// Whether TLS clients must present a certificate signed by the configured CA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAuth {
+    No,
+    Optional,
+    Required,
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub port: u16,
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+    pub ca_cert_file: Option<PathBuf>,
+    pub auth_clients: ClientAuth,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub bind: String,
+    // port 0 disables the plaintext listener, same as redis-server
+    pub port: u16,
+    pub tls: Option<TlsConfig>,
+    pub unixsocket: Option<PathBuf>,
+    // Octal file mode for the socket, e.g. 700
+    pub unixsocketperm: Option<u32>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            bind: "127.0.0.1".to_string(),
+            port: 6379,
+            tls: None,
+            unixsocket: None,
+            unixsocketperm: None,
+        }
+    }
+}
+
+impl Config {
+    /// Parses redis-server style `--option value` pairs, e.g.
+    /// `--port 0 --tls-port 6380 --tls-cert-file redis.crt --tls-key-file redis.key`
+    /// or `--unixsocket /tmp/redis.sock --unixsocketperm 700`.
+    pub fn from_args<I>(args: I) -> Result<Config, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut config = Config::default();
+
+        let mut tls_port = None;
+        let mut cert_file = None;
+        let mut key_file = None;
+        let mut ca_cert_file = None;
+        let mut auth_clients = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let name = match arg.strip_prefix("--") {
+                Some(name) => name.to_string(),
+                None => return Err(format!("unexpected argument '{}'", arg)),
+            };
+            let value = match args.next() {
+                Some(value) => value,
+                None => return Err(format!("option '--{}' requires a value", name)),
+            };
+
+            match name.as_str() {
+                "bind" => config.bind = value,
+                "port" => config.port = parse_port(&name, &value)?,
+                "unixsocket" => config.unixsocket = Some(PathBuf::from(value)),
+                "unixsocketperm" => {
+                    let perm = u32::from_str_radix(&value, 8)
+                        .ok()
+                        .filter(|perm| *perm <= 0o777)
+                        .ok_or_else(|| {
+                            format!("invalid octal mode '{}' for '--{}'", value, name)
+                        })?;
+                    config.unixsocketperm = Some(perm);
+                }
+                "tls-port" => tls_port = Some(parse_port(&name, &value)?),
+                "tls-cert-file" => cert_file = Some(PathBuf::from(value)),
+                "tls-key-file" => key_file = Some(PathBuf::from(value)),
+                "tls-ca-cert-file" => ca_cert_file = Some(PathBuf::from(value)),
+                "tls-auth-clients" => {
+                    auth_clients = Some(match value.to_lowercase().as_str() {
+                        "yes" => ClientAuth::Required,
+                        "optional" => ClientAuth::Optional,
+                        "no" => ClientAuth::No,
+                        _ => {
+                            return Err(format!(
+                                "tls-auth-clients must be yes, no or optional, got '{}'",
+                                value
+                            ));
+                        }
+                    })
+                }
+                _ => return Err(format!("unknown option '--{}'", name)),
+            }
+        }
+
+        if config.unixsocketperm.is_some() && config.unixsocket.is_none() {
+            return Err("unixsocketperm requires unixsocket".to_string());
+        }
+
+        let port = match tls_port {
+            Some(port) if port != 0 => port,
+            _ => return Ok(config),
+        };
+
+        let cert_file = cert_file.ok_or("tls-port requires tls-cert-file")?;
+        let key_file = key_file.ok_or("tls-port requires tls-key-file")?;
+
+        // Like redis-server, clients must authenticate by default once a CA is configured
+        let auth_clients = match (auth_clients, &ca_cert_file) {
+            (Some(ClientAuth::No), _) => ClientAuth::No,
+            (Some(_), None) => return Err("tls-auth-clients requires tls-ca-cert-file".to_string()),
+            (Some(auth), Some(_)) => auth,
+            (None, Some(_)) => ClientAuth::Required,
+            (None, None) => ClientAuth::No,
+        };
+
+        config.tls = Some(TlsConfig {
+            port,
+            cert_file,
+            key_file,
+            ca_cert_file,
+            auth_clients,
+        });
+
+        Ok(config)
+    }
+}
+
+fn parse_port(name: &str, value: &str) -> Result<u16, String> {
+    value
+        .parse::<u16>()
+        .map_err(|_| format!("invalid value '{}' for '--{}'", value, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = Config::from_args(Vec::new()).unwrap();
+        assert_eq!(config.bind, "127.0.0.1");
+        assert_eq!(config.port, 6379);
+        assert!(config.tls.is_none());
+        assert!(config.unixsocket.is_none());
+    }
+
+    #[test]
+    fn test_unixsocket_options() {
+        let config = Config::from_args(args(&[
+            "--unixsocket",
+            "/tmp/redis.sock",
+            "--unixsocketperm",
+            "770",
+        ]))
+        .unwrap();
+
+        assert_eq!(config.unixsocket, Some(PathBuf::from("/tmp/redis.sock")));
+        assert_eq!(config.unixsocketperm, Some(0o770));
+
+        assert!(
+            Config::from_args(args(&["--unixsocket", "a", "--unixsocketperm", "789"])).is_err()
+        );
+        assert!(
+            Config::from_args(args(&["--unixsocket", "a", "--unixsocketperm", "1777"])).is_err()
+        );
+        assert!(Config::from_args(args(&["--unixsocketperm", "700"])).is_err());
+    }
+
+    #[test]
+    fn test_tls_options() {
+        let config = Config::from_args(args(&[
+            "--port",
+            "0",
+            "--tls-port",
+            "6380",
+            "--tls-cert-file",
+            "redis.crt",
+            "--tls-key-file",
+            "redis.key",
+        ]))
+        .unwrap();
+
+        assert_eq!(config.port, 0);
+        let tls = config.tls.expect("TLS should be enabled");
+        assert_eq!(tls.port, 6380);
+        assert_eq!(tls.cert_file, PathBuf::from("redis.crt"));
+        assert_eq!(tls.auth_clients, ClientAuth::No);
+    }
+
+    #[test]
+    fn test_ca_enables_client_auth() {
+        let config = Config::from_args(args(&[
+            "--tls-port",
+            "6380",
+            "--tls-cert-file",
+            "redis.crt",
+            "--tls-key-file",
+            "redis.key",
+            "--tls-ca-cert-file",
+            "ca.crt",
+        ]))
+        .unwrap();
+
+        assert_eq!(config.tls.unwrap().auth_clients, ClientAuth::Required);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        // Missing key file
+        assert!(Config::from_args(args(&["--tls-port", "6380", "--tls-cert-file", "a"])).is_err());
+        // Client auth without a CA to verify against
+        assert!(
+            Config::from_args(args(&[
+                "--tls-port",
+                "6380",
+                "--tls-cert-file",
+                "a",
+                "--tls-key-file",
+                "b",
+                "--tls-auth-clients",
+                "yes",
+            ]))
+            .is_err()
+        );
+        assert!(Config::from_args(args(&["--port", "http"])).is_err());
+        assert!(Config::from_args(args(&["--port"])).is_err());
+        assert!(Config::from_args(args(&["--nope", "1"])).is_err());
+    }
+}
@@ -1,23 +1,69 @@
 use bytes::BytesMut;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
 
 mod commands;
+mod config;
 mod protocol;
 mod storage;
+mod tls;
+mod unix;
 
 use commands::Command;
+use config::Config;
 use protocol::decode;
 use storage::Db;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::from_args(std::env::args().skip(1))?;
+
     // Intialize shared Database
     let db = Db::new();
 
-    let listener = TcpListener::bind("127.0.0.1:6379").await?;
-    println!("mini-redis listening on 127.0.0.1:6379");
+    let mut servers = JoinSet::new();
+
+    if config.port != 0 {
+        let addr = format!("{}:{}", config.bind, config.port);
+        let listener = TcpListener::bind(&addr).await?;
+        println!("mini-redis listening on {}", addr);
+
+        servers.spawn(serve_tcp(listener, db.clone()));
+    }
+
+    if let Some(tls_config) = &config.tls {
+        let acceptor = tls::build_acceptor(tls_config)?;
+
+        let addr = format!("{}:{}", config.bind, tls_config.port);
+        let listener = TcpListener::bind(&addr).await?;
+        println!("mini-redis listening on {} (TLS)", addr);
 
+        servers.spawn(serve_tls(listener, acceptor, db.clone()));
+    }
+
+    if let Some(path) = &config.unixsocket {
+        let listener = unix::bind(path, config.unixsocketperm)?;
+        println!("mini-redis listening on {}", path.display());
+
+        servers.spawn(serve_unix(listener, db.clone()));
+    }
+
+    if servers.is_empty() {
+        return Err("no listeners configured, set --port, --tls-port or --unixsocket".into());
+    }
+
+    // Listeners only return when accepting fails
+    while let Some(result) = servers.join_next().await {
+        result??;
+    }
+
+    Ok(())
+}
+
+async fn serve_tcp(listener: TcpListener, db: Db) -> io::Result<()> {
     loop {
         let (socket, _) = listener.accept().await?;
 
@@ -29,12 +75,44 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 }
 
-async fn process_connection(mut socket: TcpStream, db: Db) {
+async fn serve_tls(listener: TcpListener, acceptor: TlsAcceptor, db: Db) -> io::Result<()> {
+    loop {
+        let (socket, peer) = listener.accept().await?;
+
+        let acceptor = acceptor.clone();
+        let db_handle = db.clone();
+
+        // Handshake runs inside the task so a slow client can't stall the accept loop
+        tokio::spawn(async move {
+            match acceptor.accept(socket).await {
+                Ok(stream) => process_connection(stream, db_handle).await,
+                Err(e) => eprintln!("TLS handshake with {} failed; err = {:?}", peer, e),
+            }
+        });
+    }
+}
+
+async fn serve_unix(listener: UnixListener, db: Db) -> io::Result<()> {
+    loop {
+        let (socket, _) = listener.accept().await?;
+
+        let db_handle = db.clone();
+
+        tokio::spawn(async move {
+            process_connection(socket, db_handle).await;
+        });
+    }
+}
+
+async fn process_connection<S>(mut socket: S, db: Db)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     let mut buffer = BytesMut::with_capacity(4096);
 
     loop {
         let _n = match socket.read_buf(&mut buffer).await {
-            Ok(n) if n == 0 => return,
+            Ok(0) => return,
             Ok(n) => n,
             Err(e) => {
                 eprintln!("failed to read from socket; err = {:?}", e);
@@ -64,3 +142,175 @@ async fn process_connection(mut socket: TcpStream, db: Db) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::RespType;
+    use std::time::Duration;
+    use tokio::net::TcpStream;
+    use tokio::time::timeout;
+
+    // Boots the plaintext server on an ephemeral port, sharing one Db like main() does
+    async fn start_server() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(serve_tcp(listener, Db::new()));
+        port
+    }
+
+    fn command(args: &[&str]) -> Vec<u8> {
+        let items = args
+            .iter()
+            .map(|arg| RespType::BulkString(arg.as_bytes().to_vec()))
+            .collect();
+        RespType::Array(items).serialize()
+    }
+
+    struct Client {
+        stream: TcpStream,
+        buffer: BytesMut,
+    }
+
+    impl Client {
+        async fn connect(port: u16) -> Client {
+            Client {
+                stream: TcpStream::connect(("127.0.0.1", port)).await.unwrap(),
+                buffer: BytesMut::new(),
+            }
+        }
+
+        async fn send(&mut self, bytes: &[u8]) {
+            self.stream.write_all(bytes).await.unwrap();
+        }
+
+        // Reads exactly one reply frame, failing the test if the server goes quiet
+        async fn read_reply(&mut self) -> RespType {
+            loop {
+                if let Some(frame) = decode(&mut self.buffer).unwrap() {
+                    return frame;
+                }
+
+                let read = timeout(
+                    Duration::from_secs(5),
+                    self.stream.read_buf(&mut self.buffer),
+                );
+                let n = read.await.expect("timed out waiting for reply").unwrap();
+                assert!(n > 0, "server closed the connection");
+            }
+        }
+
+        async fn request(&mut self, args: &[&str]) -> RespType {
+            self.send(&command(args)).await;
+            self.read_reply().await
+        }
+    }
+
+    fn ok() -> RespType {
+        RespType::SimpleString("OK".to_string())
+    }
+
+    fn bulk(s: &str) -> RespType {
+        RespType::BulkString(s.as_bytes().to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_basic_commands() {
+        let port = start_server().await;
+        let mut client = Client::connect(port).await;
+
+        assert_eq!(
+            client.request(&["PING"]).await,
+            RespType::SimpleString("PONG".to_string())
+        );
+        assert_eq!(client.request(&["PING", "hi"]).await, bulk("hi"));
+        assert_eq!(client.request(&["SET", "key", "value"]).await, ok());
+        assert_eq!(client.request(&["GET", "key"]).await, bulk("value"));
+        assert_eq!(client.request(&["DEL", "key"]).await, RespType::Integer(1));
+        assert_eq!(client.request(&["GET", "key"]).await, RespType::Null);
+    }
+
+    #[tokio::test]
+    async fn test_errors_keep_connection_open() {
+        let port = start_server().await;
+        let mut client = Client::connect(port).await;
+
+        assert_eq!(
+            client.request(&["FLY"]).await,
+            RespType::Error("unknown command 'FLY'".to_string())
+        );
+        assert!(matches!(client.request(&["GET"]).await, RespType::Error(_)));
+        assert_eq!(
+            client.request(&["PING"]).await,
+            RespType::SimpleString("PONG".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pipelining() {
+        let port = start_server().await;
+        let mut client = Client::connect(port).await;
+
+        // Every command goes out in a single write, replies must come back in order
+        let mut batch = Vec::new();
+        for i in 0..100 {
+            batch.extend_from_slice(&command(&["SET", &format!("key{}", i), &i.to_string()]));
+            batch.extend_from_slice(&command(&["GET", &format!("key{}", i)]));
+        }
+        client.send(&batch).await;
+
+        for i in 0..100 {
+            assert_eq!(client.read_reply().await, ok());
+            assert_eq!(client.read_reply().await, bulk(&i.to_string()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_split_packets() {
+        let port = start_server().await;
+        let mut client = Client::connect(port).await;
+
+        let value = "x".repeat(10_000);
+        let mut bytes = command(&["SET", "big", &value]);
+        bytes.extend_from_slice(&command(&["GET", "big"]));
+
+        // Dribble the frames out a byte at a time for the headers, then in odd-sized chunks
+        let (head, rest) = bytes.split_at(20);
+        for byte in head {
+            client.send(&[*byte]).await;
+            client.stream.flush().await.unwrap();
+            tokio::task::yield_now().await;
+        }
+        for chunk in rest.chunks(997) {
+            client.send(chunk).await;
+            client.stream.flush().await.unwrap();
+            tokio::task::yield_now().await;
+        }
+
+        assert_eq!(client.read_reply().await, ok());
+        assert_eq!(client.read_reply().await, bulk(&value));
+    }
+
+    #[tokio::test]
+    async fn test_state_shared_between_connections() {
+        let port = start_server().await;
+        let mut writer = Client::connect(port).await;
+        let mut reader = Client::connect(port).await;
+
+        assert_eq!(writer.request(&["SET", "shared", "1"]).await, ok());
+        assert_eq!(reader.request(&["GET", "shared"]).await, bulk("1"));
+    }
+
+    #[tokio::test]
+    async fn test_protocol_error_closes_connection() {
+        let port = start_server().await;
+        let mut client = Client::connect(port).await;
+
+        client.send(b"?garbage\r\n").await;
+
+        let mut buf = [0u8; 16];
+        let read = timeout(Duration::from_secs(5), client.stream.read(&mut buf));
+        assert_eq!(read.await.expect("connection was not closed").unwrap(), 0);
+    }
+}
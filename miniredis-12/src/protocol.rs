@@ -1,7 +1,12 @@
 use bytes::{Buf, BytesMut};
 use std::io::{Cursor, Read};
 
-#[derive(Debug)]
+// Same limit as redis-server's proto-max-bulk-len default
+const MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+// Nested arrays are parsed recursively, cap the depth so input can't overflow the stack
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum RespType {
     SimpleString(String),
     Error(String),
@@ -24,7 +29,13 @@ impl RespType {
                 out
             }
             RespType::Null => b"$-1\r\n".to_vec(),
-            RespType::Array(_) => b"-ERR array serialization not supported\r\n".to_vec(),
+            RespType::Array(items) => {
+                let mut out = format!("*{}\r\n", items.len()).into_bytes();
+                for item in items {
+                    out.extend_from_slice(&item.serialize());
+                }
+                out
+            }
         }
     }
 }
@@ -47,7 +58,7 @@ pub fn decode(buff: &mut BytesMut) -> Result<Option<RespType>, RespError> {
 
     // parse_next is the real parseing function
     // only the decode function can advance the buffer clearing the old frame
-    match parse_next(&mut cursor) {
+    match parse_next(&mut cursor, 0) {
         Ok(Some(value)) => {
             let position = cursor.position() as usize;
             buff.advance(position);
@@ -59,7 +70,7 @@ pub fn decode(buff: &mut BytesMut) -> Result<Option<RespType>, RespError> {
     }
 }
 
-fn parse_next(cursor: &mut Cursor<&[u8]>) -> Result<Option<RespType>, RespError> {
+fn parse_next(cursor: &mut Cursor<&[u8]>, depth: usize) -> Result<Option<RespType>, RespError> {
     let mut prefix_byte = [0u8; 1];
     // This check is crucial because we want to know if buffer is empty or not in get_array
     if cursor.read_exact(&mut prefix_byte).is_err() {
@@ -68,9 +79,10 @@ fn parse_next(cursor: &mut Cursor<&[u8]>) -> Result<Option<RespType>, RespError>
 
     match prefix_byte[0] {
         b'+' => get_simple_string(cursor),
+        b'-' => get_error(cursor),
         b':' => get_decimal(cursor),
         b'$' => get_bulk_string(cursor),
-        b'*' => get_array(cursor),
+        b'*' => get_array(cursor, depth),
         _ => Err(RespError::InvalidProtocol),
     }
 }
@@ -96,6 +108,14 @@ fn get_simple_string(cursor: &mut Cursor<&[u8]>) -> Result<Option<RespType>, Res
     }
 }
 
+fn get_error(cursor: &mut Cursor<&[u8]>) -> Result<Option<RespType>, RespError> {
+    // Errors share the simple string framing, only the prefix differs
+    match get_simple_string(cursor)? {
+        Some(RespType::SimpleString(msg)) => Ok(Some(RespType::Error(msg))),
+        other => Ok(other),
+    }
+}
+
 fn get_decimal(cursor: &mut Cursor<&[u8]>) -> Result<Option<RespType>, RespError> {
     let start = cursor.position() as usize;
     let end = cursor.get_ref().len();
@@ -145,6 +165,10 @@ fn get_bulk_string(cursor: &mut Cursor<&[u8]>) -> Result<Option<RespType>, RespE
         return Ok(Some(RespType::Null));
     }
 
+    if !(0..=MAX_BULK_LEN).contains(&len) {
+        return Err(RespError::InvalidProtocol);
+    }
+
     let data_len = len as usize;
     let total_frame_len = header_end + 2 + data_len + 2;
 
@@ -162,7 +186,11 @@ fn get_bulk_string(cursor: &mut Cursor<&[u8]>) -> Result<Option<RespType>, RespE
     Ok(Some(RespType::BulkString(data)))
 }
 
-fn get_array(cursor: &mut Cursor<&[u8]>) -> Result<Option<RespType>, RespError> {
+fn get_array(cursor: &mut Cursor<&[u8]>, depth: usize) -> Result<Option<RespType>, RespError> {
+    if depth >= MAX_DEPTH {
+        return Err(RespError::InvalidProtocol);
+    }
+
     let start = cursor.position() as usize;
     let end = cursor.get_ref().len();
     // This function as well as 2 reads one for number of elements and the elements itself
@@ -186,9 +214,15 @@ fn get_array(cursor: &mut Cursor<&[u8]>) -> Result<Option<RespType>, RespError>
         return Ok(Some(RespType::Null));
     }
 
-    let mut items = Vec::with_capacity(count as usize);
+    if count < 0 {
+        return Err(RespError::InvalidProtocol);
+    }
+
+    // Every element takes at least 3 bytes, so don't trust the header with the allocation
+    let remaining = end - (header_end + 2);
+    let mut items = Vec::with_capacity((count as usize).min(remaining / 3));
     for _ in 0..count {
-        match parse_next(cursor)? {
+        match parse_next(cursor, depth + 1)? {
             Some(value) => items.push(value),
             None => return Ok(None),
         }
@@ -337,13 +371,12 @@ mod test {
         let mut buffer = BytesMut::from("*1\r\n*1\r\n:5\r\n");
         let result = decode(&mut buffer).unwrap();
 
-        if let Some(RespType::Array(ref outer)) = result {
-            if let RespType::Array(inner) = &outer[0] {
-                if let RespType::Integer(val) = inner[0] {
-                    assert_eq!(val, 5);
-                    return;
-                }
-            }
+        if let Some(RespType::Array(ref outer)) = result
+            && let RespType::Array(inner) = &outer[0]
+            && let RespType::Integer(val) = inner[0]
+        {
+            assert_eq!(val, 5);
+            return;
         }
         panic!("Nested array parsing failed! Got: {:?}", result);
     }
@@ -420,4 +453,148 @@ mod test {
         let resp = RespType::Error("Error message".to_string());
         assert_eq!(resp.serialize(), b"-Error message\r\n");
     }
+
+    #[test]
+    fn test_serialize_array() {
+        let resp = RespType::Array(vec![
+            RespType::BulkString(b"GET".to_vec()),
+            RespType::Integer(7),
+        ]);
+        assert_eq!(resp.serialize(), b"*2\r\n$3\r\nGET\r\n:7\r\n");
+    }
+
+    #[test]
+    fn test_error_success() {
+        let mut buffer = BytesMut::from("-ERR oops\r\n");
+        let result = decode(&mut buffer).unwrap();
+
+        assert_eq!(result, Some(RespType::Error("ERR oops".to_string())));
+        assert_eq!(buffer.len(), 0);
+    }
+
+    #[test]
+    fn test_negative_lengths_rejected() {
+        let mut buffer = BytesMut::from("$-5\r\n");
+        assert!(matches!(
+            decode(&mut buffer),
+            Err(RespError::InvalidProtocol)
+        ));
+
+        let mut buffer = BytesMut::from("*-9223372036854775808\r\n");
+        assert!(matches!(
+            decode(&mut buffer),
+            Err(RespError::InvalidProtocol)
+        ));
+    }
+
+    #[test]
+    fn test_oversized_lengths_rejected() {
+        let mut buffer = BytesMut::from("$9223372036854775807\r\n");
+        assert!(matches!(
+            decode(&mut buffer),
+            Err(RespError::InvalidProtocol)
+        ));
+
+        // A huge element count is fine as long as we don't allocate for it up front
+        let mut buffer = BytesMut::from("*9223372036854775807\r\n:1\r\n");
+        assert!(decode(&mut buffer).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_deep_nesting_rejected() {
+        let mut buffer = BytesMut::from("*1\r\n".repeat(MAX_DEPTH + 1).as_str());
+        assert!(matches!(
+            decode(&mut buffer),
+            Err(RespError::InvalidProtocol)
+        ));
+    }
+
+    mod properties {
+        use super::*;
+        use proptest::prelude::*;
+
+        // Simple strings and errors can't carry CR or LF, everything else is fair game
+        fn line() -> impl Strategy<Value = String> {
+            "[^\r\n]{0,16}"
+        }
+
+        fn resp_value() -> impl Strategy<Value = RespType> {
+            let leaf = prop_oneof![
+                line().prop_map(RespType::SimpleString),
+                line().prop_map(RespType::Error),
+                any::<i64>().prop_map(RespType::Integer),
+                proptest::collection::vec(any::<u8>(), 0..32).prop_map(RespType::BulkString),
+                Just(RespType::Null),
+            ];
+
+            leaf.prop_recursive(4, 32, 8, |inner| {
+                proptest::collection::vec(inner, 0..8).prop_map(RespType::Array)
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn decode_never_panics(bytes in proptest::collection::vec(any::<u8>(), 0..256)) {
+                let mut buffer = BytesMut::from(&bytes[..]);
+                let _ = decode(&mut buffer);
+                prop_assert!(buffer.len() <= bytes.len());
+            }
+
+            #[test]
+            fn decode_never_panics_on_resp_like_input(
+                parts in proptest::collection::vec(
+                    prop_oneof![
+                        Just("*".to_string()),
+                        Just("$".to_string()),
+                        Just(":".to_string()),
+                        Just("+".to_string()),
+                        Just("-".to_string()),
+                        Just("\r\n".to_string()),
+                        any::<i64>().prop_map(|n| n.to_string()),
+                        "[a-z]{0,4}",
+                    ],
+                    0..32,
+                )
+            ) {
+                let mut buffer = BytesMut::from(parts.concat().as_str());
+                while let Ok(Some(_)) = decode(&mut buffer) {}
+            }
+
+            #[test]
+            fn serialize_round_trips(value in resp_value()) {
+                let bytes = value.serialize();
+                let mut buffer = BytesMut::from(&bytes[..]);
+
+                prop_assert_eq!(decode(&mut buffer).unwrap(), Some(value));
+                prop_assert_eq!(buffer.len(), 0);
+            }
+
+            #[test]
+            fn partial_frames_wait_for_more_data(value in resp_value(), cut in any::<prop::sample::Index>()) {
+                let bytes = value.serialize();
+                let cut = cut.index(bytes.len());
+
+                // Any strict prefix of a frame is incomplete and must leave the buffer alone
+                let mut buffer = BytesMut::from(&bytes[..cut]);
+                prop_assert_eq!(decode(&mut buffer).unwrap(), None);
+                prop_assert_eq!(buffer.len(), cut);
+
+                buffer.extend_from_slice(&bytes[cut..]);
+                prop_assert_eq!(decode(&mut buffer).unwrap(), Some(value));
+            }
+
+            #[test]
+            fn pipelined_frames_decode_in_order(values in proptest::collection::vec(resp_value(), 1..8)) {
+                let mut buffer = BytesMut::new();
+                for value in &values {
+                    buffer.extend_from_slice(&value.serialize());
+                }
+
+                for value in values {
+                    prop_assert_eq!(decode(&mut buffer).unwrap(), Some(value));
+                }
+                prop_assert_eq!(buffer.len(), 0);
+            }
+        }
+    }
 }
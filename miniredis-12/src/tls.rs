@@ -0,0 +1,229 @@
+use crate::config::{ClientAuth, TlsConfig};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::RootCertStore;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+
+pub fn build_acceptor(config: &TlsConfig) -> Result<TlsAcceptor, Box<dyn std::error::Error>> {
+    let provider = Arc::new(ring::default_provider());
+
+    let certs = load_certs(&config.cert_file)?;
+    let key = load_key(&config.key_file)?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let server_config = match (&config.ca_cert_file, config.auth_clients) {
+        (Some(ca_file), ClientAuth::Optional | ClientAuth::Required) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_file)? {
+                roots.add(cert)?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = if config.auth_clients == ClientAuth::Optional {
+                verifier.allow_unauthenticated().build()?
+            } else {
+                verifier.build()?
+            };
+
+            builder
+                .with_client_cert_verifier(verifier)
+                .with_single_cert(certs, key)?
+        }
+        _ => builder.with_no_client_auth().with_single_cert(certs, key)?,
+    };
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<io::Result<Vec<_>>>()?;
+
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no certificates found in {}", path.display()),
+        ));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> io::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    match rustls_pemfile::private_key(&mut reader)? {
+        Some(key) => Ok(key),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no private key found in {}", path.display()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Db;
+    use rcgen::{BasicConstraints, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::TlsConnector;
+    use tokio_rustls::rustls::ClientConfig;
+    use tokio_rustls::rustls::pki_types::{PrivatePkcs8KeyDer, ServerName};
+
+    struct TestPki {
+        dir: TempDir,
+        ca_der: CertificateDer<'static>,
+        client_cert: CertificateDer<'static>,
+        client_key: PrivateKeyDer<'static>,
+    }
+
+    impl TestPki {
+        // CA, server cert for "localhost" and a client cert, all signed by the CA
+        fn generate() -> TestPki {
+            let dir = tempfile::tempdir().unwrap();
+
+            let ca_key = KeyPair::generate().unwrap();
+            let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+            ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca = ca_params.self_signed(&ca_key).unwrap();
+
+            let server_key = KeyPair::generate().unwrap();
+            let server = CertificateParams::new(vec!["localhost".to_string()])
+                .unwrap()
+                .signed_by(&server_key, &ca, &ca_key)
+                .unwrap();
+
+            let client_key = KeyPair::generate().unwrap();
+            let mut client_params = CertificateParams::new(vec!["client".to_string()]).unwrap();
+            client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+            let client = client_params.signed_by(&client_key, &ca, &ca_key).unwrap();
+
+            std::fs::write(dir.path().join("ca.crt"), ca.pem()).unwrap();
+            std::fs::write(dir.path().join("redis.crt"), server.pem()).unwrap();
+            std::fs::write(dir.path().join("redis.key"), server_key.serialize_pem()).unwrap();
+
+            TestPki {
+                dir,
+                ca_der: ca.der().clone(),
+                client_cert: client.der().clone(),
+                client_key: PrivatePkcs8KeyDer::from(client_key.serialize_der()).into(),
+            }
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.dir.path().join(name)
+        }
+
+        fn server_config(&self, auth_clients: ClientAuth) -> TlsConfig {
+            TlsConfig {
+                port: 0,
+                cert_file: self.path("redis.crt"),
+                key_file: self.path("redis.key"),
+                ca_cert_file: Some(self.path("ca.crt")),
+                auth_clients,
+            }
+        }
+
+        fn connector(&self, with_client_cert: bool) -> TlsConnector {
+            let mut roots = RootCertStore::empty();
+            roots.add(self.ca_der.clone()).unwrap();
+
+            let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots);
+
+            let config = if with_client_cert {
+                builder
+                    .with_client_auth_cert(
+                        vec![self.client_cert.clone()],
+                        self.client_key.clone_key(),
+                    )
+                    .unwrap()
+            } else {
+                builder.with_no_client_auth()
+            };
+
+            TlsConnector::from(Arc::new(config))
+        }
+    }
+
+    async fn start_server(config: &TlsConfig) -> u16 {
+        let acceptor = build_acceptor(config).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(crate::serve_tls(listener, acceptor, Db::new()));
+        port
+    }
+
+    // Sends PING over TLS and returns whatever the server answered, or None if the
+    // handshake or the exchange failed.
+    async fn tls_ping(port: u16, connector: TlsConnector) -> Option<Vec<u8>> {
+        let tcp = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let name = ServerName::try_from("localhost").unwrap();
+        let mut stream = connector.connect(name, tcp).await.ok()?;
+
+        stream.write_all(b"*1\r\n$4\r\nPING\r\n").await.ok()?;
+
+        let mut response = vec![0u8; 64];
+        match stream.read(&mut response).await {
+            Ok(0) | Err(_) => None,
+            Ok(n) => Some(response[..n].to_vec()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tls_ping() {
+        let pki = TestPki::generate();
+        let mut config = pki.server_config(ClientAuth::No);
+        config.ca_cert_file = None;
+
+        let port = start_server(&config).await;
+
+        let response = tls_ping(port, pki.connector(false)).await;
+        assert_eq!(response.as_deref(), Some(&b"+PONG\r\n"[..]));
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls() {
+        let pki = TestPki::generate();
+        let port = start_server(&pki.server_config(ClientAuth::Required)).await;
+
+        // Client presenting a certificate signed by the CA is accepted
+        let response = tls_ping(port, pki.connector(true)).await;
+        assert_eq!(response.as_deref(), Some(&b"+PONG\r\n"[..]));
+
+        // Client without a certificate is rejected
+        assert!(tls_ping(port, pki.connector(false)).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_optional_client_auth() {
+        let pki = TestPki::generate();
+        let port = start_server(&pki.server_config(ClientAuth::Optional)).await;
+
+        let response = tls_ping(port, pki.connector(false)).await;
+        assert_eq!(response.as_deref(), Some(&b"+PONG\r\n"[..]));
+    }
+
+    #[test]
+    fn test_missing_key_file() {
+        let pki = TestPki::generate();
+        let mut config = pki.server_config(ClientAuth::No);
+        config.key_file = pki.path("missing.key");
+
+        assert!(build_acceptor(&config).is_err());
+    }
+}
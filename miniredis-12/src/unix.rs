@@ -0,0 +1,76 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+use tokio::net::UnixListener;
+
+pub fn bind(path: &Path, perm: Option<u32>) -> io::Result<UnixListener> {
+    // A socket left behind by a previous run would make bind fail with AddrInUse.
+    // Only sockets are removed so a typo in the path can't delete a regular file.
+    if let Ok(metadata) = fs::symlink_metadata(path)
+        && metadata.file_type().is_socket()
+    {
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+
+    if let Some(mode) = perm {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Db;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixStream;
+
+    #[tokio::test]
+    async fn test_unix_socket_ping() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redis.sock");
+
+        let listener = bind(&path, Some(0o700)).unwrap();
+        tokio::spawn(crate::serve_unix(listener, Db::new()));
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n*2\r\n$3\r\nGET\r\n$1\r\nk\r\n")
+            .await
+            .unwrap();
+
+        let expected = b"+OK\r\n$1\r\nv\r\n";
+        let mut response = vec![0u8; expected.len()];
+        stream.read_exact(&mut response).await.unwrap();
+        assert_eq!(response, expected);
+    }
+
+    #[tokio::test]
+    async fn test_rebind_replaces_stale_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redis.sock");
+
+        // Dropping the listener leaves the socket file on disk
+        drop(bind(&path, None).unwrap());
+        assert!(path.exists());
+
+        assert!(bind(&path, None).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_bind_keeps_regular_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("not-a-socket");
+        fs::write(&path, b"data").unwrap();
+
+        assert!(bind(&path, None).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"data");
+    }
+}
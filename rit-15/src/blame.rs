@@ -0,0 +1,444 @@
+use anyhow::Result;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::commands;
+use crate::diff::{self, Edit};
+use crate::index;
+use crate::objects::{self, Commit, Signature};
+use crate::refs;
+use crate::repository::Repository;
+use crate::revision;
+
+/// What `rit blame` shows and how
+#[derive(Debug, Default)]
+pub struct Options {
+    /// The commit to start from. Without one, the working tree version is blamed and lines
+    /// that differ from HEAD belong to no commit yet.
+    pub revision: Option<String>,
+    pub path: String,
+    /// `-L` ranges like "10,20", "10,+5", "10,-5", "10" (to the end) or ",20"
+    pub ranges: Vec<String>,
+    /// Machine-readable output for editors, like `git blame --porcelain`
+    pub porcelain: bool,
+}
+
+/// Who a line of the file is blamed on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub commit: String,
+    /// 1-based line number in the version of the file that commit introduced
+    pub orig_line: usize,
+}
+
+/// Every line of a file attributed to the commit that last changed it
+pub struct Blame {
+    pub path: String,
+    /// The file being blamed, one entry per line (each keeping its '\n')
+    pub content: Vec<Vec<u8>>,
+    pub lines: Vec<Line>,
+    /// The commits lines are blamed on; the working tree is there under the zero id
+    pub commits: HashMap<String, Commit>,
+    /// For each blamed commit, the first parent that had the file: git's "previous"
+    pub previous: HashMap<String, String>,
+}
+
+impl Blame {
+    /// Root commits are shown as boundaries, like git without `--root`
+    pub fn is_boundary(&self, hash: &str) -> bool {
+        self.commits[hash].parents.is_empty() && hash != Repository::current().format().zero_id()
+    }
+}
+
+/// A version of the file being passed down the history: the commit it's in and its blob
+struct Suspect {
+    blob: String,
+    /// The lines still looking for their commit, as (final line, line here), 0-based
+    lines: Vec<(usize, usize)>,
+}
+
+/// Walks history from `start`, giving each line to the oldest commit it survives back to.
+/// Lines a commit shares with a parent are passed on to that parent, the rest stay with it.
+pub fn blame(revision: Option<&str>, path: &str) -> Result<Blame> {
+    let repo = Repository::current();
+    let mut commits = HashMap::new();
+    let mut previous = HashMap::new();
+    let mut blobs: HashMap<String, Option<String>> = HashMap::new();
+    // File contents by blob, which is also where the working tree version is found
+    let mut contents: HashMap<String, Vec<u8>> = HashMap::new();
+
+    let (start, content) = match revision {
+        Some(rev) => {
+            let hash = revision::resolve_commit(repo, rev)?;
+            let commit = Commit::read(repo, &hash)?;
+            let blob = blob_at(&commit.tree, path)?
+                .ok_or_else(|| anyhow::anyhow!("no such path {} in {}", path, rev))?;
+            let data = objects::read_blob(repo, &blob)?;
+            contents.insert(blob.clone(), data.clone());
+            blobs.insert(hash.clone(), Some(blob));
+            commits.insert(hash.clone(), commit);
+            (hash, data)
+        }
+        None => {
+            let head = refs::head_commit(repo)?;
+            let in_head = match &head {
+                Some(head) => blob_at(&Commit::read(repo, head)?.tree, path)?.is_some(),
+                None => false,
+            };
+            let file = Path::new(path);
+            if !file.is_file() || !(in_head || index::read_index()?.contains_key(path)) {
+                anyhow::bail!("no such path '{}' in HEAD", path);
+            }
+            let data = commands::read_worktree_file(file)?;
+            let hash = repo.format().zero_id();
+            let blob = repo.format().hash_object("blob", &data);
+            contents.insert(blob.clone(), data.clone());
+            blobs.insert(hash.clone(), Some(blob));
+            commits.insert(hash.clone(), not_committed_yet(path, head)?);
+            (hash, data)
+        }
+    };
+
+    let content: Vec<Vec<u8>> = diff::split_lines(&content).into_iter().map(<[u8]>::to_vec).collect();
+    let mut lines = vec![None; content.len()];
+
+    // Newest first, so that a commit reached through several children is handled once,
+    // after all of them passed their lines on
+    let mut queue = BinaryHeap::new();
+    let mut suspects: HashMap<String, Suspect> = HashMap::new();
+    suspects.insert(
+        start.clone(),
+        Suspect {
+            blob: blobs[&start].clone().unwrap(),
+            lines: (0..content.len()).map(|i| (i, i)).collect(),
+        },
+    );
+    queue.push((i64::MAX, start));
+
+    while let Some((_, hash)) = queue.pop() {
+        let Suspect { blob, lines: mut remaining } = suspects.remove(&hash).unwrap();
+        let parents = commits[&hash].parents.clone();
+
+        // Where each parent has the file, if it does
+        let mut parent_blobs = Vec::new();
+        for parent in &parents {
+            if !commits.contains_key(parent) {
+                commits.insert(parent.clone(), Commit::read(repo, parent)?);
+            }
+            if !blobs.contains_key(parent) {
+                blobs.insert(parent.clone(), blob_at(&commits[parent].tree, path)?);
+            }
+            if let Some(parent_blob) = &blobs[parent] {
+                parent_blobs.push((parent.clone(), parent_blob.clone()));
+            }
+        }
+        if let Some((parent, _)) = parent_blobs.first() {
+            previous.insert(hash.clone(), parent.clone());
+        }
+
+        // A parent with the very same file takes every line, otherwise each parent takes
+        // the lines it has in common with this version
+        let mut passed: Vec<(String, Suspect)> = Vec::new();
+        if let Some((parent, parent_blob)) = parent_blobs.iter().find(|(_, parent_blob)| *parent_blob == blob) {
+            let lines = std::mem::take(&mut remaining);
+            passed.push((parent.clone(), Suspect { blob: parent_blob.clone(), lines }));
+        } else if !parent_blobs.is_empty() {
+            for wanted in parent_blobs.iter().map(|(_, parent_blob)| parent_blob).chain([&blob]) {
+                if !contents.contains_key(wanted) {
+                    contents.insert(wanted.clone(), objects::read_blob(repo, wanted)?);
+                }
+            }
+            let here = diff::split_lines(&contents[&blob]);
+            for (parent, parent_blob) in &parent_blobs {
+                if remaining.is_empty() {
+                    break;
+                }
+                let parent_data = &contents[parent_blob];
+                let mut unchanged = HashMap::new();
+                for edit in diff::diff(&diff::split_lines(parent_data), &here) {
+                    if let Edit::Equal(old, new) = edit {
+                        unchanged.insert(new, old);
+                    }
+                }
+
+                let (kept, left): (Vec<_>, Vec<_>) =
+                    remaining.into_iter().partition(|(_, line)| unchanged.contains_key(line));
+                remaining = left;
+                let kept: Vec<_> = kept.into_iter().map(|(line, here)| (line, unchanged[&here])).collect();
+                if !kept.is_empty() {
+                    passed.push((parent.clone(), Suspect { blob: parent_blob.clone(), lines: kept }));
+                }
+            }
+        }
+
+        for (line, orig) in remaining {
+            lines[line] = Some(Line { commit: hash.clone(), orig_line: orig + 1 });
+        }
+        // A parent dated after its child may come up again after it was handled
+        for (parent, suspect) in passed {
+            match suspects.get_mut(&parent) {
+                Some(waiting) => waiting.lines.extend(suspect.lines),
+                None => {
+                    queue.push((commits[&parent].committer.timestamp, parent.clone()));
+                    suspects.insert(parent, suspect);
+                }
+            }
+        }
+    }
+
+    // Only the commits something was blamed on are kept
+    let lines: Vec<Line> = lines.into_iter().map(Option::unwrap).collect();
+    let blamed: HashSet<&String> = lines.iter().map(|line| &line.commit).collect();
+    commits.retain(|hash, _| blamed.contains(hash));
+    previous.retain(|hash, _| blamed.contains(hash));
+
+    Ok(Blame { path: path.to_string(), content, lines, commits, previous })
+}
+
+// The working tree version stands in as a commit on top of HEAD, as git shows it
+fn not_committed_yet(path: &str, head: Option<String>) -> Result<Commit> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let signature = Signature {
+        name: "Not Committed Yet".to_string(),
+        email: "not.committed.yet".to_string(),
+        timestamp: now,
+        timezone: objects::format_offset(objects::local_offset(now)),
+    };
+    Ok(Commit {
+        tree: String::new(),
+        parents: head.into_iter().collect(),
+        author: signature.clone(),
+        committer: signature,
+        extra_headers: Vec::new(),
+        message: format!("Version of {} from {}\n", path, path).into_bytes(),
+    })
+}
+
+// The blob at `path` in a tree, looking only into the directories on the way
+fn blob_at(tree: &str, path: &str) -> Result<Option<String>> {
+    let repo = Repository::current();
+    let mut tree = tree.to_string();
+    let mut components = path.split('/').peekable();
+    while let Some(name) = components.next() {
+        let entries = commands::read_tree(repo, &tree)?;
+        let Some(entry) = entries.into_iter().find(|entry| entry.name == name) else {
+            return Ok(None);
+        };
+        match (components.peek(), entry.mode.as_str()) {
+            (None, "40000") | (None, "160000") => return Ok(None),
+            (None, _) => return Ok(Some(entry.hash)),
+            (Some(_), "40000") => tree = entry.hash,
+            (Some(_), _) => return Ok(None),
+        }
+    }
+    Ok(None)
+}
+
+/// Turns `-L` ranges into sorted, merged 1-based (start, end) pairs for a file of `total` lines
+pub fn parse_ranges(ranges: &[String], path: &str, total: usize) -> Result<Vec<(usize, usize)>> {
+    if ranges.is_empty() {
+        return Ok(if total == 0 { Vec::new() } else { vec![(1, total)] });
+    }
+
+    let number = |text: &str| -> Result<usize> {
+        match text.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => anyhow::bail!("-L invalid line number: {}", text),
+        }
+    };
+
+    let mut parsed = Vec::new();
+    for range in ranges {
+        let (start, end) = range.split_once(',').unwrap_or((range, ""));
+        let start = if start.is_empty() { 1 } else { number(start)? };
+        if start > total {
+            anyhow::bail!("file {} has only {} lines", path, total);
+        }
+
+        let (start, end) = if end.is_empty() {
+            (start, total)
+        } else if let Some(count) = end.strip_prefix('+') {
+            (start, start + number(count)? - 1)
+        } else if let Some(count) = end.strip_prefix('-') {
+            (start.saturating_sub(number(count)? - 1).max(1), start)
+        } else {
+            let end = number(end)?;
+            (start.min(end), start.max(end))
+        };
+        parsed.push((start, end.min(total)));
+    }
+
+    parsed.sort();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in parsed {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    Ok(merged)
+}
+
+/// `rit blame`
+pub fn show(options: &Options) -> Result<()> {
+    let blame = blame(options.revision.as_deref(), &options.path)?;
+    let ranges = parse_ranges(&options.ranges, &options.path, blame.lines.len())?;
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    if options.porcelain {
+        write_porcelain(&mut out, &blame, &ranges)?;
+    } else {
+        write_default(&mut out, &blame, &ranges)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// "2c8f9c17 (Author Name 2023-11-14 23:13:20 +0100  3) line", columns sized to what's shown
+fn write_default(out: &mut impl Write, blame: &Blame, ranges: &[(usize, usize)]) -> Result<()> {
+    let shown = || ranges.iter().flat_map(|&(start, end)| start..=end);
+    let name_width = shown()
+        .map(|n| blame.commits[&blame.lines[n - 1].commit].author.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let number_width = shown().last().unwrap_or(0).to_string().len();
+
+    for n in shown() {
+        let line = &blame.lines[n - 1];
+        let author = &blame.commits[&line.commit].author;
+        let hash = if blame.is_boundary(&line.commit) {
+            format!("^{}", &line.commit[..7])
+        } else {
+            line.commit[..8].to_string()
+        };
+
+        write!(
+            out,
+            "{} ({:<name_width$} {} {:>number_width$}) ",
+            hash,
+            author.name,
+            iso_date(author),
+            n
+        )?;
+        write_content(out, &blame.content[n - 1])?;
+    }
+    Ok(())
+}
+
+// Each line after a header naming its commit; the commit's details come the first time only
+fn write_porcelain(out: &mut impl Write, blame: &Blame, ranges: &[(usize, usize)]) -> Result<()> {
+    let mut described = HashSet::new();
+
+    for &(start, end) in ranges {
+        let mut n = start;
+        while n <= end {
+            // Consecutive lines from consecutive lines of the same commit form a group
+            let line = &blame.lines[n - 1];
+            let mut size = 1;
+            while n + size <= end && {
+                let next = &blame.lines[n + size - 1];
+                next.commit == line.commit && next.orig_line == line.orig_line + size
+            } {
+                size += 1;
+            }
+
+            for i in 0..size {
+                let line = &blame.lines[n + i - 1];
+                if i == 0 {
+                    writeln!(out, "{} {} {} {}", line.commit, line.orig_line, n, size)?;
+                } else {
+                    writeln!(out, "{} {} {}", line.commit, line.orig_line, n + i)?;
+                }
+                if described.insert(line.commit.clone()) {
+                    describe(out, blame, &line.commit)?;
+                }
+                out.write_all(b"\t")?;
+                write_content(out, &blame.content[n + i - 1])?;
+            }
+            n += size;
+        }
+    }
+    Ok(())
+}
+
+fn describe(out: &mut impl Write, blame: &Blame, hash: &str) -> Result<()> {
+    let commit = &blame.commits[hash];
+    for (role, signature) in [("author", &commit.author), ("committer", &commit.committer)] {
+        writeln!(out, "{} {}", role, signature.name)?;
+        writeln!(out, "{}-mail <{}>", role, signature.email)?;
+        writeln!(out, "{}-time {}", role, signature.timestamp)?;
+        writeln!(out, "{}-tz {}", role, signature.timezone)?;
+    }
+    writeln!(out, "summary {}", commit.message_text().lines().next().unwrap_or(""))?;
+    if blame.is_boundary(hash) {
+        writeln!(out, "boundary")?;
+    }
+    if let Some(parent) = blame.previous.get(hash) {
+        writeln!(out, "previous {} {}", parent, blame.path)?;
+    }
+    writeln!(out, "filename {}", blame.path)?;
+    Ok(())
+}
+
+// A line of the file, with a newline even if the file's last line has none
+fn write_content(out: &mut impl Write, content: &[u8]) -> Result<()> {
+    out.write_all(content)?;
+    if !content.ends_with(b"\n") {
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+// "2023-11-14 23:13:20 +0100", in the signature's own timezone
+fn iso_date(signature: &Signature) -> String {
+    let local = signature.timestamp + objects::timezone_offset(&signature.timezone);
+    let (year, month, day) = objects::civil_from_days(local.div_euclid(86400));
+    let seconds = local.rem_euclid(86400);
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02}:{:02} {}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60,
+        signature.timezone
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(specs: &[&str], total: usize) -> Result<Vec<(usize, usize)>> {
+        let specs: Vec<String> = specs.iter().map(|spec| spec.to_string()).collect();
+        parse_ranges(&specs, "a.txt", total)
+    }
+
+    #[test]
+    fn line_ranges() {
+        assert_eq!(ranges(&[], 10).unwrap(), [(1, 10)]);
+        assert_eq!(ranges(&["2,4"], 10).unwrap(), [(2, 4)]);
+        assert_eq!(ranges(&["5,3"], 10).unwrap(), [(3, 5)]);
+        assert_eq!(ranges(&["3,+2"], 10).unwrap(), [(3, 4)]);
+        assert_eq!(ranges(&["4,-2"], 10).unwrap(), [(3, 4)]);
+        assert_eq!(ranges(&["9"], 10).unwrap(), [(9, 10)]);
+        assert_eq!(ranges(&[",2"], 10).unwrap(), [(1, 2)]);
+        assert_eq!(ranges(&["9,100"], 10).unwrap(), [(9, 10)]);
+        assert_eq!(ranges(&["9", "1,2", "2,3"], 10).unwrap(), [(1, 3), (9, 10)]);
+
+        assert_eq!(ranges(&["20"], 10).unwrap_err().to_string(), "file a.txt has only 10 lines");
+        assert_eq!(ranges(&["0,2"], 10).unwrap_err().to_string(), "-L invalid line number: 0");
+        assert!(ranges(&["abc"], 10).is_err());
+    }
+
+    #[test]
+    fn iso_dates() {
+        let signature = Signature::parse("A <a@example.com> 1700000000 +0100").unwrap();
+        assert_eq!(iso_date(&signature), "2023-11-14 23:13:20 +0100");
+        let signature = Signature::parse("A <a@example.com> 1700000000 -0530").unwrap();
+        assert_eq!(iso_date(&signature), "2023-11-14 16:43:20 -0530");
+    }
+}
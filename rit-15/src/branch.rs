@@ -0,0 +1,154 @@
+use anyhow::Result;
+
+use crate::commands;
+use crate::reflog;
+use crate::refs::{self, Head};
+use crate::repository::Repository;
+use crate::revision;
+
+fn branch_ref(name: &str) -> String {
+    format!("refs/heads/{}", name)
+}
+
+/// Lists local branches, marking the checked out one with '*'
+pub fn list() -> Result<()> {
+    let repo = Repository::current();
+    let head = refs::read_head(repo)?;
+
+    if let Head::Detached(hash) = &head {
+        println!("* \x1b[32m(HEAD detached at {})\x1b[0m", &hash[..hash.len().min(7)]);
+    }
+
+    for (name, _) in refs::list_refs(repo, "refs/heads")? {
+        match &head {
+            Head::Branch(current) if *current == branch_ref(&name) => {
+                println!("* \x1b[32m{}\x1b[0m", name)
+            }
+            _ => println!("  {}", name),
+        }
+    }
+    Ok(())
+}
+
+/// Creates a branch at `start` (any commit or branch), defaulting to HEAD
+pub fn create(name: &str, start: Option<&str>) -> Result<()> {
+    let repo = Repository::current();
+    refs::validate_branch_name(name)?;
+
+    if refs::read_ref(repo, &branch_ref(name))?.is_some() {
+        anyhow::bail!("a branch named '{}' already exists", name);
+    }
+
+    let start = start.unwrap_or("HEAD");
+    let commit_hash = revision::resolve_commit(repo, start)?;
+    refs::write_ref(repo, &branch_ref(name), &commit_hash, &format!("branch: Created from {}", start))?;
+    Ok(())
+}
+
+/// Deletes a branch. Without `force` the branch must already be merged into HEAD.
+pub fn delete(name: &str, force: bool) -> Result<()> {
+    let repo = Repository::current();
+    let hash = refs::read_ref(repo, &branch_ref(name))?
+        .ok_or_else(|| anyhow::anyhow!("branch '{}' not found", name))?;
+
+    if refs::current_branch(repo)?.as_deref() == Some(name) {
+        anyhow::bail!("cannot delete branch '{}' which is currently checked out", name);
+    }
+
+    if !force {
+        let merged = match refs::head_commit(repo)? {
+            Some(head) => refs::is_ancestor(repo, &hash, &head)?,
+            None => false,
+        };
+        if !merged {
+            anyhow::bail!(
+                "the branch '{}' is not fully merged.\nIf you are sure you want to delete it, run 'rit branch -D {}'",
+                name,
+                name
+            );
+        }
+    }
+
+    refs::delete_ref(repo, &branch_ref(name))?;
+    println!("Deleted branch {} (was {}).", name, &hash[..7]);
+    Ok(())
+}
+
+/// Renames `old` (the current branch when None) to `new`, keeping HEAD attached
+pub fn rename(old: Option<&str>, new: &str, force: bool) -> Result<()> {
+    let repo = Repository::current();
+    let old = match old {
+        Some(old) => old.to_string(),
+        None => refs::current_branch(repo)?
+            .ok_or_else(|| anyhow::anyhow!("cannot rename the current branch while not on any"))?,
+    };
+    refs::validate_branch_name(new)?;
+
+    if !force && refs::read_ref(repo, &branch_ref(new))?.is_some() {
+        anyhow::bail!("a branch named '{}' already exists", new);
+    }
+
+    let is_current = refs::current_branch(repo)?.as_deref() == Some(old.as_str());
+
+    match refs::read_ref(repo, &branch_ref(&old))? {
+        Some(hash) => {
+            // The log moves with the branch and gets one more entry for the rename
+            reflog::rename(repo, &branch_ref(&old), &branch_ref(new))?;
+            refs::delete_ref(repo, &branch_ref(&old))?;
+            let reason = format!("Branch: renamed {} to {}", branch_ref(&old), branch_ref(new));
+            refs::write_ref(repo, &branch_ref(new), &hash, &reason)?;
+        }
+        // An unborn current branch has no ref file yet, only HEAD needs to change
+        None if is_current => {}
+        None => anyhow::bail!("branch '{}' not found", old),
+    }
+
+    if is_current {
+        refs::set_head_branch(repo, &branch_ref(new))?;
+    }
+    Ok(())
+}
+
+/// Checks out a branch, creating it at `start` (or HEAD) first when `create` is set
+pub fn switch(name: &str, create: bool, start: Option<&str>) -> Result<()> {
+    let repo = Repository::current();
+    let head = refs::head_commit(repo)?;
+    let name = &if create { name.to_string() } else { revision::expand_previous(repo, name)? };
+
+    if create {
+        // On an unborn branch there is nothing to point the new branch at yet,
+        // so just move HEAD and let the first commit create the ref
+        if head.is_none() && start.is_none() {
+            refs::validate_branch_name(name)?;
+            if refs::read_ref(repo, &branch_ref(name))?.is_some() {
+                anyhow::bail!("a branch named '{}' already exists", name);
+            }
+            refs::set_head_branch(repo, &branch_ref(name))?;
+            println!("Switched to a new branch '{}'", name);
+            return Ok(());
+        }
+        self::create(name, start)?;
+    }
+
+    let target = refs::read_ref(repo, &branch_ref(name))?
+        .ok_or_else(|| anyhow::anyhow!("invalid reference: {}", name))?;
+
+    if refs::current_branch(repo)?.as_deref() == Some(name) {
+        println!("Already on '{}'", name);
+        return Ok(());
+    }
+
+    // Same commit means the same tree, only HEAD has to move
+    if head.as_deref() != Some(target.as_str()) {
+        commands::checkout_commit(&target)?;
+    }
+    reflog::log_checkout(repo, name, &target)?;
+    refs::set_head_branch(repo, &branch_ref(name))?;
+
+    if create {
+        println!("Switched to a new branch '{}'", name);
+    } else {
+        println!("Switched to branch '{}'", name);
+    }
+    Ok(())
+}
@@ -1,584 +1,564 @@
 use anyhow::Result;
-use flate2::Compression;
-use flate2::write::ZlibEncoder;
-use flate2::read::ZlibDecoder;
-use sha1::{Digest, Sha1};
-use std::collections::HashMap;
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
 use std::fs;
-use std::io::{Read, Write};
-use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::io::BufRead;
-use std::io::BufReader;
+use std::io::Write;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use walkdir::WalkDir;
-use std::os::unix::fs::PermissionsExt; 
 
+use crate::config;
+use crate::ignore::{Ignore, IGNORE_FILE};
+use crate::index;
+use crate::merge;
+use crate::objects::{self, Commit, ObjectDatabase, ObjectFormat, Signature, Tree, TreeEntry};
+use crate::reflog;
+use crate::refs;
+use crate::repository::Repository;
+use crate::revision;
+use crate::status;
+
+/// Name of the repository directory in a working tree
 pub const RIT_DIR: &str = ".rit";
 
-pub fn init() -> Result<()> {
-    fs::create_dir(RIT_DIR)?;
+static REPOSITORY_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// The repository directory the commands work on. It is `.rit` unless `--git-dir` or the
+/// `RIT_DIR` environment variable names another, such as the `.git` of a git repository.
+pub fn rit_dir() -> &'static Path {
+    REPOSITORY_DIR.get_or_init(|| match std::env::var_os("RIT_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(RIT_DIR),
+    })
+}
+
+/// Overrides the repository directory, as `--git-dir` does. It has to come before any command.
+pub fn set_rit_dir(dir: impl Into<PathBuf>) -> Result<()> {
+    REPOSITORY_DIR
+        .set(dir.into())
+        .map_err(|_| anyhow::anyhow!("the repository directory is already in use"))
+}
+
+pub fn init(object_format: Option<&str>) -> Result<()> {
+    let format = match object_format {
+        Some(name) => ObjectFormat::from_name(name)?,
+        None => ObjectFormat::Sha1,
+    };
+    init_repository(format)?;
+    println!("Initialized empty Rit  repository in {}", rit_dir().display());
+    Ok(())
+}
+
+/// Creates the repository directory (`.rit` by default) without printing anything
+pub fn init_repository(format: ObjectFormat) -> Result<()> {
+    fs::create_dir(rit_dir())?;
 
     // This stores blobs and trees
-    let objects_path = format!("{}/objects", RIT_DIR);
+    let objects_path = rit_dir().join("objects");
     fs::create_dir(&objects_path)?;
 
     // we store branches
-    let refs_path = format!("{}/refs", RIT_DIR);
+    let refs_path = rit_dir().join("refs");
     fs::create_dir(&refs_path)?;
 
-    let heads_path = format!("{}/heads", refs_path);
+    let heads_path = refs_path.join("heads");
     fs::create_dir(&heads_path)?;
 
     // HEAD file pointer
-    let head_path = format!("{}/HEAD", RIT_DIR);
+    let head_path = rit_dir().join("HEAD");
     fs::write(&head_path, "ref: refs/heads/main\n")?;
 
-    println!("Initialized empty Rit  repository in {}", RIT_DIR);
+    // Like git, only repositories that need an extension are marked as version 1
+    if format != ObjectFormat::Sha1 {
+        config::set("core.repositoryformatversion", "1")?;
+        config::set("extensions.objectformat", format.name())?;
+    }
     Ok(())
 }
 
 pub fn hash_object(file_path: &str, write: bool) -> Result<String> {
-    let content = fs::read(file_path)?;
-
-    let header = format!("blob {}\0", content.len());
-
-    // combine header and content
-    let mut store = header.into_bytes();
-    store.extend(&content);
-
-    // Calculate SHA-1 Hash
-    let mut hasher = Sha1::new();
-    hasher.update(&store);
-    let result = hasher.finalize();
-    let hash_string = hex::encode(result);
+    let content = read_worktree_file(Path::new(file_path))?;
 
+    // "blob <size>\0<content>", hashed with the repository's object format and zlib compressed on disk
+    let repo = Repository::current();
     if write {
-        let dir_name = &hash_string[..2];
-        let file_name = &hash_string[2..];
-
-        let object_dir = format!("{}/objects/{}", RIT_DIR, dir_name);
-        let object_path = format!("{}/{}", object_dir, file_name);
-
-        if !Path::new(&object_dir).exists() {
-            fs::create_dir(&object_dir)?;
-        }
-
-        // compress data using zlib and write to file
-        let file = fs::File::create(&object_path)?;
-        let mut encoder = ZlibEncoder::new(file, Compression::default());
-        encoder.write_all(&store)?;
-        encoder.finish()?;
+        repo.objects().write_raw("blob", &content)
+    } else {
+        Ok(repo.format().hash_object("blob", &content))
     }
-
-    //println!("{}", hash_string);
-    Ok(hash_string)
 }
 
-pub fn cat_file(object_hash: &str, _pretty_print: bool) -> Result<()> {
-
-    // We assume the user gives a full 40-char hash not the file name of the file
-    let dir_name = &object_hash[..2];
-    let file_name = &object_hash[2..];
-    let object_path = format!("{}/objects/{}/{}", RIT_DIR, dir_name, file_name);
-
-    if !Path::new(&object_path).exists() {
-        anyhow::bail!("Object {} not found", object_hash);
+// Blob content of a working tree file. A symlink stores the path it points to, not the target's data.
+pub fn read_worktree_file(path: &Path) -> Result<Vec<u8>> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        return Ok(fs::read_link(path)?.into_os_string().into_vec());
     }
+    Ok(fs::read(path)?)
+}
 
-    let file = fs::File::open(&object_path)?;
-
-    // decompress the data
-    let mut decoder = ZlibDecoder::new(file);
-    let mut contents = Vec::new();
-    decoder.read_to_end(&mut contents)?;
+// Tree mode for a file, from its lstat metadata
+pub fn file_mode(metadata: &fs::Metadata) -> &'static str {
+    if metadata.file_type().is_symlink() {
+        "120000"
+    } else if metadata.permissions().mode() & 0o111 != 0 {
+        "100755"
+    } else {
+        "100644"
+    }
+}
 
-    
-    // split header from body
-    // format <type> <size>\0<content>
-    let null_index = contents.iter()
-        .position(|&b| b == 0) 
-        .ok_or_else(|| anyhow::anyhow!("Invalid object format"))?;
+// Mode and blob hash of a working tree file, as `add` would stage it
+pub fn worktree_entry(file_path: &str, write: bool) -> Result<index::Entry> {
+    let metadata = fs::symlink_metadata(file_path)?;
+    Ok(index::Entry {
+        mode: file_mode(&metadata).to_string(),
+        hash: hash_object(file_path, write)?,
+    })
+}
 
-    let header = String::from_utf8(contents[..null_index].to_vec())?;
+pub fn cat_file(object_hash: &str, pretty_print: bool) -> Result<()> {
+    let repo = Repository::current();
+    let (kind, body) = repo.objects().read_raw(&revision::resolve(repo, object_hash)?)?;
 
-    let body = &contents[null_index + 1..];
     let mut stdout = std::io::stdout();
-    stdout.write_all(body)?;
+    if pretty_print && kind == "tag" {
+        // Parsed and written back, so a malformed tag is reported instead of dumped
+        let tag = objects::Tag::parse(&String::from_utf8_lossy(&body))?;
+        write!(stdout, "{}", tag)?;
+        return Ok(());
+    }
+    if pretty_print && kind == "tree" {
+        write!(stdout, "{}", Tree::parse(&body, repo.format())?)?;
+        return Ok(());
+    }
+    stdout.write_all(&body)?;
     Ok(())
 }
 
-struct TreeEntry {
-    name: String,
-    mode: String,
-    hash: String,
+// Builds tree objects from the index alone, so only staged content ends up in a commit
+pub fn write_tree() -> Result<String> {
+    write_tree_from(&index::read_index()?)
 }
 
-pub fn write_tree(path: &str) -> Result<String> {
-   let index_path = format!("{}/index", RIT_DIR);
-    let mut index_map = HashMap::new();
-    
-    if Path::new(&index_path).exists() {
-        let file = fs::File::open(&index_path)?;
-        let reader = BufReader::new(file);
-        for line in reader.lines() {
-            let line = line?;
-            if let Some((p, h)) = line.split_once(' ') {
-                index_map.insert(p.to_string(), h.to_string());
-            }
-        }
-    }
-
-    write_tree_recursive(path, &index_map)
+/// Builds tree objects for any snapshot of path -> mode and hash; the blobs must exist
+pub fn write_tree_from(files: &index::Index) -> Result<String> {
+    let entries: Vec<(&str, &index::Entry)> = files
+        .iter()
+        .map(|(path, entry)| (path.as_str(), entry))
+        .collect();
+    write_tree_level(&entries)
 }
 
-// The internal recursive function
-fn write_tree_recursive(dir_path: &str, index: &HashMap<String, String>) -> Result<String> {
-    let mut entries = Vec::new();
-    
-    for entry in fs::read_dir(dir_path)? {
-        let entry = entry?;
-        let path = entry.path();
-        let name = entry.file_name().into_string().unwrap();
-        
-        if name == RIT_DIR || name == ".git" || name == "target" { continue; }
-
-        // Clean path string (remove ./ for matching)
-        let path_str = path.to_str().unwrap();
-        let relative_path = path_str.trim_start_matches("./");
-
-        if path.is_dir() {
-            // For directories, we recurse
-            // Optimization: In real git, we would check if the directory contains *any* staged files
-            // For now, we just recurse and see what returns
-            if let Ok(dir_hash) = write_tree_recursive(path_str, index) {
-                // Only add the directory if it wasn't empty
-                entries.push(TreeEntry {
-                    name,
-                    mode: "40000".to_string(),
-                    hash: dir_hash,
+// Writes one directory level. `entries` are sorted and relative to this directory.
+fn write_tree_level(entries: &[(&str, &index::Entry)]) -> Result<String> {
+    let mut tree = Vec::new();
+    let mut i = 0;
+
+    while i < entries.len() {
+        let (path, entry) = entries[i];
+
+        match path.split_once('/') {
+            None => {
+                tree.push(TreeEntry {
+                    name: path.to_string(),
+                    mode: entry.mode.clone(),
+                    hash: entry.hash.clone(),
                 });
+                i += 1;
             }
-        } else {
-            // Check if this file is in our Index
-            if let Some(hash) = index.get(relative_path) {
-                // Use the hash from the index! (We don't need to re-hash the file)
-                entries.push(TreeEntry {
-                    name,
-                    mode: "100644".to_string(),
-                    hash: hash.clone(),
+            Some((dir, _)) => {
+                // Everything under "dir/" is contiguous in sorted order
+                let prefix = format!("{}/", dir);
+                let children: Vec<(&str, &index::Entry)> = entries[i..]
+                    .iter()
+                    .take_while(|(child, _)| child.starts_with(&prefix))
+                    .map(|(child, entry)| (&child[prefix.len()..], *entry))
+                    .collect();
+                i += children.len();
+
+                tree.push(TreeEntry {
+                    name: dir.to_string(),
+                    mode: "40000".to_string(),
+                    hash: write_tree_level(&children)?,
                 });
-            } else {
-                // File is on disk but not in index -> IGNORE IT (Untracked)
-                // println!("Skipping untracked file: {}", name);
             }
         }
     }
-    
-    // If the tree is empty (no staged files in this folder), we might want to return an error or handle it
-    if entries.is_empty() {
-        // For simplicity, let's just return a "empty tree" hash
-    }
 
-    // ... (The rest of sorting, packing, and writing is exactly the same as before) ...
-    entries.sort_by(|a, b| a.name.cmp(&b.name));
-    
-    let mut body = Vec::new();
-    for entry in entries {
-        let line = format!("{} {}\0", entry.mode, entry.name);
-        body.extend(line.as_bytes());
-        let hash_bytes = hex::decode(&entry.hash)?;
-        body.extend(&hash_bytes);
-    }
-
-    let header = format!("tree {}\0", body.len());
-    let mut store = header.into_bytes();
-    store.extend(&body);
-
-    let mut hasher = Sha1::new();
-    hasher.update(&store);
-    let tree_hash = hex::encode(hasher.finalize());
-    
-    // Write to disk
-   
-    let dir_name = &tree_hash[..2];
-    let file_name = &tree_hash[2..];
-    let object_dir = format!("{}/objects/{}", RIT_DIR, dir_name);
-    if !Path::new(&object_dir).exists() { fs::create_dir(&object_dir)?; }
-    let path = format!("{}/{}", object_dir, file_name);
-    let file = fs::File::create(path)?;
-    let mut encoder = ZlibEncoder::new(file, Compression::default());
-    encoder.write_all(&store)?;
-    encoder.finish()?;
-
-    Ok(tree_hash)
+    let mut tree = Tree { entries: tree };
+    tree.sort();
+    Repository::current().objects().write_raw("tree", &tree.serialize()?)
 }
 
-pub fn commit_tree(tree_hash: &str, parent_hash: Option<&str>, message: &str) -> Result<String> {
-    let mut body = String::new();
-    
-    //  Add Tree
-    body.push_str(&format!("tree {}\n", tree_hash));
-    
-    // Add Parent (if it exists)
-    if let Some(parent) = parent_hash {
-        body.push_str(&format!("parent {}\n", parent));
-    }
-    
-    //  Add Author/Committer (Using current time)
-    let start = SystemTime::now();
-    let since_the_epoch = start.duration_since(UNIX_EPOCH)?;
-    let timestamp = since_the_epoch.as_secs();
-    
-    let author_string = format!("author Rit User <rit@example.com> {} +0000\n", timestamp);
-    let committer_string = format!("committer Rit User <rit@example.com> {} +0000\n", timestamp);
-    
-    body.push_str(&author_string);
-    body.push_str(&committer_string);
-    
-    // Add Message (preceded by an empty line)
-    body.push_str("\n");
-    body.push_str(message);
-    body.push_str("\n"); // Git usually ends with a newline
-
-    //  Create the object header + body
-    let header = format!("commit {}\0", body.len());
-    let mut store = header.into_bytes();
-    store.extend(body.as_bytes());
-
-    //  Hash and Save (Standard logic)
-    let mut hasher = Sha1::new();
-    hasher.update(&store);
-    let result = hasher.finalize();
-    let commit_hash = hex::encode(result);
-
-    // Write to disk
-    let dir_name = &commit_hash[..2];
-    let file_name = &commit_hash[2..];
-    let object_dir = format!("{}/objects/{}", RIT_DIR, dir_name);
-    let object_path = format!("{}/{}", object_dir, file_name);
-
-    if !Path::new(&object_dir).exists() {
-        fs::create_dir(&object_dir)?;
-    }
-    
-    let file = fs::File::create(&object_path)?;
-    let mut encoder = ZlibEncoder::new(file, Compression::default());
-    encoder.write_all(&store)?;
-    encoder.finish()?;
+pub fn commit_tree(tree_hash: &str, parents: &[String], message: &str) -> Result<String> {
+    commit_tree_with_author(tree_hash, parents, message, &Signature::current("author")?)
+}
 
-    Ok(commit_hash)
+/// Like `commit_tree`, but keeping the author of a commit being replayed
+pub fn commit_tree_with_author(
+    tree_hash: &str,
+    parents: &[String],
+    message: &str,
+    author: &Signature,
+) -> Result<String> {
+    // The committer comes from the environment or config, at the current time
+    let commit = Commit {
+        tree: tree_hash.to_string(),
+        parents: parents.to_vec(),
+        author: author.clone(),
+        committer: Signature::current("committer")?,
+        // Git usually ends with a newline
+        extra_headers: Vec::new(),
+        message: format!("{}\n", message).into_bytes(),
+    };
+    Repository::current().objects().write_raw("commit", &commit.serialize())
 }
 
-pub fn update_ref(ref_name: &str, oid: &str) -> Result<()> {
-    // ref_name will be something like "refs/heads/main"
-    let ref_path = format!("{}/{}", RIT_DIR, ref_name);
-    
-    let path_obj = Path::new(&ref_path);
-    if let Some(parent) = path_obj.parent() {
-        if !parent.exists() {
-            fs::create_dir_all(parent)?;
-        }
-    }
+pub fn update_ref(ref_name: &str, oid: &str, reason: &str) -> Result<()> {
+    // ref_name will be something like "refs/heads/main"; the move is logged with `reason`
+    refs::write_ref(Repository::current(), ref_name, oid, reason)?;
 
-    // We add a newline because Git creates refs with a trailing newline
-    fs::write(&ref_path, format!("{}\n", oid))?;
-    
     println!("Updated {} to {}", ref_name, oid);
     Ok(())
 }
 
 // The public entry point
-pub fn add(path: &str) -> Result<()> {
+pub fn add(path: &str, force: bool) -> Result<()> {
     // Check if the path exists and what type it is
-    let metadata = fs::metadata(path)?;
+    let metadata = fs::symlink_metadata(path)?;
+
+    // Read the index once and write it once, however many files get added
+    let (mut index_map, mut stats) = index::read_index_with_stats()?;
+    let mut ignore = Ignore::new();
 
     if metadata.is_dir() {
-        // If it's a directory (like "."), walk through it recursively
-        for entry in WalkDir::new(path) {
+        // If it's a directory (like "."), walk through it recursively, skipping ignored
+        // files unless they are already tracked. Forcing never adds the repository itself.
+        let mut walker = WalkDir::new(path).into_iter();
+        while let Some(entry) = walker.next() {
             let entry = entry?;
-            let entry_path = entry.path();
-            
-            // We only want to add files, not sub-directories themselves
-            if entry_path.is_file() {
-                let path_str = entry_path.to_str().unwrap();
-                
-                // IMPORTANT: Do not add the .rit folder itself!
-                // We check if the path string contains our hidden dir name
-                if path_str.contains(RIT_DIR) || path_str.contains(".git") {
-                    continue;
+            let path_str = entry.path().to_str().unwrap();
+            let clean_path = path_str.trim_start_matches("./");
+            let is_dir = entry.file_type().is_dir();
+
+            let skip = if force {
+                entry.file_name() == RIT_DIR || entry.file_name() == ".git" || entry.path() == rit_dir()
+            } else {
+                ignore.is_ignored(clean_path, is_dir)? && !is_tracked(&index_map, clean_path, is_dir)
+            };
+            if skip {
+                if is_dir {
+                    walker.skip_current_dir();
                 }
+                continue;
+            }
 
-                // Call the actual logic
-                add_file(path_str)?;
+            // We only want to add files (and symlinks), not sub-directories themselves
+            if !is_dir {
+                add_file(path_str, &mut index_map, &mut stats)?;
             }
         }
     } else {
-        // If it's just a single file, add it directly
-        add_file(path)?;
+        // A single file is added directly, unless it is ignored and not tracked yet
+        let clean_path = path.trim_start_matches("./");
+        if !force && ignore.is_ignored(clean_path, false)? && !index_map.contains_key(clean_path) {
+            anyhow::bail!(
+                "The following paths are ignored by one of your {} files:\n{}\nUse -f if you really want to add them.",
+                IGNORE_FILE,
+                clean_path
+            );
+        }
+        add_file(path, &mut index_map, &mut stats)?;
     }
-    
-    Ok(())
+
+    index::write_index_with_stats(&index_map, &stats)
 }
 
-fn add_file(file_path: &str) -> Result<()> {
-    let hash = hash_object(file_path, true)?;
-    
-    let index_path = format!("{}/index", RIT_DIR);
-    let mut index_map = HashMap::new();
-    
-    if Path::new(&index_path).exists() {
-        let file = fs::File::open(&index_path)?;
-        let reader = BufReader::new(file);
-        for line in reader.lines() {
-            let line = line?;
-            if let Some((path, h)) = line.split_once(' ') {
-                index_map.insert(path.to_string(), h.to_string());
-            }
-        }
+/// Whether `path` is named by `spec`: the path itself, a directory above it, or "."
+pub fn matches_pathspec(path: &str, spec: &str) -> bool {
+    let spec = spec.trim_start_matches("./").trim_end_matches('/');
+    spec.is_empty()
+        || spec == "."
+        || path == spec
+        || path.strip_prefix(spec).is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Whether the index has `path`, or anything below it when it is a directory.
+/// Tracked files stay visible to add and status even when they match an ignore pattern.
+pub fn is_tracked(index_map: &index::Index, path: &str, is_dir: bool) -> bool {
+    if !is_dir {
+        return index_map.contains_key(path);
     }
-    
+    let prefix = format!("{}/", path);
+    index_map
+        .range(prefix.clone()..)
+        .next()
+        .is_some_and(|(tracked, _)| tracked.starts_with(&prefix))
+}
+
+fn add_file(file_path: &str, index_map: &mut index::Index, stats: &mut index::StatCache) -> Result<()> {
     // Clean path (remove ./ prefix)
     let clean_path = file_path.trim_start_matches("./");
-    index_map.insert(clean_path.to_string(), hash);
-    
-    let mut file = fs::File::create(&index_path)?;
-    let mut entries: Vec<_> = index_map.iter().collect();
-    entries.sort_by_key(|(path, _)| *path);
-    
-    for (path, hash) in entries {
-        writeln!(file, "{} {}", path, hash)?;
+
+    // Stat before reading, so a write racing with us leaves a stale stat rather than a wrong one
+    let metadata = fs::symlink_metadata(file_path)?;
+    let stat = index::Stat::from_metadata(&metadata);
+
+    // Same stat data as when it was staged: the file hasn't changed, skip hashing it
+    let unchanged = stats.get(clean_path) == Some(&stat)
+        && index_map.get(clean_path).is_some_and(|entry| entry.mode == file_mode(&metadata));
+
+    if !unchanged {
+        let entry = worktree_entry(file_path, true)?;
+        if index_map.get(clean_path) != Some(&entry) {
+            println!("Added '{}'", clean_path);
+        }
+        index_map.insert(clean_path.to_string(), entry);
+        stats.insert(clean_path.to_string(), stat);
     }
-    
-    println!("Added '{}'", clean_path);
+
+    // Staging a conflicted file marks it as resolved
+    merge::mark_resolved(clean_path)?;
     Ok(())
 }
+
 pub fn commit(message: &str) -> Result<()> {
     // Create the Tree from the Index
     // (This uses your new filtered write_tree logic)
-    let tree_hash = write_tree(".")?;
-    
-    // Find the Parent Commit (if it exists)
-    let head_path = format!("{}/HEAD", RIT_DIR);
-    let head_content = fs::read_to_string(&head_path)?;
-    // head_content is "ref: refs/heads/main\n"
-    
-    let ref_path_str = head_content.trim().strip_prefix("ref: ").unwrap_or(head_content.trim());
-    let full_ref_path = format!("{}/{}", RIT_DIR, ref_path_str);
-    
-    let parent_hash = if Path::new(&full_ref_path).exists() {
-        // If refs/heads/main exists, read the hash inside it
-        Some(fs::read_to_string(full_ref_path)?.trim().to_string())
+    let repo = Repository::current();
+    let tree_hash = write_tree()?;
+
+    // Find the Parent Commit (None means this is the FIRST commit, the Root commit)
+    let mut parents: Vec<String> = refs::head_commit(repo)?.into_iter().collect();
+
+    // Whatever left conflicts behind (merge, cherry-pick, stash), they must be resolved first
+    let conflicts = merge::read_conflicts()?;
+    if !conflicts.is_empty() {
+        anyhow::bail!(
+            "Committing is not possible because you have unmerged files: {}\nFix them up in the work tree, then use 'rit add <file>'",
+            conflicts.join(", ")
+        );
+    }
+
+    // Concluding a merge: the merged commit becomes the second parent
+    let merge_head = merge::merge_head()?;
+    if let Some(merge_head) = &merge_head {
+        parents.push(merge_head.clone());
+    }
+
+    // Create the Commit Object
+    let commit_hash = commit_tree(&tree_hash, &parents, message)?;
+
+    // Move the branch pointer, or HEAD itself when detached
+    let kind = if merge_head.is_some() {
+        "commit (merge)"
+    } else if parents.is_empty() {
+        "commit (initial)"
     } else {
-        // If it doesn't exist, this is the FIRST commit (Root commit)
-        None
+        "commit"
     };
-    
-    // Create the Commit Object
-    // We pass parent_hash.as_deref() to convert Option<String> to Option<&str>
-    let commit_hash = commit_tree(&tree_hash, parent_hash.as_deref(), message)?;
-    
-    // Update the Reference (Move the branch pointer)
-    update_ref(ref_path_str, &commit_hash)?;
-    
+    let subject = message.lines().next().unwrap_or_default();
+    refs::advance_head(repo, &commit_hash, &format!("{}: {}", kind, subject))?;
+
+    // Concludes a merge or a conflicted cherry-pick
+    merge::clear_state()?;
+
     println!("[{}] {}", &commit_hash[..7], message);
     Ok(())
 }
 
-// Make sure you have these imports
-pub fn log(oid: &str) -> Result<()> {
-    // Resolve the starting commit hash
-    let mut current_hash = oid.to_string();
-
-    // If the user didn't pass a specific hash, look up where HEAD points
-    if current_hash == "HEAD" {
-        let head_path = format!("{}/HEAD", RIT_DIR);
-        let head_content = fs::read_to_string(&head_path)?;
-        // head_content is like "ref: refs/heads/main\n"
-        let ref_path_str = head_content.trim().strip_prefix("ref: ").unwrap_or(head_content.trim());
-        let full_ref_path = format!("{}/{}", RIT_DIR, ref_path_str);
-
-        if Path::new(&full_ref_path).exists() {
-            current_hash = fs::read_to_string(full_ref_path)?.trim().to_string();
+pub fn checkout(target: &str) -> Result<()> {
+    // "-" and "@{-1}" go back to the branch we came from
+    let repo = Repository::current();
+    let target = revision::expand_previous(repo, target)?;
+
+    // Resolve target: Is it a branch name (like 'main') or any other revision?
+    let branch = refs::read_ref(repo, &format!("refs/heads/{}", target))?;
+    let (commit_hash, new_head_content) = if let Some(hash) = branch {
+        // It is a branch! Its hash is where we go.
+        (hash, format!("ref: refs/heads/{}\n", target))
+    } else {
+        // A commit, tag or relative revision: HEAD is detached and holds the hash directly
+        let hash = revision::resolve_commit(repo, &target)?;
+        let content = format!("{}\n", hash);
+        (hash, content)
+    };
+
+    checkout_commit(&commit_hash)?;
+    reflog::log_checkout(repo, &target, &commit_hash)?;
+
+    //  Update HEAD
+    fs::write(repo.rit_dir().join("HEAD"), new_head_content)?;
+
+    println!("Switched to '{}'", target);
+    Ok(())
+}
+
+// Moves the working tree and the index from HEAD to another commit.
+// Only paths that differ between the two commits are touched, so local changes
+// elsewhere are carried over. Local changes on those paths make the checkout fail.
+pub fn checkout_commit(commit_hash: &str) -> Result<()> {
+    let head = status::head_files()?;
+    let target = flatten_tree(&Commit::read(Repository::current(), commit_hash)?.tree)?;
+    let mut index_map = index::read_index()?;
+    let worktree = status::working_files()?;
+
+    let paths: BTreeSet<&String> = head.keys().chain(target.keys()).collect();
+    let changing: Vec<&String> = paths
+        .into_iter()
+        .filter(|path| head.get(*path) != target.get(*path))
+        .collect();
+
+    let mut modified = Vec::new();
+    let mut untracked = Vec::new();
+    for path in &changing {
+        let staged = index_map.get(*path);
+        let on_disk = worktree.get(*path);
+
+        // Nothing is lost if the path matches either side already
+        let matches = |side: Option<&index::Entry>| staged == side && on_disk == side;
+        if matches(head.get(*path)) || matches(target.get(*path)) {
+            continue;
+        }
+
+        if staged.is_none() && !head.contains_key(*path) {
+            untracked.push(path.as_str());
         } else {
-            anyhow::bail!("HEAD points to '{}', which does not exist yet. Make a commit first!", ref_path_str);
+            modified.push(path.as_str());
         }
     }
 
-    //  Walk the graph backwards
-    println!("Printing history for commit: {}\n", current_hash);
-
-    loop {
-        // Read the commit object
-        let dir_name = &current_hash[..2];
-        let file_name = &current_hash[2..];
-        let object_path = format!("{}/objects/{}/{}", RIT_DIR, dir_name, file_name);
-        
-        let file = fs::File::open(&object_path).map_err(|_| anyhow::anyhow!("Commit object {} missing", current_hash))?;
-        let mut decoder = ZlibDecoder::new(file);
-        let mut contents = Vec::new();
-        decoder.read_to_end(&mut contents)?;
-
-        // Split header from body (commit <size>\0<body>)
-        let null_index = contents.iter().position(|&b| b == 0).unwrap();
-        let body_bytes = &contents[null_index + 1..];
-        let body = String::from_utf8_lossy(body_bytes);
-
-        //  Parse the commit body to find metadata
-        let mut parent = None;
-        let mut author = String::from("(unknown)");
-        let mut message = String::new();
-        
-        // We split by lines. The message is separated by an empty line.
-        let mut parsing_headers = true;
-
-        for line in body.lines() {
-            if parsing_headers {
-                if line.is_empty() {
-                    parsing_headers = false;
-                } else if line.starts_with("parent ") {
-                    parent = Some(line["parent ".len()..].to_string());
-                } else if line.starts_with("author ") {
-                    author = line["author ".len()..].to_string();
-                }
-            } else {
-                // Collect the message
-                message.push_str(line);
-                message.push('\n');
-            }
-        }
+    if !modified.is_empty() {
+        anyhow::bail!(
+            "Your local changes to the following files would be overwritten by checkout:\n\t{}\n\
+             Please commit your changes or stash them before you switch branches.",
+            modified.join("\n\t")
+        );
+    }
+    if !untracked.is_empty() {
+        anyhow::bail!(
+            "The following untracked working tree files would be overwritten by checkout:\n\t{}\n\
+             Please move or remove them before you switch branches.",
+            untracked.join("\n\t")
+        );
+    }
 
-        // Pretty print the commit info
-        // \x1b[33m makes the hash yellow in the terminal
-        println!("\x1b[33mcommit {}\x1b[0m", current_hash);
-        println!("Author: {}", author);
-        println!("\n    {}", message.trim());
-        println!("{}", "-".repeat(50));
-
-        // Move to parent or stop
-        match parent {
-            Some(p) => current_hash = p,
-            None => break, // Reached the root commit
+    let mut from = index::Index::new();
+    let mut to = index::Index::new();
+    for path in changing {
+        if let Some(entry) = worktree.get(path) {
+            from.insert(path.clone(), entry.clone());
+        }
+        match target.get(path) {
+            Some(entry) => {
+                to.insert(path.clone(), entry.clone());
+                index_map.insert(path.clone(), entry.clone());
+            }
+            None => {
+                index_map.remove(path);
+            }
         }
     }
 
-    Ok(())
+    update_worktree(&from, &to)?;
+    index::write_index(&index_map)
 }
 
-
-pub fn checkout(target: &str) -> Result<()> {
-    // Resolve target: Is it a branch name (like 'main') or a Hash?
-    let refs_path = format!("{}/refs/heads/{}", RIT_DIR, target);
-    let mut commit_hash = target.to_string();
-    let mut new_head_content = format!("ref: refs/heads/{}\n", target);
-
-    if Path::new(&refs_path).exists() {
-        // It is a branch! Read the hash inside it.
-        commit_hash = fs::read_to_string(&refs_path)?.trim().to_string();
-    } else {
-        // It's likely a raw commit hash (Detached HEAD state)
-        // Verify object exists
-        let dir = &commit_hash[..2];
-        let file = &commit_hash[2..];
-        if !Path::new(&format!("{}/objects/{}/{}", RIT_DIR, dir, file)).exists() {
-            anyhow::bail!("Target '{}' does not exist.", target);
-        }
-        // In detached HEAD, HEAD contains the hash directly, not a ref: path
-        new_head_content = format!("{}\n", commit_hash);
+// Parses a tree object into its entries
+pub fn read_tree(repo: &Repository, tree_hash: &str) -> Result<Vec<TreeEntry>> {
+    // Get raw bytes of the tree object (entries contain raw binary SHAs, not text)
+    let (kind, buffer) = repo.objects().read_raw(tree_hash)?;
+    if kind != "tree" {
+        anyhow::bail!("Object {} is a {}, not a tree", tree_hash, kind);
     }
+    let tree = Tree::parse(&buffer, repo.format())
+        .map_err(|err| anyhow::anyhow!("Invalid tree {}: {}", tree_hash, err))?;
+    Ok(tree.entries)
+}
 
-    // Read the Commit Object to find the Root Tree
-    let commit_content = read_object_content(&commit_hash)?;
-    // Content is "tree <hash>\nparent..."
-    // We parse the first line
-    let tree_line = commit_content.lines().next().ok_or(anyhow::anyhow!("Invalid commit"))?;
-    let tree_hash = tree_line.strip_prefix("tree ").ok_or(anyhow::anyhow!("Invalid commit format"))?;
-
-    // Clear current directory (Optional safety step)
-    // In a real git, this is complex. Here, we just overwrite files.
-    // Ideally, we would delete files that exist here but NOT in the new tree.
-    
-    // Restore the Tree recursively
-    restore_tree(tree_hash, Path::new("."))?;
+// Every blob reachable from a tree as "dir/file" path -> blob hash
+pub fn flatten_tree(tree_hash: &str) -> Result<index::Index> {
+    let mut files = index::Index::new();
+    flatten_tree_into(tree_hash, "", &mut files)?;
+    Ok(files)
+}
 
-    //  Update HEAD
-    fs::write(format!("{}/HEAD", RIT_DIR), new_head_content)?;
+fn flatten_tree_into(tree_hash: &str, prefix: &str, files: &mut index::Index) -> Result<()> {
+    for entry in read_tree(Repository::current(), tree_hash)? {
+        let path = format!("{}{}", prefix, entry.name);
 
-    println!("Switched to '{}'", target);
+        if entry.mode == "40000" {
+            flatten_tree_into(&entry.hash, &format!("{}/", path), files)?;
+        } else {
+            files.insert(path, index::Entry { mode: entry.mode, hash: entry.hash });
+        }
+    }
     Ok(())
 }
 
-// Helper to read and decompress any object
-fn read_object_content(hash: &str) -> Result<String> {
-    let dir = &hash[..2];
-    let file = &hash[2..];
-    let path = format!("{}/objects/{}/{}", RIT_DIR, dir, file);
-    let f = fs::File::open(path)?;
-    let mut decoder = ZlibDecoder::new(f);
-    let mut buffer = Vec::new();
-    decoder.read_to_end(&mut buffer)?;
-    
-    // Split header and body
-    let null_index = buffer.iter().position(|&b| b == 0).unwrap();
-    let body = String::from_utf8_lossy(&buffer[null_index+1..]).to_string();
-    Ok(body)
+// The tree hash recorded in a commit
+pub fn commit_tree_hash(commit_hash: &str) -> Result<String> {
+    Ok(Commit::read(Repository::current(), commit_hash)?.tree)
 }
 
-// The Recursive Restorer
-fn restore_tree(tree_hash: &str, current_path: &Path) -> Result<()> {
-    // Get raw bytes of the tree object
-    // (We can't use read_object_content because trees contain raw binary SHAs, not text)
-    let dir = &tree_hash[..2];
-    let file = &tree_hash[2..];
-    let path = format!("{}/objects/{}/{}", RIT_DIR, dir, file);
-    
-    let f = fs::File::open(path)?;
-    let mut decoder = ZlibDecoder::new(f);
-    let mut buffer = Vec::new();
-    decoder.read_to_end(&mut buffer)?;
-    
-    // Skip header "tree <size>\0"
-    let null_index = buffer.iter().position(|&b| b == 0).unwrap();
-    let mut body = &buffer[null_index+1..];
-
-    // Parse entries loop
-    while !body.is_empty() {
-        // Format: [mode] [space] [name] [null] [20 bytes sha]
-        
-        // Find null byte after mode/name
-        let null_idx = body.iter().position(|&b| b == 0).unwrap();
-        let mode_name = std::str::from_utf8(&body[..null_idx])?;
-        let (mode, name) = mode_name.split_once(' ').unwrap();
-        
-        // Advance past null byte
-        body = &body[null_idx + 1..];
-        
-        // Read 20 bytes for SHA
-        let sha_bytes = &body[..20];
-        let sha_hex = hex::encode(sha_bytes);
-        body = &body[20..]; // Advance for next iteration
-
-        let entry_path = current_path.join(name);
-
-        if mode == "40000" {
-            // It's a directory
-            if !entry_path.exists() {
-                fs::create_dir(&entry_path)?;
+// Moves the working tree from one snapshot to another, touching only the paths that differ
+pub fn update_worktree(from: &index::Index, to: &index::Index) -> Result<()> {
+    for (path, entry) in to {
+        if from.get(path) == Some(entry) {
+            continue;
+        }
+        write_worktree_file(Path::new(path), entry)?;
+    }
+
+    for path in from.keys() {
+        if to.contains_key(path) {
+            continue;
+        }
+        let file_path = Path::new(path);
+        if fs::symlink_metadata(file_path).is_ok() {
+            fs::remove_file(file_path)?;
+        }
+        // Drop directories the removal left empty
+        let mut dir = file_path.parent();
+        while let Some(parent) = dir {
+            if parent.as_os_str().is_empty() || fs::remove_dir(parent).is_err() {
+                break;
             }
-            restore_tree(&sha_hex, &entry_path)?;
-        } else {
-            // It's a file (blob)
-            // Read the blob content
-            // We reuse our text reader, assuming files are text. 
-            // For binary files, you'd need a Vec<u8> version.
-            let content = read_object_content(&sha_hex)?;
-            fs::write(&entry_path, content)?;
-            
-            // Optional: Set executable permissions if mode is 100755
-            // if mode == "100755" { ... }
+            dir = parent.parent();
         }
     }
-    
+
     Ok(())
 }
 
+// Writes a blob to disk as a regular file, an executable or a symlink depending on its mode
+fn write_worktree_file(path: &Path, entry: &index::Entry) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
 
+    // Never write through an existing symlink, and a symlink can't be created over a file
+    if let Ok(metadata) = fs::symlink_metadata(path)
+        && !metadata.is_dir()
+    {
+        fs::remove_file(path)?;
+    }
+
+    let content = objects::read_blob(Repository::current(), &entry.hash)?;
+
+    if entry.mode == "120000" {
+        std::os::unix::fs::symlink(OsStr::from_bytes(&content), path)?;
+        return Ok(());
+    }
+
+    fs::write(path, content)?;
+
+    let mut permissions = fs::metadata(path)?.permissions();
+    let mode = permissions.mode();
+    permissions.set_mode(if entry.mode == "100755" { mode | 0o111 } else { mode & !0o111 });
+    fs::set_permissions(path, permissions)?;
+    Ok(())
+}
@@ -0,0 +1,349 @@
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::commands::rit_dir;
+
+/// A config key split git-style: "remote.origin.url" is section "remote",
+/// subsection "origin" and name "url". Section and name are case-insensitive.
+struct Key {
+    section: String,
+    subsection: Option<String>,
+    name: String,
+}
+
+impl Key {
+    fn parse(key: &str) -> Result<Key> {
+        let invalid = || anyhow::anyhow!("key does not contain a section: {}", key);
+
+        let (section, rest) = key.split_once('.').ok_or_else(invalid)?;
+        let (subsection, name) = match rest.rsplit_once('.') {
+            Some((subsection, name)) => (Some(subsection.to_string()), name),
+            None => (None, rest),
+        };
+        if section.is_empty() || name.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Key {
+            section: section.to_lowercase(),
+            subsection,
+            name: name.to_lowercase(),
+        })
+    }
+
+    fn header(&self) -> String {
+        match &self.subsection {
+            Some(subsection) => format!("[{} \"{}\"]", self.section, subsection),
+            None => format!("[{}]", self.section),
+        }
+    }
+}
+
+/// What a line of a config file is
+enum Line {
+    Section { section: String, subsection: Option<String> },
+    Entry { name: String, value: String },
+    Other,
+}
+
+fn parse_line(line: &str) -> Line {
+    let line = line.trim();
+
+    if let Some(header) = line.strip_prefix('[').and_then(|rest| rest.split(']').next()) {
+        let (section, subsection) = match header.split_once(' ') {
+            Some((section, subsection)) => {
+                (section, Some(subsection.trim().trim_matches('"').to_string()))
+            }
+            None => (header, None),
+        };
+        return Line::Section {
+            section: section.to_lowercase(),
+            subsection,
+        };
+    }
+
+    if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+        return Line::Other;
+    }
+
+    // A bare name means boolean true
+    let (name, value) = line.split_once('=').unwrap_or((line, "true"));
+    Line::Entry {
+        name: name.trim().to_lowercase(),
+        value: unquote(value.trim()),
+    }
+}
+
+fn unquote(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        return value[1..value.len() - 1].replace("\\\"", "\"").replace("\\\\", "\\");
+    }
+    // Unquoted values end at a comment
+    value.split([';', '#']).next().unwrap_or("").trim_end().to_string()
+}
+
+fn quote(value: &str) -> String {
+    let needs_quotes = value.contains(['#', ';', '"', '\\']) || value.trim() != value;
+    if needs_quotes {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// A config file kept as its lines, so rewriting one value leaves the rest untouched
+pub struct ConfigFile {
+    lines: Vec<String>,
+}
+
+impl ConfigFile {
+    pub fn parse(text: &str) -> ConfigFile {
+        ConfigFile {
+            lines: text.lines().map(|line| line.to_string()).collect(),
+        }
+    }
+
+    // Index of every line holding `key`, plus the index of the last line of its section
+    fn locate(&self, key: &Key) -> (Vec<usize>, Option<usize>) {
+        let mut found = Vec::new();
+        let mut section_end = None;
+        let mut in_section = false;
+
+        for (i, line) in self.lines.iter().enumerate() {
+            match parse_line(line) {
+                Line::Section { section, subsection } => {
+                    in_section = section == key.section && subsection == key.subsection;
+                    if in_section {
+                        section_end = Some(i);
+                    }
+                }
+                Line::Entry { name, .. } if in_section => {
+                    section_end = Some(i);
+                    if name == key.name {
+                        found.push(i);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        (found, section_end)
+    }
+
+    /// The value of a key, the last one wins if it is set several times
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        let key = Key::parse(key)?;
+        let (found, _) = self.locate(&key);
+
+        Ok(found.last().and_then(|&i| match parse_line(&self.lines[i]) {
+            Line::Entry { value, .. } => Some(value),
+            _ => None,
+        }))
+    }
+
+    /// Every entry as ("section.subsection.name", value), in file order
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let mut entries = Vec::new();
+        let mut prefix = String::new();
+
+        for line in &self.lines {
+            match parse_line(line) {
+                Line::Section { section, subsection } => {
+                    prefix = match subsection {
+                        Some(subsection) => format!("{}.{}", section, subsection),
+                        None => section,
+                    };
+                }
+                Line::Entry { name, value } => entries.push((format!("{}.{}", prefix, name), value)),
+                Line::Other => {}
+            }
+        }
+        entries
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let parsed = Key::parse(key)?;
+        let line = format!("\t{} = {}", parsed.name, quote(value));
+
+        match self.locate(&parsed) {
+            (found, _) if !found.is_empty() => {
+                self.lines[*found.last().unwrap()] = line;
+            }
+            (_, Some(end)) => self.lines.insert(end + 1, line),
+            (_, None) => {
+                self.lines.push(parsed.header());
+                self.lines.push(line);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every value of a key, returning whether there was any
+    pub fn unset(&mut self, key: &str) -> Result<bool> {
+        let (found, _) = self.locate(&Key::parse(key)?);
+        for &i in found.iter().rev() {
+            self.lines.remove(i);
+        }
+        Ok(!found.is_empty())
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = self.lines.join("\n");
+        text.push('\n');
+        text
+    }
+}
+
+/// Where a setting lives. Repository settings override global ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// `~/.ritconfig`, shared by every repository of the user
+    Global,
+    /// `.rit/config`
+    Repository,
+}
+
+impl Level {
+    fn path(self) -> Option<PathBuf> {
+        match self {
+            Level::Global => std::env::var_os("HOME").map(|home| Path::new(&home).join(".ritconfig")),
+            Level::Repository => Some(rit_dir().join("config")),
+        }
+    }
+
+    pub fn load(self) -> Result<ConfigFile> {
+        match self.path() {
+            Some(path) if path.exists() => Ok(ConfigFile::parse(&fs::read_to_string(path)?)),
+            _ => Ok(ConfigFile::parse("")),
+        }
+    }
+
+    pub fn save(self, config: &ConfigFile) -> Result<()> {
+        let path = self
+            .path()
+            .ok_or_else(|| anyhow::anyhow!("$HOME not set, there is no global config file"))?;
+        fs::write(path, config.text())?;
+        Ok(())
+    }
+}
+
+/// Reads a key, the repository's value winning over the global one
+pub fn get(key: &str) -> Result<Option<String>> {
+    let mut value = None;
+    for level in [Level::Global, Level::Repository] {
+        if let Some(found) = level.load()?.get(key)? {
+            value = Some(found);
+        }
+    }
+    Ok(value)
+}
+
+/// Sets a key in the repository's `.rit/config`
+pub fn set(key: &str, value: &str) -> Result<()> {
+    let mut config = Level::Repository.load()?;
+    config.set(key, value)?;
+    Level::Repository.save(&config)
+}
+
+/// `rit config`: prints, sets, unsets or lists settings. Without `--global`, reads
+/// see both levels and writes go to the repository.
+pub fn config(global: bool, key: Option<&str>, value: Option<&str>, unset: bool, list: bool) -> Result<()> {
+    let levels = if global {
+        vec![Level::Global]
+    } else {
+        vec![Level::Global, Level::Repository]
+    };
+    let write_level = if global { Level::Global } else { Level::Repository };
+
+    if list {
+        for level in levels {
+            for (key, value) in level.load()?.entries() {
+                println!("{}={}", key, value);
+            }
+        }
+        return Ok(());
+    }
+
+    let key = key.ok_or_else(|| anyhow::anyhow!("a config key is required"))?;
+    if unset {
+        let mut config = write_level.load()?;
+        if !config.unset(key)? {
+            anyhow::bail!("key '{}' is not set", key);
+        }
+        return write_level.save(&config);
+    }
+
+    match value {
+        Some(value) => {
+            let mut config = write_level.load()?;
+            config.set(key, value)?;
+            write_level.save(&config)
+        }
+        None => {
+            let mut found = None;
+            for level in levels {
+                found = level.load()?.get(key)?.or(found);
+            }
+            let value = found.ok_or_else(|| anyhow::anyhow!("key '{}' is not set", key))?;
+            println!("{}", value);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "# comment\n\
+                          [core]\n\
+                          \tbare = false\n\
+                          [remote \"origin\"]\n\
+                          \turl = /srv/repo ; trailing comment\n\
+                          \tfetch = +refs/heads/*:refs/remotes/origin/*\n\
+                          [Remote \"Other.Name\"]\n\
+                          \tURL = \"/with spaces \\\"quoted\\\"\"\n\
+                          \tbool\n";
+
+    #[test]
+    fn test_get() {
+        let config = ConfigFile::parse(SAMPLE);
+
+        assert_eq!(config.get("core.bare").unwrap().as_deref(), Some("false"));
+        assert_eq!(config.get("remote.origin.url").unwrap().as_deref(), Some("/srv/repo"));
+        assert_eq!(
+            config.get("remote.Other.Name.url").unwrap().as_deref(),
+            Some("/with spaces \"quoted\"")
+        );
+        assert_eq!(config.get("remote.Other.Name.bool").unwrap().as_deref(), Some("true"));
+        // Subsections are case sensitive
+        assert_eq!(config.get("remote.ORIGIN.url").unwrap(), None);
+        assert!(config.get("nosection").is_err());
+
+        let entries = config.entries();
+        assert_eq!(entries[0], ("core.bare".to_string(), "false".to_string()));
+        assert_eq!(entries[3], ("remote.Other.Name.url".to_string(), "/with spaces \"quoted\"".to_string()));
+    }
+
+    #[test]
+    fn test_set_keeps_other_lines() {
+        let mut config = ConfigFile::parse(SAMPLE);
+        config.set("remote.origin.url", "/elsewhere").unwrap();
+        config.set("remote.origin.pushurl", "/push").unwrap();
+        config.set("branch.main.remote", "origin").unwrap();
+
+        let text = config.text();
+        assert!(text.starts_with("# comment\n[core]\n\tbare = false\n"));
+        assert!(text.contains("\turl = /elsewhere\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n\tpushurl = /push\n"));
+        assert!(text.ends_with("[branch \"main\"]\n\tremote = origin\n"));
+
+        assert!(config.unset("remote.origin.pushurl").unwrap());
+        assert!(!config.unset("remote.origin.pushurl").unwrap());
+        assert_eq!(config.get("remote.origin.pushurl").unwrap(), None);
+
+        let reparsed = ConfigFile::parse(&text);
+        assert_eq!(reparsed.get("remote.origin.url").unwrap().as_deref(), Some("/elsewhere"));
+        assert_eq!(reparsed.get("branch.main.remote").unwrap().as_deref(), Some("origin"));
+    }
+}
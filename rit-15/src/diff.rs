@@ -0,0 +1,605 @@
+use anyhow::Result;
+use std::io::Write;
+use std::path::Path;
+
+use crate::commands;
+use crate::index::{self, Entry, Index};
+use crate::objects::{self, Commit};
+use crate::repository::Repository;
+use crate::revision;
+use crate::status::{self, Change};
+
+/// One step of an edit script, holding line indices into the old and new sequences
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Shortest edit script turning `old` into `new` (Myers' O(ND) algorithm, in its linear
+/// space form: each step splits the problem at the middle snake of the path)
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    diff_range(old, new, 0, 0, &mut edits);
+    edits
+}
+
+// Appends the edit script for `old` against `new`, which start at lines `x` and `y`
+// of the whole sequences
+fn diff_range<T: PartialEq>(old: &[T], new: &[T], x: usize, y: usize, edits: &mut Vec<Edit>) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+    let suffix = old_rest.iter().rev().zip(new_rest.iter().rev()).take_while(|(a, b)| a == b).count();
+    let old_mid = &old_rest[..old_rest.len() - suffix];
+    let new_mid = &new_rest[..new_rest.len() - suffix];
+
+    edits.extend((0..prefix).map(|i| Edit::Equal(x + i, y + i)));
+    let (x, y) = (x + prefix, y + prefix);
+
+    if old_mid.is_empty() {
+        edits.extend((0..new_mid.len()).map(|i| Edit::Insert(y + i)));
+    } else if new_mid.is_empty() {
+        edits.extend((0..old_mid.len()).map(|i| Edit::Delete(x + i)));
+    } else {
+        let (start_x, start_y, end_x, end_y) = middle_snake(old_mid, new_mid);
+        diff_range(&old_mid[..start_x], &new_mid[..start_y], x, y, edits);
+        edits.extend((0..end_x - start_x).map(|i| Edit::Equal(x + start_x + i, y + start_y + i)));
+        diff_range(&old_mid[end_x..], &new_mid[end_y..], x + end_x, y + end_y, edits);
+    }
+
+    let (x, y) = (x + old_mid.len(), y + new_mid.len());
+    edits.extend((0..suffix).map(|i| Edit::Equal(x + i, y + i)));
+}
+
+// The snake in the middle of a shortest path, searched for from both ends at once, as
+// (start x, start y, end x, end y). Both sides must be non-empty and differ at each end.
+fn middle_snake<T: PartialEq>(old: &[T], new: &[T]) -> (usize, usize, usize, usize) {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let delta = n - m;
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+
+    // forward[k + offset] is the furthest x reached on diagonal k = x - y from the start,
+    // backward[k + offset] the same counted from the end of both sequences
+    let mut forward = vec![0isize; 2 * offset as usize + 1];
+    let mut backward = vec![0isize; 2 * offset as usize + 1];
+    let furthest = |v: &[isize], d: isize, k: isize| {
+        let idx = (k + offset) as usize;
+        if k == -d || (k != d && v[idx - 1] < v[idx + 1]) { v[idx + 1] } else { v[idx - 1] + 1 }
+    };
+
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = furthest(&forward, d, k);
+            let (start_x, start_y) = (x, x - k);
+            while x < n && x - k < m && old[x as usize] == new[(x - k) as usize] {
+                x += 1;
+            }
+            forward[(k + offset) as usize] = x;
+
+            // With an odd delta the paths meet on a forward step
+            let back_k = delta - k;
+            if delta % 2 != 0 && back_k.abs() < d && x + backward[(back_k + offset) as usize] >= n {
+                return (start_x as usize, start_y as usize, x as usize, (x - k) as usize);
+            }
+        }
+
+        for k in (-d..=d).step_by(2) {
+            let mut x = furthest(&backward, d, k);
+            let (start_x, start_y) = (x, x - k);
+            while x < n && x - k < m && old[(n - x - 1) as usize] == new[(m - x + k - 1) as usize] {
+                x += 1;
+            }
+            backward[(k + offset) as usize] = x;
+
+            let forward_k = delta - k;
+            if delta % 2 == 0 && forward_k.abs() <= d && x + forward[(forward_k + offset) as usize] >= n {
+                let (end_x, end_y) = (n - start_x, m - start_y);
+                return ((n - x) as usize, (m - x + k) as usize, end_x as usize, end_y as usize);
+            }
+        }
+    }
+    unreachable!("a shortest path is at most {} edits long", n + m)
+}
+
+/// Splits content into lines, each keeping its trailing '\n' (the last one may not have it)
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&b| b == b'\n').collect()
+}
+
+/// Same heuristic as git: a NUL byte in the first 8000 bytes means binary
+pub fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(8000).any(|&b| b == 0)
+}
+
+/// A contiguous block of changes plus surrounding context, as indices into the edit script
+struct Hunk {
+    start: usize,
+    end: usize,
+}
+
+fn hunks(edits: &[Edit], context: usize) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+
+    for (i, edit) in edits.iter().enumerate() {
+        if matches!(edit, Edit::Equal(..)) {
+            continue;
+        }
+
+        let start = i.saturating_sub(context);
+        let end = (i + 1 + context).min(edits.len());
+
+        match hunks.last_mut() {
+            // Overlapping or touching context joins the previous hunk
+            Some(last) if start <= last.end => last.end = end,
+            _ => hunks.push(Hunk { start, end }),
+        }
+    }
+
+    hunks
+}
+
+// "start,count" as in a hunk header; an empty range points at the line before it
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+fn push_line(out: &mut String, prefix: char, line: &[u8]) {
+    out.push(prefix);
+    out.push_str(&String::from_utf8_lossy(line));
+    if !line.ends_with(b"\n") {
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+/// The "@@ ... @@" hunks of a unified diff between two texts
+pub fn unified(old: &[u8], new: &[u8], context: usize) -> String {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let edits = diff(&old_lines, &new_lines);
+
+    let mut out = String::new();
+
+    for hunk in hunks(&edits, context) {
+        let slice = &edits[hunk.start..hunk.end];
+
+        // Position in each file where the hunk begins
+        let (old_start, new_start) = edits[..hunk.start].iter().fold((0, 0), |(o, n), edit| {
+            match edit {
+                Edit::Equal(..) => (o + 1, n + 1),
+                Edit::Delete(_) => (o + 1, n),
+                Edit::Insert(_) => (o, n + 1),
+            }
+        });
+        let old_count = slice.iter().filter(|e| !matches!(e, Edit::Insert(_))).count();
+        let new_count = slice.iter().filter(|e| !matches!(e, Edit::Delete(_))).count();
+
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_count),
+            hunk_range(new_start, new_count)
+        ));
+
+        for edit in slice {
+            match *edit {
+                Edit::Equal(o, _) => push_line(&mut out, ' ', old_lines[o]),
+                Edit::Delete(o) => push_line(&mut out, '-', old_lines[o]),
+                Edit::Insert(n) => push_line(&mut out, '+', new_lines[n]),
+            }
+        }
+    }
+
+    out
+}
+
+/// One side of a file comparison
+pub struct FileVersion {
+    pub hash: String,
+    pub mode: String,
+    pub data: Vec<u8>,
+}
+
+const NULL_HASH: &str = "0000000";
+
+/// A git-style patch for one file; `None` on a side means the file doesn't exist there
+pub fn file_patch(
+    path: &str,
+    old: Option<&FileVersion>,
+    new: Option<&FileVersion>,
+    context: usize,
+) -> String {
+    let mut out = format!("diff --git a/{} b/{}\n", path, path);
+
+    let old_hash = old.map_or(NULL_HASH, |f| &f.hash[..7]);
+    let new_hash = new.map_or(NULL_HASH, |f| &f.hash[..7]);
+
+    match (old, new) {
+        (None, Some(new)) => {
+            out.push_str(&format!("new file mode {}\n", new.mode));
+            out.push_str(&format!("index {}..{}\n", old_hash, new_hash));
+        }
+        (Some(old), None) => {
+            out.push_str(&format!("deleted file mode {}\n", old.mode));
+            out.push_str(&format!("index {}..{}\n", old_hash, new_hash));
+        }
+        (Some(old), Some(new)) if old.mode != new.mode => {
+            out.push_str(&format!("old mode {}\nnew mode {}\n", old.mode, new.mode));
+            if old.hash == new.hash {
+                return out;
+            }
+            out.push_str(&format!("index {}..{}\n", old_hash, new_hash));
+        }
+        (Some(old), Some(_)) => {
+            out.push_str(&format!("index {}..{} {}\n", old_hash, new_hash, old.mode));
+        }
+        (None, None) => return String::new(),
+    }
+
+    let empty = Vec::new();
+    let old_data = old.map_or(&empty, |f| &f.data);
+    let new_data = new.map_or(&empty, |f| &f.data);
+
+    if is_binary(old_data) || is_binary(new_data) {
+        let old_name = old.map_or("/dev/null".to_string(), |_| format!("a/{}", path));
+        let new_name = new.map_or("/dev/null".to_string(), |_| format!("b/{}", path));
+        out.push_str(&format!("Binary files {} and {} differ\n", old_name, new_name));
+        return out;
+    }
+
+    // An empty file being added or removed has no hunks and no ---/+++ lines
+    if old_data.is_empty() && new_data.is_empty() {
+        return out;
+    }
+
+    out.push_str(&match old {
+        Some(_) => format!("--- a/{}\n", path),
+        None => "--- /dev/null\n".to_string(),
+    });
+    out.push_str(&match new {
+        Some(_) => format!("+++ b/{}\n", path),
+        None => "+++ /dev/null\n".to_string(),
+    });
+    out.push_str(&unified(old_data, new_data, context));
+    out
+}
+
+/// Where the content of the new side comes from
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Objects,
+    Worktree,
+}
+
+fn load(path: &str, entry: &Entry, source: Source) -> Result<FileVersion> {
+    let data = match source {
+        Source::Objects => objects::read_blob(Repository::current(), &entry.hash)?,
+        Source::Worktree => commands::read_worktree_file(Path::new(path))?,
+    };
+
+    Ok(FileVersion {
+        hash: entry.hash.clone(),
+        mode: entry.mode.clone(),
+        data,
+    })
+}
+
+/// Full patch between two snapshots of path -> mode and hash
+fn diff_snapshots(old: &Index, new: &Index, new_source: Source, context: usize) -> Result<String> {
+    let mut out = String::new();
+
+    for (path, change) in status::diff_maps(old, new) {
+        let old_file = match change {
+            Change::Added => None,
+            _ => Some(load(&path, &old[&path], Source::Objects)?),
+        };
+        let new_file = match change {
+            Change::Deleted => None,
+            _ => Some(load(&path, &new[&path], new_source)?),
+        };
+
+        out.push_str(&file_patch(&path, old_file.as_ref(), new_file.as_ref(), context));
+    }
+
+    Ok(out)
+}
+
+// Changes of one file in a diffstat
+enum FileStat {
+    Text { insertions: usize, deletions: usize },
+    Binary { old: usize, new: usize },
+}
+
+// Widest "+++--" bar before the counts get scaled down
+const STAT_WIDTH: usize = 40;
+
+fn plural(count: usize, word: &str) -> String {
+    format!("{} {}{}", count, word, if count == 1 { "" } else { "s" })
+}
+
+/// `git diff --stat` between two snapshots: " path | 3 ++-" per file and a summary line
+pub fn stat(old: &Index, new: &Index) -> Result<String> {
+    let mut files = Vec::new();
+    for path in status::diff_maps(old, new).into_keys() {
+        let read = |files: &Index| match files.get(&path) {
+            Some(entry) => objects::read_blob(Repository::current(), &entry.hash),
+            None => Ok(Vec::new()),
+        };
+        let (old_data, new_data) = (read(old)?, read(new)?);
+
+        let file = if is_binary(&old_data) || is_binary(&new_data) {
+            FileStat::Binary { old: old_data.len(), new: new_data.len() }
+        } else {
+            let edits = diff(&split_lines(&old_data), &split_lines(&new_data));
+            FileStat::Text {
+                insertions: edits.iter().filter(|edit| matches!(edit, Edit::Insert(_))).count(),
+                deletions: edits.iter().filter(|edit| matches!(edit, Edit::Delete(_))).count(),
+            }
+        };
+        files.push((path, file));
+    }
+    if files.is_empty() {
+        return Ok(String::new());
+    }
+
+    let name_width = files.iter().map(|(path, _)| path.len()).max().unwrap_or(0);
+    let most = files
+        .iter()
+        .map(|(_, file)| match file {
+            FileStat::Text { insertions, deletions } => insertions + deletions,
+            FileStat::Binary { .. } => 0,
+        })
+        .max()
+        .unwrap_or(0);
+    let count_width = most.to_string().len();
+    let scale = |n: usize| if most <= STAT_WIDTH || n == 0 { n } else { (n * STAT_WIDTH / most).max(1) };
+
+    let mut out = String::new();
+    let (mut insertions, mut deletions) = (0, 0);
+    for (path, file) in &files {
+        match *file {
+            FileStat::Text { insertions: plus, deletions: minus } => {
+                insertions += plus;
+                deletions += minus;
+                let bar = format!("{}{}", "+".repeat(scale(plus)), "-".repeat(scale(minus)));
+                let line = format!(" {:<name_width$} | {:>count_width$} {}", path, plus + minus, bar);
+                out.push_str(line.trim_end());
+            }
+            FileStat::Binary { old, new } => {
+                out.push_str(&format!(" {:<name_width$} | Bin {} -> {} bytes", path, old, new));
+            }
+        }
+        out.push('\n');
+    }
+
+    out.push_str(&format!(" {} changed", plural(files.len(), "file")));
+    if insertions > 0 || deletions == 0 {
+        out.push_str(&format!(", {}(+)", plural(insertions, "insertion")));
+    }
+    if deletions > 0 || insertions == 0 {
+        out.push_str(&format!(", {}(-)", plural(deletions, "deletion")));
+    }
+    out.push('\n');
+    Ok(out)
+}
+
+/// The given paths as they are on disk, skipping ones that no longer exist
+fn worktree_files<'a>(paths: impl Iterator<Item = &'a String>) -> Result<Index> {
+    let mut files = Index::new();
+
+    for path in paths {
+        let exists = std::fs::symlink_metadata(path).is_ok_and(|metadata| !metadata.is_dir());
+        if exists {
+            files.insert(path.clone(), commands::worktree_entry(path, false)?);
+        }
+    }
+    Ok(files)
+}
+
+fn commit_files(rev: &str) -> Result<Index> {
+    let repo = Repository::current();
+    let commit = revision::resolve_commit(repo, rev)?;
+    commands::flatten_tree(&Commit::read(repo, &commit)?.tree)
+}
+
+/// `rit diff`: index vs worktree, `--cached` for HEAD vs index, or against one or two commits
+pub fn diff_command(commits: &[String], cached: bool, context: usize) -> Result<()> {
+    let patch = match (commits, cached) {
+        ([], false) => {
+            let index = index::read_index()?;
+            let worktree = worktree_files(index.keys())?;
+            diff_snapshots(&index, &worktree, Source::Worktree, context)?
+        }
+        ([], true) => {
+            let head = status::head_files()?;
+            diff_snapshots(&head, &index::read_index()?, Source::Objects, context)?
+        }
+        ([commit], false) => {
+            let old = commit_files(commit)?;
+            let index = index::read_index()?;
+            let worktree = worktree_files(old.keys().chain(index.keys()))?;
+            diff_snapshots(&old, &worktree, Source::Worktree, context)?
+        }
+        ([commit], true) => {
+            let old = commit_files(commit)?;
+            diff_snapshots(&old, &index::read_index()?, Source::Objects, context)?
+        }
+        ([from, to], false) => {
+            diff_snapshots(&commit_files(from)?, &commit_files(to)?, Source::Objects, context)?
+        }
+        ([_, _], true) => anyhow::bail!("--cached takes at most one commit"),
+        _ => anyhow::bail!("too many revisions, expected at most two"),
+    };
+
+    std::io::stdout().write_all(patch.as_bytes())?;
+    Ok(())
+}
+
+/// `rit show`: commit header and message followed by the patch against its parent
+pub fn show(rev: &str, context: usize) -> Result<()> {
+    let repo = Repository::current();
+    let hash = revision::resolve_commit(repo, rev)?;
+    let commit = Commit::read(repo, &hash)?;
+
+    println!("\x1b[33mcommit {}\x1b[0m", hash);
+    if commit.parents.len() > 1 {
+        let short: Vec<&str> = commit.parents.iter().map(|p| &p[..7]).collect();
+        println!("Merge: {}", short.join(" "));
+    }
+    println!("Author: {} <{}>", commit.author.name, commit.author.email);
+    println!("Date:   {}", commit.author.date());
+    println!();
+    for line in commit.message_text().lines() {
+        println!("    {}", line);
+    }
+
+    // Like `git log -p`, merges are shown without a patch
+    if commit.parents.len() > 1 {
+        return Ok(());
+    }
+
+    let old = match commit.parents.first() {
+        Some(parent) => commands::flatten_tree(&Commit::read(repo, parent)?.tree)?,
+        None => Index::new(),
+    };
+    let new = commands::flatten_tree(&commit.tree)?;
+
+    let patch = diff_snapshots(&old, &new, Source::Objects, context)?;
+    if !patch.is_empty() {
+        println!();
+        std::io::stdout().write_all(patch.as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(old: &[&str], new: &[&str], edits: &[Edit]) -> Vec<String> {
+        // Rebuild `new` from the script to check it is a valid transformation of `old`
+        let mut out = Vec::new();
+        for edit in edits {
+            match *edit {
+                Edit::Equal(o, n) => {
+                    assert_eq!(old[o], new[n]);
+                    out.push(old[o].to_string());
+                }
+                Edit::Insert(n) => out.push(new[n].to_string()),
+                Edit::Delete(_) => {}
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_diff_is_minimal_and_valid() {
+        let old = ["a", "b", "c", "a", "b", "b", "a"];
+        let new = ["c", "b", "a", "b", "a", "c"];
+
+        let edits = diff(&old, &new);
+        assert_eq!(apply(&old, &new, &edits), new);
+
+        // The classic example from Myers' paper has D = 5
+        let changes = edits.iter().filter(|e| !matches!(e, Edit::Equal(..))).count();
+        assert_eq!(changes, 5);
+    }
+
+    #[test]
+    fn test_diff_edge_cases() {
+        let empty: [&str; 0] = [];
+        assert!(diff(&empty, &empty).is_empty());
+        assert_eq!(diff(&empty, &["a"]), vec![Edit::Insert(0)]);
+        assert_eq!(diff(&["a"], &empty), vec![Edit::Delete(0)]);
+        assert_eq!(diff(&["a", "b"], &["a", "b"]), vec![Edit::Equal(0, 0), Edit::Equal(1, 1)]);
+    }
+
+    #[test]
+    fn test_diff_matches_lcs_length() {
+        // Small sequences over a tiny alphabet, checked against the textbook LCS table
+        let mut seed = 7u32;
+        let mut next = || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            ["a", "b", "c"][(seed >> 16) as usize % 3]
+        };
+        for round in 0..200 {
+            let old: Vec<&str> = (0..round % 13).map(|_| next()).collect();
+            let new: Vec<&str> = (0..round % 11).map(|_| next()).collect();
+
+            let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+            for i in (0..old.len()).rev() {
+                for j in (0..new.len()).rev() {
+                    lcs[i][j] =
+                        if old[i] == new[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+                }
+            }
+
+            let edits = diff(&old, &new);
+            assert_eq!(apply(&old, &new, &edits), new);
+            let changes = edits.iter().filter(|e| !matches!(e, Edit::Equal(..))).count();
+            assert_eq!(changes, old.len() + new.len() - 2 * lcs[0][0], "{:?} -> {:?}", old, new);
+        }
+    }
+
+    #[test]
+    fn test_diff_of_rewritten_files_stays_small() {
+        // D is 8000 here, a trace of every round would hold 32 million diagonals
+        let old: Vec<String> = (0..4000).map(|i| format!("old {}", i)).collect();
+        let new: Vec<String> = (0..4000).map(|i| format!("new {}", i)).collect();
+        let edits = diff(&old, &new);
+        assert_eq!(edits.len(), 8000);
+        assert!(edits[..4000].iter().all(|edit| matches!(edit, Edit::Delete(_))));
+    }
+
+    #[test]
+    fn test_unified_hunks() {
+        let old = b"1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let new = b"1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n";
+
+        assert_eq!(
+            unified(old, new, 1),
+            "@@ -2,3 +2,3 @@\n 2\n-3\n+three\n 4\n@@ -12 +12,2 @@\n 12\n+13\n"
+        );
+
+        // With more context the two hunks merge into one
+        let merged = unified(old, new, 5);
+        assert_eq!(merged.matches("@@ -").count(), 1);
+        assert!(merged.starts_with("@@ -1,12 +1,13 @@\n"));
+    }
+
+    #[test]
+    fn test_unified_missing_newline() {
+        assert_eq!(
+            unified(b"a\nb", b"a\nc\n", 3),
+            "@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+c\n"
+        );
+        assert_eq!(unified(b"", b"x\n", 3), "@@ -0,0 +1 @@\n+x\n");
+    }
+
+    #[test]
+    fn test_file_patch_headers() {
+        let file = FileVersion {
+            hash: "ce013625030ba8dba906f756967f9e9ca394464a".to_string(),
+            mode: "100644".to_string(),
+            data: b"hello\n".to_vec(),
+        };
+
+        assert_eq!(
+            file_patch("a.txt", None, Some(&file), 3),
+            "diff --git a/a.txt b/a.txt\nnew file mode 100644\nindex 0000000..ce01362\n\
+             --- /dev/null\n+++ b/a.txt\n@@ -0,0 +1 @@\n+hello\n"
+        );
+
+        let binary = FileVersion {
+            data: vec![0, 1, 2],
+            ..file
+        };
+        assert!(file_patch("a.bin", Some(&binary), None, 3)
+            .ends_with("Binary files a/a.bin and /dev/null differ\n"));
+    }
+}
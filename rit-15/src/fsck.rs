@@ -0,0 +1,339 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, HashSet};
+use thiserror::Error;
+
+use crate::index;
+use crate::merge;
+use crate::objects::{ObjectDatabase, Signature, Tree};
+use crate::reflog;
+use crate::refs;
+use crate::repository::Repository;
+
+/// A problem `rit fsck` found with the object store
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FsckError {
+    #[error("object {hash} is unreadable: {reason}")]
+    Unreadable { hash: String, reason: String },
+
+    #[error("hash mismatch for {hash}: the content hashes to {actual}")]
+    HashMismatch { hash: String, actual: String },
+
+    #[error("error in {kind} {hash}: {reason}")]
+    BadObject { kind: String, hash: String, reason: String },
+
+    #[error("missing {kind} {hash} (referenced from {from})")]
+    Missing { kind: &'static str, hash: String, from: String },
+
+    #[error("object {hash} referenced from {from} is a {actual}, not a {expected}")]
+    WrongType { hash: String, from: String, expected: &'static str, actual: String },
+}
+
+/// Kinds of object a reference can point to; a ref or tag may name any of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    Any,
+    Kind(&'static str),
+}
+
+impl Expect {
+    fn name(self) -> &'static str {
+        match self {
+            Expect::Any => "object",
+            Expect::Kind(kind) => kind,
+        }
+    }
+}
+
+/// A stored object that could be read and parsed, with the objects it points to
+struct Valid {
+    kind: String,
+    links: Vec<(Expect, String)>,
+}
+
+fn kind_from_str(kind: &str) -> Option<&'static str> {
+    ["blob", "tree", "commit", "tag"].into_iter().find(|known| *known == kind)
+}
+
+fn check_hash_line<'a>(line: Option<&'a str>, field: &str) -> Result<&'a str, String> {
+    let hash = line
+        .and_then(|line| line.strip_prefix(field))
+        .and_then(|rest| rest.strip_prefix(' '))
+        .ok_or_else(|| format!("missing '{}' line", field))?;
+    if !Repository::current().format().is_hash(hash) {
+        return Err(format!("invalid '{}' line format - bad sha1", field));
+    }
+    Ok(hash)
+}
+
+fn check_signature(line: Option<&str>, field: &str) -> Result<(), String> {
+    let value = line
+        .and_then(|line| line.strip_prefix(field))
+        .and_then(|rest| rest.strip_prefix(' '))
+        .ok_or_else(|| format!("missing '{}' line", field))?;
+    let signature = Signature::parse(value).map_err(|_| format!("invalid {} line", field))?;
+
+    let timezone = signature.timezone.as_bytes();
+    let valid_timezone =
+        timezone.len() == 5 && matches!(timezone[0], b'+' | b'-') && timezone[1..].iter().all(u8::is_ascii_digit);
+    if !valid_timezone || signature.email.contains(['<', '>']) {
+        return Err(format!("invalid {} line", field));
+    }
+    Ok(())
+}
+
+// Same checks as git: known modes, sane names, sorted entries without duplicates
+fn check_tree(body: &[u8]) -> Result<Vec<(Expect, String)>, String> {
+    let entries = Tree::parse(body, Repository::current().format()).map_err(|err| err.to_string())?.entries;
+    let mut links = Vec::new();
+    let mut previous: Option<Vec<u8>> = None;
+
+    for entry in entries {
+        let expect = match entry.mode.as_str() {
+            "100644" | "100755" | "120000" => Expect::Kind("blob"),
+            "40000" => Expect::Kind("tree"),
+            // Submodule commits live in another repository
+            "160000" => Expect::Any,
+            mode => return Err(format!("bad file mode {} for '{}'", mode, entry.name)),
+        };
+        if entry.name.is_empty() || entry.name.contains('/') || [".", "..", ".git", ".rit"].contains(&entry.name.as_str())
+        {
+            return Err(format!("bad entry name '{}'", entry.name));
+        }
+
+        // Trees sort as if directory names ended with '/'
+        let mut key = entry.name.clone().into_bytes();
+        if entry.mode == "40000" {
+            key.push(b'/');
+        }
+        if let Some(previous) = &previous {
+            match previous.cmp(&key) {
+                std::cmp::Ordering::Less => {}
+                std::cmp::Ordering::Equal => return Err(format!("duplicate entry '{}'", entry.name)),
+                std::cmp::Ordering::Greater => return Err("entries not sorted properly".to_string()),
+            }
+        }
+        previous = Some(key);
+
+        if entry.mode != "160000" {
+            links.push((expect, entry.hash));
+        }
+    }
+    Ok(links)
+}
+
+fn check_commit(body: &[u8]) -> Result<Vec<(Expect, String)>, String> {
+    let text = std::str::from_utf8(body).map_err(|_| "not valid UTF-8".to_string())?;
+    let headers = text.split_once("\n\n").map_or(text, |(headers, _)| headers);
+    let mut lines = headers.lines().peekable();
+
+    let mut links = vec![(Expect::Kind("tree"), check_hash_line(lines.next(), "tree")?.to_string())];
+    while lines.peek().is_some_and(|line| line.starts_with("parent ")) {
+        links.push((Expect::Kind("commit"), check_hash_line(lines.next(), "parent")?.to_string()));
+    }
+    check_signature(lines.next(), "author")?;
+    check_signature(lines.next(), "committer")?;
+    Ok(links)
+}
+
+fn check_tag(body: &[u8]) -> Result<Vec<(Expect, String)>, String> {
+    let text = std::str::from_utf8(body).map_err(|_| "not valid UTF-8".to_string())?;
+    let headers = text.split_once("\n\n").map_or(text, |(headers, _)| headers);
+    let mut lines = headers.lines();
+
+    let object = check_hash_line(lines.next(), "object")?.to_string();
+    let kind = lines
+        .next()
+        .and_then(|line| line.strip_prefix("type "))
+        .ok_or_else(|| "missing 'type' line".to_string())?;
+    let kind = kind_from_str(kind).ok_or_else(|| format!("invalid 'type' value '{}'", kind))?;
+    let name = lines.next().and_then(|line| line.strip_prefix("tag ")).ok_or_else(|| "missing 'tag' line".to_string())?;
+    if name.is_empty() {
+        return Err("empty 'tag' line".to_string());
+    }
+    // Very old tags have no tagger
+    if let Some(line) = lines.next() {
+        check_signature(Some(line), "tagger")?;
+    }
+    Ok(vec![(Expect::Kind(kind), object)])
+}
+
+// Reads one object, checking that its name matches its content and that it parses
+fn check_object(hash: &str) -> Result<Valid, FsckError> {
+    let repo = Repository::current();
+    let (kind, body) = repo.objects().read_raw(hash).map_err(|err| FsckError::Unreadable {
+        hash: hash.to_string(),
+        reason: err.to_string(),
+    })?;
+
+    let actual = repo.format().hash_object(&kind, &body);
+    if actual != hash {
+        return Err(FsckError::HashMismatch { hash: hash.to_string(), actual });
+    }
+
+    let links = match kind.as_str() {
+        "blob" => Ok(Vec::new()),
+        "tree" => check_tree(&body),
+        "commit" => check_commit(&body),
+        "tag" => check_tag(&body),
+        _ => Err("unknown object type".to_string()),
+    };
+    let links = links.map_err(|reason| FsckError::BadObject {
+        kind: kind.clone(),
+        hash: hash.to_string(),
+        reason,
+    })?;
+    Ok(Valid { kind, links })
+}
+
+/// Everything `rit fsck` found: problems, and objects nothing points to
+pub struct Report {
+    pub errors: Vec<FsckError>,
+    /// (kind, hash) of objects that are neither reachable nor referenced by another object
+    pub dangling: Vec<(String, String)>,
+}
+
+/// Checks every stored object, then walks from the refs, HEAD, reflogs and the index to
+/// find what is missing and what is dangling
+pub fn check() -> Result<Report> {
+    let repo = Repository::current();
+    let mut errors = Vec::new();
+    let mut valid = BTreeMap::new();
+    let mut stored = HashSet::new();
+
+    for prefix in 0..=0xff {
+        for hash in repo.objects().find(&format!("{:02x}", prefix))? {
+            stored.insert(hash.clone());
+            match check_object(&hash) {
+                Ok(object) => {
+                    valid.insert(hash, object);
+                }
+                Err(err) => errors.push(err),
+            }
+        }
+    }
+
+    let mut roots: Vec<(Expect, String, String)> = Vec::new();
+    for (name, hash) in refs::list_refs(repo, "refs")? {
+        roots.push((Expect::Any, hash, format!("refs/{}", name)));
+    }
+    if let Some(head) = refs::head_commit(repo)? {
+        roots.push((Expect::Kind("commit"), head, "HEAD".to_string()));
+    }
+    if let Some(merge_head) = merge::merge_head()? {
+        roots.push((Expect::Kind("commit"), merge_head, "MERGE_HEAD".to_string()));
+    }
+    for (path, entry) in index::read_index()? {
+        if entry.mode != "160000" {
+            roots.push((Expect::Kind("blob"), entry.hash, format!("index entry {}", path)));
+        }
+    }
+    // Old reflog entries may point at objects gc has already pruned
+    for hash in reflog::all_objects(repo)? {
+        if stored.contains(&hash) {
+            roots.push((Expect::Any, hash, "reflog".to_string()));
+        }
+    }
+
+    let mut reachable = HashSet::new();
+    let mut pending = roots;
+    while let Some((expect, hash, from)) = pending.pop() {
+        let Some(object) = valid.get(&hash) else {
+            // Unreadable objects were reported already
+            if !stored.contains(&hash) {
+                errors.push(FsckError::Missing { kind: expect.name(), hash, from });
+            }
+            continue;
+        };
+        if let Expect::Kind(expected) = expect
+            && object.kind != expected
+        {
+            errors.push(FsckError::WrongType { hash, from, expected, actual: object.kind.clone() });
+            continue;
+        }
+        if !reachable.insert(hash.clone()) {
+            continue;
+        }
+        for (expect, link) in &object.links {
+            pending.push((*expect, link.clone(), format!("{} {}", object.kind, hash)));
+        }
+    }
+
+    // Unreachable objects still referenced by another one are only reported through it
+    let referenced: HashSet<&String> = valid.values().flat_map(|object| object.links.iter().map(|(_, hash)| hash)).collect();
+    let dangling = valid
+        .iter()
+        .filter(|(hash, _)| !reachable.contains(*hash) && !referenced.contains(hash))
+        .map(|(hash, object)| (object.kind.clone(), hash.clone()))
+        .collect();
+
+    Ok(Report { errors, dangling })
+}
+
+/// `rit fsck`: prints dangling objects and every problem found, failing if there was one
+pub fn fsck() -> Result<()> {
+    let report = check()?;
+
+    for (kind, hash) in &report.dangling {
+        println!("dangling {} {}", kind, hash);
+    }
+    for error in &report.errors {
+        eprintln!("error: {}", error);
+    }
+
+    if !report.errors.is_empty() {
+        anyhow::bail!("fsck found {} problem(s)", report.errors.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "1234567890123456789012345678901234567890";
+
+    fn tree(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (mode, name) in entries {
+            body.extend(format!("{} {}\0", mode, name).as_bytes());
+            body.extend(hex::decode(HASH).unwrap());
+        }
+        body
+    }
+
+    #[test]
+    fn test_check_tree() {
+        let links = check_tree(&tree(&[("100644", "a"), ("40000", "a.d"), ("40000", "b")])).unwrap();
+        assert_eq!(links, [(Expect::Kind("blob"), HASH.to_string()), (Expect::Kind("tree"), HASH.to_string()), (
+            Expect::Kind("tree"),
+            HASH.to_string()
+        )]);
+
+        // "a.d" sorts before the directory "a", which counts as "a/"
+        assert!(check_tree(&tree(&[("100644", "a.d"), ("40000", "a")])).is_ok());
+        assert_eq!(check_tree(&tree(&[("100644", "b"), ("100644", "a")])), Err("entries not sorted properly".into()));
+        assert_eq!(check_tree(&tree(&[("100644", "a"), ("100644", "a")])), Err("duplicate entry 'a'".into()));
+        assert_eq!(check_tree(&tree(&[("100664", "a")])), Err("bad file mode 100664 for 'a'".into()));
+        assert_eq!(check_tree(&tree(&[("100644", "..")])), Err("bad entry name '..'".into()));
+        assert!(check_tree(&tree(&[("100644", "a")])[..10]).is_err());
+    }
+
+    #[test]
+    fn test_check_commit_and_tag() {
+        let sig = "A U Thor <a@example.com> 1700000000 +0100";
+        let commit = format!("tree {}\nparent {}\nauthor {}\ncommitter {}\n\nmsg\n", HASH, HASH, sig, sig);
+        assert_eq!(check_commit(commit.as_bytes()).unwrap().len(), 2);
+
+        let no_tree = format!("author {}\ncommitter {}\n\nmsg\n", sig, sig);
+        assert_eq!(check_commit(no_tree.as_bytes()), Err("missing 'tree' line".into()));
+        let bad_parent = format!("tree {}\nparent xyz\nauthor {}\ncommitter {}\n\n", HASH, sig, sig);
+        assert_eq!(check_commit(bad_parent.as_bytes()), Err("invalid 'parent' line format - bad sha1".into()));
+        let bad_date = format!("tree {}\nauthor A <a@b> soon +0100\ncommitter {}\n\n", HASH, sig);
+        assert_eq!(check_commit(bad_date.as_bytes()), Err("invalid author line".into()));
+
+        let tag = format!("object {}\ntype commit\ntag v1\ntagger {}\n\nrelease\n", HASH, sig);
+        assert_eq!(check_tag(tag.as_bytes()).unwrap(), [(Expect::Kind("commit"), HASH.to_string())]);
+        let bad_type = format!("object {}\ntype thing\ntag v1\n\n", HASH);
+        assert_eq!(check_tag(bad_type.as_bytes()), Err("invalid 'type' value 'thing'".into()));
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::commands::rit_dir;
+use crate::index;
+use crate::merge;
+use crate::objects::ObjectDatabase;
+use crate::pack;
+use crate::reflog;
+use crate::refs;
+use crate::repository::Repository;
+
+/// Objects reachable from refs, HEAD, reflogs, a merge in progress and the index,
+/// as hash -> the path each was first reached by
+fn reachable() -> Result<BTreeMap<String, String>> {
+    let repo = Repository::current();
+    let mut tips: Vec<String> = refs::list_refs(repo, "refs")?.into_iter().map(|(_, hash)| hash).collect();
+    tips.extend(refs::head_commit(repo)?);
+    tips.extend(merge::merge_head()?);
+    // What the reflogs remember stays recoverable through "@{n}"
+    tips.extend(reflog::all_objects(repo)?.into_iter().filter(|hash| repo.objects().contains(hash)));
+
+    let mut found = pack::reachable_objects(repo, tips, &HashSet::new())?;
+    for (path, entry) in index::read_index()? {
+        found.entry(entry.hash).or_insert(path);
+    }
+    Ok(found)
+}
+
+// Every loose object file as (hash, path)
+fn loose_objects() -> Result<Vec<(String, String)>> {
+    let mut loose = Vec::new();
+
+    for dir in fs::read_dir(rit_dir().join("objects"))? {
+        let dir = dir?;
+        let prefix = dir.file_name().to_string_lossy().to_string();
+        if prefix.len() != 2 || !dir.file_type()?.is_dir() {
+            continue;
+        }
+        for file in fs::read_dir(dir.path())? {
+            let file = file?;
+            let hash = format!("{}{}", prefix, file.file_name().to_string_lossy());
+            loose.push((hash, file.path().to_string_lossy().to_string()));
+        }
+    }
+    Ok(loose)
+}
+
+/// `rit gc`: packs every reachable object into a single pack and deletes everything else
+pub fn gc() -> Result<()> {
+    let repo = Repository::current();
+    let reachable = reachable()?;
+
+    let old_packs = pack::packs(repo)?;
+    let loose = loose_objects()?;
+
+    let mut stored: HashSet<String> = loose.iter().map(|(hash, _)| hash.clone()).collect();
+    for old in &old_packs {
+        stored.extend(old.hashes());
+    }
+    let pruned = stored.iter().filter(|hash| !reachable.contains_key(*hash)).count();
+
+    let dir = pack::pack_dir(repo);
+    fs::create_dir_all(&dir)?;
+
+    let mut new_name = None;
+    if !reachable.is_empty() {
+        let written = pack::pack_objects(repo, &reachable, true)?;
+        let name = format!("pack-{}", hex::encode(written.checksum()));
+
+        // The .idx goes last: a pack is only looked at once its index exists
+        fs::write(dir.join(format!("{}.pack", name)), &written.data)?;
+        fs::write(
+            dir.join(format!("{}.idx", name)),
+            pack::write_index(repo.format(), &written.entries, written.checksum())?,
+        )?;
+
+        println!(
+            "Packed {} objects ({} deltas) into {}.pack",
+            reachable.len(),
+            written.deltas,
+            name
+        );
+        new_name = Some(name);
+    }
+
+    // Everything reachable is in the new pack now, the rest can go
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string());
+        if stem.is_some() && stem != new_name {
+            fs::remove_file(&path)?;
+        }
+    }
+    repo.objects().forget_packs();
+    for (_, path) in &loose {
+        fs::remove_file(path)?;
+        if let Some(parent) = Path::new(path).parent() {
+            // Only succeeds once the fan-out directory is empty
+            let _ = fs::remove_dir(parent);
+        }
+    }
+
+    println!("Pruned {} unreachable objects", pruned);
+    Ok(())
+}
@@ -0,0 +1,293 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::commands::{rit_dir, RIT_DIR};
+
+/// Name of the per-directory ignore files, read with gitignore semantics
+pub const IGNORE_FILE: &str = ".ritignore";
+
+/// One line of an ignore file
+#[derive(Debug)]
+struct Pattern {
+    glob: Vec<u8>,
+    /// "!pattern" re-includes what an earlier pattern excluded
+    negated: bool,
+    /// "pattern/" only matches directories
+    dir_only: bool,
+    /// A slash anywhere but at the end ties the pattern to the ignore file's directory,
+    /// otherwise it matches a name at any depth
+    anchored: bool,
+}
+
+impl Pattern {
+    fn parse(line: &str) -> Option<Pattern> {
+        let mut line = line.strip_suffix('\r').unwrap_or(line);
+        // Trailing spaces are dropped unless escaped with a backslash
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line = &line[..line.len() - 1];
+        }
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+
+        Some(Pattern {
+            glob: line.as_bytes().to_vec(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// `path` is relative to the directory of the file the pattern came from
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            glob_match(&self.glob, path.as_bytes(), true)
+        } else {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            glob_match(&self.glob, name.as_bytes(), true)
+        }
+    }
+}
+
+/// Matches `text` against a gitignore glob: "*" and "?" stop at slashes, "[...]" is a
+/// character class, "\" escapes, and a "**" segment spans any number of directories.
+/// `at_segment` is true when the pattern is at the start of a path component.
+fn glob_match(pattern: &[u8], text: &[u8], at_segment: bool) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] if at_segment && (rest.is_empty() || rest[0] == b'/') => {
+            let Some(rest) = rest.get(1..) else {
+                // Trailing "**" matches everything inside
+                return true;
+            };
+            // "**/" matches zero or more leading directories
+            glob_match(rest, text, true)
+                || text
+                    .iter()
+                    .enumerate()
+                    .any(|(i, &c)| c == b'/' && glob_match(rest, &text[i + 1..], true))
+        }
+        [b'*', rest @ ..] => {
+            for i in 0..=text.len() {
+                if glob_match(rest, &text[i..], false) {
+                    return true;
+                }
+                if text.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        [b'?', rest @ ..] => match text {
+            [c, text @ ..] if *c != b'/' => glob_match(rest, text, false),
+            _ => false,
+        },
+        [b'[', class @ ..] if class_len(class).is_some() => {
+            let len = class_len(class).unwrap_or_default();
+            match text {
+                [c, text @ ..] if *c != b'/' && class_matches(&class[..len], *c) => {
+                    glob_match(&class[len + 1..], text, false)
+                }
+                _ => false,
+            }
+        }
+        [b'\\', literal, rest @ ..] | [literal, rest @ ..] => match text {
+            [c, text @ ..] if c == literal => glob_match(rest, text, *c == b'/'),
+            _ => false,
+        },
+    }
+}
+
+// Length of a character class body up to (not including) its closing "]", None if unclosed.
+// A "]" right after the opening bracket (or its negation) is a literal.
+fn class_len(class: &[u8]) -> Option<usize> {
+    let start = match class.first() {
+        Some(b'!' | b'^') => 1,
+        _ => 0,
+    };
+    let mut i = start;
+    while i < class.len() {
+        match class[i] {
+            b']' if i > start => return Some(i),
+            b'\\' => i += 2,
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+fn class_matches(class: &[u8], c: u8) -> bool {
+    let (negated, mut class) = match class {
+        [b'!' | b'^', rest @ ..] => (true, rest),
+        _ => (false, class),
+    };
+
+    let mut found = false;
+    while let Some(&first) = class.first() {
+        let (low, rest) = match class {
+            [b'\\', escaped, rest @ ..] => (*escaped, rest),
+            [_, rest @ ..] => (first, rest),
+            [] => break,
+        };
+        (found, class) = match rest {
+            [b'-', high, rest @ ..] if *high != b']' => (found || (low..=*high).contains(&c), rest),
+            _ => (found || low == c, rest),
+        };
+    }
+    found != negated
+}
+
+/// The patterns of every ignore file seen so far, keyed by the directory holding it
+/// ("" for the top level). Files are read the first time a path below them is checked.
+#[derive(Default)]
+pub struct Ignore {
+    files: HashMap<String, Vec<Pattern>>,
+}
+
+impl Ignore {
+    pub fn new() -> Ignore {
+        Ignore::default()
+    }
+
+    /// Whether `path` (relative to the repository root) is ignored, either by a pattern or
+    /// because a directory above it is. `.rit` and `.git` always are.
+    pub fn is_ignored(&mut self, path: &str, is_dir: bool) -> Result<bool> {
+        let path = path.trim_start_matches("./");
+        if path.is_empty() || path == "." {
+            return Ok(false);
+        }
+
+        // Like git, nothing inside an excluded directory can be re-included
+        let mut end = 0;
+        while let Some(slash) = path[end..].find('/') {
+            end += slash;
+            if self.matches(&path[..end], true)? {
+                return Ok(true);
+            }
+            end += 1;
+        }
+        self.matches(path, is_dir)
+    }
+
+    // Checks the path itself against the ignore files of the directories above it.
+    // Deeper files win over shallower ones and later lines over earlier ones.
+    fn matches(&mut self, path: &str, is_dir: bool) -> Result<bool> {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        if name == RIT_DIR || name == ".git" || Path::new(path) == rit_dir() {
+            return Ok(true);
+        }
+
+        let mut dir = path;
+        while let Some(slash) = dir.rfind('/') {
+            dir = &dir[..slash];
+            if let Some(ignored) = self.check(dir, &path[dir.len() + 1..], is_dir)? {
+                return Ok(ignored);
+            }
+        }
+        Ok(self.check("", path, is_dir)?.unwrap_or(false))
+    }
+
+    // The verdict of `dir`'s ignore file on `relative`, None if no pattern in it matches
+    fn check(&mut self, dir: &str, relative: &str, is_dir: bool) -> Result<Option<bool>> {
+        if !self.files.contains_key(dir) {
+            let file = Path::new(dir).join(IGNORE_FILE);
+            let patterns = match fs::read_to_string(&file) {
+                Ok(text) => text.lines().filter_map(Pattern::parse).collect(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                // A directory or unreadable file by that name ignores nothing
+                Err(_) if !file.is_file() => Vec::new(),
+                Err(e) => return Err(e.into()),
+            };
+            self.files.insert(dir.to_string(), patterns);
+        }
+
+        let patterns = &self.files[dir];
+        let verdict = patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(relative, is_dir))
+            .map(|pattern| !pattern.negated);
+        Ok(verdict)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str, is_dir: bool) -> bool {
+        Pattern::parse(pattern).unwrap().matches(path, is_dir)
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*.o", b"main.o", true));
+        assert!(!glob_match(b"*.o", b"src/main.o", true));
+        assert!(glob_match(b"src/*.rs", b"src/main.rs", true));
+        assert!(glob_match(b"fo?", b"foo", true));
+        assert!(!glob_match(b"fo?", b"fo/", true));
+        assert!(glob_match(b"[a-c]x[!0-9]", b"bxy", true));
+        assert!(!glob_match(b"[a-c]x[!0-9]", b"bx1", true));
+        assert!(glob_match(b"[]]", b"]", true));
+        assert!(glob_match(b"[abc", b"[abc", true));
+        assert!(glob_match(b"\\*", b"*", true));
+        assert!(!glob_match(b"\\*", b"a", true));
+
+        assert!(glob_match(b"**/foo", b"foo", true));
+        assert!(glob_match(b"**/foo", b"a/b/foo", true));
+        assert!(glob_match(b"a/**/b", b"a/b", true));
+        assert!(glob_match(b"a/**/b", b"a/x/y/b", true));
+        assert!(glob_match(b"a/**", b"a/x/y", true));
+        assert!(!glob_match(b"a/**", b"a", true));
+        assert!(!glob_match(b"a**b", b"a/b", true));
+    }
+
+    #[test]
+    fn test_pattern() {
+        assert!(Pattern::parse("# comment").is_none());
+        assert!(Pattern::parse("   ").is_none());
+        assert!(Pattern::parse("/").is_none());
+
+        // Unanchored patterns match the name at any depth
+        assert!(matches("*.log", "debug.log", false));
+        assert!(matches("*.log", "logs/debug.log", false));
+        assert!(matches("target", "sub/target", true));
+
+        // A slash anchors the pattern to the ignore file's directory
+        assert!(matches("/target", "target", true));
+        assert!(!matches("/target", "sub/target", true));
+        assert!(matches("doc/*.txt", "doc/notes.txt", false));
+        assert!(!matches("doc/*.txt", "sub/doc/notes.txt", false));
+
+        // Directory-only patterns
+        assert!(matches("build/", "build", true));
+        assert!(!matches("build/", "build", false));
+
+        let negated = Pattern::parse("!keep.log").unwrap();
+        assert!(negated.negated && negated.matches("keep.log", false));
+
+        assert!(matches("\\#hash", "#hash", false));
+        assert!(matches("\\!bang", "!bang", false));
+        assert!(matches("trailing\\ ", "trailing ", false));
+        assert!(matches("spaces   ", "spaces", false));
+    }
+}
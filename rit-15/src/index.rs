@@ -0,0 +1,435 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::commands::rit_dir;
+use crate::objects::ObjectFormat;
+use crate::repository::Repository;
+
+/// A staged file: its tree mode ("100644", "100755" or "120000") and blob hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub mode: String,
+    pub hash: String,
+}
+
+/// Staged files by path. Sorted by path, which is also the on-disk order.
+pub type Index = BTreeMap<String, Entry>;
+
+/// The versions of a path a merge couldn't reconcile: the merge base's, ours and theirs,
+/// stored as stages 1 to 3. A side that doesn't have the file has no entry.
+pub type Stages = [Option<Entry>; 3];
+
+/// Unmerged paths, by path
+pub type Unmerged = BTreeMap<String, Stages>;
+
+/// The lstat data git keeps per index entry. When it still matches the file on disk,
+/// the file is assumed unchanged and isn't hashed again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stat {
+    pub ctime: u32,
+    pub ctime_nsec: u32,
+    pub mtime: u32,
+    pub mtime_nsec: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+}
+
+impl Stat {
+    /// Like git, every field is truncated to 32 bits
+    pub fn from_metadata(metadata: &fs::Metadata) -> Stat {
+        Stat {
+            ctime: metadata.ctime() as u32,
+            ctime_nsec: metadata.ctime_nsec() as u32,
+            mtime: metadata.mtime() as u32,
+            mtime_nsec: metadata.mtime_nsec() as u32,
+            dev: metadata.dev() as u32,
+            ino: metadata.ino() as u32,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.size() as u32,
+        }
+    }
+}
+
+/// Cached stat data by path, for the entries that have any
+pub type StatCache = BTreeMap<String, Stat>;
+
+const SIGNATURE: &[u8; 4] = b"DIRC";
+const VERSION: u32 = 2;
+const HEADER_LEN: usize = 12;
+// ctime..size (10 u32s) before the object name, then 2 bytes of flags
+const STAT_LEN: usize = 40;
+const NAME_MASK: usize = 0xfff;
+
+fn index_path() -> PathBuf {
+    rit_dir().join("index")
+}
+
+/// Reads `.rit/index`. A missing index is an empty one. Unmerged paths show up with
+/// our version, when we have one.
+pub fn read_index() -> Result<Index> {
+    Ok(read_index_with_stats()?.0)
+}
+
+pub fn read_index_with_stats() -> Result<(Index, StatCache)> {
+    let (index, stats, _) = read_all()?;
+    Ok((index, stats))
+}
+
+/// The paths left unmerged by a conflicted merge, with their stages
+pub fn read_unmerged() -> Result<Unmerged> {
+    Ok(read_all()?.2)
+}
+
+fn read_all() -> Result<(Index, StatCache, Unmerged)> {
+    if !Path::new(&index_path()).exists() {
+        return Ok((Index::new(), StatCache::new(), Unmerged::new()));
+    }
+    decode(&fs::read(index_path())?, Repository::current().format())
+}
+
+/// Records conflicts: each path is written as its stages until it is resolved
+pub fn write_unmerged(conflicts: &Unmerged) -> Result<()> {
+    let (index, stats, mut unmerged) = read_all()?;
+    unmerged.extend(conflicts.iter().map(|(path, stages)| (path.clone(), stages.clone())));
+    store(&index, &stats, &unmerged)
+}
+
+/// Forgets every conflict. Unmerged paths are left with our version, if we have one.
+pub fn clear_unmerged() -> Result<()> {
+    let (index, stats, unmerged) = read_all()?;
+    if unmerged.is_empty() {
+        return Ok(());
+    }
+    store(&index, &stats, &Unmerged::new())
+}
+
+/// Drops the stages of `path`, so the index holds its plain entry again. Returns whether
+/// it was unmerged.
+pub fn resolve(path: &str) -> Result<bool> {
+    let (index, stats, mut unmerged) = read_all()?;
+    if unmerged.remove(path).is_none() {
+        return Ok(false);
+    }
+    store(&index, &stats, &unmerged)?;
+    Ok(true)
+}
+
+/// Writes the index, keeping the cached stat data of entries that didn't change.
+/// Changed entries get none, so the next `status` or `add` hashes those files again.
+pub fn write_index(index: &Index) -> Result<()> {
+    let (old, old_stats) = read_index_with_stats()?;
+
+    let stats: StatCache = index
+        .iter()
+        .filter(|(path, entry)| old.get(*path) == Some(*entry))
+        .filter_map(|(path, _)| old_stats.get(path).map(|stat| (path.clone(), *stat)))
+        .collect();
+
+    write_index_with_stats(index, &stats)
+}
+
+/// Unmerged paths keep their stages, whatever `index` says about them, until they are
+/// resolved
+pub fn write_index_with_stats(index: &Index, stats: &StatCache) -> Result<()> {
+    let unmerged = read_unmerged()?;
+    store(index, stats, &unmerged)
+}
+
+// Written to index.lock first and renamed over the index, so readers never see half of it
+// and two writers can't interleave
+fn store(index: &Index, stats: &StatCache, unmerged: &Unmerged) -> Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as u32;
+    let data = encode(index, stats, unmerged, now, Repository::current().format())?;
+
+    let lock = rit_dir().join("index.lock");
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&lock)
+        .map_err(|err| anyhow::anyhow!("Unable to create '{}': {}", lock.display(), err))?;
+    if let Err(err) = file.write_all(&data).and_then(|_| file.sync_all()) {
+        let _ = fs::remove_file(&lock);
+        return Err(err.into());
+    }
+    fs::rename(&lock, index_path())?;
+    Ok(())
+}
+
+/// Serializes an index in git's DIRC version 2 format. Unmerged paths are written as their
+/// stages instead of their entry in `index`.
+pub fn encode(
+    index: &Index,
+    stats: &StatCache,
+    unmerged: &Unmerged,
+    now: u32,
+    format: ObjectFormat,
+) -> Result<Vec<u8>> {
+    // Sorted by path, then stage
+    let mut entries: Vec<(&String, u16, &Entry)> = Vec::new();
+    let paths: BTreeSet<&String> = index.keys().chain(unmerged.keys()).collect();
+    for path in paths {
+        match unmerged.get(path) {
+            Some(stages) => {
+                let present = stages.iter().zip(1..).filter_map(|(entry, stage)| Some((stage, entry.as_ref()?)));
+                entries.extend(present.map(|(stage, entry)| (path, stage, entry)));
+            }
+            None => entries.push((path, 0, &index[path])),
+        }
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(SIGNATURE);
+    out.extend_from_slice(&VERSION.to_be_bytes());
+    out.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+
+    for (path, stage, entry) in entries {
+        let mut stat = match stage {
+            0 => stats.get(path).copied().unwrap_or_default(),
+            _ => Stat::default(),
+        };
+
+        // A file modified in the same second the index is written could change again
+        // without its mtime moving, so such "racily clean" entries must be rehashed next time
+        if stat.mtime >= now {
+            stat.size = 0;
+        }
+
+        let mode = u32::from_str_radix(&entry.mode, 8)
+            .map_err(|_| anyhow::anyhow!("Invalid mode {} for {}", entry.mode, path))?;
+        let hash = hex::decode(&entry.hash)?;
+
+        let start = out.len();
+        for field in [
+            stat.ctime,
+            stat.ctime_nsec,
+            stat.mtime,
+            stat.mtime_nsec,
+            stat.dev,
+            stat.ino,
+            mode,
+            stat.uid,
+            stat.gid,
+            stat.size,
+        ] {
+            out.extend_from_slice(&field.to_be_bytes());
+        }
+        out.extend_from_slice(&hash);
+
+        // The stage, then the name length capped at 0xfff like git does
+        let flags = (stage << 12) | path.len().min(NAME_MASK) as u16;
+        out.extend_from_slice(&flags.to_be_bytes());
+        out.extend_from_slice(path.as_bytes());
+
+        // 1 to 8 NULs so every entry is a multiple of 8 bytes long
+        let len = out.len() - start;
+        out.resize(start + ((len + 8) & !7), 0);
+    }
+
+    let checksum = format.digest(&out);
+    out.extend_from_slice(&checksum);
+    Ok(out)
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+/// Parses a DIRC version 2 index, verifying its trailing checksum. Extensions are skipped.
+/// Unmerged paths are also in the returned index with their stage 2 entry, if any.
+pub fn decode(data: &[u8], format: ObjectFormat) -> Result<(Index, StatCache, Unmerged)> {
+    let corrupt = || anyhow::anyhow!("index file corrupt");
+    let hash_len = format.raw_len();
+    let entry_fixed_len = STAT_LEN + hash_len + 2;
+
+    if data.len() < HEADER_LEN + hash_len {
+        return Err(corrupt());
+    }
+    let (content, checksum) = data.split_at(data.len() - hash_len);
+    if format.digest(content) != checksum {
+        anyhow::bail!("bad index file sha1 signature");
+    }
+
+    if &content[..4] != SIGNATURE {
+        anyhow::bail!("bad index file signature");
+    }
+    let version = read_u32(content, 4);
+    if version != VERSION {
+        anyhow::bail!("index file version {} is not supported", version);
+    }
+    let count = read_u32(content, 8);
+
+    let mut index = Index::new();
+    let mut stats = StatCache::new();
+    let mut unmerged = Unmerged::new();
+    let mut pos = HEADER_LEN;
+
+    for _ in 0..count {
+        if pos + entry_fixed_len > content.len() {
+            return Err(corrupt());
+        }
+        let field = |i: usize| read_u32(content, pos + i * 4);
+
+        let stat = Stat {
+            ctime: field(0),
+            ctime_nsec: field(1),
+            mtime: field(2),
+            mtime_nsec: field(3),
+            dev: field(4),
+            ino: field(5),
+            uid: field(7),
+            gid: field(8),
+            size: field(9),
+        };
+        let mode = field(6);
+        let hash = hex::encode(&content[pos + STAT_LEN..pos + STAT_LEN + hash_len]);
+        let flags_at = pos + STAT_LEN + hash_len;
+        let flags = u16::from_be_bytes([content[flags_at], content[flags_at + 1]]);
+
+        // The length in the flags saturates, the NUL after the name is authoritative
+        let name_start = pos + entry_fixed_len;
+        let name_len = content[name_start..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(corrupt)?;
+        let path = String::from_utf8(content[name_start..name_start + name_len].to_vec())?;
+
+        pos += (entry_fixed_len + name_len + 8) & !7;
+        if pos > content.len() {
+            return Err(corrupt());
+        }
+
+        let entry = Entry {
+            mode: format!("{:o}", mode),
+            hash,
+        };
+        match (flags >> 12) & 0x3 {
+            0 => {
+                index.insert(path.clone(), entry);
+                stats.insert(path, stat);
+            }
+            stage => {
+                if stage == 2 {
+                    index.insert(path.clone(), entry.clone());
+                }
+                unmerged.entry(path).or_default()[stage as usize - 1] = Some(entry);
+            }
+        }
+    }
+
+    Ok((index, stats, unmerged))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(mode: &str, hash: &str) -> Entry {
+        Entry {
+            mode: mode.to_string(),
+            hash: hash.to_string(),
+        }
+    }
+
+    fn sample() -> (Index, StatCache) {
+        let mut index = Index::new();
+        index.insert("a.txt".to_string(), entry("100644", &"ab".repeat(20)));
+        index.insert("bin/run".to_string(), entry("100755", &"cd".repeat(20)));
+        index.insert("link".to_string(), entry("120000", &"ef".repeat(20)));
+
+        let stat = Stat {
+            ctime: 1,
+            ctime_nsec: 2,
+            mtime: 3,
+            mtime_nsec: 4,
+            dev: 5,
+            ino: 6,
+            uid: 7,
+            gid: 8,
+            size: 9,
+        };
+        let mut stats = StatCache::new();
+        stats.insert("a.txt".to_string(), stat);
+        stats.insert("link".to_string(), stat);
+        (index, stats)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (index, stats) = sample();
+        let data = encode(&index, &stats, &Unmerged::new(), 1000, ObjectFormat::Sha1).unwrap();
+
+        assert_eq!(&data[..4], b"DIRC");
+        assert_eq!(read_u32(&data, 4), 2);
+        assert_eq!(read_u32(&data, 8), 3);
+        // "a.txt" is 62 + 5 bytes, padded with NULs to 72
+        assert_eq!(&data[HEADER_LEN + 67..HEADER_LEN + 72], &[0; 5]);
+
+        let (decoded, decoded_stats, _) = decode(&data, ObjectFormat::Sha1).unwrap();
+        assert_eq!(decoded, index);
+        assert_eq!(decoded_stats["a.txt"], stats["a.txt"]);
+        assert_eq!(decoded_stats["bin/run"], Stat::default());
+    }
+
+    #[test]
+    fn test_racily_clean_entries_are_smudged() {
+        let (index, stats) = sample();
+        let data = encode(&index, &stats, &Unmerged::new(), 3, ObjectFormat::Sha1).unwrap();
+
+        let (_, decoded_stats, _) = decode(&data, ObjectFormat::Sha1).unwrap();
+        assert_eq!(decoded_stats["a.txt"].size, 0);
+        assert_eq!(decoded_stats["a.txt"].mtime, 3);
+    }
+
+    #[test]
+    fn test_rejects_corruption() {
+        let (index, stats) = sample();
+        let mut data = encode(&index, &stats, &Unmerged::new(), 1000, ObjectFormat::Sha1).unwrap();
+
+        data[HEADER_LEN + 70] ^= 1;
+        assert!(decode(&data, ObjectFormat::Sha1).is_err());
+        assert!(decode(b"DIRC", ObjectFormat::Sha1).is_err());
+    }
+
+    #[test]
+    fn test_unmerged_stages_round_trip() {
+        let (mut index, stats) = sample();
+        let mut unmerged = Unmerged::new();
+        let stages = [Some(entry("100644", &"01".repeat(20))), None, Some(entry("100644", &"03".repeat(20)))];
+        unmerged.insert("a.txt".to_string(), stages.clone());
+        unmerged.insert("b.txt".to_string(), [None, Some(entry("100644", &"02".repeat(20))), None]);
+        let data = encode(&index, &stats, &unmerged, 1000, ObjectFormat::Sha1).unwrap();
+
+        // a.txt's stage 0 entry gives way to its two stages, b.txt only has stage 2
+        assert_eq!(read_u32(&data, 8), 5);
+        let (decoded, decoded_stats, decoded_unmerged) = decode(&data, ObjectFormat::Sha1).unwrap();
+        assert_eq!(decoded_unmerged, unmerged);
+        assert!(!decoded.contains_key("a.txt") && !decoded_stats.contains_key("a.txt"));
+        assert_eq!(decoded["b.txt"], unmerged["b.txt"][1].clone().unwrap());
+
+        index.remove("a.txt");
+        index.insert("b.txt".to_string(), unmerged["b.txt"][1].clone().unwrap());
+        assert_eq!(decoded, index);
+    }
+
+    #[test]
+    fn test_sha256_round_trip() {
+        let mut index = Index::new();
+        index.insert("a.txt".to_string(), entry("100644", &"ab".repeat(32)));
+        let data = encode(&index, &StatCache::new(), &Unmerged::new(), 1000, ObjectFormat::Sha256).unwrap();
+
+        // 40 bytes of stat data, a 32 byte name, flags and "a.txt" padded to 80
+        assert_eq!(data.len(), HEADER_LEN + 80 + 32);
+        let (decoded, _, _) = decode(&data, ObjectFormat::Sha256).unwrap();
+        assert_eq!(decoded, index);
+        assert!(decode(&data, ObjectFormat::Sha1).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+//! rit, a small git written in Rust, as a library.
+//!
+//! [`Repository`] finds and opens repositories anywhere on disk and gives access to their
+//! refs and, through the [`ObjectDatabase`] trait, to their objects, parsed into [`Blob`],
+//! [`Tree`], [`Commit`] and [`Tag`]. The functions in `refs`, `reflog`, `revision` and
+//! `pack` take the repository to work on. The other modules are the `rit` commands, which
+//! work on [`Repository::current`] and print their results.
+
+pub mod blame;
+pub mod branch;
+pub mod commands;
+pub mod config;
+pub mod diff;
+pub mod fsck;
+pub mod gc;
+pub mod ignore;
+pub mod index;
+pub mod log;
+pub mod merge;
+pub mod objects;
+pub mod pack;
+pub mod pktline;
+pub mod rebase;
+pub mod reflog;
+pub mod refs;
+pub mod remote;
+pub mod repository;
+pub mod reset;
+pub mod revision;
+pub mod stash;
+pub mod status;
+pub mod tag;
+pub mod transport;
+
+pub use objects::{
+    Blob, Commit, Object, ObjectDatabase, ObjectFormat, ObjectStore, Signature, Tag, Tree, TreeEntry,
+};
+pub use refs::Head;
+pub use repository::Repository;
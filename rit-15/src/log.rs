@@ -0,0 +1,539 @@
+use anyhow::Result;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::commands;
+use crate::index::Index;
+use crate::objects::{self, Commit};
+use crate::repository::Repository;
+use crate::revision;
+
+/// What `rit log` shows and how
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Where the walk starts, HEAD when empty
+    pub revisions: Vec<String>,
+    pub oneline: bool,
+    pub graph: bool,
+    pub max_count: Option<usize>,
+    /// Substring of the author's "Name <email>"
+    pub author: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// "oneline", "medium", or placeholders like "%h %s" (optionally after "format:")
+    pub format: Option<String>,
+    /// Only commits changing these files or directories
+    pub paths: Vec<String>,
+}
+
+/// Commits reachable from the starting points, newest first and never a parent before
+/// one of its children. The whole graph is read up front, which `--graph` needs.
+struct History {
+    order: Vec<String>,
+    commits: HashMap<String, Commit>,
+    /// The parents the walk went on to. With paths given, a merge that didn't change them
+    /// compared to one of its parents only leads to that parent, like git's default
+    /// history simplification.
+    followed: HashMap<String, Vec<String>>,
+    /// Commits that changed the paths (all of them when no paths are given)
+    changed: HashSet<String>,
+}
+
+// Tree contents restricted to the paths being followed, cached by tree hash
+struct TreeCache {
+    paths: Vec<String>,
+    trees: HashMap<String, Index>,
+}
+
+impl TreeCache {
+    fn files(&mut self, tree: &str) -> Result<&Index> {
+        if !self.trees.contains_key(tree) {
+            let files: Index = commands::flatten_tree(tree)?
+                .into_iter()
+                .filter(|(path, _)| self.paths.iter().any(|spec| commands::matches_pathspec(path, spec)))
+                .collect();
+            self.trees.insert(tree.to_string(), files);
+        }
+        Ok(&self.trees[tree])
+    }
+
+    fn same(&mut self, a: &str, b: &str) -> Result<bool> {
+        if a == b {
+            return Ok(true);
+        }
+        self.files(a)?;
+        self.files(b)?;
+        Ok(self.trees[a] == self.trees[b])
+    }
+}
+
+impl TreeCache {
+    fn new(paths: &[String]) -> TreeCache {
+        TreeCache {
+            paths: paths.to_vec(),
+            trees: HashMap::new(),
+        }
+    }
+
+    // The parents the walk goes on to from `commit`, and whether it changed the paths
+    fn simplify(&mut self, commit: &Commit) -> Result<(Vec<String>, bool)> {
+        if self.paths.is_empty() {
+            return Ok((commit.parents.clone(), true));
+        }
+
+        let mut same_as = None;
+        for parent in &commit.parents {
+            if self.same(&commit.tree, &Commit::read(Repository::current(), parent)?.tree)? {
+                same_as = Some(parent.clone());
+                break;
+            }
+        }
+        let changed = match (&same_as, commit.parents.is_empty()) {
+            (Some(_), _) => false,
+            (None, true) => !self.files(&commit.tree)?.is_empty(),
+            (None, false) => true,
+        };
+        match same_as {
+            Some(parent) if commit.parents.len() > 1 => Ok((vec![parent], changed)),
+            _ => Ok((commit.parents.clone(), changed)),
+        }
+    }
+}
+
+/// Commits newest first, read as they are needed: each step takes the newest commit
+/// waiting and queues the parents it leads to. Unlike `History` this never looks further
+/// back than what is shown, so `-n` stops early.
+struct DateWalk {
+    trees: TreeCache,
+    // Ties go to the commit queued first, so a merge's first parent comes before its second
+    queue: BinaryHeap<(i64, Reverse<usize>, String)>,
+    queued: HashMap<String, Commit>,
+    seen: HashSet<String>,
+}
+
+impl DateWalk {
+    fn new(starts: &[String], paths: &[String]) -> Result<DateWalk> {
+        let mut walk = DateWalk {
+            trees: TreeCache::new(paths),
+            queue: BinaryHeap::new(),
+            queued: HashMap::new(),
+            seen: HashSet::new(),
+        };
+        for start in starts {
+            walk.push(start)?;
+        }
+        Ok(walk)
+    }
+
+    fn push(&mut self, hash: &str) -> Result<()> {
+        if self.seen.insert(hash.to_string()) {
+            let commit = Commit::read(Repository::current(), hash)?;
+            self.queue.push((commit.committer.timestamp, Reverse(self.seen.len()), hash.to_string()));
+            self.queued.insert(hash.to_string(), commit);
+        }
+        Ok(())
+    }
+
+    /// The next commit and whether it changed the paths
+    fn next(&mut self) -> Result<Option<(String, Commit, bool)>> {
+        let Some((_, _, hash)) = self.queue.pop() else {
+            return Ok(None);
+        };
+        let commit = self.queued.remove(&hash).expect("queued with its commit");
+        let (followed, changed) = self.trees.simplify(&commit)?;
+        for parent in &followed {
+            self.push(parent)?;
+        }
+        Ok(Some((hash, commit, changed)))
+    }
+}
+
+impl History {
+    fn walk(starts: &[String], paths: &[String]) -> Result<History> {
+        let mut trees = TreeCache::new(paths);
+        let mut history = History {
+            order: Vec::new(),
+            commits: HashMap::new(),
+            followed: HashMap::new(),
+            changed: HashSet::new(),
+        };
+
+        let mut pending: Vec<String> = starts.to_vec();
+        while let Some(hash) = pending.pop() {
+            if history.commits.contains_key(&hash) {
+                continue;
+            }
+            let commit = Commit::read(Repository::current(), &hash)?;
+            let (followed, changed) = trees.simplify(&commit)?;
+
+            if changed {
+                history.changed.insert(hash.clone());
+            }
+            pending.extend(followed.iter().cloned());
+            history.followed.insert(hash.clone(), followed);
+            history.commits.insert(hash, commit);
+        }
+
+        history.order = history.date_order(starts);
+        Ok(history)
+    }
+
+    // Newest commit date first, but never a parent before one of its children
+    fn date_order(&self, starts: &[String]) -> Vec<String> {
+        let mut children: HashMap<&str, usize> = HashMap::new();
+        for parents in self.followed.values() {
+            for parent in parents {
+                *children.entry(parent.as_str()).or_default() += 1;
+            }
+        }
+
+        // Ties go to the commit seen first, so a merge's first parent comes before its second
+        let mut seen = 0;
+        let mut ready = BinaryHeap::new();
+        let mut queued = HashSet::new();
+        for start in starts {
+            if !children.contains_key(start.as_str()) && queued.insert(start.as_str()) {
+                ready.push((self.commits[start].committer.timestamp, Reverse(seen), start.as_str()));
+                seen += 1;
+            }
+        }
+
+        let mut order = Vec::new();
+        while let Some((_, _, hash)) = ready.pop() {
+            order.push(hash.to_string());
+            for parent in &self.followed[hash] {
+                let waiting = children.get_mut(parent.as_str()).expect("counted above");
+                *waiting -= 1;
+                if *waiting == 0 {
+                    ready.push((self.commits[parent].committer.timestamp, Reverse(seen), parent.as_str()));
+                    seen += 1;
+                }
+            }
+        }
+        order
+    }
+
+    // The parents drawn in the graph: the nearest shown ancestors along followed parents
+    fn shown_parents(&self, shown: &HashSet<&str>) -> HashMap<String, Vec<String>> {
+        let mut rewritten: HashMap<String, Vec<String>> = HashMap::new();
+        // Parents come after their children in `order`, so walk it backwards
+        for hash in self.order.iter().rev() {
+            let mut parents = Vec::new();
+            for parent in &self.followed[hash] {
+                let through = if shown.contains(parent.as_str()) {
+                    vec![parent.clone()]
+                } else {
+                    rewritten[parent].clone()
+                };
+                for parent in through {
+                    if !parents.contains(&parent) {
+                        parents.push(parent);
+                    }
+                }
+            }
+            rewritten.insert(hash.clone(), parents);
+        }
+        rewritten
+    }
+}
+
+/// ASCII art for `--graph`: one lane per line of history still waiting for its next commit
+#[derive(Default)]
+struct Graph {
+    lanes: Vec<String>,
+}
+
+impl Graph {
+    // Draws `marks` as (column, character) pairs, columns being two characters apart
+    fn row(marks: impl IntoIterator<Item = (usize, char)>) -> String {
+        let mut row: Vec<char> = Vec::new();
+        for (at, mark) in marks {
+            if row.len() <= at {
+                row.resize(at + 1, ' ');
+            }
+            row[at] = mark;
+        }
+        row.into_iter().collect()
+    }
+
+    // A row where lane `at` closes and the lanes to its right move one lane left.
+    // A lane that `joins` the one to its left is drawn doing so, otherwise it just ends.
+    fn close(&mut self, at: usize, joins: bool) -> String {
+        let row = Graph::row((0..self.lanes.len()).filter(|&k| joins || k != at).map(|k| {
+            if k < at { (2 * k, '|') } else { (2 * k - 1, '/') }
+        }));
+        self.lanes.remove(at);
+        row
+    }
+
+    // A row where a new lane opens at `at`, pushing the ones from there on to the right
+    fn open(&mut self, at: usize, hash: String) -> String {
+        self.lanes.insert(at, hash);
+        Graph::row((0..self.lanes.len()).map(|k| if k < at { (2 * k, '|') } else { (2 * k - 1, '\\') }))
+    }
+
+    /// The rows for one commit: lanes joining into it, its own row (returned separately
+    /// so the caller can put text next to it), then its parents branching off
+    fn commit(&mut self, hash: &str, parents: &[String]) -> (Vec<String>, String, Vec<String>) {
+        let col = match self.lanes.iter().position(|lane| lane == hash) {
+            Some(col) => col,
+            None => {
+                self.lanes.push(hash.to_string());
+                self.lanes.len() - 1
+            }
+        };
+
+        let mut before = Vec::new();
+        while let Some(dup) = self.lanes.iter().skip(col + 1).position(|lane| lane == hash) {
+            before.push(self.close(col + 1 + dup, true));
+        }
+
+        let own = Graph::row((0..self.lanes.len()).map(|k| (2 * k, if k == col { '*' } else { '|' })));
+
+        let mut after = Vec::new();
+        match parents.split_first() {
+            None => {
+                // Nothing left to draw in this lane
+                let last = col + 1 == self.lanes.len();
+                let row = self.close(col, false);
+                if !last {
+                    after.push(row);
+                }
+            }
+            Some((first, rest)) => {
+                self.lanes[col] = first.clone();
+                for (i, parent) in rest.iter().enumerate() {
+                    after.push(self.open(col + 1 + i, parent.clone()));
+                }
+            }
+        }
+        (before, own, after)
+    }
+
+    /// What goes in front of text lines below a commit
+    fn padding(&self) -> String {
+        Graph::row((0..self.lanes.len()).map(|k| (2 * k, '|')))
+    }
+}
+
+// "3 days ago" style limits as well as anything `parse_date` understands
+fn parse_limit(text: &str) -> Result<i64> {
+    if let Some(ago) = text.trim().strip_suffix(" ago")
+        && let Some((count, unit)) = ago.split_once(' ')
+        && let Ok(count) = count.parse::<i64>()
+    {
+        let seconds = match unit.trim_end_matches('s') {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 3600,
+            "day" => 86400,
+            "week" => 7 * 86400,
+            "month" => 30 * 86400,
+            "year" => 365 * 86400,
+            _ => anyhow::bail!("invalid date format: {}", text),
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        return Ok(now - count * seconds);
+    }
+    Ok(objects::parse_date(text)?.0)
+}
+
+// The first paragraph of a message as one line, and everything after it
+fn split_message(message: &str) -> (String, String) {
+    let message = message.trim_start_matches('\n');
+    let (subject, body) = message.split_once("\n\n").unwrap_or((message, ""));
+    let subject = subject.lines().map(str::trim).collect::<Vec<_>>().join(" ");
+    (subject, body.trim_start_matches('\n').to_string())
+}
+
+/// Expands `--format` placeholders for one commit. Unknown ones are kept as they are.
+pub fn expand_format(format: &str, hash: &str, commit: &Commit) -> String {
+    let (subject, body) = split_message(&commit.message_text());
+    let short = |hash: &str| hash[..hash.len().min(7)].to_string();
+
+    let mut out = String::new();
+    let mut rest = format;
+    while let Some(at) = rest.find('%') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at + 1..];
+
+        let two = rest.get(..2).unwrap_or("");
+        let (value, len) = match two {
+            "an" => (commit.author.name.clone(), 2),
+            "ae" => (commit.author.email.clone(), 2),
+            "ad" => (commit.author.date(), 2),
+            "at" => (commit.author.timestamp.to_string(), 2),
+            "cn" => (commit.committer.name.clone(), 2),
+            "ce" => (commit.committer.email.clone(), 2),
+            "cd" => (commit.committer.date(), 2),
+            "ct" => (commit.committer.timestamp.to_string(), 2),
+            _ => match rest.chars().next() {
+                Some('H') => (hash.to_string(), 1),
+                Some('h') => (short(hash), 1),
+                Some('T') => (commit.tree.clone(), 1),
+                Some('t') => (short(&commit.tree), 1),
+                Some('P') => (commit.parents.join(" "), 1),
+                Some('p') => (commit.parents.iter().map(|p| short(p)).collect::<Vec<_>>().join(" "), 1),
+                Some('s') => (subject.clone(), 1),
+                Some('b') => (body.clone(), 1),
+                Some('B') => (commit.message_text().into_owned(), 1),
+                Some('n') => ("\n".to_string(), 1),
+                Some('%') => ("%".to_string(), 1),
+                _ => ("%".to_string(), 0),
+            },
+        };
+        out.push_str(&value);
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// The lines shown for one commit in git's default "medium" format
+fn medium(hash: &str, commit: &Commit) -> Vec<String> {
+    let mut lines = vec![format!("\x1b[33mcommit {}\x1b[0m", hash)];
+    if commit.parents.len() > 1 {
+        let short: Vec<&str> = commit.parents.iter().map(|p| &p[..7]).collect();
+        lines.push(format!("Merge: {}", short.join(" ")));
+    }
+    lines.push(format!("Author: {} <{}>", commit.author.name, commit.author.email));
+    lines.push(format!("Date:   {}", commit.author.date()));
+    lines.push(String::new());
+    lines.extend(commit.message_text().trim_end().lines().map(|line| format!("    {}", line)));
+    lines
+}
+
+/// `rit log`: walks history from the given revisions newest first and prints the
+/// commits that pass every filter
+pub fn log(options: &Options) -> Result<()> {
+    let revisions = match options.revisions.is_empty() {
+        true => vec!["HEAD".to_string()],
+        false => options.revisions.clone(),
+    };
+    let starts = revisions
+        .iter()
+        .map(|rev| revision::resolve_commit(Repository::current(), rev))
+        .collect::<Result<Vec<_>>>()?;
+
+    let since = options.since.as_deref().map(parse_limit).transpose()?;
+    let until = options.until.as_deref().map(parse_limit).transpose()?;
+    let wanted = |commit: &Commit| {
+        let date = commit.committer.timestamp;
+        since.is_none_or(|since| date >= since)
+            && until.is_none_or(|until| date <= until)
+            && options.author.as_deref().is_none_or(|author| {
+                format!("{} <{}>", commit.author.name, commit.author.email).contains(author)
+            })
+    };
+    let max_count = options.max_count.unwrap_or(usize::MAX);
+
+    let format = match options.format.as_deref() {
+        _ if options.oneline => Some("%h %s"),
+        Some("oneline") => Some("%h %s"),
+        Some("medium") | None => None,
+        Some(format) => Some(format.strip_prefix("tformat:").or(format.strip_prefix("format:")).unwrap_or(format)),
+    };
+    let lines = |hash: &str, commit: &Commit| -> Vec<String> {
+        match format {
+            Some(format) => expand_format(format, hash, commit).split('\n').map(str::to_string).collect(),
+            None => medium(hash, commit),
+        }
+    };
+
+    if !options.graph {
+        let mut walk = DateWalk::new(&starts, &options.paths)?;
+        let mut count = 0;
+        while count < max_count
+            && let Some((hash, commit, changed)) = walk.next()?
+        {
+            if !changed || !wanted(&commit) {
+                continue;
+            }
+            // The medium format separates commits with a blank line
+            if format.is_none() && count > 0 {
+                println!();
+            }
+            for line in lines(&hash, &commit) {
+                println!("{}", line);
+            }
+            count += 1;
+        }
+        return Ok(());
+    }
+
+    let history = History::walk(&starts, &options.paths)?;
+    let shown: Vec<&String> = history
+        .order
+        .iter()
+        .filter(|hash| history.changed.contains(*hash) && wanted(&history.commits[*hash]))
+        .collect();
+    let parents = history.shown_parents(&shown.iter().map(|hash| hash.as_str()).collect());
+
+    let mut graph = Graph::default();
+    let mut first = true;
+    for hash in shown.into_iter().take(max_count) {
+        let (before, own, after) = graph.commit(hash, &parents[hash]);
+        let padding = graph.padding();
+        if format.is_none() && !first {
+            println!("{}", padding.trim_end());
+        }
+        for row in before {
+            println!("{}", row);
+        }
+        let mut lines = lines(hash, &history.commits[hash]).into_iter();
+        println!("{} {}", own, lines.next().unwrap_or_default());
+        for row in after {
+            println!("{}", row);
+        }
+        let padding = graph.padding();
+        for line in lines {
+            println!("{}", format!("{} {}", padding, line).trim_end());
+        }
+        first = false;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(history: &[(&str, &[&str])]) -> Vec<String> {
+        let mut graph = Graph::default();
+        let mut rows = Vec::new();
+        for (hash, parents) in history {
+            let parents: Vec<String> = parents.iter().map(|p| p.to_string()).collect();
+            let (before, own, after) = graph.commit(hash, &parents);
+            rows.extend(before);
+            rows.push(format!("{} {}", own, hash));
+            rows.extend(after);
+        }
+        rows
+    }
+
+    #[test]
+    fn test_graph() {
+        let rows = draw(&[
+            ("merge", &["c3", "s1"]),
+            ("c3", &["c2"]),
+            ("s1", &["c1"]),
+            ("c2", &["c1"]),
+            ("c1", &[]),
+        ]);
+        assert_eq!(rows, ["* merge", "|\\", "* | c3", "| * s1", "* | c2", "|/", "* c1"]);
+
+        // Two unrelated roots
+        let rows = draw(&[("a", &["b", "x"]), ("b", &[]), ("x", &[])]);
+        assert_eq!(rows, ["* a", "|\\", "* | b", " /", "* x"]);
+    }
+
+    #[test]
+    fn test_split_message() {
+        assert_eq!(split_message("one\n"), ("one".to_string(), String::new()));
+        assert_eq!(
+            split_message("one\ncontinued\n\n\nbody\nmore\n"),
+            ("one continued".to_string(), "body\nmore\n".to_string())
+        );
+    }
+}
@@ -1,12 +1,20 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
-mod commands;
+use rit::{
+    blame, branch, commands, config, diff, fsck, gc, log, merge, objects, rebase, reflog, remote, reset, revision, stash,
+    status, tag, transport, Repository,
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "rit")]
 #[command(about = "A small custom git clone written in rust")]
 struct Cli {
+    /// Use this repository directory instead of .rit, e.g. the .git of a git repository
+    #[arg(long, global = true, value_name = "PATH")]
+    git_dir: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -14,7 +22,11 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Initialize a new rit repository
-    Init,
+    Init {
+        /// Hash function to name objects with: sha1 (the default) or sha256
+        #[arg(long, value_name = "FORMAT")]
+        object_format: Option<String>,
+    },
 
     /// Compute object ID and optionlly create blob a blob from a file
     HashObject {
@@ -41,9 +53,9 @@ enum Commands {
         /// The tree object to commit
         tree_hash: String,
 
-        /// The parent commit hash (optional)
+        /// The parent commit hashes (none for a root commit, repeat for a merge)
         #[arg(short = 'p')]
-        parent_hash: Option<String>,
+        parent_hash: Vec<String>,
 
         /// The commit message
         #[arg(short = 'm')]
@@ -57,11 +69,19 @@ enum Commands {
 
         /// The new commit hash
         oid: String,
+
+        /// The reason recorded in the ref's log
+        #[arg(short = 'm', default_value = "")]
+        message: String,
     },
     
     /// Add a file to the staging area
     Add {
-        file: String
+        file: String,
+
+        /// Add ignored files too
+        #[arg(short = 'f', long)]
+        force: bool,
     },
 
     /// Record changes to the repository
@@ -73,9 +93,62 @@ enum Commands {
 
     /// Show commit logs
     Log {
-        /// The commit hash to start from (defaults to HEAD)
+        /// The commits to start from (defaults to HEAD)
+        revisions: Vec<String>,
+
+        /// One line per commit: the abbreviated hash and the subject
+        #[arg(long)]
+        oneline: bool,
+
+        /// Draw the history as an ASCII graph next to the commits
+        #[arg(long)]
+        graph: bool,
+
+        /// Show at most this many commits
+        #[arg(short = 'n', long)]
+        max_count: Option<usize>,
+
+        /// Only commits whose author's "Name <email>" contains this
+        #[arg(long)]
+        author: Option<String>,
+
+        /// Only commits made at or after this date ("2024-01-15", "2 weeks ago", ...)
+        #[arg(long, alias = "after")]
+        since: Option<String>,
+
+        /// Only commits made at or before this date
+        #[arg(long, alias = "before")]
+        until: Option<String>,
+
+        /// "oneline", "medium" or a format string like "%h %an %s"
+        #[arg(long, alias = "pretty")]
+        format: Option<String>,
+
+        /// Only commits changing these paths (after "--")
+        #[arg(last = true)]
+        paths: Vec<String>,
+    },
+
+    /// Show what commit last changed each line of a file
+    Blame {
+        /// Only these lines: "10,20", "10,+5", "10,-5", "10" (to the end) or ",20"
+        #[arg(short = 'L', value_name = "RANGE")]
+        ranges: Vec<String>,
+
+        /// Machine-readable output with each commit's details, for editors
+        #[arg(short = 'p', long)]
+        porcelain: bool,
+
+        /// The commit to start from (defaults to the working tree), then the file
+        #[arg(required = true, num_args = 1..=2, value_name = "[REV] FILE")]
+        args: Vec<String>,
+    },
+
+    /// Show where HEAD or a branch has been
+    Reflog {
+        /// The ref whose log to show
         #[arg(default_value = "HEAD")]
-        oid: String,
+        ref_name: String,
     },
 
     /// Switch branches or restore working tree files
@@ -84,14 +157,338 @@ enum Commands {
         target: String,
     },
 
+    /// List, create, rename or delete branches
+    Branch {
+        /// The branch to create, delete or rename
+        name: Option<String>,
+
+        /// Where the new branch starts (defaults to HEAD), or the new name with -m
+        start_point: Option<String>,
+
+        /// Delete a branch that is merged into HEAD
+        #[arg(short = 'd', long, conflicts_with_all = ["force_delete", "rename", "force_rename"])]
+        delete: bool,
+
+        /// Delete a branch even if it is not merged
+        #[arg(short = 'D', conflicts_with_all = ["rename", "force_rename"])]
+        force_delete: bool,
+
+        /// Rename a branch (the current one if only a new name is given)
+        #[arg(short = 'm', long = "move", conflicts_with = "force_rename")]
+        rename: bool,
+
+        /// Rename a branch even if the new name already exists
+        #[arg(short = 'M')]
+        force_rename: bool,
+    },
+
+    /// List, create or delete tags
+    Tag {
+        /// The tag to create or delete
+        name: Option<String>,
+
+        /// The object to tag (defaults to HEAD)
+        object: Option<String>,
+
+        /// Make an annotated tag object (implied by -m)
+        #[arg(short = 'a', requires = "message")]
+        annotate: bool,
+
+        /// The annotated tag's message
+        #[arg(short = 'm')]
+        message: Option<String>,
+
+        /// Delete the tag
+        #[arg(short = 'd', long, conflicts_with_all = ["object", "annotate", "message", "force"])]
+        delete: bool,
+
+        /// Replace an existing tag
+        #[arg(short = 'f', long)]
+        force: bool,
+    },
+
+    /// Set local changes aside and go back to a clean working tree
+    #[command(args_conflicts_with_subcommands = true)]
+    Stash {
+        #[command(subcommand)]
+        action: Option<StashAction>,
+
+        /// Describe the stash instead of using the HEAD commit's subject
+        #[arg(short = 'm', long)]
+        message: Option<String>,
+
+        /// Stash untracked files too
+        #[arg(short = 'u', long)]
+        include_untracked: bool,
+    },
+
+    /// Move the current branch to another commit, resetting the index and working tree
+    Reset {
+        /// The commit to move to
+        #[arg(default_value = "HEAD")]
+        commit: String,
+
+        /// Leave the index and working tree as they are
+        #[arg(long, group = "mode")]
+        soft: bool,
+
+        /// Reset the index but not the working tree (the default)
+        #[arg(long, group = "mode")]
+        mixed: bool,
+
+        /// Reset the index and the working tree, discarding local changes to tracked files
+        #[arg(long, group = "mode")]
+        hard: bool,
+    },
+
+    /// Restore working tree files from the index, or staged files from HEAD
+    Restore {
+        /// The files or directories to restore
+        #[arg(required = true)]
+        paths: Vec<String>,
+
+        /// Restore the index instead of the working tree
+        #[arg(short = 'S', long)]
+        staged: bool,
+
+        /// Take the content from this commit instead
+        #[arg(short = 's', long)]
+        source: Option<String>,
+    },
+
+    /// Remove files from the index and the working tree
+    Rm {
+        /// The files to remove
+        #[arg(required = true)]
+        paths: Vec<String>,
+
+        /// Only remove the files from the index, keeping them on disk
+        #[arg(long)]
+        cached: bool,
+
+        /// Allow removing whole directories
+        #[arg(short = 'r')]
+        recursive: bool,
+
+        /// Remove files even if they have changes that would be lost
+        #[arg(short = 'f', long)]
+        force: bool,
+    },
+
+    /// Show staged, unstaged and untracked changes
+    Status {
+        /// Give the output in the short format
+        #[arg(short = 's', long)]
+        short: bool,
+    },
+
+    /// Show changes between the index, the working tree and commits
+    Diff {
+        /// Compare the index against HEAD (or the given commit) instead of the working tree
+        #[arg(long, alias = "staged")]
+        cached: bool,
+
+        /// Number of context lines around each change
+        #[arg(short = 'U', long = "unified", default_value_t = 3)]
+        context: usize,
+
+        /// Up to two commits to compare
+        commits: Vec<String>,
+    },
+
+    /// Show a commit and the changes it introduced
+    Show {
+        /// The commit to show
+        #[arg(default_value = "HEAD")]
+        commit: String,
+
+        /// Number of context lines around each change
+        #[arg(short = 'U', long = "unified", default_value_t = 3)]
+        context: usize,
+    },
+
+    /// Switch branches
+    Switch {
+        /// Create the branch before switching to it
+        #[arg(short = 'c', long)]
+        create: bool,
+
+        /// The branch to switch to
+        branch: String,
+
+        /// Where the new branch starts when using -c (defaults to HEAD)
+        start_point: Option<String>,
+    },
+
+    /// Join another branch into the current one
+    Merge {
+        /// The branch or commit to merge
+        #[arg(required_unless_present = "abort")]
+        target: Option<String>,
+
+        /// Abort a conflicted merge and restore the pre-merge state
+        #[arg(long, conflicts_with = "target")]
+        abort: bool,
+
+        /// Merge a branch that shares no history with HEAD
+        #[arg(long)]
+        allow_unrelated_histories: bool,
+    },
+
+    /// Apply the changes introduced by an existing commit
+    CherryPick {
+        /// The commit to replay on top of HEAD
+        #[arg(required_unless_present_any = ["continue_", "abort"])]
+        commit: Option<String>,
+
+        /// Commit the resolved conflicts of a stopped cherry-pick
+        #[arg(long = "continue", conflicts_with_all = ["commit", "abort"])]
+        continue_: bool,
+
+        /// Give up a stopped cherry-pick and go back to HEAD
+        #[arg(long, conflicts_with = "commit")]
+        abort: bool,
+    },
+
+    /// Replay the commits of the current branch on top of another base
+    Rebase {
+        /// The branch or commit to rebase onto
+        #[arg(required_unless_present = "action", conflicts_with = "action")]
+        upstream: Option<String>,
+
+        /// Commit the resolved conflicts and carry on
+        #[arg(long = "continue", group = "action")]
+        continue_: bool,
+
+        /// Drop the commit the rebase stopped at and carry on
+        #[arg(long, group = "action")]
+        skip: bool,
+
+        /// Go back to the branch as it was before the rebase
+        #[arg(long, group = "action")]
+        abort: bool,
+    },
+
+    /// Verify the connectivity and validity of the objects in the repository
+    Fsck,
+
+    /// Pack reachable objects and prune unreachable ones
+    Gc,
+
+    /// Clone a repository into a new directory
+    Clone {
+        /// Path or file:// URL of the repository to clone
+        url: String,
+
+        /// The directory to clone into (defaults to the repository's name)
+        directory: Option<String>,
+    },
+
+    /// Download objects and refs from another repository
+    Fetch {
+        /// The remote to fetch from
+        #[arg(default_value = "origin")]
+        remote: String,
+    },
+
+    /// Update a remote branch along with the objects it needs
+    Push {
+        /// The remote to push to
+        #[arg(default_value = "origin")]
+        remote: String,
+
+        /// The branch to push (defaults to the current one)
+        branch: Option<String>,
+
+        /// Overwrite the remote branch even if it is not an ancestor of ours
+        #[arg(short = 'f', long)]
+        force: bool,
+    },
+
+    /// Get and set repository or global options
+    Config {
+        /// Use ~/.ritconfig instead of the repository's .rit/config
+        #[arg(long)]
+        global: bool,
+
+        /// List every setting
+        #[arg(short = 'l', long, conflicts_with_all = ["key", "unset"])]
+        list: bool,
+
+        /// Remove the key
+        #[arg(long, conflicts_with = "value")]
+        unset: bool,
+
+        /// The setting, e.g. user.name
+        #[arg(required_unless_present = "list")]
+        key: Option<String>,
+
+        /// The new value (prints the current one if not given)
+        value: Option<String>,
+    },
+
+    /// Serve objects to a fetch or clone (run by the client, not by hand)
+    #[command(hide = true)]
+    UploadPack {
+        directory: String,
+    },
+
+    /// Receive objects from a push (run by the client, not by hand)
+    #[command(hide = true)]
+    ReceivePack {
+        directory: String,
+    },
+
+}
+
+#[derive(Subcommand, Debug)]
+enum StashAction {
+    /// Stash the changes in the index and working tree (the default)
+    #[command(alias = "save")]
+    Push {
+        /// Describe the stash instead of using the HEAD commit's subject
+        #[arg(short = 'm', long)]
+        message: Option<String>,
+
+        /// Stash untracked files too
+        #[arg(short = 'u', long)]
+        include_untracked: bool,
+    },
+
+    /// List the stashes, newest first
+    List,
+
+    /// Show what a stash changes (stash@{0} by default)
+    Show {
+        stash: Option<String>,
+
+        /// Show the full diff instead of a diffstat
+        #[arg(short = 'p', long)]
+        patch: bool,
+    },
+
+    /// Apply a stash on top of the working tree
+    Apply { stash: Option<String> },
+
+    /// Apply a stash and drop it
+    Pop { stash: Option<String> },
+
+    /// Remove a stash from the list
+    Drop { stash: Option<String> },
 }
 
 fn main() -> Result<()> {
     let args = Cli::parse();
+    if let Some(dir) = args.git_dir {
+        commands::set_rit_dir(dir)?;
+    }
+
+    // Never touch a repository whose objects are named in a way rit doesn't know
+    objects::ObjectFormat::configured()?;
 
     match args.command {
-        Commands::Init => {
-            commands::init()?;
+        Commands::Init { object_format } => {
+            commands::init(object_format.as_deref())?;
         }
         Commands::HashObject { file, write } => {
             let hash = commands::hash_object(&file, write)?;
@@ -103,28 +500,159 @@ fn main() -> Result<()> {
             commands::cat_file(&object_hash, pretty_print)?;
         }
         Commands::WriteTree => {
-            let tree_hash = commands::write_tree(".")?;
+            let tree_hash = commands::write_tree()?;
             println!("{}", tree_hash);
         }
         Commands::CommitTree { tree_hash, parent_hash, message } => {
-        let commit_hash = commands::commit_tree(&tree_hash, parent_hash.as_deref(), &message)?;
+        let repo = Repository::current();
+        let tree_hash = revision::resolve_tree(repo, &tree_hash)?;
+        let parent_hash = parent_hash
+            .iter()
+            .map(|parent| revision::resolve_commit(repo, parent))
+            .collect::<Result<Vec<_>>>()?;
+        let commit_hash = commands::commit_tree(&tree_hash, &parent_hash, &message)?;
         println!("{}", commit_hash);
     }
-        Commands::UpdateRef { ref_name, oid } => {
-        commands::update_ref(&ref_name, &oid)?;
+        Commands::UpdateRef { ref_name, oid, message } => {
+        commands::update_ref(&ref_name, &revision::resolve(Repository::current(), &oid)?, &message)?;
     }
-        Commands::Add { file } => {
-        commands::add(&file)?;
+        Commands::Add { file, force } => {
+        commands::add(&file, force)?;
     }
         Commands::Commit { message } => {
         commands::commit(&message)?;
     }
-        Commands::Log { oid } => {
-        commands::log(&oid)?;
-    }
+        Commands::Log { revisions, oneline, graph, max_count, author, since, until, format, paths } => {
+            log::log(&log::Options { revisions, oneline, graph, max_count, author, since, until, format, paths })?;
+        }
+        Commands::Blame { ranges, porcelain, mut args } => {
+            let path = args.pop().unwrap();
+            blame::show(&blame::Options { revision: args.pop(), path, ranges, porcelain })?;
+        }
+        Commands::Reflog { ref_name } => {
+            reflog::show(Repository::current(), &ref_name)?;
+        }
         Commands::Checkout { target } => {
         commands::checkout(&target)?;
     }
+        Commands::Branch { name, start_point, delete, force_delete, rename, force_rename } => {
+            if delete || force_delete {
+                let name = name.ok_or_else(|| anyhow::anyhow!("branch name required"))?;
+                branch::delete(&name, force_delete)?;
+            } else if rename || force_rename {
+                let name = name.ok_or_else(|| anyhow::anyhow!("branch name required"))?;
+                match start_point {
+                    Some(new_name) => branch::rename(Some(&name), &new_name, force_rename)?,
+                    None => branch::rename(None, &name, force_rename)?,
+                }
+            } else if let Some(name) = name {
+                branch::create(&name, start_point.as_deref())?;
+            } else {
+                branch::list()?;
+            }
+        }
+        Commands::Tag { name, object, annotate: _, message, delete, force } => {
+            if delete {
+                let name = name.ok_or_else(|| anyhow::anyhow!("tag name required"))?;
+                tag::delete(&name)?;
+            } else if let Some(name) = name {
+                tag::create(&name, object.as_deref(), message.as_deref(), force)?;
+            } else {
+                tag::list()?;
+            }
+        }
+        Commands::Stash { action, message, include_untracked } => match action.unwrap_or(StashAction::Push { message, include_untracked }) {
+            StashAction::Push { message, include_untracked } => stash::push(message.as_deref(), include_untracked)?,
+            StashAction::List => stash::list()?,
+            StashAction::Show { stash, patch } => stash::show(stash.as_deref(), patch)?,
+            StashAction::Apply { stash } => {
+                stash::apply(stash.as_deref())?;
+            }
+            StashAction::Pop { stash } => stash::pop(stash.as_deref())?,
+            StashAction::Drop { stash } => stash::drop(stash.as_deref())?,
+        },
+        Commands::Reset { commit, soft, mixed: _, hard } => {
+            let mode = if soft {
+                reset::Mode::Soft
+            } else if hard {
+                reset::Mode::Hard
+            } else {
+                reset::Mode::Mixed
+            };
+            reset::reset(&commit, mode)?;
+        }
+        Commands::Restore { paths, staged, source } => {
+            reset::restore(&paths, staged, source.as_deref())?;
+        }
+        Commands::Rm { paths, cached, recursive, force } => {
+            reset::rm(&paths, cached, recursive, force)?;
+        }
+        Commands::Status { short } => {
+            status::status(short)?;
+        }
+        Commands::Diff { cached, context, commits } => {
+            diff::diff_command(&commits, cached, context)?;
+        }
+        Commands::Show { commit, context } => {
+            diff::show(&commit, context)?;
+        }
+        Commands::Switch { create, branch, start_point } => {
+            if start_point.is_some() && !create {
+                anyhow::bail!("a start point can only be given together with -c");
+            }
+            branch::switch(&branch, create, start_point.as_deref())?;
+        }
+        Commands::Merge { target, abort, allow_unrelated_histories } => {
+            if abort {
+                merge::abort()?;
+            } else if let Some(target) = target {
+                merge::merge(&target, allow_unrelated_histories)?;
+            }
+        }
+        Commands::CherryPick { commit, continue_, abort } => {
+            if continue_ {
+                rebase::cherry_pick_continue()?;
+            } else if abort {
+                rebase::cherry_pick_abort()?;
+            } else if let Some(commit) = commit {
+                rebase::cherry_pick(&commit)?;
+            }
+        }
+        Commands::Rebase { upstream, continue_, skip, abort } => {
+            if continue_ {
+                rebase::rebase_continue()?;
+            } else if skip {
+                rebase::rebase_skip()?;
+            } else if abort {
+                rebase::rebase_abort()?;
+            } else if let Some(upstream) = upstream {
+                rebase::rebase(&upstream)?;
+            }
+        }
+        Commands::Fsck => {
+            fsck::fsck()?;
+        }
+        Commands::Gc => {
+            gc::gc()?;
+        }
+        Commands::Clone { url, directory } => {
+            remote::clone(&url, directory.as_deref())?;
+        }
+        Commands::Fetch { remote } => {
+            remote::fetch(&remote)?;
+        }
+        Commands::Push { remote, branch, force } => {
+            remote::push(&remote, branch.as_deref(), force)?;
+        }
+        Commands::Config { global, list, unset, key, value } => {
+            config::config(global, key.as_deref(), value.as_deref(), unset, list)?;
+        }
+        Commands::UploadPack { directory } => {
+            transport::upload_pack(&directory)?;
+        }
+        Commands::ReceivePack { directory } => {
+            transport::receive_pack(&directory)?;
+        }
     }
     Ok(())
 }
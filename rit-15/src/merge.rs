@@ -0,0 +1,446 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::commands::{self, rit_dir};
+use crate::diff::{self, Edit};
+use crate::index::{self, Entry, Index, Unmerged};
+use crate::objects::{self, Commit, ObjectDatabase};
+use crate::refs;
+use crate::repository::Repository;
+use crate::revision;
+use crate::status;
+
+/// Result of a line-level three-way merge
+pub struct MergedText {
+    pub content: Vec<u8>,
+    pub conflicts: usize,
+}
+
+fn push_lines(out: &mut Vec<u8>, lines: &[&[u8]]) {
+    for line in lines {
+        out.extend_from_slice(line);
+    }
+}
+
+fn push_marker(out: &mut Vec<u8>, marker: &str) {
+    // A side that ends without a newline still needs the marker on its own line
+    if !out.is_empty() && !out.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+    out.extend_from_slice(marker.as_bytes());
+    out.push(b'\n');
+}
+
+/// Maps each base line kept unchanged in `other` to its line number there
+fn matches(base: &[&[u8]], other: &[&[u8]]) -> HashMap<usize, usize> {
+    diff::diff(base, other)
+        .into_iter()
+        .filter_map(|edit| match edit {
+            Edit::Equal(b, o) => Some((b, o)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// diff3-style merge: regions changed on only one side are taken from that side,
+/// regions both sides changed differently become conflicts wrapped in markers
+pub fn merge3(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    ours_label: &str,
+    theirs_label: &str,
+) -> MergedText {
+    let base_lines = diff::split_lines(base);
+    let our_lines = diff::split_lines(ours);
+    let their_lines = diff::split_lines(theirs);
+
+    let ours_match = matches(&base_lines, &our_lines);
+    let theirs_match = matches(&base_lines, &their_lines);
+
+    // Base lines untouched by both sides anchor the merge
+    let stable: Vec<usize> = (0..base_lines.len())
+        .filter(|i| ours_match.contains_key(i) && theirs_match.contains_key(i))
+        .collect();
+
+    let mut out = Vec::new();
+    let mut conflicts = 0;
+    let (mut b, mut o, mut t) = (0, 0, 0);
+
+    let mut resolve = |base_chunk: &[&[u8]],
+                       our_chunk: &[&[u8]],
+                       their_chunk: &[&[u8]],
+                       out: &mut Vec<u8>| {
+        if our_chunk == their_chunk || their_chunk == base_chunk {
+            push_lines(out, our_chunk);
+        } else if our_chunk == base_chunk {
+            push_lines(out, their_chunk);
+        } else {
+            conflicts += 1;
+            push_marker(out, &format!("<<<<<<< {}", ours_label));
+            push_lines(out, our_chunk);
+            push_marker(out, "=======");
+            push_lines(out, their_chunk);
+            push_marker(out, &format!(">>>>>>> {}", theirs_label));
+        }
+    };
+
+    for &anchor in &stable {
+        let (next_o, next_t) = (ours_match[&anchor], theirs_match[&anchor]);
+
+        if anchor > b || next_o > o || next_t > t {
+            resolve(
+                &base_lines[b..anchor],
+                &our_lines[o..next_o],
+                &their_lines[t..next_t],
+                &mut out,
+            );
+        }
+
+        out.extend_from_slice(base_lines[anchor]);
+        b = anchor + 1;
+        o = next_o + 1;
+        t = next_t + 1;
+    }
+
+    if b < base_lines.len() || o < our_lines.len() || t < their_lines.len() {
+        resolve(&base_lines[b..], &our_lines[o..], &their_lines[t..], &mut out);
+    }
+
+    MergedText {
+        content: out,
+        conflicts,
+    }
+}
+
+/// Outcome of merging three snapshots of the repository
+pub struct TreeMerge {
+    /// What the index should hold afterwards. Conflicted paths keep our version (or theirs
+    /// if we don't have one) until the user resolves them.
+    pub index: Index,
+    /// Content with conflict markers to leave in the working tree
+    pub conflict_files: BTreeMap<String, Vec<u8>>,
+    /// Human readable conflict descriptions, one per path
+    pub conflicts: Vec<(String, &'static str)>,
+    /// The base, our and their version of every conflicted path, for the index
+    pub unmerged: Unmerged,
+}
+
+pub fn merge_trees(
+    base: &Index,
+    ours: &Index,
+    theirs: &Index,
+    ours_label: &str,
+    theirs_label: &str,
+) -> Result<TreeMerge> {
+    let repo = Repository::current();
+    let paths: BTreeSet<&String> = base.keys().chain(ours.keys()).chain(theirs.keys()).collect();
+
+    let mut result = TreeMerge {
+        index: Index::new(),
+        conflict_files: BTreeMap::new(),
+        conflicts: Vec::new(),
+        unmerged: Unmerged::new(),
+    };
+
+    for path in paths {
+        let (b, o, t) = (base.get(path), ours.get(path), theirs.get(path));
+
+        // One side left the file alone, or both made the same change
+        let clean = if o == t || b == t {
+            Some(o)
+        } else if b == o {
+            Some(t)
+        } else {
+            None
+        };
+
+        if let Some(entry) = clean {
+            if let Some(entry) = entry {
+                result.index.insert(path.clone(), entry.clone());
+            }
+            continue;
+        }
+
+        let stages = [b.cloned(), o.cloned(), t.cloned()];
+        match (o, t) {
+            (Some(o), Some(t)) => {
+                let base_data = match b {
+                    Some(b) => objects::read_blob(repo, &b.hash)?,
+                    None => Vec::new(),
+                };
+                let our_data = objects::read_blob(repo, &o.hash)?;
+                let their_data = objects::read_blob(repo, &t.hash)?;
+
+                // Symlink targets and binary data can't be merged line by line
+                let unmergeable = o.mode == "120000"
+                    || t.mode == "120000"
+                    || [&base_data, &our_data, &their_data].iter().any(|data| diff::is_binary(data));
+                if unmergeable {
+                    result.index.insert(path.clone(), o.clone());
+                    result.conflicts.push((path.clone(), "binary"));
+                    result.unmerged.insert(path.clone(), stages);
+                    continue;
+                }
+
+                // An executable bit flipped on their side only carries over
+                let mode = match b {
+                    Some(b) if b.mode == o.mode => t.mode.clone(),
+                    _ => o.mode.clone(),
+                };
+
+                let merged = merge3(&base_data, &our_data, &their_data, ours_label, theirs_label);
+                if merged.conflicts == 0 {
+                    let hash = repo.objects().write_raw("blob", &merged.content)?;
+                    result.index.insert(path.clone(), Entry { mode, hash });
+                } else {
+                    result.index.insert(path.clone(), o.clone());
+                    result.conflict_files.insert(path.clone(), merged.content);
+                    let kind = if b.is_some() { "content" } else { "add/add" };
+                    result.conflicts.push((path.clone(), kind));
+                    result.unmerged.insert(path.clone(), stages);
+                }
+            }
+            // Modified on one side, deleted on the other: keep the surviving version around
+            (Some(o), None) => {
+                result.index.insert(path.clone(), o.clone());
+                result.conflicts.push((path.clone(), "modify/delete"));
+                result.unmerged.insert(path.clone(), stages);
+            }
+            (None, Some(t)) => {
+                result.conflict_files.insert(path.clone(), objects::read_blob(repo, &t.hash)?);
+                result.conflicts.push((path.clone(), "modify/delete"));
+                result.unmerged.insert(path.clone(), stages);
+            }
+            (None, None) => {}
+        }
+    }
+
+    Ok(result)
+}
+
+fn state_path(name: &str) -> PathBuf {
+    rit_dir().join(name)
+}
+
+/// The commit being merged in, while a conflicted merge is waiting to be committed
+pub fn merge_head() -> Result<Option<String>> {
+    let path = state_path("MERGE_HEAD");
+    if !Path::new(&path).exists() {
+        return Ok(None);
+    }
+    Ok(Some(fs::read_to_string(path)?.trim().to_string()))
+}
+
+/// Paths that still have unresolved conflicts, i.e. unmerged paths in the index
+pub fn read_conflicts() -> Result<Vec<String>> {
+    Ok(index::read_unmerged()?.into_keys().collect())
+}
+
+/// Records the paths that still need resolving as stages 1 to 3 in the index, like git
+pub fn write_conflicts(conflicts: &Unmerged) -> Result<()> {
+    index::write_unmerged(conflicts)
+}
+
+/// Called when a path is staged; a staged conflict counts as resolved
+pub fn mark_resolved(path: &str) -> Result<()> {
+    index::resolve(path)?;
+    Ok(())
+}
+
+/// Removes MERGE_HEAD and friends once the merge or cherry-pick is committed or aborted,
+/// along with any conflicts left in the index
+pub fn clear_state() -> Result<()> {
+    for name in ["MERGE_HEAD", "MERGE_MSG", "CHERRY_PICK_HEAD"] {
+        let path = state_path(name);
+        if Path::new(&path).exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    index::clear_unmerged()
+}
+
+/// `rit merge <branch>`: fast-forward when possible, otherwise a three-way merge.
+/// Histories without a common ancestor are only merged when `allow_unrelated` is set.
+pub fn merge(target: &str, allow_unrelated: bool) -> Result<()> {
+    let repo = Repository::current();
+    if merge_head()?.is_some() {
+        anyhow::bail!(
+            "You have not concluded your merge (MERGE_HEAD exists).\n\
+             Please commit your changes or run 'rit merge --abort'."
+        );
+    }
+
+    let head = refs::head_commit(repo)?
+        .ok_or_else(|| anyhow::anyhow!("Cannot merge into a branch with no commits yet"))?;
+    let theirs = revision::resolve_commit(repo, target)?;
+
+    if !status::compute()?.is_clean() {
+        anyhow::bail!(
+            "Your local changes would be overwritten by merge.\n\
+             Please commit them before you merge."
+        );
+    }
+
+    let base = refs::merge_base(repo, &head, &theirs)?;
+    if base.is_none() && !allow_unrelated {
+        anyhow::bail!("refusing to merge unrelated histories");
+    }
+
+    if base.as_deref() == Some(theirs.as_str()) {
+        println!("Already up to date.");
+        return Ok(());
+    }
+
+    let our_files = commands::flatten_tree(&Commit::read(repo, &head)?.tree)?;
+    let their_files = commands::flatten_tree(&Commit::read(repo, &theirs)?.tree)?;
+
+    if base.as_deref() == Some(head.as_str()) {
+        check_untracked(&our_files, &their_files)?;
+
+        commands::update_worktree(&our_files, &their_files)?;
+        index::write_index(&their_files)?;
+        refs::advance_head(repo, &theirs, &format!("merge {}: Fast-forward", target))?;
+
+        println!("Updating {}..{}", &head[..7], &theirs[..7]);
+        println!("Fast-forward");
+        return Ok(());
+    }
+
+    let base_files = match &base {
+        Some(base) => commands::flatten_tree(&Commit::read(repo, base)?.tree)?,
+        None => Index::new(),
+    };
+
+    let result = merge_trees(&base_files, &our_files, &their_files, "HEAD", target)?;
+    check_untracked(&our_files, &result.index)?;
+
+    commands::update_worktree(&our_files, &result.index)?;
+    for (path, content) in &result.conflict_files {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+    }
+    index::write_index(&result.index)?;
+
+    let message = format!("Merge branch '{}'", target);
+
+    if result.conflicts.is_empty() {
+        let tree_hash = commands::write_tree()?;
+        let commit_hash = commands::commit_tree(&tree_hash, &[head, theirs], &message)?;
+        let reason = format!("merge {}: Merge made by the 'three-way' strategy.", target);
+        refs::advance_head(repo, &commit_hash, &reason)?;
+
+        println!("Merge made by the 'three-way' strategy.");
+        println!("[{}] {}", &commit_hash[..7], message);
+        return Ok(());
+    }
+
+    fs::write(state_path("MERGE_HEAD"), format!("{}\n", theirs))?;
+    fs::write(state_path("MERGE_MSG"), format!("{}\n", message))?;
+
+    write_conflicts(&result.unmerged)?;
+
+    for (path, kind) in &result.conflicts {
+        println!("CONFLICT ({}): Merge conflict in {}", kind, path);
+    }
+    println!("Automatic merge failed; fix conflicts and then commit the result.");
+    Ok(())
+}
+
+/// Refuses to overwrite files that rit doesn't know about
+pub fn check_untracked(ours: &Index, merged: &Index) -> Result<()> {
+    for path in merged.keys() {
+        if !ours.contains_key(path) && Path::new(path).exists() {
+            anyhow::bail!(
+                "The following untracked working tree file would be overwritten by merge:\n\t{}",
+                path
+            );
+        }
+    }
+    Ok(())
+}
+
+/// `rit merge --abort`: throw away the conflicted merge and go back to HEAD
+pub fn abort() -> Result<()> {
+    if merge_head()?.is_none() {
+        anyhow::bail!("There is no merge to abort (MERGE_HEAD missing).");
+    }
+    discard_changes()
+}
+
+/// Resets the index and the tracked files to HEAD and forgets any conflicts, leaving
+/// untracked files alone
+pub fn discard_changes() -> Result<()> {
+    let head_files = status::head_files()?;
+    let index = index::read_index()?;
+    let conflicts = read_conflicts()?;
+
+    // Only undo what the merge or pick touched, untracked files are left alone
+    let touched: Index = status::working_files()?
+        .into_iter()
+        .filter(|(path, _)| {
+            head_files.contains_key(path) || index.contains_key(path) || conflicts.contains(path)
+        })
+        .collect();
+
+    commands::update_worktree(&touched, &head_files)?;
+    clear_state()?;
+    index::write_index(&head_files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(base: &str, ours: &str, theirs: &str) -> (String, usize) {
+        let result = merge3(base.as_bytes(), ours.as_bytes(), theirs.as_bytes(), "ours", "theirs");
+        (String::from_utf8(result.content).unwrap(), result.conflicts)
+    }
+
+    #[test]
+    fn test_non_overlapping_changes_merge_cleanly() {
+        let base = "a\nb\nc\nd\ne\n";
+        let ours = "A\nb\nc\nd\ne\n";
+        let theirs = "a\nb\nc\nd\nE\n";
+
+        assert_eq!(merge(base, ours, theirs), ("A\nb\nc\nd\nE\n".to_string(), 0));
+    }
+
+    #[test]
+    fn test_identical_changes_and_insertions() {
+        let base = "a\nb\n";
+        assert_eq!(merge(base, "a\nx\nb\n", "a\nx\nb\n"), ("a\nx\nb\n".to_string(), 0));
+        assert_eq!(merge(base, "start\na\nb\n", "a\nb\nend\n"), ("start\na\nb\nend\n".to_string(), 0));
+        assert_eq!(merge(base, "a\n", base), ("a\n".to_string(), 0));
+    }
+
+    #[test]
+    fn test_conflicting_changes_get_markers() {
+        let (content, conflicts) = merge("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n");
+
+        assert_eq!(conflicts, 1);
+        assert_eq!(
+            content,
+            "a\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\nc\n"
+        );
+    }
+
+    #[test]
+    fn test_conflict_without_trailing_newline() {
+        let (content, conflicts) = merge("x", "y", "z");
+
+        assert_eq!(conflicts, 1);
+        assert_eq!(content, "<<<<<<< ours\ny\n=======\nz\n>>>>>>> theirs\n");
+    }
+
+    #[test]
+    fn test_add_add_with_empty_base() {
+        let (content, conflicts) = merge("", "same\nmine\n", "same\nyours\n");
+        assert_eq!(conflicts, 1);
+        assert!(content.starts_with("<<<<<<< ours\n"));
+    }
+}
@@ -0,0 +1,920 @@
+use anyhow::Result;
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{self, ConfigFile};
+use crate::pack;
+use crate::repository::Repository;
+
+/// The hash function a repository names its objects with, chosen once at `rit init`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectFormat {
+    Sha1,
+    Sha256,
+}
+
+impl ObjectFormat {
+    pub fn from_name(name: &str) -> Result<ObjectFormat> {
+        match name.to_lowercase().as_str() {
+            "sha1" => Ok(ObjectFormat::Sha1),
+            "sha256" => Ok(ObjectFormat::Sha256),
+            _ => anyhow::bail!("unknown object format '{}'", name),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ObjectFormat::Sha1 => "sha1",
+            ObjectFormat::Sha256 => "sha256",
+        }
+    }
+
+    /// Bytes in a binary object name, as stored in trees, the index and packs
+    pub fn raw_len(self) -> usize {
+        match self {
+            ObjectFormat::Sha1 => 20,
+            ObjectFormat::Sha256 => 32,
+        }
+    }
+
+    pub fn hex_len(self) -> usize {
+        self.raw_len() * 2
+    }
+
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            ObjectFormat::Sha1 => Sha1::digest(data).to_vec(),
+            ObjectFormat::Sha256 => Sha256::digest(data).to_vec(),
+        }
+    }
+
+    /// Whether `hash` is a full object name in this format, in lowercase hex
+    pub fn is_hash(self, hash: &str) -> bool {
+        hash.len() == self.hex_len() && hash.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    }
+
+    /// The format a repository's config asks for with `extensions.objectFormat`, SHA-1 if unset
+    pub fn from_config(config: &ConfigFile) -> Result<ObjectFormat> {
+        match config.get("extensions.objectformat")? {
+            Some(name) => ObjectFormat::from_name(&name),
+            None => Ok(ObjectFormat::Sha1),
+        }
+    }
+
+    /// The format of the repository in the current directory
+    pub fn configured() -> Result<ObjectFormat> {
+        ObjectFormat::from_config(&config::Level::Repository.load()?)
+    }
+
+    /// The object name meaning "no object", e.g. a ref being created or deleted
+    pub fn zero_id(self) -> String {
+        "0".repeat(self.hex_len())
+    }
+
+    /// The name `data` would have as an object of the given type, without storing it
+    pub fn hash_object(self, kind: &str, data: &[u8]) -> String {
+        hex::encode(self.digest(&object_store(kind, data)))
+    }
+}
+
+/// Something objects can be read from and stored in. `ObjectStore` keeps them on disk the
+/// way git does; other implementations can keep them anywhere.
+pub trait ObjectDatabase {
+    /// How objects are named
+    fn format(&self) -> ObjectFormat;
+
+    /// Reads an object and splits it into its type ("blob", "tree", ...) and raw body
+    fn read_raw(&self, hash: &str) -> Result<(String, Vec<u8>)>;
+
+    /// Stores an object of the given type and returns its name
+    fn write_raw(&self, kind: &str, data: &[u8]) -> Result<String>;
+
+    fn contains(&self, hash: &str) -> bool;
+
+    /// Reads and parses an object
+    fn read(&self, hash: &str) -> Result<Object> {
+        let (kind, body) = self.read_raw(hash)?;
+        Object::parse(&kind, &body, self.format())
+            .map_err(|err| anyhow::anyhow!("Invalid {} {}: {}", kind, hash, err))
+    }
+
+    fn write(&self, object: &Object) -> Result<String> {
+        self.write_raw(object.kind(), &object.serialize()?)
+    }
+}
+
+/// A repository's `objects` directory: zlib compressed loose objects plus packs
+pub struct ObjectStore {
+    dir: PathBuf,
+    format: ObjectFormat,
+    // The pack indexes, read on first use and kept until `forget_packs`
+    packs: Mutex<Option<Arc<Vec<pack::Pack>>>>,
+}
+
+impl ObjectStore {
+    pub fn new(dir: impl Into<PathBuf>, format: ObjectFormat) -> ObjectStore {
+        ObjectStore { dir: dir.into(), format, packs: Mutex::new(None) }
+    }
+
+    fn loose_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(&hash[..2]).join(&hash[2..])
+    }
+
+    fn packs(&self) -> Result<Arc<Vec<pack::Pack>>> {
+        let mut cached = self.packs.lock().unwrap();
+        if let Some(packs) = &*cached {
+            return Ok(packs.clone());
+        }
+        let packs = Arc::new(pack::packs_in(&self.dir.join("pack"), self.format)?);
+        *cached = Some(packs.clone());
+        Ok(packs)
+    }
+
+    /// Drops the cached pack list, so packs added or removed since are seen
+    pub fn forget_packs(&self) {
+        *self.packs.lock().unwrap() = None;
+    }
+
+    /// Every stored object whose name starts with `prefix`, loose or packed, sorted
+    pub fn find(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut found = Vec::new();
+        if prefix.len() < 2 || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Ok(found);
+        }
+        let prefix = prefix.to_lowercase();
+
+        let dir = self.dir.join(&prefix[..2]);
+        if dir.is_dir() {
+            for entry in fs::read_dir(dir)? {
+                let hash = format!("{}{}", &prefix[..2], entry?.file_name().to_string_lossy());
+                if hash.starts_with(&prefix) && self.format.is_hash(&hash) {
+                    found.push(hash);
+                }
+            }
+        }
+        for pack in self.packs()?.iter() {
+            found.extend(pack.hashes().into_iter().filter(|hash| hash.starts_with(&prefix)));
+        }
+
+        found.sort();
+        found.dedup();
+        Ok(found)
+    }
+}
+
+impl ObjectDatabase for ObjectStore {
+    fn format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    fn read_raw(&self, hash: &str) -> Result<(String, Vec<u8>)> {
+        if !self.format.is_hash(hash) {
+            anyhow::bail!("Invalid object name {}", hash);
+        }
+        let file = match fs::File::open(self.loose_path(hash)) {
+            Ok(file) => file,
+            Err(_) => {
+                for pack in self.packs()?.iter() {
+                    if let Some(object) = pack.read(hash)? {
+                        return Ok(object);
+                    }
+                }
+                // Another process may have added a pack since the list was read
+                self.forget_packs();
+                for pack in self.packs()?.iter() {
+                    if let Some(object) = pack.read(hash)? {
+                        return Ok(object);
+                    }
+                }
+                anyhow::bail!("Object {} not found", hash);
+            }
+        };
+
+        let mut decoder = ZlibDecoder::new(file);
+        let mut contents = Vec::new();
+        decoder
+            .read_to_end(&mut contents)
+            .map_err(|err| anyhow::anyhow!("Object {} is corrupt: {}", hash, err))?;
+
+        // format <type> <size>\0<content>
+        let null_index = contents
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| anyhow::anyhow!("Invalid object format in {}", hash))?;
+        let header = std::str::from_utf8(&contents[..null_index])?;
+        let (kind, size) = header
+            .split_once(' ')
+            .ok_or_else(|| anyhow::anyhow!("Invalid object header in {}", hash))?;
+
+        let body = contents[null_index + 1..].to_vec();
+        if size.parse::<usize>().ok() != Some(body.len()) {
+            anyhow::bail!("Object {} is corrupt: header says {} bytes, found {}", hash, size, body.len());
+        }
+        Ok((kind.to_string(), body))
+    }
+
+    fn write_raw(&self, kind: &str, data: &[u8]) -> Result<String> {
+        let store = object_store(kind, data);
+        let hash = hex::encode(self.format.digest(&store));
+
+        // Objects are immutable, a stored one already has this exact content
+        if !self.contains(&hash) {
+            let object_path = self.loose_path(&hash);
+            fs::create_dir_all(object_path.parent().unwrap())?;
+            let file = fs::File::create(&object_path)?;
+            let mut encoder = ZlibEncoder::new(file, Compression::default());
+            encoder.write_all(&store)?;
+            encoder.finish()?;
+        }
+
+        Ok(hash)
+    }
+
+    fn contains(&self, hash: &str) -> bool {
+        self.format.is_hash(hash)
+            && (self.loose_path(hash).exists()
+                || self.packs().is_ok_and(|packs| packs.iter().any(|pack| pack.contains(hash))))
+    }
+}
+
+fn object_store(kind: &str, data: &[u8]) -> Vec<u8> {
+    let mut store = format!("{} {}\0", kind, data.len()).into_bytes();
+    store.extend_from_slice(data);
+    store
+}
+
+/// Reads a blob's bytes, failing if the object is some other type
+pub fn read_blob(repo: &Repository, hash: &str) -> Result<Vec<u8>> {
+    let (kind, body) = repo.objects().read_raw(hash)?;
+    if kind != "blob" {
+        anyhow::bail!("Object {} is a {}, not a blob", hash, kind);
+    }
+    Ok(body)
+}
+
+/// Identity used when neither the environment nor the config names anyone
+const DEFAULT_NAME: &str = "Rit User";
+const DEFAULT_EMAIL: &str = "rit@example.com";
+
+/// An author or committer line: "Name <email> 1700000000 +0100"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    pub timestamp: i64,
+    pub timezone: String,
+}
+
+impl Signature {
+    pub fn parse(line: &str) -> Result<Signature> {
+        let invalid = || anyhow::anyhow!("Invalid signature '{}'", line);
+
+        let (name, rest) = line.split_once(" <").ok_or_else(invalid)?;
+        let (email, rest) = rest.split_once("> ").ok_or_else(invalid)?;
+        let (timestamp, timezone) = rest.trim().split_once(' ').ok_or_else(invalid)?;
+
+        Ok(Signature {
+            name: name.to_string(),
+            email: email.to_string(),
+            timestamp: timestamp.parse().map_err(|_| invalid())?,
+            timezone: timezone.to_string(),
+        })
+    }
+
+    /// Who is acting as `role` ("author" or "committer") right now: `RIT_AUTHOR_NAME`,
+    /// `RIT_AUTHOR_EMAIL` and `RIT_AUTHOR_DATE` (or the committer ones), then git's
+    /// `GIT_AUTHOR_*` equivalents, win over the `user.name` and `user.email` config,
+    /// and the date defaults to now in local time
+    pub fn current(role: &str) -> Result<Signature> {
+        let env = |field: &str| {
+            let role = role.to_uppercase();
+            std::env::var(format!("RIT_{}_{}", role, field))
+                .or_else(|_| std::env::var(format!("GIT_{}_{}", role, field)))
+                .ok()
+        };
+
+        let name = match env("NAME") {
+            Some(name) => name,
+            None => config::get("user.name")?.unwrap_or_else(|| DEFAULT_NAME.to_string()),
+        };
+        let email = match env("EMAIL") {
+            Some(email) => email,
+            None => config::get("user.email")?.unwrap_or_else(|| DEFAULT_EMAIL.to_string()),
+        };
+        // Angle brackets and newlines would break the header line
+        if name.contains(['<', '>', '\n']) || email.contains(['<', '>', '\n']) {
+            anyhow::bail!("invalid {} identity '{} <{}>'", role, name, email);
+        }
+
+        let (timestamp, timezone) = match env("DATE") {
+            Some(date) => parse_date(&date)?,
+            None => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+                (now, format_offset(local_offset(now)))
+            }
+        };
+
+        Ok(Signature {
+            name,
+            email,
+            timestamp,
+            timezone,
+        })
+    }
+
+    /// Date in git's default format, in the signature's own timezone
+    pub fn date(&self) -> String {
+        format_date(self.timestamp, &self.timezone)
+    }
+}
+
+impl std::fmt::Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} <{}> {} {}", self.name, self.email, self.timestamp, self.timezone)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub tree: String,
+    pub parents: Vec<String>,
+    pub author: Signature,
+    pub committer: Signature,
+    /// Headers rit doesn't interpret (gpgsig, mergetag, encoding, ...), in order, with
+    /// continuation lines joined by newlines
+    pub extra_headers: Vec<(String, String)>,
+    /// The message as stored, which need not be UTF-8
+    pub message: Vec<u8>,
+}
+
+impl Commit {
+    pub fn read(repo: &Repository, hash: &str) -> Result<Commit> {
+        let (kind, body) = repo.objects().read_raw(hash)?;
+        if kind != "commit" {
+            anyhow::bail!("Object {} is a {}, not a commit", hash, kind);
+        }
+        Commit::parse(&body)
+    }
+
+    pub fn parse(body: &[u8]) -> Result<Commit> {
+        let split = body.windows(2).position(|pair| pair == b"\n\n");
+        let (headers, message) = match split {
+            Some(at) => (&body[..at], &body[at + 2..]),
+            None => (body, &[][..]),
+        };
+
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        let mut extra_headers: Vec<(String, String)> = Vec::new();
+
+        let headers = String::from_utf8_lossy(headers);
+        for line in headers.split('\n').filter(|line| !line.is_empty()) {
+            if let Some(rest) = line.strip_prefix(' ') {
+                // Continues the previous header, as in a multi-line gpgsig
+                let Some((_, value)) = extra_headers.last_mut() else {
+                    anyhow::bail!("Commit has a continuation line with no header");
+                };
+                value.push('\n');
+                value.push_str(rest);
+            } else if let Some(hash) = line.strip_prefix("tree ") {
+                tree = Some(hash.to_string());
+            } else if let Some(hash) = line.strip_prefix("parent ") {
+                parents.push(hash.to_string());
+            } else if let Some(sig) = line.strip_prefix("author ") {
+                author = Some(Signature::parse(sig)?);
+            } else if let Some(sig) = line.strip_prefix("committer ") {
+                committer = Some(Signature::parse(sig)?);
+            } else if let Some((key, value)) = line.split_once(' ') {
+                extra_headers.push((key.to_string(), value.to_string()));
+            } else {
+                anyhow::bail!("Malformed commit header '{}'", line);
+            }
+        }
+
+        Ok(Commit {
+            tree: tree.ok_or_else(|| anyhow::anyhow!("Commit has no tree"))?,
+            parents,
+            author: author.ok_or_else(|| anyhow::anyhow!("Commit has no author"))?,
+            committer: committer.ok_or_else(|| anyhow::anyhow!("Commit has no committer"))?,
+            extra_headers,
+            message: message.to_vec(),
+        })
+    }
+
+    /// The object body, in the header order git writes and expects
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = format!("tree {}\n", self.tree);
+        for parent in &self.parents {
+            out.push_str(&format!("parent {}\n", parent));
+        }
+        out.push_str(&format!("author {}\n", self.author));
+        out.push_str(&format!("committer {}\n", self.committer));
+        for (key, value) in &self.extra_headers {
+            out.push_str(&format!("{} {}\n", key, value.replace('\n', "\n ")));
+        }
+        out.push('\n');
+
+        let mut out = out.into_bytes();
+        out.extend_from_slice(&self.message);
+        out
+    }
+
+    /// The message for display, with any invalid UTF-8 replaced
+    pub fn message_text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.message)
+    }
+}
+
+/// An annotated tag: a named, signed pointer to another object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub object: String,
+    pub kind: String,
+    pub name: String,
+    /// Missing from some very old tags
+    pub tagger: Option<Signature>,
+    pub message: String,
+}
+
+impl Tag {
+    pub fn parse(body: &str) -> Result<Tag> {
+        let (headers, message) = body.split_once("\n\n").unwrap_or((body, ""));
+
+        let mut object = None;
+        let mut kind = None;
+        let mut name = None;
+        let mut tagger = None;
+
+        for line in headers.lines() {
+            if let Some(hash) = line.strip_prefix("object ") {
+                object = Some(hash.to_string());
+            } else if let Some(value) = line.strip_prefix("type ") {
+                kind = Some(value.to_string());
+            } else if let Some(value) = line.strip_prefix("tag ") {
+                name = Some(value.to_string());
+            } else if let Some(sig) = line.strip_prefix("tagger ") {
+                tagger = Some(Signature::parse(sig)?);
+            }
+        }
+
+        Ok(Tag {
+            object: object.ok_or_else(|| anyhow::anyhow!("Tag has no object"))?,
+            kind: kind.ok_or_else(|| anyhow::anyhow!("Tag has no type"))?,
+            name: name.ok_or_else(|| anyhow::anyhow!("Tag has no name"))?,
+            tagger,
+            message: message.to_string(),
+        })
+    }
+}
+
+/// The object body, in the header order git writes and expects
+impl std::fmt::Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "object {}", self.object)?;
+        writeln!(f, "type {}", self.kind)?;
+        writeln!(f, "tag {}", self.name)?;
+        if let Some(tagger) = &self.tagger {
+            writeln!(f, "tagger {}", tagger)?;
+        }
+        write!(f, "\n{}", self.message)
+    }
+}
+
+/// File contents, stored as is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blob {
+    pub data: Vec<u8>,
+}
+
+/// One entry of a tree: a file, symlink, submodule commit or subdirectory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub name: String,
+    pub mode: String,
+    pub hash: String,
+}
+
+impl TreeEntry {
+    /// The kind of object the entry points at, judging by its mode
+    pub fn kind(&self) -> &'static str {
+        match self.mode.as_str() {
+            "40000" => "tree",
+            "160000" => "commit",
+            _ => "blob",
+        }
+    }
+}
+
+/// A directory listing, sorted the way git sorts it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tree {
+    pub entries: Vec<TreeEntry>,
+}
+
+impl Tree {
+    /// Splits a tree object's body into its entries, failing on truncated or malformed ones
+    pub fn parse(body: &[u8], format: ObjectFormat) -> Result<Tree> {
+        let mut body = body;
+        let mut entries = Vec::new();
+
+        while !body.is_empty() {
+            // [mode] [space] [name] [NUL] [binary object name]
+            let null_idx = body
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| anyhow::anyhow!("entry without a name terminator"))?;
+            let mode_name = std::str::from_utf8(&body[..null_idx])?;
+            let (mode, name) = mode_name
+                .split_once(' ')
+                .ok_or_else(|| anyhow::anyhow!("entry '{}' has no mode", mode_name))?;
+            body = &body[null_idx + 1..];
+
+            if body.len() < format.raw_len() {
+                anyhow::bail!("entry '{}' is truncated", name);
+            }
+            let hash = hex::encode(&body[..format.raw_len()]);
+            body = &body[format.raw_len()..];
+
+            entries.push(TreeEntry {
+                name: name.to_string(),
+                mode: mode.to_string(),
+                hash,
+            });
+        }
+
+        Ok(Tree { entries })
+    }
+
+    /// Puts the entries in git's order: by name, directories as if they ended with '/'
+    pub fn sort(&mut self) {
+        self.entries.sort_by_key(|entry| {
+            let mut key = entry.name.clone().into_bytes();
+            if entry.mode == "40000" {
+                key.push(b'/');
+            }
+            key
+        });
+    }
+
+    /// The object body, with entries in their current order
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        for entry in &self.entries {
+            body.extend(format!("{} {}\0", entry.mode, entry.name).as_bytes());
+            body.extend(hex::decode(&entry.hash)?);
+        }
+        Ok(body)
+    }
+}
+
+/// One line per entry, as `git cat-file -p` and `git ls-tree` show trees
+impl std::fmt::Display for Tree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{:0>6} {} {}\t{}", entry.mode, entry.kind(), entry.hash, entry.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Any object, parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Object {
+    Blob(Blob),
+    Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
+}
+
+impl Object {
+    pub fn parse(kind: &str, body: &[u8], format: ObjectFormat) -> Result<Object> {
+        match kind {
+            "blob" => Ok(Object::Blob(Blob { data: body.to_vec() })),
+            "tree" => Ok(Object::Tree(Tree::parse(body, format)?)),
+            "commit" => Ok(Object::Commit(Commit::parse(body)?)),
+            "tag" => Ok(Object::Tag(Tag::parse(&String::from_utf8_lossy(body))?)),
+            _ => anyhow::bail!("unknown object type '{}'", kind),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Object::Blob(_) => "blob",
+            Object::Tree(_) => "tree",
+            Object::Commit(_) => "commit",
+            Object::Tag(_) => "tag",
+        }
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        match self {
+            Object::Blob(blob) => Ok(blob.data.clone()),
+            Object::Tree(tree) => tree.serialize(),
+            Object::Commit(commit) => Ok(commit.serialize()),
+            Object::Tag(tag) => Ok(tag.to_string().into_bytes()),
+        }
+    }
+}
+
+/// Parses a "+hhmm"/"-hhmm" offset into seconds east of UTC
+pub fn timezone_offset(timezone: &str) -> i64 {
+    let sign = if timezone.starts_with('-') { -1 } else { 1 };
+    let digits = timezone.trim_start_matches(['+', '-']);
+
+    match (digits.get(..2), digits.get(2..4)) {
+        (Some(hours), Some(minutes)) => {
+            let hours: i64 = hours.parse().unwrap_or(0);
+            let minutes: i64 = minutes.parse().unwrap_or(0);
+            sign * (hours * 3600 + minutes * 60)
+        }
+        _ => 0,
+    }
+}
+
+/// Formats a unix timestamp like git does: "Mon Oct 18 18:51:54 2026 +0000"
+pub fn format_date(timestamp: i64, timezone: &str) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let local = timestamp + timezone_offset(timezone);
+    let days = local.div_euclid(86400);
+    let seconds = local.rem_euclid(86400);
+
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{} {} {} {:02}:{:02}:{:02} {} {}",
+        DAYS[days.rem_euclid(7) as usize],
+        MONTHS[(month - 1) as usize],
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60,
+        year,
+        timezone
+    )
+}
+
+/// Converts days since 1970-01-01 into (year, month, day), Howard Hinnant's algorithm
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// Seconds east of UTC of the local timezone at `timestamp`, as the C library works it
+/// out from `TZ` or `/etc/localtime`
+pub fn local_offset(timestamp: i64) -> i64 {
+    let time = timestamp as libc::time_t;
+    // SAFETY: localtime_r only writes into the zeroed `tm` we hand it
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return 0;
+        }
+        tm.tm_gmtoff as i64
+    }
+}
+
+/// Formats seconds east of UTC the way commits record them: "+0100", "-0530"
+pub fn format_offset(offset: i64) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let minutes = offset.abs() / 60;
+    format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60)
+}
+
+/// Parses a date given in the environment (`RIT_AUTHOR_DATE`, `GIT_AUTHOR_DATE` and friends)
+/// into a timestamp and offset. Accepts git's raw "1700000000 +0100" (optionally with an
+/// "@") and ISO 8601 like "2024-01-15T12:00:00+01:00", or just "2024-01-15" for midnight.
+/// Without an offset, local time is meant.
+pub fn parse_date(text: &str) -> Result<(i64, String)> {
+    let invalid = || anyhow::anyhow!("invalid date format: {}", text);
+    let text = text.trim();
+
+    let raw = text.strip_prefix('@').unwrap_or(text);
+    let (seconds, zone) = raw.split_once(' ').unwrap_or((raw, ""));
+    if let Ok(timestamp) = seconds.parse::<i64>() {
+        let offset = match zone {
+            "" => local_offset(timestamp),
+            zone => parse_offset(zone).ok_or_else(invalid)?,
+        };
+        return Ok((timestamp, format_offset(offset)));
+    }
+
+    // "YYYY-MM-DD", then "T" or a space, "HH:MM:SS", then an optional zone
+    let (date, rest) = match text.find(['T', ' ']) {
+        Some(at) => (&text[..at], text[at + 1..].trim_start()),
+        None => (text, "00:00:00"),
+    };
+    let time_len = rest.find(|c: char| !c.is_ascii_digit() && c != ':').unwrap_or(rest.len());
+    let (time, zone) = rest.split_at(time_len);
+
+    let numbers = |text: &str, sep: char| -> Option<Vec<i64>> {
+        text.split(sep).map(|part| part.parse().ok()).collect()
+    };
+    let [year, month, day] = numbers(date, '-').ok_or_else(invalid)?[..] else {
+        return Err(invalid());
+    };
+    let [hour, minute, second] = numbers(time, ':').ok_or_else(invalid)?[..] else {
+        return Err(invalid());
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return Err(invalid());
+    }
+
+    let local = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    let offset = match zone.trim() {
+        "" => local_offset(local - local_offset(local)),
+        "Z" => 0,
+        zone => parse_offset(zone).ok_or_else(invalid)?,
+    };
+    Ok((local - offset, format_offset(offset)))
+}
+
+// "+0100", "-05:30" into seconds east of UTC
+fn parse_offset(zone: &str) -> Option<i64> {
+    let sign = match zone.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits = zone[1..].replace(':', "");
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Days since 1970-01-01 of a date, the inverse of `civil_from_days`
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commit() {
+        let body = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+                    parent aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\n\
+                    parent bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\n\
+                    author A U Thor <author@example.com> 1700000000 +0100\n\
+                    committer C O Mitter <committer@example.com> 1700000001 -0530\n\
+                    \n\
+                    Subject line\n\nBody\n";
+
+        let commit = Commit::parse(body.as_bytes()).unwrap();
+        assert_eq!(commit.tree, "4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+        assert_eq!(commit.parents.len(), 2);
+        assert_eq!(commit.author.name, "A U Thor");
+        assert_eq!(commit.author.email, "author@example.com");
+        assert_eq!(commit.author.timestamp, 1700000000);
+        assert_eq!(commit.author.timezone, "+0100");
+        assert_eq!(commit.message, b"Subject line\n\nBody\n");
+
+        // Written back byte for byte
+        assert_eq!(commit.serialize(), body.as_bytes());
+
+        let (headers, _) = body.split_once("\n\n").unwrap();
+        let orphan = headers.lines().filter(|line| !line.starts_with("committer ")).collect::<Vec<_>>();
+        let error = Commit::parse(format!("{}\n\nmessage\n", orphan.join("\n")).as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "Commit has no committer");
+    }
+
+    #[test]
+    fn test_signed_commit_round_trip() {
+        // Made by `git commit -S`; the signature's blank line is a lone space
+        let mut body = b"tree 08585692ce06452da6f82ae66b90d98b55536fca\n\
+                         author Ada Lovelace <ada@example.com> 1700000000 +0100\n\
+                         committer Ada Lovelace <ada@example.com> 1700000000 +0100\n\
+                         gpgsig -----BEGIN PGP SIGNATURE-----\n \n \
+                         iIYEABYIAC4WIQRECt+K5wH/0s8AGTeqWEUPLFOf8AUCatUzkxAcYWRhQGV4YW1w\n \
+                         bGUuY29tAAoJEKpYRQ8sU5/wVHEA/3OjqkgL+ZIov3QsZmOquf8aB+36IxVIxtUd\n \
+                         eYFC4q6xAQD2YNd9rgIvHQrf4DD22yhS6AnJnr0Fbu/z6t62lyuyBw==\n \
+                         =h4H1\n \
+                         -----END PGP SIGNATURE-----\n\
+                         \n\
+                         signed\n"
+            .to_vec();
+
+        let commit = Commit::parse(&body).unwrap();
+        assert_eq!(commit.extra_headers.len(), 1);
+        assert_eq!(commit.extra_headers[0].0, "gpgsig");
+        assert!(commit.extra_headers[0].1.starts_with("-----BEGIN PGP SIGNATURE-----\n\niIYE"));
+        assert_eq!(commit.serialize(), body);
+        let hash = ObjectFormat::Sha1.digest(&object_store("commit", &commit.serialize()));
+        assert_eq!(hex::encode(hash), "82491aa40a3001a0cc2ff840bbd3df6d850783fe");
+
+        // A message in another encoding is kept as it was
+        body.truncate(body.len() - b"signed\n".len());
+        body.extend_from_slice(b"caf\xe9\n");
+        assert_eq!(Commit::parse(&body).unwrap().serialize(), body);
+    }
+
+    #[test]
+    fn test_tree_round_trip() {
+        let mut tree = Tree {
+            entries: vec![
+                TreeEntry { name: "lib".into(), mode: "40000".into(), hash: "ab".repeat(32) },
+                TreeEntry { name: "lib.rs".into(), mode: "100644".into(), hash: "cd".repeat(32) },
+            ],
+        };
+        // The directory "lib" sorts as "lib/", after "lib.rs"
+        tree.sort();
+        assert_eq!(tree.entries[0].name, "lib.rs");
+
+        let body = tree.serialize().unwrap();
+        assert_eq!(body.len(), "100644 lib.rs\0".len() + 32 + "40000 lib\0".len() + 32);
+        assert_eq!(Tree::parse(&body, ObjectFormat::Sha256).unwrap(), tree);
+        assert!(Tree::parse(&body[..body.len() - 1], ObjectFormat::Sha256).is_err());
+    }
+
+    #[test]
+    fn test_object_parse() {
+        let object = Object::parse("blob", b"data", ObjectFormat::Sha1).unwrap();
+        assert_eq!(object, Object::Blob(Blob { data: b"data".to_vec() }));
+        assert_eq!(object.kind(), "blob");
+
+        let tag = "object 4b825dc642cb6eb9a060e54bf8d69288fbee4904\ntype tree\ntag v1\n\nnotes\n";
+        let object = Object::parse("tag", tag.as_bytes(), ObjectFormat::Sha1).unwrap();
+        assert!(matches!(&object, Object::Tag(tag) if tag.name == "v1" && tag.tagger.is_none()));
+        assert_eq!(object.serialize().unwrap(), tag.as_bytes());
+
+        assert!(Object::parse("note", b"", ObjectFormat::Sha1).is_err());
+    }
+
+    #[test]
+    fn test_signature_round_trip() {
+        let line = "Rit User <rit@example.com> 1700000000 +0000";
+        assert_eq!(Signature::parse(line).unwrap().to_string(), line);
+        assert!(Signature::parse("no email here").is_err());
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0, "+0000"), "Thu Jan 1 00:00:00 1970 +0000");
+        assert_eq!(format_date(1700000000, "+0000"), "Tue Nov 14 22:13:20 2023 +0000");
+        assert_eq!(format_date(1700000000, "+0530"), "Wed Nov 15 03:43:20 2023 +0530");
+        assert_eq!(format_date(1700000000, "-0800"), "Tue Nov 14 14:13:20 2023 -0800");
+    }
+
+    fn at(year: i64, month: i64, day: i64, hour: i64) -> i64 {
+        days_from_civil(year, month, day) * 86400 + hour * 3600
+    }
+
+    #[test]
+    fn test_days_from_civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        for days in [-1000, 0, 11016, 20000, 50000] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1700000000 +0100").unwrap(), (1700000000, "+0100".to_string()));
+        assert_eq!(parse_date("@1700000000 -0530").unwrap(), (1700000000, "-0530".to_string()));
+        assert_eq!(
+            parse_date("2024-01-15T12:00:00+01:00").unwrap(),
+            (at(2024, 1, 15, 11), "+0100".to_string())
+        );
+        assert_eq!(parse_date("2024-01-15 12:00:00 Z").unwrap(), (at(2024, 1, 15, 12), "+0000".to_string()));
+        assert_eq!(parse_date("2024-01-15 Z").unwrap_err().to_string(), "invalid date format: 2024-01-15 Z");
+        assert_eq!(parse_date("2024-01-15").unwrap().0, parse_date("2024-01-15 00:00:00").unwrap().0);
+        assert!(parse_date("yesterday").is_err());
+        assert!(parse_date("2024-13-01T00:00:00Z").is_err());
+        assert!(parse_date("1700000000 0100").is_err());
+    }
+}
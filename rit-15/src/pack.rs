@@ -0,0 +1,995 @@
+use anyhow::Result;
+use flate2::Compression;
+use flate2::bufread::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::commands;
+use crate::objects::{Commit, ObjectDatabase, ObjectFormat, ObjectStore};
+use crate::repository::Repository;
+
+const OBJ_COMMIT: u8 = 1;
+const OBJ_TREE: u8 = 2;
+const OBJ_BLOB: u8 = 3;
+const OBJ_TAG: u8 = 4;
+const OBJ_OFS_DELTA: u8 = 6;
+const OBJ_REF_DELTA: u8 = 7;
+
+const PACK_SIGNATURE: &[u8; 4] = b"PACK";
+const IDX_SIGNATURE: &[u8; 4] = b"\xfftOc";
+const VERSION: u32 = 2;
+
+/// How many preceding objects of the same type are tried as delta bases
+const WINDOW: usize = 10;
+/// Longest chain of deltas on top of deltas before an object is stored whole
+const MAX_DEPTH: usize = 50;
+/// Bytes hashed together when looking for copyable regions of a delta base
+const BLOCK: usize = 16;
+
+pub fn pack_dir(repo: &Repository) -> PathBuf {
+    repo.rit_dir().join("objects").join("pack")
+}
+
+fn type_code(kind: &str) -> Result<u8> {
+    match kind {
+        "commit" => Ok(OBJ_COMMIT),
+        "tree" => Ok(OBJ_TREE),
+        "blob" => Ok(OBJ_BLOB),
+        "tag" => Ok(OBJ_TAG),
+        _ => anyhow::bail!("Unknown object type {}", kind),
+    }
+}
+
+fn type_name(code: u8) -> Result<&'static str> {
+    match code {
+        OBJ_COMMIT => Ok("commit"),
+        OBJ_TREE => Ok("tree"),
+        OBJ_BLOB => Ok("blob"),
+        OBJ_TAG => Ok("tag"),
+        _ => anyhow::bail!("Invalid object type {} in pack", code),
+    }
+}
+
+// Little-endian base 128, used for the sizes at the start of a delta
+fn push_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Result<usize> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*pos).ok_or_else(|| anyhow::anyhow!("Truncated delta"))?;
+        *pos += 1;
+        if shift > 63 {
+            anyhow::bail!("Delta size is too large");
+        }
+        value |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn push_copy(out: &mut Vec<u8>, mut offset: usize, mut len: usize) {
+    while len > 0 {
+        let chunk = len.min(0xff_ffff);
+        let mut cmd = 0x80u8;
+        let mut args = Vec::new();
+
+        for i in 0..4 {
+            let byte = (offset >> (8 * i)) as u8;
+            if byte != 0 {
+                cmd |= 1 << i;
+                args.push(byte);
+            }
+        }
+        for i in 0..3 {
+            let byte = (chunk >> (8 * i)) as u8;
+            if byte != 0 {
+                cmd |= 0x10 << i;
+                args.push(byte);
+            }
+        }
+
+        out.push(cmd);
+        out.extend_from_slice(&args);
+        offset += chunk;
+        len -= chunk;
+    }
+}
+
+fn push_insert(out: &mut Vec<u8>, data: &[u8]) {
+    for chunk in data.chunks(0x7f) {
+        out.push(chunk.len() as u8);
+        out.extend_from_slice(chunk);
+    }
+}
+
+/// Encodes `target` as copy and insert instructions against `base`, in git's delta format
+pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    push_varint(&mut out, base.len());
+    push_varint(&mut out, target.len());
+
+    // First position of every aligned block in the base
+    let mut blocks: HashMap<&[u8], usize> = HashMap::new();
+    for start in (0..base.len().saturating_sub(BLOCK - 1)).step_by(BLOCK) {
+        blocks.entry(&base[start..start + BLOCK]).or_insert(start);
+    }
+
+    let mut literal: Vec<u8> = Vec::new();
+    let mut i = 0;
+
+    while i < target.len() {
+        let found = target.get(i..i + BLOCK).and_then(|block| blocks.get(block));
+
+        let Some(&start) = found else {
+            literal.push(target[i]);
+            i += 1;
+            continue;
+        };
+
+        let mut len = BLOCK;
+        while start + len < base.len() && i + len < target.len() && base[start + len] == target[i + len] {
+            len += 1;
+        }
+
+        // The match may also start before the aligned block, inside the pending literal
+        let mut back = 0;
+        while back < literal.len() && back < start && base[start - back - 1] == target[i - back - 1] {
+            back += 1;
+        }
+        literal.truncate(literal.len() - back);
+
+        push_insert(&mut out, &literal);
+        literal.clear();
+        push_copy(&mut out, start - back, len + back);
+        i += len;
+    }
+
+    push_insert(&mut out, &literal);
+    out
+}
+
+/// Rebuilds an object from its delta base
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut pos = 0;
+    let base_len = read_varint(delta, &mut pos)?;
+    let target_len = read_varint(delta, &mut pos)?;
+    if base_len != base.len() {
+        anyhow::bail!("Delta base size mismatch");
+    }
+
+    // The sizes come from the pack, so they are checked at the end rather than trusted here
+    let mut out = Vec::new();
+    while pos < delta.len() {
+        let cmd = delta[pos];
+        pos += 1;
+
+        if cmd & 0x80 != 0 {
+            let mut arg = |present: bool| -> Result<usize> {
+                if !present {
+                    return Ok(0);
+                }
+                let byte = *delta.get(pos).ok_or_else(|| anyhow::anyhow!("Truncated delta"))?;
+                pos += 1;
+                Ok(byte as usize)
+            };
+
+            let mut offset = 0;
+            for i in 0..4 {
+                offset |= arg(cmd & (1 << i) != 0)? << (8 * i);
+            }
+            let mut len = 0;
+            for i in 0..3 {
+                len |= arg(cmd & (0x10 << i) != 0)? << (8 * i);
+            }
+            if len == 0 {
+                len = 0x10000;
+            }
+
+            let end = offset
+                .checked_add(len)
+                .filter(|&end| end <= base.len())
+                .ok_or_else(|| anyhow::anyhow!("Delta copies outside its base"))?;
+            out.extend_from_slice(&base[offset..end]);
+        } else if cmd != 0 {
+            let end = pos + cmd as usize;
+            let data = delta.get(pos..end).ok_or_else(|| anyhow::anyhow!("Truncated delta"))?;
+            out.extend_from_slice(data);
+            pos = end;
+        } else {
+            anyhow::bail!("Invalid delta instruction");
+        }
+    }
+
+    if out.len() != target_len {
+        anyhow::bail!("Delta produced {} bytes, expected {}", out.len(), target_len);
+    }
+    Ok(out)
+}
+
+// Type and inflated size: 3 type bits and 4 size bits, then 7 size bits per byte
+fn push_object_header(out: &mut Vec<u8>, code: u8, mut size: usize) {
+    let mut byte = (code << 4) | (size & 0x0f) as u8;
+    size >>= 4;
+    while size > 0 {
+        out.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    out.push(byte);
+}
+
+// OFS_DELTA distance, big-endian base 128 where each continuation also adds one
+fn push_offset(out: &mut Vec<u8>, mut distance: u64) {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance > 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+    out.extend_from_slice(&bytes);
+}
+
+fn read_byte(reader: &mut impl Read) -> Result<u8> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_object_header(reader: &mut impl Read) -> Result<(u8, usize)> {
+    let mut byte = read_byte(reader)?;
+    let code = (byte >> 4) & 0x7;
+    let mut size = (byte & 0x0f) as usize;
+    let mut shift = 4;
+    while byte & 0x80 != 0 {
+        byte = read_byte(reader)?;
+        if shift > 63 {
+            anyhow::bail!("Packed object size is too large");
+        }
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+    }
+    Ok((code, size))
+}
+
+fn read_offset(reader: &mut impl Read) -> Result<u64> {
+    let mut byte = read_byte(reader)?;
+    let mut distance = (byte & 0x7f) as u64;
+    while byte & 0x80 != 0 {
+        byte = read_byte(reader)?;
+        distance = distance
+            .checked_add(1)
+            .and_then(|distance| distance.checked_mul(0x80))
+            .ok_or_else(|| anyhow::anyhow!("Delta base offset is too large"))?
+            | (byte & 0x7f) as u64;
+    }
+    Ok(distance)
+}
+
+fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+/// An object to pack. `name` is the path it was reached by, objects with the same
+/// name are likely versions of one file and make good delta bases for each other.
+pub struct PackInput {
+    pub hash: String,
+    pub kind: String,
+    pub data: Vec<u8>,
+    pub name: String,
+}
+
+/// Where an object landed in a pack, which is what the .idx records
+pub struct IndexEntry {
+    pub hash: String,
+    pub offset: u64,
+    pub crc: u32,
+}
+
+pub struct WrittenPack {
+    pub format: ObjectFormat,
+    pub data: Vec<u8>,
+    pub entries: Vec<IndexEntry>,
+    pub deltas: usize,
+}
+
+impl WrittenPack {
+    /// The pack's trailing checksum, which also names its files
+    pub fn checksum(&self) -> &[u8] {
+        &self.data[self.data.len() - self.format.raw_len()..]
+    }
+}
+
+/// Builds a version 2 pack. Deltas refer to their base by offset (OFS_DELTA) when
+/// `ofs_delta` is set and by object name (REF_DELTA) otherwise.
+pub fn write_pack(format: ObjectFormat, objects: &[PackInput], ofs_delta: bool) -> Result<WrittenPack> {
+    // Like git: group by type and name, biggest first, so smaller versions become deltas
+    let mut order: Vec<usize> = (0..objects.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&objects[a], &objects[b]);
+        (&a.kind, &a.name, b.data.len()).cmp(&(&b.kind, &b.name, a.data.len()))
+    });
+
+    let mut data = Vec::new();
+    data.extend_from_slice(PACK_SIGNATURE);
+    data.extend_from_slice(&VERSION.to_be_bytes());
+    data.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+
+    let mut entries = Vec::with_capacity(objects.len());
+    let mut offsets: HashMap<usize, u64> = HashMap::new();
+    let mut depths: HashMap<usize, usize> = HashMap::new();
+    let mut deltas = 0;
+
+    for (position, &current) in order.iter().enumerate() {
+        let object = &objects[current];
+
+        // Smallest delta against the previous objects of the same type, if it pays off
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        for &candidate in order[position.saturating_sub(WINDOW)..position].iter().rev() {
+            let base = &objects[candidate];
+            if base.kind != object.kind || depths[&candidate] >= MAX_DEPTH {
+                continue;
+            }
+            let delta = create_delta(&base.data, &object.data);
+            let smaller = best.as_ref().is_none_or(|(_, best)| delta.len() < best.len());
+            if delta.len() < object.data.len() / 2 && smaller {
+                best = Some((candidate, delta));
+            }
+        }
+
+        let offset = data.len() as u64;
+        match &best {
+            Some((base, delta)) => {
+                if ofs_delta {
+                    push_object_header(&mut data, OBJ_OFS_DELTA, delta.len());
+                    push_offset(&mut data, offset - offsets[base]);
+                } else {
+                    push_object_header(&mut data, OBJ_REF_DELTA, delta.len());
+                    data.extend_from_slice(&hex::decode(&objects[*base].hash)?);
+                }
+                data.extend_from_slice(&compress(delta)?);
+                depths.insert(current, depths[base] + 1);
+                deltas += 1;
+            }
+            None => {
+                push_object_header(&mut data, type_code(&object.kind)?, object.data.len());
+                data.extend_from_slice(&compress(&object.data)?);
+                depths.insert(current, 0);
+            }
+        }
+
+        offsets.insert(current, offset);
+        entries.push(IndexEntry {
+            hash: object.hash.clone(),
+            offset,
+            crc: crc32fast::hash(&data[offset as usize..]),
+        });
+    }
+
+    let checksum = format.digest(&data);
+    data.extend_from_slice(&checksum);
+
+    Ok(WrittenPack { format, data, entries, deltas })
+}
+
+/// Builds a version 2 .idx for a pack with the given checksum
+pub fn write_index(format: ObjectFormat, entries: &[IndexEntry], pack_checksum: &[u8]) -> Result<Vec<u8>> {
+    let mut sorted: Vec<(Vec<u8>, &IndexEntry)> = entries
+        .iter()
+        .map(|entry| Ok((hex::decode(&entry.hash)?, entry)))
+        .collect::<Result<_>>()?;
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out = Vec::new();
+    out.extend_from_slice(IDX_SIGNATURE);
+    out.extend_from_slice(&VERSION.to_be_bytes());
+
+    // fanout[b] = number of objects whose first byte is <= b
+    let mut fanout = [0u32; 256];
+    for (hash, _) in &sorted {
+        fanout[hash[0] as usize] += 1;
+    }
+    let mut total = 0;
+    for count in fanout.iter_mut() {
+        total += *count;
+        *count = total;
+    }
+    for count in fanout {
+        out.extend_from_slice(&count.to_be_bytes());
+    }
+
+    for (hash, _) in &sorted {
+        out.extend_from_slice(hash);
+    }
+    for (_, entry) in &sorted {
+        out.extend_from_slice(&entry.crc.to_be_bytes());
+    }
+
+    // Offsets past 2GB go to a separate 64-bit table, the 32-bit slot points into it
+    let mut large = Vec::new();
+    for (_, entry) in &sorted {
+        if entry.offset < 0x8000_0000 {
+            out.extend_from_slice(&(entry.offset as u32).to_be_bytes());
+        } else {
+            out.extend_from_slice(&(0x8000_0000 | (large.len() / 8) as u32).to_be_bytes());
+            large.extend_from_slice(&entry.offset.to_be_bytes());
+        }
+    }
+    out.extend_from_slice(&large);
+
+    out.extend_from_slice(pack_checksum);
+    let checksum = format.digest(&out);
+    out.extend_from_slice(&checksum);
+    Ok(out)
+}
+
+/// A pack on disk, looked up through its .idx
+pub struct Pack {
+    pack_path: PathBuf,
+    idx: Vec<u8>,
+    count: usize,
+    format: ObjectFormat,
+    hash_len: usize,
+}
+
+impl Pack {
+    /// Opens `pack-<hash>.pack` given the path of its .idx
+    pub fn open(idx_path: &Path, format: ObjectFormat) -> Result<Pack> {
+        let idx = fs::read(idx_path)?;
+        let hash_len = format.raw_len();
+        if idx.len() < 8 + 256 * 4 + 2 * hash_len || &idx[..4] != IDX_SIGNATURE {
+            anyhow::bail!("{} is not a version 2 pack index", idx_path.display());
+        }
+        if u32::from_be_bytes(idx[4..8].try_into()?) != VERSION {
+            anyhow::bail!("{} has an unsupported version", idx_path.display());
+        }
+
+        let count = u32::from_be_bytes(idx[8 + 255 * 4..8 + 256 * 4].try_into()?) as usize;
+        if idx.len() < 8 + 256 * 4 + count * (hash_len + 8) + 2 * hash_len {
+            anyhow::bail!("{} is truncated", idx_path.display());
+        }
+
+        Ok(Pack {
+            pack_path: idx_path.with_extension("pack"),
+            idx,
+            count,
+            format,
+            hash_len,
+        })
+    }
+
+    fn fanout(&self, byte: usize) -> usize {
+        let at = 8 + byte * 4;
+        u32::from_be_bytes(self.idx[at..at + 4].try_into().unwrap()) as usize
+    }
+
+    fn hash_at(&self, i: usize) -> &[u8] {
+        let at = 8 + 256 * 4 + i * self.hash_len;
+        &self.idx[at..at + self.hash_len]
+    }
+
+    fn offset_at(&self, i: usize) -> u64 {
+        let table = 8 + 256 * 4 + self.count * (self.hash_len + 4);
+        let at = table + i * 4;
+        let offset = u32::from_be_bytes(self.idx[at..at + 4].try_into().unwrap());
+        if offset & 0x8000_0000 == 0 {
+            return offset as u64;
+        }
+
+        let large = table + self.count * 4 + (offset & 0x7fff_ffff) as usize * 8;
+        u64::from_be_bytes(self.idx[large..large + 8].try_into().unwrap())
+    }
+
+    /// Every object name in the pack, sorted
+    pub fn hashes(&self) -> Vec<String> {
+        (0..self.count).map(|i| hex::encode(self.hash_at(i))).collect()
+    }
+
+    fn find(&self, hash: &[u8]) -> Option<u64> {
+        // The fanout narrows the search to objects sharing the first byte
+        let low = if hash[0] == 0 { 0 } else { self.fanout(hash[0] as usize - 1) };
+        let high = self.fanout(hash[0] as usize);
+
+        let (mut low, mut high) = (low, high);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.hash_at(mid).cmp(hash) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(self.offset_at(mid)),
+            }
+        }
+        None
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        hex::decode(hash).is_ok_and(|hash| hash.len() == self.hash_len && self.find(&hash).is_some())
+    }
+
+    pub fn read(&self, hash: &str) -> Result<Option<(String, Vec<u8>)>> {
+        let raw = hex::decode(hash)?;
+        match self.find(&raw) {
+            Some(offset) => Ok(Some(self.read_at(&mut fs::File::open(&self.pack_path)?, offset)?)),
+            None => Ok(None),
+        }
+    }
+
+    // The pack is opened once per read and shared by every step of a delta chain
+    fn read_at(&self, file: &mut fs::File, offset: u64) -> Result<(String, Vec<u8>)> {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut reader = BufReader::new(&mut *file);
+
+        let (code, size) = read_object_header(&mut reader)?;
+
+        match code {
+            OBJ_OFS_DELTA => {
+                let distance = read_offset(&mut reader)?;
+                let delta = inflate(&mut reader, size)?;
+                drop(reader);
+                let base_offset = offset
+                    .checked_sub(distance)
+                    .ok_or_else(|| anyhow::anyhow!("Delta base before the start of the pack"))?;
+                let (kind, base) = self.read_at(file, base_offset)?;
+                Ok((kind, apply_delta(&base, &delta)?))
+            }
+            OBJ_REF_DELTA => {
+                let mut base_hash = vec![0u8; self.hash_len];
+                reader.read_exact(&mut base_hash)?;
+                let delta = inflate(&mut reader, size)?;
+                drop(reader);
+                // Bases outside the pack are in the same object store
+                let (kind, base) = match self.find(&base_hash) {
+                    Some(base_offset) => self.read_at(file, base_offset)?,
+                    None => {
+                        let objects_dir = self.pack_path.parent().and_then(Path::parent);
+                        let store = ObjectStore::new(objects_dir.unwrap_or(Path::new(".")), self.format);
+                        store.read_raw(&hex::encode(base_hash))?
+                    }
+                };
+                Ok((kind, apply_delta(&base, &delta)?))
+            }
+            _ => Ok((type_name(code)?.to_string(), inflate(&mut reader, size)?)),
+        }
+    }
+}
+
+// The size is the pack's claim, so nothing is allocated up front and at most one byte
+// more than it is read
+fn inflate(reader: &mut impl BufRead, size: usize) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    ZlibDecoder::new(reader).take(size as u64 + 1).read_to_end(&mut data)?;
+    if data.len() != size {
+        anyhow::bail!("Packed object is {} bytes, expected {}", data.len(), size);
+    }
+    Ok(data)
+}
+
+/// All packs of a repository
+pub fn packs(repo: &Repository) -> Result<Vec<Pack>> {
+    packs_in(&pack_dir(repo), repo.format())
+}
+
+/// All packs in a `pack` directory
+pub fn packs_in(dir: &Path, format: ObjectFormat) -> Result<Vec<Pack>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut packs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "idx") {
+            packs.push(Pack::open(&path, format)?);
+        }
+    }
+    Ok(packs)
+}
+
+/// A packed object before its deltas are resolved
+struct RawObject {
+    code: u8,
+    base: Option<DeltaBase>,
+    data: Vec<u8>,
+}
+
+enum DeltaBase {
+    Offset(u64),
+    Hash(String),
+}
+
+/// Computes the .idx entries of a pack received from elsewhere, resolving every delta
+/// to learn the object names. REF_DELTA bases missing from the pack are looked up locally.
+pub fn index_pack(repo: &Repository, data: &[u8]) -> Result<Vec<IndexEntry>> {
+    let format = repo.format();
+    if data.len() < 12 + format.raw_len() || &data[..4] != PACK_SIGNATURE {
+        anyhow::bail!("not a pack file");
+    }
+    let (content, checksum) = data.split_at(data.len() - format.raw_len());
+    if format.digest(content) != checksum {
+        anyhow::bail!("pack checksum mismatch");
+    }
+    let count = u32::from_be_bytes(content[8..12].try_into()?) as usize;
+
+    let mut raw = Vec::new();
+    let mut offsets = Vec::new();
+    let mut crcs = Vec::new();
+    let mut pos = 12;
+
+    for _ in 0..count {
+        let offset = pos;
+        let mut reader = content
+            .get(pos..)
+            .ok_or_else(|| anyhow::anyhow!("pack is truncated"))?;
+        let (code, size) = read_object_header(&mut reader)?;
+
+        let base = match code {
+            OBJ_OFS_DELTA => {
+                let distance = read_offset(&mut reader)?;
+                let base = (offset as u64)
+                    .checked_sub(distance)
+                    .ok_or_else(|| anyhow::anyhow!("Delta base before the start of the pack"))?;
+                Some(DeltaBase::Offset(base))
+            }
+            OBJ_REF_DELTA => {
+                let mut hash = vec![0u8; format.raw_len()];
+                reader.read_exact(&mut hash)?;
+                Some(DeltaBase::Hash(hex::encode(hash)))
+            }
+            _ => {
+                type_name(code)?;
+                None
+            }
+        };
+
+        // The zlib stream has no length prefix, the decoder reports how much it consumed
+        let mut decoder = ZlibDecoder::new(reader).take(size as u64 + 1);
+        let mut object = Vec::new();
+        decoder.read_to_end(&mut object)?;
+        if object.len() != size {
+            anyhow::bail!("Packed object is {} bytes, expected {}", object.len(), size);
+        }
+        pos = content.len() - decoder.into_inner().into_inner().len();
+
+        crcs.push(crc32fast::hash(&content[offset..pos]));
+        offsets.push(offset as u64);
+        raw.push(RawObject { code, base, data: object });
+    }
+
+    if pos != content.len() {
+        anyhow::bail!("pack has {} bytes of garbage after its objects", content.len() - pos);
+    }
+
+    let by_offset: HashMap<u64, usize> = offsets.iter().enumerate().map(|(i, &offset)| (offset, i)).collect();
+    let mut resolved: Vec<Option<(String, Vec<u8>)>> = vec![None; count];
+    let mut by_hash: HashMap<String, usize> = HashMap::new();
+
+    // Whole objects first, then deltas in rounds as their bases become known
+    let mut pending: Vec<usize> = Vec::new();
+    for (i, object) in raw.iter().enumerate() {
+        if object.base.is_none() {
+            let kind = type_name(object.code)?.to_string();
+            by_hash.insert(format.hash_object(&kind, &object.data), i);
+            resolved[i] = Some((kind, object.data.clone()));
+        } else {
+            pending.push(i);
+        }
+    }
+
+    while !pending.is_empty() {
+        let mut waiting = Vec::new();
+
+        for i in pending.iter().copied() {
+            let base = match &raw[i].base {
+                Some(DeltaBase::Offset(offset)) => {
+                    let index = *by_offset
+                        .get(offset)
+                        .ok_or_else(|| anyhow::anyhow!("Delta base at {} is not an object", offset))?;
+                    resolved[index].clone()
+                }
+                Some(DeltaBase::Hash(hash)) => match by_hash.get(hash) {
+                    Some(&index) => resolved[index].clone(),
+                    None if repo.objects().contains(hash) => Some(repo.objects().read_raw(hash)?),
+                    None => None,
+                },
+                None => unreachable!("whole objects are resolved up front"),
+            };
+
+            match base {
+                Some((kind, base)) => {
+                    let object = apply_delta(&base, &raw[i].data)?;
+                    by_hash.insert(format.hash_object(&kind, &object), i);
+                    resolved[i] = Some((kind, object));
+                }
+                None => waiting.push(i),
+            }
+        }
+
+        if waiting.len() == pending.len() {
+            anyhow::bail!("pack has {} deltas with missing bases", waiting.len());
+        }
+        pending = waiting;
+    }
+
+    let mut names = vec![String::new(); count];
+    for (hash, i) in by_hash {
+        names[i] = hash;
+    }
+
+    Ok(names
+        .into_iter()
+        .zip(offsets)
+        .zip(crcs)
+        .map(|((hash, offset), crc)| IndexEntry { hash, offset, crc })
+        .collect())
+}
+
+/// Indexes a pack and adds it to the object store. Returns how many objects it held.
+pub fn store_pack(repo: &Repository, data: &[u8]) -> Result<usize> {
+    let entries = index_pack(repo, data)?;
+    if entries.is_empty() {
+        return Ok(0);
+    }
+
+    let checksum = &data[data.len() - repo.format().raw_len()..];
+    let name = format!("pack-{}", hex::encode(checksum));
+    let dir = pack_dir(repo);
+    fs::create_dir_all(&dir)?;
+
+    // The .idx goes last: a pack is only looked at once its index exists
+    fs::write(dir.join(format!("{}.pack", name)), data)?;
+    fs::write(dir.join(format!("{}.idx", name)), write_index(repo.format(), &entries, checksum)?)?;
+    repo.objects().forget_packs();
+    Ok(entries.len())
+}
+
+/// Every object reachable from `tips`, as hash -> the path it was first reached by.
+/// Walking stops at anything in `exclude`, which the other side already has.
+pub fn reachable_objects(
+    repo: &Repository,
+    tips: impl IntoIterator<Item = String>,
+    exclude: &HashSet<String>,
+) -> Result<BTreeMap<String, String>> {
+    let mut pending: Vec<String> = tips.into_iter().collect();
+    let mut found = BTreeMap::new();
+
+    while let Some(hash) = pending.pop() {
+        if exclude.contains(&hash) || found.contains_key(&hash) {
+            continue;
+        }
+        let (kind, data) = repo.objects().read_raw(&hash)?;
+
+        match kind.as_str() {
+            "commit" => {
+                let commit = Commit::parse(&data)?;
+                found.insert(hash, String::new());
+                walk_tree(repo, &commit.tree, "", exclude, &mut found)?;
+                pending.extend(commit.parents);
+            }
+            // An annotated tag names the object it points at on its first line
+            "tag" => {
+                let text = String::from_utf8_lossy(&data);
+                let target = text.lines().next().and_then(|line| line.strip_prefix("object "));
+                let target = target.ok_or_else(|| anyhow::anyhow!("Tag {} has no object", hash))?;
+                pending.push(target.to_string());
+                found.insert(hash, String::new());
+            }
+            "tree" => walk_tree(repo, &hash, "", exclude, &mut found)?,
+            _ => {
+                found.insert(hash, String::new());
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// Packs objects found by `reachable_objects`, named by path so versions of a file delta well
+pub fn pack_objects(
+    repo: &Repository,
+    found: &BTreeMap<String, String>,
+    ofs_delta: bool,
+) -> Result<WrittenPack> {
+    let mut inputs = Vec::with_capacity(found.len());
+    for (hash, name) in found {
+        let (kind, data) = repo.objects().read_raw(hash)?;
+        inputs.push(PackInput {
+            hash: hash.clone(),
+            kind,
+            data,
+            name: name.clone(),
+        });
+    }
+    write_pack(repo.format(), &inputs, ofs_delta)
+}
+
+fn walk_tree(
+    repo: &Repository,
+    tree_hash: &str,
+    path: &str,
+    exclude: &HashSet<String>,
+    found: &mut BTreeMap<String, String>,
+) -> Result<()> {
+    if exclude.contains(tree_hash) || found.contains_key(tree_hash) {
+        return Ok(());
+    }
+    found.insert(tree_hash.to_string(), path.to_string());
+
+    for entry in commands::read_tree(repo, tree_hash)? {
+        match entry.mode.as_str() {
+            "40000" => walk_tree(repo, &entry.hash, &entry.name, exclude, found)?,
+            // Submodule commits live in another repository
+            "160000" => {}
+            _ if exclude.contains(&entry.hash) => {}
+            _ => {
+                found.entry(entry.hash).or_insert(entry.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(kind: &str, name: &str, data: &[u8]) -> PackInput {
+        PackInput {
+            hash: ObjectFormat::Sha1.hash_object(kind, data),
+            kind: kind.to_string(),
+            data: data.to_vec(),
+            name: name.to_string(),
+        }
+    }
+
+    // Somewhere for index_pack to look up delta bases, which never has any
+    fn empty_repository() -> (tempfile::TempDir, Repository) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        let repo = Repository::open_with_rit_dir(dir.path(), dir.path()).unwrap();
+        (dir, repo)
+    }
+
+    fn versions() -> Vec<PackInput> {
+        let base: String = (0..200).map(|i| format!("line number {}\n", i)).collect();
+        let edited = base.replace("line number 100\n", "an edited line\n");
+        let appended = format!("{}one more\n", edited);
+
+        vec![
+            input("blob", "file.txt", base.as_bytes()),
+            input("blob", "file.txt", edited.as_bytes()),
+            input("blob", "file.txt", appended.as_bytes()),
+            input("blob", "other", b"small"),
+            input("commit", "", b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\nmsg\n"),
+        ]
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        let base: Vec<u8> = (0..5000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let mut target = base.clone();
+        target.splice(100..100, b"inserted".iter().copied());
+        target.truncate(15000);
+        target.extend_from_slice(b"tail");
+
+        let delta = create_delta(&base, &target);
+        assert!(delta.len() < 100);
+        assert_eq!(apply_delta(&base, &delta).unwrap(), target);
+
+        // Nothing in common still produces a valid, insert-only delta
+        let delta = create_delta(b"abc", b"completely different content");
+        assert_eq!(apply_delta(b"abc", &delta).unwrap(), b"completely different content");
+        assert_eq!(apply_delta(b"", &create_delta(b"", b"")).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_apply_delta_rejects_bad_input() {
+        let delta = create_delta(b"0123456789abcdef0123", b"0123456789abcdef0123!");
+        assert!(apply_delta(b"wrong base", &delta).is_err());
+        assert!(apply_delta(b"0123456789abcdef0123", &delta[..delta.len() - 1]).is_err());
+        // Copy of 0x10 bytes from offset 0x10 of a 4 byte base
+        assert!(apply_delta(b"abcd", &[4, 16, 0x91, 0x10, 0x10]).is_err());
+    }
+
+    #[test]
+    fn test_headers_round_trip() {
+        for size in [0, 15, 16, 127, 128, 1 << 20, usize::MAX >> 8] {
+            let mut out = Vec::new();
+            push_object_header(&mut out, OBJ_BLOB, size);
+            assert_eq!(read_object_header(&mut &out[..]).unwrap(), (OBJ_BLOB, size));
+        }
+        for distance in [1, 127, 128, 16511, 16512, 1 << 40] {
+            let mut out = Vec::new();
+            push_offset(&mut out, distance);
+            assert_eq!(read_offset(&mut &out[..]).unwrap(), distance);
+        }
+    }
+
+    #[test]
+    fn test_oversized_headers_are_errors() {
+        let endless = [0xffu8; 16];
+        assert!(read_object_header(&mut &endless[..]).is_err());
+        assert!(read_offset(&mut &endless[..]).is_err());
+        assert!(read_varint(&endless, &mut 0).is_err());
+        assert!(apply_delta(b"", &[0xff; 16]).is_err());
+
+        // A pack with a valid checksum whose only object header never ends
+        let mut pack = b"PACK\0\0\0\x02\0\0\0\x01".to_vec();
+        pack.extend([0xff; 12]);
+        pack.push(0);
+        pack.extend(ObjectFormat::Sha1.digest(&pack));
+        let (_dir, repo) = empty_repository();
+        let err = index_pack(&repo, &pack).err().unwrap();
+        assert_eq!(err.to_string(), "Packed object size is too large");
+    }
+
+    fn round_trip(ofs_delta: bool) {
+        let dir = tempfile::tempdir().unwrap();
+        let objects = versions();
+
+        let pack = write_pack(ObjectFormat::Sha1, &objects, ofs_delta).unwrap();
+        assert_eq!(pack.deltas, 2);
+
+        let pack_path = dir.path().join("pack-test.pack");
+        fs::write(&pack_path, &pack.data).unwrap();
+        fs::write(
+            pack_path.with_extension("idx"),
+            write_index(ObjectFormat::Sha1, &pack.entries, pack.checksum()).unwrap(),
+        )
+        .unwrap();
+
+        let opened = Pack::open(&pack_path.with_extension("idx"), ObjectFormat::Sha1).unwrap();
+        assert_eq!(opened.hashes().len(), objects.len());
+        for object in &objects {
+            let (kind, data) = opened.read(&object.hash).unwrap().unwrap();
+            assert_eq!(kind, object.kind);
+            assert_eq!(data, object.data);
+        }
+        assert!(!opened.contains(&"00".repeat(20)));
+        assert!(opened.read(&"ff".repeat(20)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_index_pack_matches_writer() {
+        let (_dir, repo) = empty_repository();
+        for ofs_delta in [true, false] {
+            let pack = write_pack(ObjectFormat::Sha1, &versions(), ofs_delta).unwrap();
+            let indexed = index_pack(&repo, &pack.data).unwrap();
+
+            assert_eq!(indexed.len(), pack.entries.len());
+            for (ours, theirs) in indexed.iter().zip(&pack.entries) {
+                assert_eq!(ours.hash, theirs.hash);
+                assert_eq!(ours.offset, theirs.offset);
+                assert_eq!(ours.crc, theirs.crc);
+            }
+        }
+
+        let mut corrupt = write_pack(ObjectFormat::Sha1, &versions(), true).unwrap().data;
+        corrupt[20] ^= 1;
+        assert!(index_pack(&repo, &corrupt).is_err());
+    }
+
+    #[test]
+    fn test_pack_round_trip_with_ofs_deltas() {
+        round_trip(true);
+    }
+
+    #[test]
+    fn test_pack_round_trip_with_ref_deltas() {
+        round_trip(false);
+    }
+}
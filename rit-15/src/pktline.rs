@@ -0,0 +1,107 @@
+use anyhow::Result;
+use std::io::{Read, Write};
+
+/// Longest payload a pkt-line can carry (65520 bytes minus the 4 byte length prefix)
+const MAX_PAYLOAD: usize = 65516;
+
+/// One unit of git's pkt-line framing
+#[derive(Debug, PartialEq, Eq)]
+pub enum Packet {
+    Data(Vec<u8>),
+    /// "0000", ends a section of the conversation
+    Flush,
+}
+
+impl Packet {
+    /// The payload as text without its trailing newline, None for a flush
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Packet::Data(data) => {
+                let text = std::str::from_utf8(data).ok()?;
+                Some(text.strip_suffix('\n').unwrap_or(text))
+            }
+            Packet::Flush => None,
+        }
+    }
+}
+
+/// Writes a data packet: four hex digits holding the total length, then the payload
+pub fn write_data(writer: &mut impl Write, data: &[u8]) -> Result<()> {
+    if data.len() > MAX_PAYLOAD {
+        anyhow::bail!("pkt-line payload of {} bytes is too long", data.len());
+    }
+    write!(writer, "{:04x}", data.len() + 4)?;
+    writer.write_all(data)?;
+    Ok(())
+}
+
+/// Writes a line of text, adding the newline git expects
+pub fn write_line(writer: &mut impl Write, line: &str) -> Result<()> {
+    write_data(writer, format!("{}\n", line).as_bytes())
+}
+
+pub fn write_flush(writer: &mut impl Write) -> Result<()> {
+    writer.write_all(b"0000")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads the next packet. None when the stream ends cleanly between packets.
+pub fn read_packet(reader: &mut impl Read) -> Result<Option<Packet>> {
+    let mut prefix = [0u8; 4];
+    match reader.read_exact(&mut prefix) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+
+    let len = std::str::from_utf8(&prefix)
+        .ok()
+        .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| anyhow::anyhow!("protocol error: bad line length {:?}", prefix))?;
+
+    match len {
+        0 => Ok(Some(Packet::Flush)),
+        1..=3 => anyhow::bail!("protocol error: bad line length {}", len),
+        _ => {
+            let mut data = vec![0u8; len - 4];
+            reader.read_exact(&mut data)?;
+            Ok(Some(Packet::Data(data)))
+        }
+    }
+}
+
+/// Reads a packet that must be there
+pub fn expect_packet(reader: &mut impl Read) -> Result<Packet> {
+    read_packet(reader)?.ok_or_else(|| anyhow::anyhow!("protocol error: unexpected end of stream"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framing() {
+        let mut out = Vec::new();
+        write_line(&mut out, "want abc").unwrap();
+        write_data(&mut out, b"").unwrap();
+        write_flush(&mut out).unwrap();
+        assert_eq!(out, b"000dwant abc\n00040000");
+
+        let mut reader = &out[..];
+        let first = read_packet(&mut reader).unwrap().unwrap();
+        assert_eq!(first.text(), Some("want abc"));
+        assert_eq!(read_packet(&mut reader).unwrap(), Some(Packet::Data(Vec::new())));
+        assert_eq!(read_packet(&mut reader).unwrap(), Some(Packet::Flush));
+        assert_eq!(read_packet(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_rejects_bad_lengths() {
+        assert!(read_packet(&mut &b"zzzz"[..]).is_err());
+        assert!(read_packet(&mut &b"0002"[..]).is_err());
+        // Length says 16 bytes but the stream ends early
+        assert!(read_packet(&mut &b"0010abc"[..]).is_err());
+        assert!(write_data(&mut Vec::new(), &vec![0; MAX_PAYLOAD + 1]).is_err());
+    }
+}
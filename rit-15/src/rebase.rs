@@ -0,0 +1,396 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::commands::{self, rit_dir};
+use crate::index::{self, Index};
+use crate::merge;
+use crate::objects::Commit;
+use crate::reflog;
+use crate::refs::{self, Head};
+use crate::repository::Repository;
+use crate::revision;
+use crate::status;
+
+/// What happened when replaying one commit
+enum Pick {
+    /// Committed as this new commit
+    Done(String),
+    /// The changes are already in HEAD
+    Empty,
+    /// Stopped with conflicts in the working tree
+    Conflicts,
+}
+
+fn subject(commit: &Commit) -> String {
+    commit.message_text().lines().next().unwrap_or_default().to_string()
+}
+
+// commit_tree adds the final newline back
+fn message(commit: &Commit) -> String {
+    let message = commit.message_text();
+    message.strip_suffix('\n').unwrap_or(&message).to_string()
+}
+
+fn require_clean(action: &str) -> Result<()> {
+    if !status::compute()?.is_clean() {
+        anyhow::bail!("cannot {}: You have unstaged or uncommitted changes.\nPlease commit or stash them.", action);
+    }
+    Ok(())
+}
+
+// Applies the changes `hash` made to its parent on top of HEAD with a three-way merge,
+// committing the result under the original author. `reason` prefixes the reflog message.
+fn pick(hash: &str, reason: &str) -> Result<Pick> {
+    let repo = Repository::current();
+    let commit = Commit::read(repo, hash)?;
+    if commit.parents.len() > 1 {
+        anyhow::bail!("commit {} is a merge, which can't be replayed", hash);
+    }
+    let head = refs::head_commit(repo)?
+        .ok_or_else(|| anyhow::anyhow!("HEAD does not point to a commit yet"))?;
+
+    let base = match commit.parents.first() {
+        Some(parent) => commands::flatten_tree(&Commit::read(repo, parent)?.tree)?,
+        None => Index::new(),
+    };
+    let ours = commands::flatten_tree(&Commit::read(repo, &head)?.tree)?;
+    let theirs = commands::flatten_tree(&commit.tree)?;
+
+    let label = format!("{} ({})", &hash[..7], subject(&commit));
+    let result = merge::merge_trees(&base, &ours, &theirs, "HEAD", &label)?;
+    merge::check_untracked(&ours, &result.index)?;
+
+    if result.conflicts.is_empty() && result.index == ours {
+        return Ok(Pick::Empty);
+    }
+
+    commands::update_worktree(&ours, &result.index)?;
+    for (path, content) in &result.conflict_files {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+    }
+    index::write_index(&result.index)?;
+
+    if !result.conflicts.is_empty() {
+        merge::write_conflicts(&result.unmerged)?;
+        fs::write(rit_dir().join("MERGE_MSG"), &commit.message)?;
+        for (path, kind) in &result.conflicts {
+            println!("CONFLICT ({}): Merge conflict in {}", kind, path);
+        }
+        println!("error: could not apply {}... {}", &hash[..7], subject(&commit));
+        return Ok(Pick::Conflicts);
+    }
+
+    let tree = commands::write_tree()?;
+    let new = commands::commit_tree_with_author(&tree, &[head], &message(&commit), &commit.author)?;
+    refs::advance_head(repo, &new, &format!("{}: {}", reason, subject(&commit)))?;
+    Ok(Pick::Done(new))
+}
+
+// Commits the resolved index of a stopped pick of `hash`, with its message and author
+fn commit_resolved(hash: &str, reason: &str) -> Result<Option<String>> {
+    let repo = Repository::current();
+    let conflicts = merge::read_conflicts()?;
+    if !conflicts.is_empty() {
+        anyhow::bail!(
+            "Committing is not possible because you have unmerged files: {}\n\
+             Fix them up in the work tree, then use 'rit add <file>'",
+            conflicts.join(", ")
+        );
+    }
+
+    let head = refs::head_commit(repo)?
+        .ok_or_else(|| anyhow::anyhow!("HEAD does not point to a commit yet"))?;
+    let tree = commands::write_tree()?;
+    // Resolving the conflicts may have left nothing to commit
+    if tree == Commit::read(repo, &head)?.tree {
+        merge::clear_state()?;
+        return Ok(None);
+    }
+
+    let commit = Commit::read(repo, hash)?;
+    let message = match fs::read_to_string(rit_dir().join("MERGE_MSG")) {
+        Ok(message) => message.strip_suffix('\n').unwrap_or(&message).to_string(),
+        Err(_) => message(&commit),
+    };
+    let new = commands::commit_tree_with_author(&tree, &[head], &message, &commit.author)?;
+    refs::advance_head(repo, &new, &format!("{}: {}", reason, subject(&commit)))?;
+    merge::clear_state()?;
+    Ok(Some(new))
+}
+
+/// The commit being cherry-picked, while its conflicts wait to be resolved
+pub fn cherry_pick_head() -> Result<Option<String>> {
+    let path = rit_dir().join("CHERRY_PICK_HEAD");
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(fs::read_to_string(path)?.trim().to_string()))
+}
+
+/// `rit cherry-pick <commit>`: replays the changes a commit made on top of HEAD
+pub fn cherry_pick(rev: &str) -> Result<()> {
+    let repo = Repository::current();
+    if cherry_pick_head()?.is_some() || merge::merge_head()?.is_some() {
+        anyhow::bail!("cherry-pick is not possible while a merge or cherry-pick is in progress");
+    }
+    require_clean("cherry-pick")?;
+
+    let hash = revision::resolve_commit(repo, rev)?;
+    match pick(&hash, "cherry-pick")? {
+        Pick::Done(new) => println!("[{}] {}", &new[..7], subject(&Commit::read(repo, &new)?)),
+        Pick::Empty => anyhow::bail!(
+            "The previous cherry-pick is now empty, possibly due to conflict resolution.\n\
+             Nothing was committed."
+        ),
+        Pick::Conflicts => {
+            fs::write(rit_dir().join("CHERRY_PICK_HEAD"), format!("{}\n", hash))?;
+            println!("hint: After resolving the conflicts, mark them with \"rit add <paths>\"");
+            println!("hint: and run \"rit cherry-pick --continue\".");
+        }
+    }
+    Ok(())
+}
+
+/// `rit cherry-pick --continue`: commits the resolved conflicts of a stopped cherry-pick
+pub fn cherry_pick_continue() -> Result<()> {
+    let hash = cherry_pick_head()?.ok_or_else(|| anyhow::anyhow!("no cherry-pick in progress"))?;
+    match commit_resolved(&hash, "cherry-pick")? {
+        Some(new) => println!("[{}] {}", &new[..7], subject(&Commit::read(Repository::current(), &new)?)),
+        None => println!("The cherry-pick is now empty, nothing was committed."),
+    }
+    Ok(())
+}
+
+/// `rit cherry-pick --abort`: back to HEAD as it was before the cherry-pick
+pub fn cherry_pick_abort() -> Result<()> {
+    if cherry_pick_head()?.is_none() {
+        anyhow::bail!("no cherry-pick in progress");
+    }
+    merge::discard_changes()
+}
+
+// Rebase state, kept in the same files as git's `.git/rebase-merge`
+fn state_dir() -> PathBuf {
+    rit_dir().join("rebase-merge")
+}
+
+fn read_state(name: &str) -> Result<String> {
+    Ok(fs::read_to_string(state_dir().join(name))?.trim_end().to_string())
+}
+
+fn write_state(name: &str, content: &str) -> Result<()> {
+    fs::write(state_dir().join(name), content)?;
+    Ok(())
+}
+
+fn in_progress() -> bool {
+    state_dir().is_dir()
+}
+
+/// The commit a rebase in progress replays onto
+pub fn rebase_onto() -> Result<Option<String>> {
+    if !in_progress() {
+        return Ok(None);
+    }
+    Ok(Some(read_state("onto")?))
+}
+
+// Commits reachable from `head` but not from `upstream`, oldest first, without merges
+fn commits_to_replay(head: &str, upstream: &str) -> Result<Vec<String>> {
+    let repo = Repository::current();
+    let mut excluded = HashSet::new();
+    let mut queue = vec![upstream.to_string()];
+    while let Some(hash) = queue.pop() {
+        if excluded.insert(hash.clone()) {
+            queue.extend(refs::commit_parents(repo, &hash)?);
+        }
+    }
+
+    // Depth first, emitting a commit once all its parents have been emitted
+    let mut order = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![(head.to_string(), false)];
+    while let Some((hash, expanded)) = stack.pop() {
+        if expanded {
+            order.push(hash);
+            continue;
+        }
+        if excluded.contains(&hash) || !seen.insert(hash.clone()) {
+            continue;
+        }
+        stack.push((hash.clone(), true));
+        for parent in refs::commit_parents(repo, &hash)?.into_iter().rev() {
+            stack.push((parent, false));
+        }
+    }
+
+    let mut commits = Vec::new();
+    for hash in order {
+        if Commit::read(repo, &hash)?.parents.len() <= 1 {
+            commits.push(hash);
+        }
+    }
+    Ok(commits)
+}
+
+/// `rit rebase <upstream>`: replays the commits of the current branch that aren't in
+/// `upstream` on top of it, one at a time, then moves the branch to the result
+pub fn rebase(upstream: &str) -> Result<()> {
+    let repo = Repository::current();
+    if in_progress() {
+        anyhow::bail!(
+            "It seems that there is already a rebase-merge directory.\n\
+             Use \"rit rebase --continue\", \"rit rebase --skip\" or \"rit rebase --abort\"."
+        );
+    }
+    if merge::merge_head()?.is_some() || cherry_pick_head()?.is_some() {
+        anyhow::bail!("cannot rebase while a merge or cherry-pick is in progress");
+    }
+    require_clean("rebase")?;
+
+    let head = refs::head_commit(repo)?
+        .ok_or_else(|| anyhow::anyhow!("HEAD does not point to a commit yet"))?;
+    let onto = revision::resolve_commit(repo, upstream)?;
+    let head_name = match refs::read_head(repo)? {
+        Head::Branch(ref_name) => ref_name,
+        Head::Detached(_) => "detached HEAD".to_string(),
+    };
+
+    if refs::is_ancestor(repo, &onto, &head)? {
+        println!("Current branch {} is up to date.", head_name.strip_prefix("refs/heads/").unwrap_or(&head_name));
+        return Ok(());
+    }
+
+    let todo: Vec<String> = commits_to_replay(&head, &onto)?
+        .iter()
+        .map(|hash| Ok(format!("pick {} {}\n", hash, subject(&Commit::read(repo, hash)?))))
+        .collect::<Result<_>>()?;
+
+    fs::create_dir_all(state_dir())?;
+    write_state("head-name", &format!("{}\n", head_name))?;
+    write_state("onto", &format!("{}\n", onto))?;
+    write_state("orig-head", &format!("{}\n", head))?;
+    write_state("git-rebase-todo", &todo.concat())?;
+    write_state("done", "")?;
+
+    // Work on a detached HEAD so the branch only moves once everything is replayed
+    let head_files = status::head_files()?;
+    let onto_files = commands::flatten_tree(&Commit::read(repo, &onto)?.tree)?;
+    merge::check_untracked(&head_files, &onto_files)?;
+    commands::update_worktree(&head_files, &onto_files)?;
+    index::write_index(&onto_files)?;
+    fs::write(rit_dir().join("HEAD"), format!("{}\n", onto))?;
+    reflog::append(repo, "HEAD", Some(&head), &onto, &format!("rebase (start): checkout {}", upstream))?;
+
+    run()
+}
+
+// Picks the remaining commits of the todo list, stopping at the first conflict
+fn run() -> Result<()> {
+    require_clean("rebase")?;
+    loop {
+        let todo = read_state("git-rebase-todo")?;
+        let mut lines = todo.lines().filter(|line| !line.is_empty());
+        let Some(line) = lines.next() else {
+            return finish();
+        };
+        let rest: String = lines.map(|line| format!("{}\n", line)).collect();
+
+        let mut done = fs::read_to_string(state_dir().join("done"))?;
+        done.push_str(line);
+        done.push('\n');
+        write_state("done", &done)?;
+        write_state("git-rebase-todo", &rest)?;
+
+        let hash = line.split(' ').nth(1).unwrap_or_default();
+        if let Pick::Conflicts = pick(hash, "rebase (pick)")? {
+            println!("hint: Resolve all conflicts manually, mark them as resolved with");
+            println!("hint: \"rit add <pathspec>\", then run \"rit rebase --continue\".");
+            println!("hint: You can instead skip this commit: run \"rit rebase --skip\".");
+            println!("hint: To abort and get back to the state before \"rit rebase\", run \"rit rebase --abort\".");
+            return Ok(());
+        }
+    }
+}
+
+// Points the rebased branch at the new history and checks it out again
+fn finish() -> Result<()> {
+    let repo = Repository::current();
+    let head_name = read_state("head-name")?;
+    let onto = read_state("onto")?;
+    let head = refs::head_commit(repo)?.unwrap_or_default();
+
+    if head_name != "detached HEAD" {
+        refs::write_ref(repo, &head_name, &head, &format!("rebase (finish): {} onto {}", head_name, onto))?;
+        refs::set_head_branch(repo, &head_name)?;
+        let reason = format!("rebase (finish): returning to {}", head_name);
+        reflog::append(repo, "HEAD", Some(&head), &head, &reason)?;
+    }
+    fs::remove_dir_all(state_dir())?;
+    println!("Successfully rebased and updated {}.", head_name);
+    Ok(())
+}
+
+// The commit the rebase stopped at: the last one moved to "done"
+fn stopped_commit() -> Result<String> {
+    let done = read_state("done")?;
+    let line = done.lines().last().ok_or_else(|| anyhow::anyhow!("no commit to continue with"))?;
+    Ok(line.split(' ').nth(1).unwrap_or_default().to_string())
+}
+
+fn require_in_progress() -> Result<()> {
+    if !in_progress() {
+        anyhow::bail!("No rebase in progress?");
+    }
+    Ok(())
+}
+
+/// `rit rebase --continue`: commits the resolved conflicts and picks the rest
+pub fn rebase_continue() -> Result<()> {
+    require_in_progress()?;
+    if merge::read_conflicts()?.is_empty() && status::compute()?.is_clean() {
+        // Nothing staged: the user resolved the pick to nothing, or committed it already
+        merge::clear_state()?;
+    } else {
+        commit_resolved(&stopped_commit()?, "rebase (continue)")?;
+    }
+    run()
+}
+
+/// `rit rebase --skip`: drops the commit the rebase stopped at and picks the rest
+pub fn rebase_skip() -> Result<()> {
+    require_in_progress()?;
+    merge::discard_changes()?;
+    run()
+}
+
+/// `rit rebase --abort`: back to the branch as it was before the rebase
+pub fn rebase_abort() -> Result<()> {
+    let repo = Repository::current();
+    require_in_progress()?;
+    let head_name = read_state("head-name")?;
+    let orig_head = read_state("orig-head")?;
+
+    merge::discard_changes()?;
+    let head_files = status::head_files()?;
+    let orig_files = commands::flatten_tree(&Commit::read(repo, &orig_head)?.tree)?;
+    commands::update_worktree(&head_files, &orig_files)?;
+    index::write_index(&orig_files)?;
+
+    let current = refs::head_commit(repo)?;
+    if head_name == "detached HEAD" {
+        fs::write(rit_dir().join("HEAD"), format!("{}\n", orig_head))?;
+    } else {
+        refs::set_head_branch(repo, &head_name)?;
+    }
+    let reason = format!("rebase (abort): returning to {}", head_name);
+    reflog::append(repo, "HEAD", current.as_deref(), &orig_head, &reason)?;
+
+    fs::remove_dir_all(state_dir())?;
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use anyhow::Result;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use walkdir::WalkDir;
+
+use crate::objects::Signature;
+use crate::refs::{self, Head};
+use crate::repository::Repository;
+use crate::revision;
+
+/// One move of a ref, as recorded in its log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// All zeros when the ref was created
+    pub old: String,
+    pub new: String,
+    pub message: String,
+}
+
+fn log_path(repo: &Repository, ref_name: &str) -> PathBuf {
+    repo.rit_dir().join("logs").join(ref_name)
+}
+
+/// Like git's `core.logAllRefUpdates`: HEAD, branches and remote-tracking branches
+/// keep a log, tags don't. The stash keeps its entries in its log.
+pub fn is_logged(ref_name: &str) -> bool {
+    ref_name == "HEAD"
+        || ref_name == "refs/stash"
+        || ref_name.starts_with("refs/heads/")
+        || ref_name.starts_with("refs/remotes/")
+}
+
+/// Records a move of `ref_name` in git's reflog format:
+/// "<old> <new> Name <email> 1700000000 +0100\t<message>"
+pub fn append(repo: &Repository, ref_name: &str, old: Option<&str>, new: &str, message: &str) -> Result<()> {
+    if !is_logged(ref_name) {
+        return Ok(());
+    }
+    let path = log_path(repo, ref_name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let who = Signature::current("committer")?;
+    let message = message.lines().next().unwrap_or_default();
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let old = old.map_or_else(|| repo.format().zero_id(), str::to_string);
+    writeln!(file, "{} {} {}\t{}", old, new, who, message)?;
+    Ok(())
+}
+
+// One line of a log. Garbled lines give None, and are skipped rather than handed to
+// whatever resolves them.
+fn parse_line(repo: &Repository, line: &str) -> Option<Entry> {
+    let (header, message) = line.split_once('\t').unwrap_or((line, ""));
+    let mut fields = header.splitn(3, ' ');
+    let (old, new) = (fields.next()?, fields.next()?);
+    if !repo.format().is_hash(old) || !repo.format().is_hash(new) {
+        return None;
+    }
+    Some(Entry {
+        old: old.to_string(),
+        new: new.to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// The entries logged for `ref_name`, newest first. Empty if it has no log.
+pub fn entries(repo: &Repository, ref_name: &str) -> Result<Vec<Entry>> {
+    let path = log_path(repo, ref_name);
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(path)?;
+    let mut entries: Vec<Entry> = content.lines().filter_map(|line| parse_line(repo, line)).collect();
+    entries.reverse();
+    Ok(entries)
+}
+
+/// The messages logged for `ref_name`, newest first
+pub fn messages(repo: &Repository, ref_name: &str) -> Result<Vec<String>> {
+    Ok(entries(repo, ref_name)?.into_iter().map(|entry| entry.message).collect())
+}
+
+/// Deletes entry `n` (counted from the newest, as `entries` lists them) from a log. The next
+/// newer entry takes over its old value so the log still reads as a chain of moves.
+pub fn delete_entry(repo: &Repository, ref_name: &str, n: usize) -> Result<()> {
+    let path = log_path(repo, ref_name);
+    let content = fs::read_to_string(&path)?;
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+
+    // Line numbers of the entries, oldest first, so garbled lines don't shift the count
+    let valid: Vec<usize> = (0..lines.len()).filter(|&i| parse_line(repo, &lines[i]).is_some()).collect();
+    if n >= valid.len() {
+        anyhow::bail!("log for '{}' only has {} entries", ref_name, valid.len());
+    }
+
+    let at = valid[valid.len() - 1 - n];
+    let removed = lines.remove(at);
+    if let Some(&newer) = valid.get(valid.len() - n)
+        && let (Some((old, _)), Some((_, rest))) = (removed.split_once(' '), lines[newer - 1].split_once(' '))
+    {
+        lines[newer - 1] = format!("{} {}", old, rest);
+    }
+
+    let mut content = lines.join("\n");
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Drops the log of a deleted ref
+pub fn remove(repo: &Repository, ref_name: &str) -> Result<()> {
+    let path = log_path(repo, ref_name);
+    if path.is_file() {
+        fs::remove_file(&path)?;
+        refs::remove_empty_parents(&path, &log_path(repo, "refs"));
+    }
+    Ok(())
+}
+
+/// Moves a renamed branch's log along with it
+pub fn rename(repo: &Repository, old: &str, new: &str) -> Result<()> {
+    let (from, to) = (log_path(repo, old), log_path(repo, new));
+    if from.is_file() {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&from, to)?;
+        refs::remove_empty_parents(&from, &log_path(repo, "refs"));
+    }
+    Ok(())
+}
+
+/// Every object any log still mentions, so gc keeps what `@{n}` can reach
+pub fn all_objects(repo: &Repository) -> Result<Vec<String>> {
+    let root = repo.rit_dir().join("logs");
+    let mut objects = Vec::new();
+    if !root.exists() {
+        return Ok(objects);
+    }
+
+    let zero = repo.format().zero_id();
+    for file in WalkDir::new(&root) {
+        let file = file?;
+        if !file.file_type().is_file() {
+            continue;
+        }
+        let Some(ref_name) = file.path().strip_prefix(&root)?.to_str() else {
+            continue;
+        };
+        for entry in entries(repo, ref_name)? {
+            objects.extend([entry.old, entry.new].into_iter().filter(|hash| *hash != zero));
+        }
+    }
+    Ok(objects)
+}
+
+/// Logs HEAD moving to `target` (a branch name or commit) at `new`.
+/// Called before HEAD itself is rewritten, so the branch being left can be named.
+pub fn log_checkout(repo: &Repository, target: &str, new: &str) -> Result<()> {
+    let from = match repo.head()? {
+        Head::Branch(ref_name) => ref_name.strip_prefix("refs/heads/").unwrap_or(&ref_name).to_string(),
+        Head::Detached(hash) => hash,
+    };
+    let message = format!("checkout: moving from {} to {}", from, target);
+    append(repo, "HEAD", repo.head_commit()?.as_deref(), new, &message)
+}
+
+/// `rit reflog [ref]`: where a ref (HEAD by default) has been, newest first
+pub fn show(repo: &Repository, name: &str) -> Result<()> {
+    let ref_name = revision::full_ref_name(repo, name)?
+        .ok_or_else(|| anyhow::anyhow!("ambiguous argument '{}': unknown revision or path", name))?;
+
+    for (n, entry) in entries(repo, &ref_name)?.iter().enumerate() {
+        let short = &entry.new[..7.min(entry.new.len())];
+        println!("{} {}@{{{}}}: {}", short, name, n, entry.message);
+    }
+    Ok(())
+}
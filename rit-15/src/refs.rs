@@ -0,0 +1,268 @@
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+use crate::objects::Commit;
+use crate::reflog;
+use crate::repository::Repository;
+
+/// What HEAD currently points at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Head {
+    /// A branch, stored as the full ref name (e.g. "refs/heads/main")
+    Branch(String),
+    /// A commit hash written directly into HEAD
+    Detached(String),
+}
+
+pub fn read_head(repo: &Repository) -> Result<Head> {
+    repo.head()
+}
+
+/// Points HEAD at a branch (symbolic) without touching the working tree
+pub fn set_head_branch(repo: &Repository, ref_name: &str) -> Result<()> {
+    repo.set_head_branch(ref_name)
+}
+
+/// Moves the current branch (or a detached HEAD) to `commit_hash`, logging `reason`
+pub fn advance_head(repo: &Repository, commit_hash: &str, reason: &str) -> Result<()> {
+    match repo.head()? {
+        Head::Branch(ref_name) => write_ref(repo, &ref_name, commit_hash, reason),
+        Head::Detached(old) => {
+            repo.set_head_detached(commit_hash)?;
+            reflog::append(repo, "HEAD", Some(&old), commit_hash, reason)
+        }
+    }
+}
+
+/// Name of the checked out branch, or None when HEAD is detached
+pub fn current_branch(repo: &Repository) -> Result<Option<String>> {
+    match repo.head()? {
+        Head::Branch(ref_name) => Ok(Some(
+            ref_name
+                .strip_prefix("refs/heads/")
+                .unwrap_or(&ref_name)
+                .to_string(),
+        )),
+        Head::Detached(_) => Ok(None),
+    }
+}
+
+/// The commit HEAD resolves to. None on an unborn branch (before the first commit).
+pub fn head_commit(repo: &Repository) -> Result<Option<String>> {
+    repo.head_commit()
+}
+
+/// Reads a ref like "refs/heads/main", returning None if it doesn't exist
+pub fn read_ref(repo: &Repository, ref_name: &str) -> Result<Option<String>> {
+    repo.read_ref(ref_name)
+}
+
+/// True if the ref exists, loose or packed
+pub fn ref_exists(repo: &Repository, ref_name: &str) -> Result<bool> {
+    repo.has_ref(ref_name)
+}
+
+/// A ref stored in `packed-refs`. Annotated tags are followed by a "^" line with the
+/// object they peel to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRef {
+    pub name: String,
+    pub hash: String,
+    pub peeled: Option<String>,
+}
+
+/// Parses a `packed-refs` file: "<hash> <name>" lines, each optionally followed by a
+/// "^<hash>" peel line, after a "# pack-refs with:" header
+pub fn parse_packed_refs(text: &str) -> Vec<PackedRef> {
+    let mut refs: Vec<PackedRef> = Vec::new();
+    for line in text.lines() {
+        if let Some(peeled) = line.strip_prefix('^') {
+            if let Some(last) = refs.last_mut() {
+                last.peeled = Some(peeled.trim().to_string());
+            }
+        } else if let Some((hash, name)) = line.split_once(' ')
+            && !line.starts_with('#')
+        {
+            refs.push(PackedRef { name: name.trim().to_string(), hash: hash.to_string(), peeled: None });
+        }
+    }
+    refs
+}
+
+/// Writes refs back in the layout git reads, sorted by name
+pub fn format_packed_refs(refs: &[PackedRef]) -> String {
+    let mut refs = refs.to_vec();
+    refs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut text = "# pack-refs with: peeled fully-peeled sorted \n".to_string();
+    for packed in refs {
+        text.push_str(&format!("{} {}\n", packed.hash, packed.name));
+        if let Some(peeled) = packed.peeled {
+            text.push_str(&format!("^{}\n", peeled));
+        }
+    }
+    text
+}
+
+/// Points a ref at `oid` and records the move and its `reason` in the ref's log,
+/// and in HEAD's too when HEAD is on that branch
+pub fn write_ref(repo: &Repository, ref_name: &str, oid: &str, reason: &str) -> Result<()> {
+    let old = repo.read_ref(ref_name)?;
+    repo.set_ref(ref_name, oid)?;
+
+    reflog::append(repo, ref_name, old.as_deref(), oid, reason)?;
+    if let Ok(Head::Branch(current)) = repo.head()
+        && current == ref_name
+    {
+        reflog::append(repo, "HEAD", old.as_deref(), oid, reason)?;
+    }
+    Ok(())
+}
+
+/// Points a ref at `oid` without logging the move, for when the log itself is being
+/// rewritten (`rit stash drop`)
+pub fn set_ref(repo: &Repository, ref_name: &str, oid: &str) -> Result<()> {
+    repo.set_ref(ref_name, oid)
+}
+
+/// Deletes a ref along with its log
+pub fn delete_ref(repo: &Repository, ref_name: &str) -> Result<()> {
+    repo.delete_ref(ref_name)?;
+    reflog::remove(repo, ref_name)
+}
+
+/// Cleans up directories left empty below `root` by names like "feature/login"
+pub fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut dir = path;
+    while let Some(parent) = dir.parent() {
+        if parent == root || !parent.starts_with(root) {
+            break;
+        }
+        if fs::remove_dir(parent).is_err() {
+            // Not empty
+            break;
+        }
+        dir = parent;
+    }
+}
+
+/// All refs under a prefix such as "refs/heads", as (short name, hash) sorted by name
+pub fn list_refs(repo: &Repository, prefix: &str) -> Result<Vec<(String, String)>> {
+    repo.refs(prefix)
+}
+
+/// Rejects names git's check-ref-format would refuse, so refs stay usable by both tools
+pub fn validate_branch_name(name: &str) -> Result<()> {
+    if !is_valid_ref_name(name) {
+        anyhow::bail!("'{}' is not a valid branch name", name);
+    }
+    Ok(())
+}
+
+pub fn validate_tag_name(name: &str) -> Result<()> {
+    if !is_valid_ref_name(name) {
+        anyhow::bail!("'{}' is not a valid tag name", name);
+    }
+    Ok(())
+}
+
+fn is_valid_ref_name(name: &str) -> bool {
+    let invalid = name.is_empty()
+        || name == "HEAD"
+        || name.starts_with('-')
+        || name.starts_with('/')
+        || name.ends_with('/')
+        || name.ends_with('.')
+        || name.ends_with(".lock")
+        || name.contains("..")
+        || name.contains("//")
+        || name.contains("@{")
+        || name.split('/').any(|part| part.starts_with('.'))
+        || name
+            .chars()
+            .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c));
+    !invalid
+}
+
+/// Parent hashes of a commit, in the order they are recorded
+pub fn commit_parents(repo: &Repository, commit_hash: &str) -> Result<Vec<String>> {
+    Ok(Commit::read(repo, commit_hash)?.parents)
+}
+
+/// True if `ancestor` is reachable from `descendant` by following parents
+pub fn is_ancestor(repo: &Repository, ancestor: &str, descendant: &str) -> Result<bool> {
+    let mut pending = vec![descendant.to_string()];
+    let mut seen = std::collections::HashSet::new();
+
+    while let Some(hash) = pending.pop() {
+        if hash == ancestor {
+            return Ok(true);
+        }
+        if seen.insert(hash.clone()) {
+            pending.extend(commit_parents(repo, &hash)?);
+        }
+    }
+    Ok(false)
+}
+
+/// Best common ancestor of two commits, None if their histories are unrelated
+pub fn merge_base(repo: &Repository, a: &str, b: &str) -> Result<Option<String>> {
+    let mut ancestors_of_a = std::collections::HashSet::new();
+    let mut pending = vec![a.to_string()];
+    while let Some(hash) = pending.pop() {
+        if ancestors_of_a.insert(hash.clone()) {
+            pending.extend(commit_parents(repo, &hash)?);
+        }
+    }
+
+    // Walk b's history breadth first, stopping at the first shared commits on each path
+    let mut candidates = Vec::new();
+    let mut queue = std::collections::VecDeque::from([b.to_string()]);
+    let mut seen = std::collections::HashSet::new();
+    while let Some(hash) = queue.pop_front() {
+        if !seen.insert(hash.clone()) {
+            continue;
+        }
+        if ancestors_of_a.contains(&hash) {
+            candidates.push(hash);
+            continue;
+        }
+        queue.extend(commit_parents(repo, &hash)?);
+    }
+
+    // A candidate reachable from another candidate is not a *best* common ancestor
+    for candidate in &candidates {
+        let mut redundant = false;
+        for other in &candidates {
+            if other != candidate && is_ancestor(repo, candidate, other)? {
+                redundant = true;
+                break;
+            }
+        }
+        if !redundant {
+            return Ok(Some(candidate.clone()));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_refs_round_trip() {
+        let text = "# pack-refs with: peeled fully-peeled sorted \n\
+                    1111111111111111111111111111111111111111 refs/heads/main\n\
+                    2222222222222222222222222222222222222222 refs/tags/v1\n\
+                    ^1111111111111111111111111111111111111111\n";
+        let refs = parse_packed_refs(text);
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs[0].name, "refs/heads/main");
+        assert_eq!(refs[0].peeled, None);
+        assert_eq!(refs[1].hash, "2".repeat(40));
+        assert_eq!(refs[1].peeled.as_deref(), Some("1".repeat(40).as_str()));
+        assert_eq!(format_packed_refs(&refs), text);
+    }
+}
@@ -0,0 +1,286 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::commands;
+use crate::config;
+use crate::objects::ObjectDatabase;
+use crate::pack;
+use crate::refs;
+use crate::repository::Repository;
+use crate::transport::{self, Advertisement, Connection};
+
+fn short(hash: &str) -> &str {
+    &hash[..7.min(hash.len())]
+}
+
+fn remote_url(remote: &str) -> Result<String> {
+    config::get(&format!("remote.{}.url", remote))?
+        .ok_or_else(|| anyhow::anyhow!("'{}' does not appear to be a rit repository", remote))
+}
+
+/// Every commit reachable from our branches and remote-tracking refs, offered
+/// as "have"s so the other side can leave them out of the pack
+fn local_commits() -> Result<Vec<String>> {
+    let repo = Repository::current();
+    let mut pending: Vec<String> = Vec::new();
+    for prefix in ["refs/heads", "refs/remotes"] {
+        pending.extend(refs::list_refs(repo, prefix)?.into_iter().map(|(_, hash)| hash));
+    }
+
+    let mut seen = HashSet::new();
+    let mut commits = Vec::new();
+    while let Some(hash) = pending.pop() {
+        if !seen.insert(hash.clone()) {
+            continue;
+        }
+        pending.extend(refs::commit_parents(repo, &hash)?);
+        commits.push(hash);
+    }
+    Ok(commits)
+}
+
+// How a ref moved, in the column git prints before "name -> name"
+fn describe_update(old: Option<&str>, new: &str, kind: &str) -> Result<String> {
+    let repo = Repository::current();
+    let summary = match old {
+        None => format!(" * [new {}]", kind),
+        Some(old) if refs::is_ancestor(repo, old, new)? => format!("   {}..{}", short(old), short(new)),
+        Some(old) => format!(" + {}...{}", short(old), short(new)),
+    };
+    Ok(format!("{:<20}", summary))
+}
+
+/// `rit fetch`: downloads what the remote has and we don't, moving its branches
+/// to `refs/remotes/<remote>/*` and creating tags we don't have yet
+pub fn fetch(remote: &str) -> Result<Advertisement> {
+    let url = remote_url(remote)?;
+    let connection = Connection::open(&transport::remote_path(&url)?, "upload-pack")?;
+    fetch_from(remote, &url, connection)
+}
+
+fn fetch_from(remote: &str, url: &str, connection: Connection) -> Result<Advertisement> {
+    let repo = Repository::current();
+    let advertisement = connection.advertisement.clone();
+
+    let mut wants = Vec::new();
+    for (name, hash) in &advertisement.refs {
+        let wanted = name.starts_with("refs/heads/") || name.starts_with("refs/tags/");
+        if wanted && !repo.objects().contains(hash) && !wants.contains(hash) {
+            wants.push(hash.clone());
+        }
+    }
+
+    let haves = if wants.is_empty() { Vec::new() } else { local_commits()? };
+    connection.fetch_pack(&wants, &haves)?;
+
+    let mut updates = Vec::new();
+    for (name, hash) in &advertisement.refs {
+        if let Some(branch) = name.strip_prefix("refs/heads/") {
+            let tracking = format!("refs/remotes/{}/{}", remote, branch);
+            let old = refs::read_ref(repo, &tracking)?;
+            if old.as_deref() == Some(hash.as_str()) {
+                continue;
+            }
+
+            let line = describe_update(old.as_deref(), hash, "branch")?;
+            let reason = if old.is_none() {
+                "storing head"
+            } else if line.starts_with(" +") {
+                "fetch: forced-update"
+            } else {
+                "fetch: fast-forward"
+            };
+            refs::write_ref(repo, &tracking, hash, reason)?;
+            let forced = if line.starts_with(" +") { "  (forced update)" } else { "" };
+            updates.push(format!("{} {} -> {}/{}{}", line, branch, remote, branch, forced));
+        } else if let Some(tag) = name.strip_prefix("refs/tags/")
+            && refs::read_ref(repo, name)?.is_none()
+        {
+            refs::write_ref(repo, name, hash, "storing tag")?;
+            updates.push(format!("{} {} -> {}", describe_update(None, hash, "tag")?, tag, tag));
+        }
+    }
+
+    if !updates.is_empty() {
+        println!("From {}", url);
+        for update in updates {
+            println!("{}", update);
+        }
+    }
+    Ok(advertisement)
+}
+
+// Name of the directory `rit clone <url>` creates: the last path component without ".git"
+fn default_directory(url: &str) -> String {
+    let path = url.trim_end_matches('/');
+    let path = path.strip_suffix("/.git").unwrap_or(path);
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.strip_suffix(".git").unwrap_or(name).to_string()
+}
+
+/// `rit clone`: creates a repository in `directory`, fetches everything from `url`
+/// as remote "origin" and checks out its default branch
+pub fn clone(url: &str, directory: Option<&str>) -> Result<()> {
+    let directory = directory.map(|dir| dir.to_string()).unwrap_or_else(|| default_directory(url));
+    let target = Path::new(&directory);
+    if target.exists() && fs::read_dir(target)?.next().is_some() {
+        anyhow::bail!(
+            "destination path '{}' already exists and is not an empty directory.",
+            directory
+        );
+    }
+
+    // Remember the source as an absolute path, it is used from inside the clone
+    let source = transport::remote_path(url)?;
+    let url = match url.strip_prefix("file://") {
+        Some(_) => format!("file://{}", source.display()),
+        None => source.display().to_string(),
+    };
+
+    println!("Cloning into '{}'...", directory);
+    let created = !target.exists();
+    fs::create_dir_all(target)?;
+    let previous = std::env::current_dir()?;
+    std::env::set_current_dir(target)?;
+
+    let result = clone_into(&url);
+    if result.is_err() {
+        // Leave nothing half-cloned behind
+        std::env::set_current_dir(&previous)?;
+        if created {
+            fs::remove_dir_all(&directory)?;
+        } else {
+            fs::remove_dir_all(Path::new(&directory).join(commands::rit_dir()))?;
+        }
+    }
+    result
+}
+
+fn clone_into(url: &str) -> Result<()> {
+    // The clone names its objects the same way as the remote. The repository is only
+    // opened once it exists, so it picks that format up.
+    let connection = Connection::open(&transport::remote_path(url)?, "upload-pack")?;
+    commands::init_repository(connection.advertisement.object_format()?)?;
+    let repo = Repository::current();
+    config::set("remote.origin.url", url)?;
+
+    let advertisement = fetch_from("origin", url, connection)?;
+
+    // The branch HEAD points at over there, or failing that one at the same commit
+    let head = advertisement.get("HEAD");
+    let branch = advertisement
+        .head_branch()
+        .filter(|name| advertisement.get(name).is_some())
+        .map(|name| name.to_string())
+        .or_else(|| {
+            advertisement
+                .refs
+                .iter()
+                .find(|(name, hash)| name.starts_with("refs/heads/") && Some(hash.as_str()) == head)
+                .map(|(name, _)| name.clone())
+        });
+
+    let Some(branch) = branch else {
+        println!("warning: You appear to have cloned an empty repository.");
+        return Ok(());
+    };
+    let commit = advertisement.get(&branch).unwrap_or_default().to_string();
+
+    // HEAD is still unborn, so this writes every file of the commit
+    commands::checkout_commit(&commit)?;
+    refs::write_ref(repo, &branch, &commit, &format!("clone: from {}", url))?;
+    refs::set_head_branch(repo, &branch)?;
+    Ok(())
+}
+
+/// `rit push`: sends a branch (the current one by default) to the same name on the
+/// remote. Without `force` the remote branch must be an ancestor of ours.
+pub fn push(remote: &str, branch: Option<&str>, force: bool) -> Result<()> {
+    let repo = Repository::current();
+    let url = remote_url(remote)?;
+    let branch = match branch {
+        Some(branch) => branch.to_string(),
+        None => refs::current_branch(repo)?
+            .ok_or_else(|| anyhow::anyhow!("You are not currently on a branch."))?,
+    };
+    let ref_name = format!("refs/heads/{}", branch);
+    let new = refs::read_ref(repo, &ref_name)?
+        .ok_or_else(|| anyhow::anyhow!("src refspec {} does not match any", branch))?;
+
+    let connection = Connection::open(&transport::remote_path(&url)?, "receive-pack")?;
+    let old = connection.advertisement.get(&ref_name).map(|hash| hash.to_string());
+
+    if old.as_deref() == Some(new.as_str()) {
+        connection.cancel()?;
+        println!("Everything up-to-date");
+        return Ok(());
+    }
+
+    if let Some(old) = old.as_deref().filter(|_| !force) {
+        let reason = if !repo.objects().contains(old) {
+            Some("fetch first")
+        } else if !refs::is_ancestor(repo, old, &new)? {
+            Some("non-fast-forward")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            connection.cancel()?;
+            anyhow::bail!(
+                "failed to push some refs to '{}'\n ! [rejected]        {} -> {} ({})\n\
+                 hint: Updates were rejected because the remote contains work that you do not\n\
+                 hint: have locally. Fetch and merge it before pushing again.",
+                url,
+                branch,
+                branch,
+                reason
+            );
+        }
+    }
+
+    // Leave out everything reachable from the remote's refs that we already have
+    let known: Vec<String> = connection
+        .advertisement
+        .refs
+        .iter()
+        .map(|(_, hash)| hash.clone())
+        .filter(|hash| repo.objects().contains(hash))
+        .collect();
+    let exclude: HashSet<String> = pack::reachable_objects(repo, known, &HashSet::new())?
+        .into_keys()
+        .collect();
+    let found = pack::reachable_objects(repo, [new.clone()], &exclude)?;
+    let written = pack::pack_objects(repo, &found, connection.advertisement.has("ofs-delta"))?;
+
+    let old_id = old.clone().unwrap_or_else(|| repo.format().zero_id());
+    let results = connection.send_pack(&[(old_id, new.clone(), ref_name.clone())], &written.data)?;
+
+    let refused = results
+        .iter()
+        .find(|(name, _)| *name == ref_name)
+        .map(|(_, reason)| reason.clone())
+        .unwrap_or_else(|| Some("no status reported".to_string()));
+    if let Some(reason) = refused {
+        anyhow::bail!(
+            "failed to push some refs to '{}'\n ! [remote rejected] {} -> {} ({})",
+            url,
+            branch,
+            branch,
+            reason
+        );
+    }
+
+    let line = match old.as_deref() {
+        // Forced over commits we never saw
+        Some(old) if !repo.objects().contains(old) => format!("{:<20}", format!(" + {}...{}", short(old), short(&new))),
+        old => describe_update(old, &new, "branch")?,
+    };
+    let forced = if line.starts_with(" +") { " (forced update)" } else { "" };
+    println!("To {}", url);
+    println!("{} {} -> {}{}", line, branch, branch, forced);
+
+    refs::write_ref(repo, &format!("refs/remotes/{}/{}", remote, branch), &new, "update by push")?;
+    Ok(())
+}
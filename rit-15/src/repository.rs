@@ -0,0 +1,232 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use walkdir::WalkDir;
+
+use crate::commands;
+use crate::config::ConfigFile;
+use crate::objects::{ObjectDatabase, ObjectFormat, ObjectStore};
+use crate::refs::{self, Head, PackedRef};
+
+/// A repository found on disk: its working tree, its repository directory (`.rit`, or
+/// whatever `--git-dir` or `RIT_DIR` names) and the objects stored there. The commands work
+/// on `Repository::current()`; the library can open any other.
+pub struct Repository {
+    work_tree: PathBuf,
+    rit_dir: PathBuf,
+    objects: ObjectStore,
+}
+
+impl Repository {
+    /// Opens the repository whose working tree is `path`. A relative repository directory
+    /// is looked for inside it.
+    pub fn open(path: impl AsRef<Path>) -> Result<Repository> {
+        let work_tree = path.as_ref().to_path_buf();
+        let rit_dir = work_tree.join(commands::rit_dir());
+        Repository::open_with_rit_dir(work_tree, rit_dir)
+    }
+
+    /// Opens a repository whose directory is not inside its working tree
+    pub fn open_with_rit_dir(
+        work_tree: impl Into<PathBuf>,
+        rit_dir: impl Into<PathBuf>,
+    ) -> Result<Repository> {
+        let (work_tree, rit_dir) = (work_tree.into(), rit_dir.into());
+        if !rit_dir.join("HEAD").is_file() {
+            anyhow::bail!("'{}' is not a rit repository", work_tree.display());
+        }
+
+        let config = match fs::read_to_string(rit_dir.join("config")) {
+            Ok(text) => ConfigFile::parse(&text),
+            Err(_) => ConfigFile::parse(""),
+        };
+        let format = ObjectFormat::from_config(&config)?;
+
+        Ok(Repository {
+            objects: ObjectStore::new(rit_dir.join("objects"), format),
+            work_tree,
+            rit_dir,
+        })
+    }
+
+    /// Finds the repository `path` is in, trying each parent directory in turn like git
+    pub fn discover(path: impl AsRef<Path>) -> Result<Repository> {
+        let start = std::path::absolute(path.as_ref())?;
+        for dir in start.ancestors() {
+            if dir.join(commands::rit_dir()).is_dir() {
+                return Repository::open(dir);
+            }
+        }
+        anyhow::bail!(
+            "not a rit repository (or any of the parent directories): {}",
+            commands::rit_dir().display()
+        )
+    }
+
+    /// The repository in the current directory, which the commands work on. Opened on first
+    /// use, so its packs are only listed once.
+    pub fn current() -> &'static Repository {
+        static CURRENT: OnceLock<Repository> = OnceLock::new();
+        CURRENT.get_or_init(|| {
+            let rit_dir = commands::rit_dir().to_path_buf();
+            // Commands check the format before they start; until `rit init` there is none
+            let format = ObjectFormat::configured().unwrap_or(ObjectFormat::Sha1);
+            Repository {
+                work_tree: PathBuf::from("."),
+                objects: ObjectStore::new(rit_dir.join("objects"), format),
+                rit_dir,
+            }
+        })
+    }
+
+    pub fn work_tree(&self) -> &Path {
+        &self.work_tree
+    }
+
+    pub fn rit_dir(&self) -> &Path {
+        &self.rit_dir
+    }
+
+    pub fn format(&self) -> ObjectFormat {
+        self.objects.format()
+    }
+
+    pub fn objects(&self) -> &ObjectStore {
+        &self.objects
+    }
+
+    pub fn head(&self) -> Result<Head> {
+        let head = fs::read_to_string(self.rit_dir.join("HEAD"))?;
+        let head = head.trim();
+        match head.strip_prefix("ref: ") {
+            Some(ref_name) => Ok(Head::Branch(ref_name.to_string())),
+            None => Ok(Head::Detached(head.to_string())),
+        }
+    }
+
+    /// Points HEAD at a branch (symbolic) without touching the working tree
+    pub fn set_head_branch(&self, ref_name: &str) -> Result<()> {
+        fs::write(self.rit_dir.join("HEAD"), format!("ref: {}\n", ref_name))?;
+        Ok(())
+    }
+
+    /// Detaches HEAD at a commit without touching the working tree
+    pub fn set_head_detached(&self, hash: &str) -> Result<()> {
+        fs::write(self.rit_dir.join("HEAD"), format!("{}\n", hash))?;
+        Ok(())
+    }
+
+    /// The commit HEAD resolves to. None on an unborn branch (before the first commit).
+    pub fn head_commit(&self) -> Result<Option<String>> {
+        match self.head()? {
+            Head::Branch(ref_name) => self.read_ref(&ref_name),
+            Head::Detached(hash) => Ok(Some(hash)),
+        }
+    }
+
+    /// Reads a ref like "refs/heads/main" from its file, else from `packed-refs`, following
+    /// symbolic refs like "ref: refs/remotes/origin/main". None if it doesn't exist.
+    pub fn read_ref(&self, ref_name: &str) -> Result<Option<String>> {
+        let mut name = ref_name.to_string();
+        // git gives up after five levels too
+        for _ in 0..5 {
+            let path = self.rit_dir.join(&name);
+            if !path.is_file() {
+                let packed = self.packed_refs()?.into_iter().find(|packed| packed.name == name);
+                return Ok(packed.map(|packed| packed.hash));
+            }
+            let content = fs::read_to_string(path)?;
+            match content.trim().strip_prefix("ref: ") {
+                Some(target) => name = target.to_string(),
+                None => return Ok(Some(content.trim().to_string())),
+            }
+        }
+        anyhow::bail!("ref {} is a symbolic ref that points too deep", ref_name)
+    }
+
+    /// True if the ref exists as a file or in `packed-refs`, whatever it points at
+    pub fn has_ref(&self, ref_name: &str) -> Result<bool> {
+        if self.rit_dir.join(ref_name).is_file() {
+            return Ok(true);
+        }
+        Ok(self.packed_refs()?.iter().any(|packed| packed.name == ref_name))
+    }
+
+    /// The refs git moved into `packed-refs` (on gc, clone or `git pack-refs`), sorted by name.
+    /// A ref that also has a file is shadowed by it.
+    pub fn packed_refs(&self) -> Result<Vec<PackedRef>> {
+        match fs::read_to_string(self.rit_dir.join("packed-refs")) {
+            Ok(text) => Ok(refs::parse_packed_refs(&text)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Points a ref at `oid`, creating it if needed. The move is not logged.
+    pub fn set_ref(&self, ref_name: &str, oid: &str) -> Result<()> {
+        let path = self.rit_dir.join(ref_name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, format!("{}\n", oid))?;
+        Ok(())
+    }
+
+    /// Deletes a ref, its file and its `packed-refs` line alike, leaving its log alone
+    pub fn delete_ref(&self, ref_name: &str) -> Result<()> {
+        let path = self.rit_dir.join(ref_name);
+        let loose = path.is_file();
+        if loose {
+            fs::remove_file(&path)?;
+            refs::remove_empty_parents(&path, &self.rit_dir.join("refs"));
+        }
+
+        let mut packed = self.packed_refs()?;
+        let count = packed.len();
+        packed.retain(|packed| packed.name != ref_name);
+        if packed.len() != count {
+            fs::write(self.rit_dir.join("packed-refs"), refs::format_packed_refs(&packed))?;
+        } else if !loose {
+            anyhow::bail!("ref {} does not exist", ref_name);
+        }
+        Ok(())
+    }
+
+    /// All refs under a prefix such as "refs/heads", as (short name, hash) sorted by name,
+    /// from their files and from `packed-refs`
+    pub fn refs(&self, prefix: &str) -> Result<Vec<(String, String)>> {
+        let mut refs = BTreeMap::new();
+        for packed in self.packed_refs()? {
+            if let Some(name) = packed.name.strip_prefix(prefix).and_then(|name| name.strip_prefix('/')) {
+                refs.insert(name.to_string(), packed.hash);
+            }
+        }
+
+        let root = self.rit_dir.join(prefix);
+        if !root.exists() {
+            return Ok(refs.into_iter().collect());
+        }
+
+        for entry in WalkDir::new(&root) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let name = entry
+                .path()
+                .strip_prefix(&root)?
+                .to_str()
+                .ok_or_else(|| anyhow::anyhow!("Invalid ref name {:?}", entry.path()))?
+                .to_string();
+            // Symbolic refs like refs/remotes/origin/HEAD list what they point at
+            if let Some(hash) = self.read_ref(&format!("{}/{}", prefix, name))? {
+                refs.insert(name, hash);
+            }
+        }
+
+        Ok(refs.into_iter().collect())
+    }
+}
@@ -0,0 +1,209 @@
+use anyhow::Result;
+use std::collections::BTreeSet;
+
+use crate::commands::{self, matches_pathspec};
+use crate::index::{self, Index};
+use crate::merge;
+use crate::objects::Commit;
+use crate::refs;
+use crate::repository::Repository;
+use crate::revision;
+use crate::status::{self, Change};
+
+/// How much of the repository `rit reset` moves along with HEAD
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Only the branch
+    Soft,
+    /// The branch and the index
+    Mixed,
+    /// The branch, the index and the working tree
+    Hard,
+}
+
+// The paths of `files` that any of `specs` names, failing for a spec that names none
+fn select<'a>(
+    files: impl Iterator<Item = &'a String> + Clone,
+    specs: &[String],
+) -> Result<BTreeSet<String>> {
+    let mut selected = BTreeSet::new();
+    for spec in specs {
+        let mut found = false;
+        for path in files.clone().filter(|path| matches_pathspec(path, spec)) {
+            selected.insert(path.clone());
+            found = true;
+        }
+        if !found {
+            anyhow::bail!("pathspec '{}' did not match any file(s) known to rit", spec);
+        }
+    }
+    Ok(selected)
+}
+
+/// `rit reset <rev>`: points the current branch (or a detached HEAD) at `rev`, then
+/// resets the index and, with `Mode::Hard`, the working tree to match it
+pub fn reset(rev: &str, mode: Mode) -> Result<()> {
+    let repo = Repository::current();
+    let target = revision::resolve_commit(repo, rev)?;
+
+    if mode == Mode::Soft && merge::merge_head()?.is_some() {
+        anyhow::bail!("Cannot do a soft reset in the middle of a merge.");
+    }
+
+    let target_files = commands::flatten_tree(&Commit::read(repo, &target)?.tree)?;
+    if mode == Mode::Hard {
+        // Only tracked files are reset, untracked ones are left alone
+        let index_map = index::read_index()?;
+        let head_files = status::head_files()?;
+        let tracked: Index = status::working_files()?
+            .into_iter()
+            .filter(|(path, _)| {
+                index_map.contains_key(path)
+                    || head_files.contains_key(path)
+                    || target_files.contains_key(path)
+            })
+            .collect();
+        commands::update_worktree(&tracked, &target_files)?;
+    }
+
+    refs::advance_head(repo, &target, &format!("reset: moving to {}", rev))?;
+
+    if mode != Mode::Soft {
+        merge::clear_state()?;
+        index::write_index(&target_files)?;
+    }
+
+    match mode {
+        Mode::Hard => {
+            let commit = Commit::read(repo, &target)?;
+            let message = commit.message_text();
+            println!("HEAD is now at {} {}", &target[..7], message.lines().next().unwrap_or(""));
+        }
+        Mode::Mixed => {
+            let unstaged = status::compute()?.unstaged;
+            if !unstaged.is_empty() {
+                println!("Unstaged changes after reset:");
+                for (path, change) in unstaged {
+                    let code = if change == Change::Deleted { 'D' } else { 'M' };
+                    println!("{}\t{}", code, path);
+                }
+            }
+        }
+        Mode::Soft => {}
+    }
+    Ok(())
+}
+
+/// `rit restore <paths>`: overwrites working tree files with their staged version, or with
+/// `staged`, sets the index entries back to HEAD. `source` takes the content from another
+/// commit instead.
+pub fn restore(paths: &[String], staged: bool, source: Option<&str>) -> Result<()> {
+    let mut index_map = index::read_index()?;
+
+    let source_files = match source {
+        Some(rev) => commands::flatten_tree(&revision::resolve_tree(Repository::current(), rev)?)?,
+        None if staged => status::head_files()?,
+        None => index_map.clone(),
+    };
+
+    // A path may be restored from the source or be tracked without being in the source,
+    // in which case restoring it means removing it
+    let known = source_files.keys().chain(index_map.keys());
+    let selected = select(known, paths)?;
+
+    if staged {
+        for path in &selected {
+            match source_files.get(path) {
+                Some(entry) => index_map.insert(path.clone(), entry.clone()),
+                None => index_map.remove(path),
+            };
+        }
+        return index::write_index(&index_map);
+    }
+
+    let conflicts = merge::read_conflicts()?;
+    if let Some(path) = selected.iter().find(|path| conflicts.contains(path)) {
+        anyhow::bail!("path '{}' is unmerged", path);
+    }
+
+    let on_disk: Index = status::working_files()?
+        .into_iter()
+        .filter(|(path, _)| selected.contains(path))
+        .collect();
+    let wanted: Index = source_files
+        .into_iter()
+        .filter(|(path, _)| selected.contains(path))
+        .collect();
+    commands::update_worktree(&on_disk, &wanted)
+}
+
+/// `rit rm <paths>`: stops tracking files and deletes them, or with `cached` only
+/// unstages them. Unless forced, files whose content would be lost are refused.
+pub fn rm(paths: &[String], cached: bool, recursive: bool, force: bool) -> Result<()> {
+    let mut index_map = index::read_index()?;
+
+    for spec in paths {
+        let spec = spec.trim_start_matches("./").trim_end_matches('/');
+        let is_dir = !index_map.contains_key(spec)
+            && index_map.keys().any(|path| matches_pathspec(path, spec));
+        if is_dir && !recursive {
+            anyhow::bail!("not removing '{}' recursively without -r", spec);
+        }
+    }
+    let selected = select(index_map.keys(), paths)?;
+
+    if !force {
+        let head_files = status::head_files()?;
+        let worktree = status::working_files()?;
+        for path in &selected {
+            let staged = index_map.get(path);
+            let on_disk = worktree.get(path);
+            let in_head = head_files.get(path);
+
+            // With --cached the file stays on disk, so only content found nowhere else counts
+            let problem = if cached {
+                (staged != in_head && on_disk.is_some() && staged != on_disk)
+                    .then_some("has staged content different from both the file and the HEAD")
+            } else if staged != in_head {
+                Some("has changes staged in the index")
+            } else if on_disk.is_some() && staged != on_disk {
+                Some("has local modifications")
+            } else {
+                None
+            };
+            if let Some(problem) = problem {
+                anyhow::bail!("the following file {}:\n    {}\n(use -f to force removal)", problem, path);
+            }
+        }
+    }
+
+    let mut removed = Index::new();
+    for path in &selected {
+        if let Some(entry) = index_map.remove(path) {
+            removed.insert(path.clone(), entry);
+        }
+        // Removing a conflicted file is one way of resolving it
+        merge::mark_resolved(path)?;
+        println!("rm '{}'", path);
+    }
+
+    if !cached {
+        commands::update_worktree(&removed, &Index::new())?;
+    }
+    index::write_index(&index_map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_pathspec() {
+        assert!(matches_pathspec("a.txt", "a.txt"));
+        assert!(matches_pathspec("src/main.rs", "src"));
+        assert!(matches_pathspec("src/main.rs", "./src/"));
+        assert!(matches_pathspec("src/main.rs", "."));
+        assert!(!matches_pathspec("src2/main.rs", "src"));
+        assert!(!matches_pathspec("src", "src/main.rs"));
+    }
+}
@@ -0,0 +1,281 @@
+use anyhow::Result;
+
+use crate::objects::{Commit, ObjectDatabase, Tag};
+use crate::reflog;
+use crate::refs::Head;
+use crate::repository::Repository;
+
+/// Shortest abbreviated object name that is looked up
+const MIN_ABBREV: usize = 4;
+
+/// What follows the name in a revision like "main~2^2^{tree}"
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    /// "~n": the n-th first-parent ancestor
+    Ancestor(usize),
+    /// "^n": the n-th parent, "^0" being the commit itself
+    Parent(usize),
+    /// "^{type}": dereference tags (and commits to trees) until a `type` is reached,
+    /// "^{}" stopping at the first object that isn't a tag
+    Peel(Option<String>),
+}
+
+// Splits "name~1^2" into the name and what to do with it
+fn parse(rev: &str) -> Result<(&str, Vec<Step>)> {
+    let invalid = || anyhow::anyhow!("not a valid object name: '{}'", rev);
+
+    // "@{...}" may contain anything, the name ends at the first "~" or "^" after it
+    let mut end = 0;
+    let bytes = rev.as_bytes();
+    while end < bytes.len() && bytes[end] != b'~' && bytes[end] != b'^' {
+        if bytes[end] == b'@' && bytes.get(end + 1) == Some(&b'{') {
+            end += rev[end..].find('}').ok_or_else(invalid)?;
+        }
+        end += 1;
+    }
+    let (name, mut rest) = rev.split_at(end);
+
+    let mut steps = Vec::new();
+    while let Some(op) = rest.chars().next() {
+        rest = &rest[op.len_utf8()..];
+
+        if op == '^' && rest.starts_with('{') {
+            let close = rest.find('}').ok_or_else(invalid)?;
+            let kind = &rest[1..close];
+            steps.push(Step::Peel((!kind.is_empty()).then(|| kind.to_string())));
+            rest = &rest[close + 1..];
+            continue;
+        }
+
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let count = match &rest[..digits] {
+            "" => 1,
+            number => number.parse().map_err(|_| invalid())?,
+        };
+        rest = &rest[digits..];
+
+        match op {
+            '~' => steps.push(Step::Ancestor(count)),
+            '^' => steps.push(Step::Parent(count)),
+            _ => return Err(invalid()),
+        }
+    }
+
+    Ok((name, steps))
+}
+
+/// The branch checked out before the n-th most recent checkout (n >= 1), from HEAD's reflog
+pub fn previous_branch(repo: &Repository, n: usize) -> Result<Option<String>> {
+    let previous = reflog::messages(repo, "HEAD")?
+        .into_iter()
+        .filter_map(|message| {
+            let moved = message.strip_prefix("checkout: moving from ")?;
+            Some(moved.split_once(" to ")?.0.to_string())
+        })
+        .nth(n.saturating_sub(1));
+    Ok(previous)
+}
+
+/// Turns "@{-n}" (and "-", short for "@{-1}") into the branch it names, leaving
+/// anything else as it is. Checkout and switch use this to stay on branches.
+pub fn expand_previous(repo: &Repository, name: &str) -> Result<String> {
+    let n = match name {
+        "-" => Some(1),
+        _ => name
+            .strip_prefix("@{-")
+            .and_then(|rest| rest.strip_suffix('}'))
+            .and_then(|n| n.parse().ok())
+            .filter(|&n: &usize| n > 0),
+    };
+    match n {
+        Some(n) => previous_branch(repo, n)?
+            .ok_or_else(|| anyhow::anyhow!("{} is not a valid revision, only {} checkouts so far", name, n - 1)),
+        None => Ok(name.to_string()),
+    }
+}
+
+/// The full name of the ref `name` refers to ("HEAD", "refs/heads/main", ...), trying
+/// the same places as git: refs spelled out in full, then tags, branches and remote
+/// branches.
+pub fn full_ref_name(repo: &Repository, name: &str) -> Result<Option<String>> {
+    if name == "HEAD" || name == "@" {
+        return Ok(Some("HEAD".to_string()));
+    }
+    if name.is_empty() || name.contains("..") {
+        return Ok(None);
+    }
+
+    for candidate in [
+        name.to_string(),
+        format!("refs/{}", name),
+        format!("refs/tags/{}", name),
+        format!("refs/heads/{}", name),
+        format!("refs/remotes/{}", name),
+        format!("refs/remotes/{}/HEAD", name),
+    ] {
+        if candidate.starts_with("refs/") && repo.has_ref(&candidate)? {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+// "main@{2}": where a ref was n moves ago according to its log. A bare "@{n}" reads the
+// current branch's log, like git.
+fn resolve_reflog(repo: &Repository, base: &str, n: usize) -> Result<Option<String>> {
+    let base = if base.is_empty() { "@" } else { base };
+    let ref_name = match (base, repo.head()?) {
+        ("@", Head::Branch(branch)) => branch,
+        _ => match full_ref_name(repo, base)? {
+            Some(ref_name) => ref_name,
+            None => return Ok(None),
+        },
+    };
+
+    let entries = reflog::entries(repo, &ref_name)?;
+    match entries.get(n) {
+        Some(entry) => Ok(Some(entry.new.clone())),
+        // A ref without a log is still where it is
+        None if n == 0 && entries.is_empty() => resolve_name(repo, base),
+        None => anyhow::bail!("log for '{}' only has {} entries", ref_name, entries.len()),
+    }
+}
+
+// Resolves the part before any "~" or "^" to an object name
+fn resolve_name(repo: &Repository, name: &str) -> Result<Option<String>> {
+    if name == "HEAD" || name == "@" {
+        return Ok(Some(repo.head_commit()?.ok_or_else(|| {
+            anyhow::anyhow!("HEAD does not point to a commit yet")
+        })?));
+    }
+    if let Some((base, selector)) = name.strip_suffix('}').and_then(|name| name.rsplit_once("@{")) {
+        if let Ok(n) = selector.parse::<usize>() {
+            return resolve_reflog(repo, base, n);
+        }
+        if base.is_empty() {
+            let branch = expand_previous(repo, name)?;
+            return if branch == name { Ok(None) } else { resolve_name(repo, &branch) };
+        }
+        return Ok(None);
+    }
+    if name.is_empty() {
+        return Ok(None);
+    }
+    if repo.objects().contains(name) {
+        return Ok(Some(name.to_string()));
+    }
+
+    // Special refs like MERGE_HEAD live directly in .rit
+    if name.bytes().all(|b| b.is_ascii_uppercase() || b == b'_')
+        && let Some(hash) = repo.read_ref(name)?
+    {
+        return Ok(Some(hash));
+    }
+
+    if let Some(ref_name) = full_ref_name(repo, name)? {
+        return repo.read_ref(&ref_name);
+    }
+
+    if name.len() >= MIN_ABBREV {
+        let found = repo.objects().find(name)?;
+        match found.len() {
+            0 => {}
+            1 => return Ok(found.into_iter().next()),
+            _ => anyhow::bail!(
+                "short object ID {} is ambiguous\nThe candidates are:\n  {}",
+                name,
+                found.join("\n  ")
+            ),
+        }
+    }
+    Ok(None)
+}
+
+// Follows tags (and a commit to its tree) until an object of `kind` is reached.
+// None for `kind` stops at the first object that isn't a tag.
+fn peel(repo: &Repository, hash: &str, kind: Option<&str>, rev: &str) -> Result<String> {
+    let mut hash = hash.to_string();
+    loop {
+        let (found, data) = repo.objects().read_raw(&hash)?;
+        if kind.is_none_or(|kind| kind == found) && (kind.is_some() || found != "tag") {
+            return Ok(hash);
+        }
+
+        hash = match (found.as_str(), kind) {
+            ("tag", _) => Tag::parse(&String::from_utf8_lossy(&data))?.object,
+            ("commit", Some("tree")) => Commit::parse(&data)?.tree,
+            _ => anyhow::bail!(
+                "{}: expected {} type, but the object dereferences to {} type",
+                rev,
+                kind.unwrap_or("non-tag"),
+                found
+            ),
+        };
+    }
+}
+
+/// Resolves any revision to an object name: full or abbreviated hashes, "HEAD" or "@",
+/// branches, tags, remote-tracking branches, "@{-n}" and reflog entries like "main@{2}",
+/// followed by any of "~n", "^n" and "^{type}"
+pub fn resolve(repo: &Repository, rev: &str) -> Result<String> {
+    let invalid = || anyhow::anyhow!("not a valid object name: '{}'", rev);
+    let (name, steps) = parse(rev)?;
+    let mut hash = resolve_name(repo, name)?.ok_or_else(invalid)?;
+
+    for step in steps {
+        hash = match step {
+            Step::Peel(kind) => peel(repo, &hash, kind.as_deref(), rev)?,
+            Step::Ancestor(n) => {
+                let mut commit = peel(repo, &hash, Some("commit"), rev)?;
+                for _ in 0..n {
+                    commit = Commit::read(repo, &commit)?.parents.into_iter().next().ok_or_else(invalid)?;
+                }
+                commit
+            }
+            Step::Parent(0) => peel(repo, &hash, Some("commit"), rev)?,
+            Step::Parent(n) => {
+                let commit = peel(repo, &hash, Some("commit"), rev)?;
+                Commit::read(repo, &commit)?.parents.into_iter().nth(n - 1).ok_or_else(invalid)?
+            }
+        };
+    }
+    Ok(hash)
+}
+
+/// Resolves a revision that must name a commit, following tags to it
+pub fn resolve_commit(repo: &Repository, rev: &str) -> Result<String> {
+    peel(repo, &resolve(repo, rev)?, Some("commit"), rev)
+}
+
+/// Resolves a revision to a tree, following tags and commits to it
+pub fn resolve_tree(repo: &Repository, rev: &str) -> Result<String> {
+    peel(repo, &resolve(repo, rev)?, Some("tree"), rev)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("HEAD").unwrap(), ("HEAD", vec![]));
+        assert_eq!(
+            parse("main~3^2^").unwrap(),
+            ("main", vec![Step::Ancestor(3), Step::Parent(2), Step::Parent(1)])
+        );
+        assert_eq!(
+            parse("v1.0^{}~").unwrap(),
+            ("v1.0", vec![Step::Peel(None), Step::Ancestor(1)])
+        );
+        assert_eq!(
+            parse("@{-1}^{tree}").unwrap(),
+            ("@{-1}", vec![Step::Peel(Some("tree".to_string()))])
+        );
+        assert_eq!(parse("abc1234^0").unwrap(), ("abc1234", vec![Step::Parent(0)]));
+
+        assert!(parse("main^{tree").is_err());
+        assert!(parse("@{-1").is_err());
+        assert!(parse("main~99999999999999999999999").is_err());
+        assert!(parse("main~é").is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::commands;
+use crate::diff;
+use crate::index::{self, Index};
+use crate::merge;
+use crate::objects::Commit;
+use crate::reflog::{self, Entry};
+use crate::refs;
+use crate::repository::Repository;
+use crate::status;
+
+/// The newest stash; older ones only live on in its log
+const STASH_REF: &str = "refs/stash";
+
+// "stash@{n}" or just "n", stash@{0} by default, as its position and log entry
+fn select(stash: Option<&str>) -> Result<(usize, Entry)> {
+    let name = stash.unwrap_or("stash@{0}");
+    let n = name
+        .strip_prefix("stash@{")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .unwrap_or(name)
+        .parse::<usize>()
+        .map_err(|_| anyhow::anyhow!("'{}' is not a stash-like commit", name))?;
+
+    let entries = reflog::entries(Repository::current(), STASH_REF)?;
+    if entries.is_empty() {
+        anyhow::bail!("No stash entries found.");
+    }
+    let entry = entries
+        .into_iter()
+        .nth(n)
+        .ok_or_else(|| anyhow::anyhow!("stash@{{{}}} is not a valid reference", n))?;
+    Ok((n, entry))
+}
+
+/// `rit stash`: records the staged and unstaged changes (and with `include_untracked`,
+/// untracked files) the way git does, then resets the working tree and index to HEAD.
+///
+/// The stash is a commit W of the working tree whose parents are HEAD, a commit I of the
+/// index and, for untracked files, a parentless commit U of just those files.
+pub fn push(message: Option<&str>, include_untracked: bool) -> Result<()> {
+    let repo = Repository::current();
+    let head = refs::head_commit(repo)?
+        .ok_or_else(|| anyhow::anyhow!("You do not have the initial commit yet"))?;
+    if let Some(path) = merge::read_conflicts()?.first() {
+        anyhow::bail!("{}: needs merge\ncannot stash while there are unmerged files", path);
+    }
+
+    let index_map = index::read_index()?;
+    let head_files = status::head_files()?;
+    let worktree = status::working_files()?;
+
+    // Tracked files as they are on disk; deleted ones are left out
+    let mut wip = Index::new();
+    for (path, staged) in &index_map {
+        match worktree.get(path) {
+            Some(entry) if entry == staged => wip.insert(path.clone(), entry.clone()),
+            Some(_) => wip.insert(path.clone(), commands::worktree_entry(path, true)?),
+            None => None,
+        };
+    }
+    let mut untracked = Index::new();
+    if include_untracked {
+        for path in worktree.keys().filter(|path| !index_map.contains_key(*path)) {
+            untracked.insert(path.clone(), commands::worktree_entry(path, true)?);
+        }
+    }
+
+    if index_map == head_files && wip == index_map && untracked.is_empty() {
+        println!("No local changes to save");
+        return Ok(());
+    }
+
+    let branch = refs::current_branch(repo)?.unwrap_or_else(|| "(no branch)".to_string());
+    let subject = Commit::read(repo, &head)?.message_text().lines().next().unwrap_or_default().to_string();
+    let on = format!("{}: {} {}", branch, &head[..7], subject);
+
+    let index_tree = commands::write_tree_from(&index_map)?;
+    let index_commit = commands::commit_tree(&index_tree, std::slice::from_ref(&head), &format!("index on {}", on))?;
+    let mut parents = vec![head, index_commit];
+    if !untracked.is_empty() {
+        let tree = commands::write_tree_from(&untracked)?;
+        parents.push(commands::commit_tree(&tree, &[], &format!("untracked files on {}", on))?);
+    }
+
+    let description = match message {
+        Some(message) => format!("On {}: {}", branch, message),
+        None => format!("WIP on {}", on),
+    };
+    let stash = commands::commit_tree(&commands::write_tree_from(&wip)?, &parents, &description)?;
+    refs::write_ref(repo, STASH_REF, &stash, &description)?;
+
+    // Back to a clean HEAD; untracked files only go when they were stashed
+    let mut on_disk: Index = worktree.into_iter().filter(|(path, _)| index_map.contains_key(path)).collect();
+    on_disk.extend(untracked);
+    commands::update_worktree(&on_disk, &head_files)?;
+    index::write_index(&head_files)?;
+
+    println!("Saved working directory and index state {}", description);
+    Ok(())
+}
+
+/// `rit stash list`: every stash, newest first
+pub fn list() -> Result<()> {
+    for (n, entry) in reflog::entries(Repository::current(), STASH_REF)?.iter().enumerate() {
+        println!("stash@{{{}}}: {}", n, entry.message);
+    }
+    Ok(())
+}
+
+// The stash commit with the snapshots it was made from
+struct Stash {
+    base: Index,
+    worktree: Index,
+    untracked: Index,
+}
+
+fn read_stash(hash: &str) -> Result<Stash> {
+    let repo = Repository::current();
+    let commit = Commit::read(repo, hash)?;
+    if commit.parents.len() < 2 {
+        anyhow::bail!("'{}' is not a stash-like commit", hash);
+    }
+    let untracked = match commit.parents.get(2) {
+        Some(untracked) => commands::flatten_tree(&Commit::read(repo, untracked)?.tree)?,
+        None => Index::new(),
+    };
+    Ok(Stash {
+        base: commands::flatten_tree(&Commit::read(repo, &commit.parents[0])?.tree)?,
+        worktree: commands::flatten_tree(&commit.tree)?,
+        untracked,
+    })
+}
+
+/// `rit stash show`: what a stash changes compared to the commit it was made on, as a
+/// diffstat or with `patch` as a full diff
+pub fn show(stash: Option<&str>, patch: bool) -> Result<()> {
+    let (_, entry) = select(stash)?;
+    if patch {
+        let commit = Commit::read(Repository::current(), &entry.new)?;
+        let base = commit.parents.first().cloned().unwrap_or_default();
+        return diff::diff_command(&[base, entry.new], false, 3);
+    }
+
+    let stash = read_stash(&entry.new)?;
+    std::io::stdout().write_all(diff::stat(&stash.base, &stash.worktree)?.as_bytes())?;
+    Ok(())
+}
+
+/// `rit stash apply`: merges a stash's changes into the working tree, using the commit it
+/// was made on as the merge base. Like git without `--index`, only new files are staged.
+/// Returns false if the merge left conflicts.
+pub fn apply(stash: Option<&str>) -> Result<bool> {
+    let (_, entry) = select(stash)?;
+    let stash = read_stash(&entry.new)?;
+    if merge::merge_head()?.is_some() {
+        anyhow::bail!("You have not concluded your merge (MERGE_HEAD exists).");
+    }
+
+    for path in stash.untracked.keys() {
+        if fs::symlink_metadata(path).is_ok() {
+            anyhow::bail!("{} already exists, no checkout\ncould not restore untracked files from stash", path);
+        }
+    }
+
+    let ours = index::read_index()?;
+    let result = merge::merge_trees(&stash.base, &ours, &stash.worktree, "Updated upstream", "Stashed changes")?;
+
+    // Only the paths the stash changes are touched, and they must not hold unsaved work
+    let worktree = status::working_files()?;
+    let changed: BTreeSet<&String> = result
+        .index
+        .keys()
+        .chain(ours.keys())
+        .filter(|path| result.index.get(*path) != ours.get(*path) || result.conflict_files.contains_key(*path))
+        .collect();
+    for path in &changed {
+        if worktree.get(*path) != ours.get(*path) {
+            anyhow::bail!(
+                "Your local changes to the following files would be overwritten by merge:\n\t{}\n\
+                 Please commit your changes or stash them before you merge.",
+                path
+            );
+        }
+    }
+
+    let filter = |files: &Index| -> Index {
+        files.iter().filter(|(path, _)| changed.contains(path)).map(|(p, e)| (p.clone(), e.clone())).collect()
+    };
+    commands::update_worktree(&filter(&worktree), &filter(&result.index))?;
+    for (path, content) in &result.conflict_files {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+    }
+    commands::update_worktree(&Index::new(), &stash.untracked)?;
+
+    let mut staged = ours.clone();
+    for (path, entry) in &result.index {
+        if !stash.base.contains_key(path) && !ours.contains_key(path) {
+            staged.insert(path.clone(), entry.clone());
+        }
+    }
+    index::write_index(&staged)?;
+
+    if !result.conflicts.is_empty() {
+        merge::write_conflicts(&result.unmerged)?;
+        for (path, kind) in &result.conflicts {
+            println!("CONFLICT ({}): Merge conflict in {}", kind, path);
+        }
+        return Ok(false);
+    }
+
+    status::status(false)?;
+    Ok(true)
+}
+
+/// `rit stash pop`: applies a stash and drops it, unless applying it left conflicts
+pub fn pop(stash: Option<&str>) -> Result<()> {
+    if apply(stash)? {
+        drop(stash)
+    } else {
+        println!("The stash entry is kept in case you need it again.");
+        Ok(())
+    }
+}
+
+/// `rit stash drop`: forgets a stash. Its commits stay until gc prunes them.
+pub fn drop(stash: Option<&str>) -> Result<()> {
+    let repo = Repository::current();
+    let (n, entry) = select(stash)?;
+    reflog::delete_entry(repo, STASH_REF, n)?;
+
+    match reflog::entries(repo, STASH_REF)?.first() {
+        Some(newest) => refs::set_ref(repo, STASH_REF, &newest.new)?,
+        None => refs::delete_ref(repo, STASH_REF)?,
+    }
+    println!("Dropped stash@{{{}}} ({})", n, entry.new);
+    Ok(())
+}
@@ -0,0 +1,310 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use walkdir::WalkDir;
+
+use crate::commands;
+use crate::ignore::Ignore;
+use crate::index::{self, Index, Stat, StatCache};
+use crate::merge;
+use crate::rebase;
+use crate::refs::{self, Head};
+use crate::repository::Repository;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Modified,
+    Deleted,
+}
+
+impl Change {
+    fn label(self) -> &'static str {
+        match self {
+            Change::Added => "new file:   ",
+            Change::Modified => "modified:   ",
+            Change::Deleted => "deleted:    ",
+        }
+    }
+
+    fn code(self) -> char {
+        match self {
+            Change::Added => 'A',
+            Change::Modified => 'M',
+            Change::Deleted => 'D',
+        }
+    }
+}
+
+pub struct Status {
+    /// HEAD tree vs index
+    pub staged: BTreeMap<String, Change>,
+    /// Index vs working tree
+    pub unstaged: BTreeMap<String, Change>,
+    /// Files on disk that are not in the index
+    pub untracked: Vec<String>,
+    /// Paths with merge conflicts that haven't been staged yet
+    pub unmerged: Vec<String>,
+}
+
+impl Status {
+    pub fn is_clean(&self) -> bool {
+        self.staged.is_empty() && self.unstaged.is_empty() && self.unmerged.is_empty()
+    }
+}
+
+/// Files in the tree of the HEAD commit, empty before the first commit
+pub fn head_files() -> Result<Index> {
+    match refs::head_commit(Repository::current())? {
+        Some(commit) => commands::flatten_tree(&commands::commit_tree_hash(&commit)?),
+        None => Ok(Index::new()),
+    }
+}
+
+/// Every file and symlink in the working directory with its mode and blob hash.
+/// Nothing is written to the object store.
+pub fn working_files() -> Result<Index> {
+    let (index, stats) = index::read_index_with_stats()?;
+    Ok(scan_worktree(&index, &stats)?.0)
+}
+
+// Walks the working tree. Files whose stat data matches the index reuse the staged entry
+// instead of being hashed. Also returns fresh stat data for every file matching the index.
+fn scan_worktree(index: &Index, stats: &StatCache) -> Result<(Index, StatCache)> {
+    let mut files = Index::new();
+    let mut fresh = StatCache::new();
+
+    let mut ignore = Ignore::new();
+    let mut walker = WalkDir::new(".").min_depth(1).into_iter();
+
+    while let Some(entry) = walker.next() {
+        let entry = entry?;
+        let path_str = entry.path().to_str().ok_or_else(|| {
+            let lossy = entry.path().to_string_lossy();
+            anyhow::anyhow!("path {} is not valid UTF-8", lossy.trim_start_matches("./"))
+        })?;
+        let path = path_str.trim_start_matches("./").to_string();
+        let is_dir = entry.file_type().is_dir();
+
+        // Ignored files only show up if they are tracked anyway
+        if ignore.is_ignored(&path, is_dir)? && !commands::is_tracked(index, &path, is_dir) {
+            if is_dir {
+                walker.skip_current_dir();
+            }
+            continue;
+        }
+        if is_dir {
+            continue;
+        }
+
+        let metadata = entry.metadata()?;
+        let stat = Stat::from_metadata(&metadata);
+        let staged = index.get(&path);
+
+        let file = match staged {
+            Some(staged)
+                if stats.get(&path) == Some(&stat) && staged.mode == commands::file_mode(&metadata) =>
+            {
+                staged.clone()
+            }
+            _ => commands::worktree_entry(path_str, false)?,
+        };
+
+        if staged == Some(&file) {
+            fresh.insert(path.clone(), stat);
+        }
+        files.insert(path, file);
+    }
+
+    Ok((files, fresh))
+}
+
+/// Per-path changes needed to turn `old` into `new`
+pub fn diff_maps(old: &Index, new: &Index) -> BTreeMap<String, Change> {
+    let mut changes = BTreeMap::new();
+
+    for (path, entry) in new {
+        match old.get(path) {
+            None => {
+                changes.insert(path.clone(), Change::Added);
+            }
+            Some(old_entry) if old_entry != entry => {
+                changes.insert(path.clone(), Change::Modified);
+            }
+            Some(_) => {}
+        }
+    }
+    for path in old.keys() {
+        if !new.contains_key(path) {
+            changes.insert(path.clone(), Change::Deleted);
+        }
+    }
+
+    changes
+}
+
+pub fn compute() -> Result<Status> {
+    let head = head_files()?;
+    let (index, stats) = index::read_index_with_stats()?;
+    let (worktree, fresh) = scan_worktree(&index, &stats)?;
+
+    // Remember the stat data of files that were hashed and found unchanged,
+    // so the next run can skip them
+    if fresh != stats {
+        index::write_index_with_stats(&index, &fresh)?;
+    }
+
+    let unmerged = merge::read_conflicts()?;
+
+    let mut staged = diff_maps(&head, &index);
+    staged.retain(|path, _| !unmerged.contains(path));
+
+    let mut unstaged = BTreeMap::new();
+    let mut untracked = Vec::new();
+    for (path, change) in diff_maps(&index, &worktree) {
+        if unmerged.contains(&path) {
+            continue;
+        }
+        match change {
+            Change::Added => untracked.push(path),
+            other => {
+                unstaged.insert(path, other);
+            }
+        }
+    }
+
+    Ok(Status {
+        staged,
+        unstaged,
+        untracked: collapse_untracked(&untracked, &index),
+        unmerged,
+    })
+}
+
+// Like git, an untracked directory is reported once as "dir/" instead of file by file
+fn collapse_untracked(untracked: &[String], index: &Index) -> Vec<String> {
+    let mut collapsed: Vec<String> = Vec::new();
+
+    for path in untracked {
+        let mut shown = path.clone();
+
+        for (i, _) in path.match_indices('/') {
+            let dir = &path[..=i];
+            if !index.keys().any(|tracked| tracked.starts_with(dir)) {
+                shown = dir.to_string();
+                break;
+            }
+        }
+
+        if collapsed.last() != Some(&shown) {
+            collapsed.push(shown);
+        }
+    }
+
+    collapsed
+}
+
+pub fn status(short: bool) -> Result<()> {
+    let status = compute()?;
+
+    if short {
+        print_short(&status);
+    } else {
+        print_long(&status)?;
+    }
+    Ok(())
+}
+
+fn print_short(status: &Status) {
+    let mut paths: Vec<&String> = status.staged.keys().chain(status.unstaged.keys()).collect();
+    paths.sort();
+    paths.dedup();
+
+    for path in &status.unmerged {
+        println!("UU {}", path);
+    }
+
+    for path in paths {
+        let x = status.staged.get(path).map_or(' ', |change| change.code());
+        let y = status.unstaged.get(path).map_or(' ', |change| change.code());
+        println!("{}{} {}", x, y, path);
+    }
+
+    for path in &status.untracked {
+        println!("?? {}", path);
+    }
+}
+
+fn print_long(status: &Status) -> Result<()> {
+    let repo = Repository::current();
+    match refs::read_head(repo)? {
+        Head::Branch(ref_name) => println!(
+            "On branch {}",
+            ref_name.strip_prefix("refs/heads/").unwrap_or(&ref_name)
+        ),
+        Head::Detached(hash) => println!("HEAD detached at {}", &hash[..hash.len().min(7)]),
+    }
+
+    if refs::head_commit(repo)?.is_none() {
+        println!("\nNo commits yet");
+    }
+
+    if merge::merge_head()?.is_some() {
+        if status.unmerged.is_empty() {
+            println!("\nAll conflicts fixed but you are still merging.");
+            println!("  (use \"rit commit\" to conclude merge)");
+        } else {
+            println!("\nYou have unmerged paths.");
+            println!("  (fix conflicts and run \"rit commit\")");
+            println!("  (use \"rit merge --abort\" to abort the merge)");
+        }
+    }
+
+    if let Some(onto) = rebase::rebase_onto()? {
+        println!("\nrebase in progress; onto {}", &onto[..7]);
+        if status.unmerged.is_empty() {
+            println!("  (all conflicts fixed: run \"rit rebase --continue\")");
+        } else {
+            println!("  (fix conflicts and then run \"rit rebase --continue\")");
+        }
+    } else if let Some(commit) = rebase::cherry_pick_head()? {
+        println!("\nYou are currently cherry-picking commit {}.", &commit[..7]);
+        println!("  (run \"rit cherry-pick --continue\" once the conflicts are fixed)");
+    }
+
+    if !status.staged.is_empty() {
+        println!("\nChanges to be committed:");
+        for (path, change) in &status.staged {
+            println!("\t\x1b[32m{}{}\x1b[0m", change.label(), path);
+        }
+    }
+
+    if !status.unmerged.is_empty() {
+        println!("\nUnmerged paths:");
+        for path in &status.unmerged {
+            println!("\t\x1b[31mboth modified:   {}\x1b[0m", path);
+        }
+    }
+
+    if !status.unstaged.is_empty() {
+        println!("\nChanges not staged for commit:");
+        for (path, change) in &status.unstaged {
+            println!("\t\x1b[31m{}{}\x1b[0m", change.label(), path);
+        }
+    }
+
+    if !status.untracked.is_empty() {
+        println!("\nUntracked files:");
+        for path in &status.untracked {
+            println!("\t\x1b[31m{}\x1b[0m", path);
+        }
+    }
+
+    if status.is_clean() {
+        if status.untracked.is_empty() {
+            println!("\nnothing to commit, working tree clean");
+        } else {
+            println!("\nnothing added to commit but untracked files present");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+
+use crate::objects::{ObjectDatabase, Signature, Tag};
+use crate::refs;
+use crate::repository::Repository;
+use crate::revision;
+
+fn tag_ref(name: &str) -> String {
+    format!("refs/tags/{}", name)
+}
+
+/// Lists tags by name
+pub fn list() -> Result<()> {
+    for (name, _) in refs::list_refs(Repository::current(), "refs/tags")? {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+/// Tags `target` (any object, defaulting to HEAD). With a message the ref points at a new
+/// annotated tag object, otherwise straight at the target. Without `force` an existing
+/// tag is never moved.
+pub fn create(name: &str, target: Option<&str>, message: Option<&str>, force: bool) -> Result<()> {
+    let repo = Repository::current();
+    refs::validate_tag_name(name)?;
+
+    let existing = refs::read_ref(repo, &tag_ref(name))?;
+    if existing.is_some() && !force {
+        anyhow::bail!("tag '{}' already exists", name);
+    }
+
+    let target = revision::resolve(repo, target.unwrap_or("HEAD"))?;
+    let hash = match message {
+        Some(message) => {
+            let (kind, _) = repo.objects().read_raw(&target)?;
+            let tag = Tag {
+                object: target,
+                kind,
+                name: name.to_string(),
+                tagger: Some(Signature::current("committer")?),
+                message: format!("{}\n", message.trim_end()),
+            };
+            repo.objects().write_raw("tag", tag.to_string().as_bytes())?
+        }
+        None => target,
+    };
+
+    refs::write_ref(repo, &tag_ref(name), &hash, "tag")?;
+    if let Some(old) = existing
+        && old != hash
+    {
+        println!("Updated tag '{}' (was {})", name, &old[..7]);
+    }
+    Ok(())
+}
+
+/// Deletes a tag ref. The tag object itself stays until gc finds it unreachable.
+pub fn delete(name: &str) -> Result<()> {
+    let repo = Repository::current();
+    let hash = refs::read_ref(repo, &tag_ref(name))?
+        .ok_or_else(|| anyhow::anyhow!("tag '{}' not found.", name))?;
+
+    refs::delete_ref(repo, &tag_ref(name))?;
+    println!("Deleted tag '{}' (was {})", name, &hash[..7]);
+    Ok(())
+}
@@ -0,0 +1,462 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use crate::commands::{rit_dir, RIT_DIR};
+use crate::objects::{ObjectDatabase, ObjectFormat};
+use crate::pack;
+use crate::pktline::{self, Packet};
+use crate::refs;
+use crate::repository::Repository;
+
+/// Turns a remote URL into the repository directory it names. Only local
+/// repositories are supported, given as plain paths or `file://` URLs.
+pub fn remote_path(url: &str) -> Result<PathBuf> {
+    let path = url.strip_prefix("file://").unwrap_or(url);
+    if path.contains("://") {
+        anyhow::bail!("Unable to find remote helper for '{}'", url.split("://").next().unwrap_or(url));
+    }
+    Path::new(path)
+        .canonicalize()
+        .map_err(|_| anyhow::anyhow!("'{}' does not appear to be a rit repository", url))
+}
+
+/// The refs a server starts the conversation with, and what it can do
+#[derive(Clone)]
+pub struct Advertisement {
+    /// (ref name, hash) including HEAD, in the order they were sent
+    pub refs: Vec<(String, String)>,
+    capabilities: Vec<String>,
+}
+
+impl Advertisement {
+    pub fn has(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|cap| cap == capability)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.refs
+            .iter()
+            .find(|(ref_name, _)| ref_name == name)
+            .map(|(_, hash)| hash.as_str())
+    }
+
+    /// How the server names objects. Servers that don't say use SHA-1.
+    pub fn object_format(&self) -> Result<ObjectFormat> {
+        match self.capabilities.iter().find_map(|cap| cap.strip_prefix("object-format=")) {
+            Some(name) => ObjectFormat::from_name(name),
+            None => Ok(ObjectFormat::Sha1),
+        }
+    }
+
+    /// The branch the server's HEAD points at, e.g. "refs/heads/main"
+    pub fn head_branch(&self) -> Option<&str> {
+        self.capabilities
+            .iter()
+            .find_map(|cap| cap.strip_prefix("symref=HEAD:"))
+    }
+
+    fn read(reader: &mut impl Read) -> Result<Advertisement> {
+        let mut advertisement = Advertisement {
+            refs: Vec::new(),
+            capabilities: Vec::new(),
+        };
+
+        while let Packet::Data(data) = pktline::expect_packet(reader)? {
+            let data = data.strip_suffix(b"\n").unwrap_or(&data);
+
+            // Capabilities follow a NUL on the first line only
+            let (line, capabilities) = match data.iter().position(|&b| b == 0) {
+                Some(nul) => (&data[..nul], Some(&data[nul + 1..])),
+                None => (data, None),
+            };
+            if let Some(capabilities) = capabilities {
+                advertisement.capabilities = String::from_utf8_lossy(capabilities)
+                    .split(' ')
+                    .filter(|cap| !cap.is_empty())
+                    .map(|cap| cap.to_string())
+                    .collect();
+            }
+
+            let line = std::str::from_utf8(line)?;
+            let (hash, name) = line
+                .split_once(' ')
+                .ok_or_else(|| anyhow::anyhow!("protocol error: bad ref line '{}'", line))?;
+
+            // An empty repository sends a placeholder just to carry its capabilities,
+            // and "^{}" lines are the commits annotated tags point at
+            if name == "capabilities^{}" || name.ends_with("^{}") {
+                continue;
+            }
+            advertisement.refs.push((name.to_string(), hash.to_string()));
+        }
+
+        Ok(advertisement)
+    }
+}
+
+/// A running upload-pack or receive-pack serving a remote repository
+pub struct Connection {
+    child: Child,
+    input: Option<BufWriter<ChildStdin>>,
+    output: BufReader<ChildStdout>,
+    pub advertisement: Advertisement,
+}
+
+impl Connection {
+    /// Starts `service` for the repository at `path`: rit itself for a rit repository,
+    /// git for anything else
+    pub fn open(path: &Path, service: &str) -> Result<Connection> {
+        let mut command = if path.join(RIT_DIR).is_dir() {
+            // The remote is served from its own .rit, whatever this repository is
+            let mut rit = Command::new(std::env::current_exe()?);
+            rit.env_remove("RIT_DIR");
+            rit
+        } else {
+            let mut git = Command::new("git");
+            git.env_remove("GIT_PROTOCOL");
+            git
+        };
+
+        let mut child = command
+            .arg(service)
+            .arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| anyhow::anyhow!("Could not run {}: {}", service, err))?;
+
+        let input = BufWriter::new(child.stdin.take().unwrap());
+        let mut output = BufReader::new(child.stdout.take().unwrap());
+        let advertisement = Advertisement::read(&mut output)?;
+
+        Ok(Connection {
+            child,
+            input: Some(input),
+            output,
+            advertisement,
+        })
+    }
+
+    // Objects can't be exchanged between repositories that name them differently
+    fn check_object_format(&self) -> Result<()> {
+        let remote = self.advertisement.object_format()?;
+        let local = Repository::current().format();
+        if remote != local {
+            anyhow::bail!("the remote uses the {} object format, this repository uses {}", remote.name(), local.name());
+        }
+        Ok(())
+    }
+
+    fn input(&mut self) -> &mut BufWriter<ChildStdin> {
+        self.input.as_mut().expect("input is open until finish")
+    }
+
+    /// Ends the conversation right after the advertisement, asking for nothing
+    pub fn cancel(mut self) -> Result<()> {
+        pktline::write_flush(self.input())?;
+        self.finish()
+    }
+
+    /// Closes our side and waits for the server to exit
+    fn finish(mut self) -> Result<()> {
+        if let Some(mut input) = self.input.take() {
+            input.flush()?;
+        }
+        let status = self.child.wait()?;
+        if !status.success() {
+            anyhow::bail!("the remote end hung up unexpectedly");
+        }
+        Ok(())
+    }
+
+    /// Asks an upload-pack for `wants`, telling it which commits we have so it
+    /// can leave out what we already have. Stores the pack it sends back.
+    pub fn fetch_pack(mut self, wants: &[String], haves: &[String]) -> Result<usize> {
+        let repo = Repository::current();
+        self.check_object_format()?;
+        if wants.is_empty() {
+            self.cancel()?;
+            return Ok(0);
+        }
+
+        let ofs_delta = self.advertisement.has("ofs-delta");
+        for (i, want) in wants.iter().enumerate() {
+            let line = if i == 0 && ofs_delta {
+                format!("want {} ofs-delta agent=rit object-format={}", want, repo.format().name())
+            } else {
+                format!("want {}", want)
+            };
+            pktline::write_line(self.input(), &line)?;
+        }
+        pktline::write_flush(self.input())?;
+
+        for have in haves {
+            pktline::write_line(self.input(), &format!("have {}", have))?;
+        }
+        pktline::write_line(self.input(), "done")?;
+        self.input().flush()?;
+
+        // One ACK for a common commit or a NAK, then the raw pack until the server exits
+        let reply = pktline::expect_packet(&mut self.output)?;
+        match reply.text() {
+            Some(text) if text.starts_with("ACK ") || text == "NAK" => {}
+            Some(text) if text.starts_with("ERR ") => anyhow::bail!("remote error: {}", &text[4..]),
+            _ => anyhow::bail!("protocol error: expected ACK/NAK, got {:?}", reply),
+        }
+
+        let mut data = Vec::new();
+        self.output.read_to_end(&mut data)?;
+        self.finish()?;
+
+        pack::store_pack(repo, &data)
+    }
+
+    /// Sends ref updates as (old, new, ref name) to a receive-pack, followed by the pack
+    /// of objects they need. Returns the server's verdict per ref, None meaning accepted.
+    pub fn send_pack(
+        mut self,
+        updates: &[(String, String, String)],
+        pack: &[u8],
+    ) -> Result<Vec<(String, Option<String>)>> {
+        let repo = Repository::current();
+        self.check_object_format()?;
+        for (i, (old, new, name)) in updates.iter().enumerate() {
+            let mut line = format!("{} {} {}", old, new, name).into_bytes();
+            if i == 0 {
+                line.push(0);
+                line.extend_from_slice(b"report-status agent=rit object-format=");
+                line.extend_from_slice(repo.format().name().as_bytes());
+            }
+            line.push(b'\n');
+            pktline::write_data(self.input(), &line)?;
+        }
+        pktline::write_flush(self.input())?;
+
+        // Deleting refs needs no objects, so no pack is expected then
+        if updates.iter().any(|(_, new, _)| *new != repo.format().zero_id()) {
+            self.input().write_all(pack)?;
+        }
+        if let Some(mut input) = self.input.take() {
+            input.flush()?;
+        }
+
+        let unpack = pktline::expect_packet(&mut self.output)?;
+        match unpack.text() {
+            Some("unpack ok") => {}
+            Some(text) => anyhow::bail!("remote unpack failed: {}", text.trim_start_matches("unpack ")),
+            None => anyhow::bail!("protocol error: missing unpack status"),
+        }
+
+        let mut results = Vec::new();
+        while let Packet::Data(data) = pktline::expect_packet(&mut self.output)? {
+            let line = String::from_utf8_lossy(&data);
+            let line = line.trim_end();
+            if let Some(name) = line.strip_prefix("ok ") {
+                results.push((name.to_string(), None));
+            } else if let Some((name, reason)) = line.strip_prefix("ng ").and_then(|rest| rest.split_once(' ')) {
+                results.push((name.to_string(), Some(reason.to_string())));
+            }
+        }
+
+        self.finish()?;
+        Ok(results)
+    }
+}
+
+/// Refs a server offers: HEAD first, then every branch and tag
+fn advertised_refs() -> Result<Vec<(String, String)>> {
+    let repo = Repository::current();
+    let mut advertised = Vec::new();
+    if let Some(head) = refs::head_commit(repo)? {
+        advertised.push(("HEAD".to_string(), head));
+    }
+    for prefix in ["refs/heads", "refs/tags"] {
+        for (name, hash) in refs::list_refs(repo, prefix)? {
+            advertised.push((format!("{}/{}", prefix, name), hash));
+        }
+    }
+    Ok(advertised)
+}
+
+fn advertise(out: &mut impl Write, advertised: &[(String, String)], capabilities: &str) -> Result<()> {
+    match advertised.split_first() {
+        Some(((name, hash), rest)) => {
+            pktline::write_data(out, format!("{} {}\0{}\n", hash, name, capabilities).as_bytes())?;
+            for (name, hash) in rest {
+                pktline::write_line(out, &format!("{} {}", hash, name))?;
+            }
+        }
+        None => {
+            let zero = Repository::current().format().zero_id();
+            let line = format!("{} capabilities^{{}}\0{}\n", zero, capabilities);
+            pktline::write_data(out, line.as_bytes())?;
+        }
+    }
+    pktline::write_flush(out)
+}
+
+fn open_repository(directory: &str) -> Result<()> {
+    std::env::set_current_dir(directory)?;
+    if !rit_dir().is_dir() {
+        anyhow::bail!("'{}' does not appear to be a rit repository", directory);
+    }
+    Ok(())
+}
+
+/// `rit upload-pack`: the server side of fetch and clone
+pub fn upload_pack(directory: &str) -> Result<()> {
+    open_repository(directory)?;
+    let repo = Repository::current();
+    let mut input = BufReader::new(io::stdin().lock());
+    let mut out = BufWriter::new(io::stdout().lock());
+
+    let mut capabilities = format!("ofs-delta agent=rit object-format={}", repo.format().name());
+    if let Some(branch) = refs::current_branch(repo)? {
+        capabilities = format!("{} symref=HEAD:refs/heads/{}", capabilities, branch);
+    }
+    advertise(&mut out, &advertised_refs()?, &capabilities)?;
+
+    // Wants until a flush. A client that wants nothing just flushes, or hangs up.
+    let mut wants = Vec::new();
+    let mut ofs_delta = false;
+    while let Some(Packet::Data(data)) = pktline::read_packet(&mut input)? {
+        let line = String::from_utf8(data)?;
+        let mut words = line.split_whitespace();
+        if words.next() != Some("want") {
+            anyhow::bail!("protocol error: expected want, got '{}'", line.trim_end());
+        }
+        let hash = words.next().unwrap_or_default().to_string();
+        if !repo.objects().contains(&hash) {
+            pktline::write_line(&mut out, &format!("ERR upload-pack: not our ref {}", hash))?;
+            pktline::write_flush(&mut out)?;
+            anyhow::bail!("not our ref {}", hash);
+        }
+        ofs_delta |= words.any(|cap| cap == "ofs-delta");
+        wants.push(hash);
+    }
+    if wants.is_empty() {
+        return Ok(());
+    }
+
+    // Haves until done. The first one we also have is acknowledged.
+    let mut common = Vec::new();
+    loop {
+        match pktline::expect_packet(&mut input)? {
+            Packet::Flush => {
+                if common.is_empty() {
+                    pktline::write_line(&mut out, "NAK")?;
+                    out.flush()?;
+                }
+            }
+            packet => {
+                let line = packet.text().unwrap_or_default().to_string();
+                if line == "done" {
+                    break;
+                }
+                let hash = line
+                    .strip_prefix("have ")
+                    .ok_or_else(|| anyhow::anyhow!("protocol error: expected have, got '{}'", line))?;
+                if repo.objects().contains(hash) {
+                    if common.is_empty() {
+                        pktline::write_line(&mut out, &format!("ACK {}", hash))?;
+                        out.flush()?;
+                    }
+                    common.push(hash.to_string());
+                }
+            }
+        }
+    }
+    if common.is_empty() {
+        pktline::write_line(&mut out, "NAK")?;
+    }
+
+    let exclude: HashSet<String> = pack::reachable_objects(repo, common, &HashSet::new())?
+        .into_keys()
+        .collect();
+    let found = pack::reachable_objects(repo, wants, &exclude)?;
+    out.write_all(&pack::pack_objects(repo, &found, ofs_delta)?.data)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Checks one pushed ref update, returning why it is refused
+fn refuse_update(old: &str, new: &str, name: &str) -> Result<Option<&'static str>> {
+    let repo = Repository::current();
+    let short = name.strip_prefix("refs/heads/").or_else(|| name.strip_prefix("refs/tags/"));
+    if short.is_none_or(|short| refs::validate_branch_name(short).is_err()) {
+        return Ok(Some("funny refname"));
+    }
+    let current = refs::read_ref(repo, name)?.unwrap_or_else(|| repo.format().zero_id());
+    if current != old {
+        return Ok(Some("failed to lock"));
+    }
+    if new != repo.format().zero_id() && !repo.objects().contains(new) {
+        return Ok(Some("missing necessary objects"));
+    }
+    if refs::current_branch(repo)?.is_some_and(|branch| format!("refs/heads/{}", branch) == name) {
+        return Ok(Some("branch is currently checked out"));
+    }
+    Ok(None)
+}
+
+/// `rit receive-pack`: the server side of push
+pub fn receive_pack(directory: &str) -> Result<()> {
+    open_repository(directory)?;
+    let repo = Repository::current();
+    let mut input = BufReader::new(io::stdin().lock());
+    let mut out = BufWriter::new(io::stdout().lock());
+
+    let capabilities = format!("report-status delete-refs ofs-delta agent=rit object-format={}", repo.format().name());
+    advertise(&mut out, &advertised_refs()?, &capabilities)?;
+
+    let mut updates = Vec::new();
+    while let Some(Packet::Data(data)) = pktline::read_packet(&mut input)? {
+        // Capabilities after a NUL on the first command, report-status is always on
+        let command = data.split(|&b| b == 0).next().unwrap_or_default();
+        let command = String::from_utf8(command.to_vec())?;
+        let parts: Vec<&str> = command.trim_end().split(' ').collect();
+        let [old, new, name] = parts[..] else {
+            anyhow::bail!("protocol error: bad command '{}'", command.trim_end());
+        };
+        updates.push((old.to_string(), new.to_string(), name.to_string()));
+    }
+    if updates.is_empty() {
+        return Ok(());
+    }
+
+    // The client closes its end once the pack is sent
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    let unpacked = if data.is_empty() {
+        Ok(0)
+    } else {
+        pack::store_pack(repo, &data)
+    };
+
+    match &unpacked {
+        Ok(_) => pktline::write_line(&mut out, "unpack ok")?,
+        Err(err) => pktline::write_line(&mut out, &format!("unpack {}", err))?,
+    }
+
+    for (old, new, name) in &updates {
+        let refused = match &unpacked {
+            Ok(_) => refuse_update(old, new, name)?,
+            Err(_) => Some("unpacker error"),
+        };
+        match refused {
+            Some(reason) => pktline::write_line(&mut out, &format!("ng {} {}", name, reason))?,
+            None => {
+                if *new == repo.format().zero_id() {
+                    refs::delete_ref(repo, name)?;
+                } else {
+                    refs::write_ref(repo, name, new, "push")?;
+                }
+                pktline::write_line(&mut out, &format!("ok {}", name))?;
+            }
+        }
+    }
+    pktline::write_flush(&mut out)
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use common::{TestRepo, git_available};
+
+// The commit column of each line, boundaries keeping their '^'
+fn blamed(output: &str) -> Vec<&str> {
+    output.lines().map(|line| line.split(' ').next().unwrap()).collect()
+}
+
+fn commit_as(repo: &TestRepo, author: &str, date: &str, message: &str) -> String {
+    repo.rit(&["add", "."]);
+    let output = repo
+        .command(&["commit", "-m", message])
+        .env("RIT_AUTHOR_NAME", author)
+        .env("RIT_AUTHOR_DATE", date)
+        .env("RIT_COMMITTER_DATE", date)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    repo.head()
+}
+
+/// a.txt is "1".."6"; the second commit changes line 3 and the third appends line 7
+fn history() -> (TestRepo, [String; 3]) {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "1\n2\n3\n4\n5\n6\n");
+    let first = commit_as(&repo, "Ada", "1700000000 +0100", "first");
+    repo.write("a.txt", "1\n2\nthree\n4\n5\n6\n");
+    let second = commit_as(&repo, "Bob Longer", "1700000100 +0100", "second one");
+    repo.write("a.txt", "1\n2\nthree\n4\n5\n6\n7");
+    let third = commit_as(&repo, "Ada", "1700000200 -0500", "third");
+    (repo, [first, second, third])
+}
+
+#[test]
+fn lines_are_blamed_on_the_commit_that_last_changed_them() {
+    let (repo, [first, second, third]) = history();
+
+    let out = repo.rit(&["blame", "a.txt"]);
+    let root = format!("^{}", &first[..7]);
+    assert_eq!(blamed(&out), [&root, &root, &second[..8], &root, &root, &root, &third[..8]]);
+    assert_eq!(
+        out.lines().nth(2).unwrap(),
+        format!("{} (Bob Longer 2023-11-14 23:15:00 +0100 3) three", &second[..8])
+    );
+    assert_eq!(
+        out.lines().last().unwrap(),
+        format!("{} (Ada        2023-11-14 17:16:40 -0500 7) 7", &third[..8])
+    );
+
+    // From an older commit, the later lines don't exist yet
+    let out = repo.rit(&["blame", "HEAD~1", "a.txt"]);
+    assert_eq!(blamed(&out), [&root, &root, &second[..8], &root, &root, &root]);
+
+    assert!(repo.rit_fails(&["blame", "nope.txt"]).contains("no such path 'nope.txt' in HEAD"));
+    assert!(repo.rit_fails(&["blame", "HEAD~2", "nope.txt"]).contains("no such path nope.txt in HEAD~2"));
+}
+
+#[test]
+fn line_ranges() {
+    let (repo, [_, second, _]) = history();
+
+    let out = repo.rit(&["blame", "-L", "2,4", "a.txt"]);
+    assert_eq!(out.lines().count(), 3);
+    assert_eq!(
+        out.lines().nth(1).unwrap(),
+        format!("{} (Bob Longer 2023-11-14 23:15:00 +0100 3) three", &second[..8])
+    );
+
+    let out = repo.rit(&["blame", "-L", "3,+1", "-L", "6", "a.txt"]);
+    let numbers: Vec<&str> = out
+        .lines()
+        .map(|line| line.split(')').next().unwrap().rsplit(' ').next().unwrap())
+        .collect();
+    assert_eq!(numbers, ["3", "6", "7"]);
+
+    assert!(repo.rit_fails(&["blame", "-L", "9", "a.txt"]).contains("file a.txt has only 7 lines"));
+    assert!(repo.rit_fails(&["blame", "-L", "0,2", "a.txt"]).contains("-L invalid line number: 0"));
+}
+
+#[test]
+fn porcelain_output() {
+    let (repo, [first, second, _]) = history();
+
+    let out = repo.rit(&["blame", "--porcelain", "-L", "2,5", "a.txt"]);
+    let expected = format!(
+        "{first} 2 2 1\n\
+         author Ada\n\
+         author-mail <rit@example.com>\n\
+         author-time 1700000000\n\
+         author-tz +0100\n\
+         committer Rit User\n\
+         committer-mail <rit@example.com>\n\
+         committer-time 1700000000\n\
+         committer-tz +0100\n\
+         summary first\n\
+         boundary\n\
+         filename a.txt\n\
+         \t2\n\
+         {second} 3 3 1\n\
+         author Bob Longer\n\
+         author-mail <rit@example.com>\n\
+         author-time 1700000100\n\
+         author-tz +0100\n\
+         committer Rit User\n\
+         committer-mail <rit@example.com>\n\
+         committer-time 1700000100\n\
+         committer-tz +0100\n\
+         summary second one\n\
+         previous {first} a.txt\n\
+         filename a.txt\n\
+         \tthree\n\
+         {first} 4 4 2\n\
+         \t4\n\
+         {first} 5 5\n\
+         \t5\n"
+    );
+    assert_eq!(out, expected);
+}
+
+#[test]
+fn uncommitted_and_merged_lines() {
+    let (repo, [_, _, third]) = history();
+    repo.rit(&["switch", "-c", "topic"]);
+    repo.write("a.txt", "1\n2\nthree\n4\nfive\n6\n7");
+    let topic = commit_as(&repo, "Ada", "1700000300 +0100", "topic");
+    repo.rit(&["switch", "main"]);
+    repo.write("a.txt", "one\n2\nthree\n4\n5\n6\n7");
+    let main = commit_as(&repo, "Ada", "1700000400 +0100", "main");
+    repo.rit(&["merge", "topic"]);
+    let merge = repo.head();
+
+    // Each side's change is blamed on the commit that made it, not on the merge
+    let out = repo.rit(&["blame", "HEAD", "a.txt"]);
+    assert_eq!(blamed(&out)[0], &main[..8]);
+    assert_eq!(blamed(&out)[4], &topic[..8]);
+    assert_eq!(blamed(&out)[6], &third[..8]);
+    assert!(!out.contains(&merge[..8]));
+
+    // Without a commit the working tree is blamed, edits included
+    repo.write("a.txt", "one\n2\nthree\nfour\nfive\n6\n7");
+    let out = repo.rit(&["blame", "a.txt"]);
+    assert!(out.lines().nth(3).unwrap().starts_with("00000000 (Not Committed Yet "));
+    assert_eq!(blamed(&out)[4], &topic[..8]);
+
+    let out = repo.rit(&["blame", "--porcelain", "-L", "4,4", "a.txt"]);
+    assert!(out.contains("author Not Committed Yet\nauthor-mail <not.committed.yet>\n"));
+    assert!(out.contains(&format!("summary Version of a.txt from a.txt\nprevious {} a.txt\n", merge)));
+}
+
+#[test]
+fn matches_git_blame() {
+    if !git_available() {
+        return;
+    }
+    let (repo, _) = history();
+    repo.write("dir/b.txt", "b\n");
+    repo.write("a.txt", "0\n1\n2\nthree\n4\n6\n7\n");
+    commit_as(&repo, "Cy", "1700000300 +0000", "fourth");
+
+    for args in [
+        vec!["HEAD", "a.txt"],
+        vec!["-L", "2,+3", "HEAD", "a.txt"],
+        vec!["--porcelain", "HEAD", "a.txt"],
+        vec!["--porcelain", "HEAD~1", "a.txt"],
+        vec!["HEAD", "dir/b.txt"],
+    ] {
+        let mut blame = vec!["blame"];
+        blame.extend(&args);
+        assert_eq!(repo.rit(&blame), repo.git(&blame), "blame {:?}", args);
+    }
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn lists_branches_and_marks_current() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("first");
+
+    repo.rit(&["branch", "feature"]);
+    repo.rit(&["branch", "topic/nested"]);
+
+    let out = repo.rit(&["branch"]);
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("  feature"));
+    assert!(lines[1].starts_with("* ") && lines[1].contains("main"));
+    assert!(lines[2].starts_with("  topic/nested"));
+}
+
+#[test]
+fn creates_branch_at_commit_and_rejects_bad_names() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    let first = repo.commit_all("first");
+    repo.write("a.txt", "b\n");
+    repo.commit_all("second");
+
+    repo.rit(&["branch", "old", &first]);
+    assert_eq!(repo.read(".rit/refs/heads/old").trim(), first);
+
+    repo.rit_fails(&["branch", "old"]);
+    repo.rit_fails(&["branch", "bad..name"]);
+    repo.rit_fails(&["branch", "-leading-dash"]);
+    repo.rit_fails(&["branch", "x", "does-not-exist"]);
+}
+
+#[test]
+fn delete_refuses_unmerged_branches() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("first");
+
+    repo.rit(&["switch", "-c", "feature"]);
+    repo.write("b.txt", "b\n");
+    repo.commit_all("on feature");
+    repo.rit(&["switch", "main"]);
+
+    let err = repo.rit_fails(&["branch", "-d", "feature"]);
+    assert!(err.contains("not fully merged"));
+
+    repo.rit(&["branch", "-D", "feature"]);
+    assert!(!repo.exists(".rit/refs/heads/feature"));
+
+    // Merged branches (here: same commit as HEAD) go with plain -d
+    repo.rit(&["branch", "merged"]);
+    repo.rit(&["branch", "-d", "merged"]);
+    assert!(!repo.exists(".rit/refs/heads/merged"));
+
+    repo.rit_fails(&["branch", "-d", "main"]);
+}
+
+#[test]
+fn rename_keeps_head_attached() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    let commit = repo.commit_all("first");
+
+    repo.rit(&["branch", "-m", "trunk"]);
+    assert_eq!(repo.read(".rit/HEAD"), "ref: refs/heads/trunk\n");
+    assert_eq!(repo.read(".rit/refs/heads/trunk").trim(), commit);
+    assert!(!repo.exists(".rit/refs/heads/main"));
+
+    repo.rit(&["branch", "other"]);
+    repo.rit_fails(&["branch", "-m", "other", "trunk"]);
+    repo.rit(&["branch", "-M", "other", "trunk"]);
+    assert!(!repo.exists(".rit/refs/heads/other"));
+}
+
+#[test]
+fn switch_create_checks_out_start_point() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "one\n");
+    let first = repo.commit_all("first");
+    repo.write("a.txt", "two\n");
+    repo.commit_all("second");
+
+    let out = repo.rit(&["switch", "-c", "old", &first]);
+    assert!(out.contains("Switched to a new branch 'old'"));
+    assert_eq!(repo.read(".rit/HEAD"), "ref: refs/heads/old\n");
+    assert_eq!(repo.read("a.txt"), "one\n");
+
+    repo.rit(&["switch", "main"]);
+    assert_eq!(repo.read("a.txt"), "two\n");
+
+    repo.rit_fails(&["switch", "missing"]);
+    repo.rit_fails(&["switch", "-c", "old"]);
+}
+
+#[test]
+fn switch_create_on_unborn_branch() {
+    let repo = TestRepo::new();
+
+    repo.rit(&["switch", "-c", "develop"]);
+    assert_eq!(repo.read(".rit/HEAD"), "ref: refs/heads/develop\n");
+
+    repo.write("a.txt", "a\n");
+    let commit = repo.commit_all("first");
+    assert_eq!(repo.read(".rit/refs/heads/develop").trim(), commit);
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use common::TestRepo;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+#[test]
+fn binary_files_survive_a_round_trip() {
+    let repo = TestRepo::new();
+    let data: Vec<u8> = (0..=255u8).chain([0xff, 0xfe, 0x00, 0x80]).collect();
+    fs::write(repo.file("image.bin"), &data).unwrap();
+    repo.commit_all("binary");
+    repo.rit(&["switch", "-c", "other"]);
+
+    fs::write(repo.file("image.bin"), b"replaced").unwrap();
+    repo.commit_all("text now");
+    repo.rit(&["switch", "main"]);
+
+    assert_eq!(fs::read(repo.file("image.bin")).unwrap(), data);
+}
+
+#[test]
+fn executable_bit_and_symlinks_are_recorded() {
+    let repo = TestRepo::new();
+    repo.write("plain.txt", "plain\n");
+    repo.commit_all("first");
+    repo.rit(&["branch", "before"]);
+
+    repo.write("run.sh", "#!/bin/sh\n");
+    fs::set_permissions(repo.file("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+    std::os::unix::fs::symlink("plain.txt", repo.file("link")).unwrap();
+    repo.commit_all("modes");
+
+    let show = repo.rit(&["show"]);
+    assert!(show.contains("diff --git a/run.sh b/run.sh\nnew file mode 100755"));
+    assert!(show.contains("diff --git a/link b/link\nnew file mode 120000"));
+
+    repo.rit(&["switch", "before"]);
+    assert!(!repo.exists("run.sh"));
+    assert!(fs::symlink_metadata(repo.file("link")).is_err());
+
+    repo.rit(&["switch", "main"]);
+    let mode = fs::metadata(repo.file("run.sh")).unwrap().permissions().mode();
+    assert_eq!(mode & 0o111, 0o111);
+    assert_eq!(fs::read_link(repo.file("link")).unwrap().to_str(), Some("plain.txt"));
+    assert!(repo.rit(&["status", "-s"]).is_empty());
+
+    // Dropping the executable bit is a change on its own
+    fs::set_permissions(repo.file("run.sh"), fs::Permissions::from_mode(0o644)).unwrap();
+    assert_eq!(repo.rit(&["status", "-s"]), " M run.sh\n");
+    assert!(repo.rit(&["diff"]).contains("old mode 100755\nnew mode 100644\n"));
+}
+
+#[test]
+fn checkout_removes_files_missing_from_target() {
+    let repo = TestRepo::new();
+    repo.write("keep.txt", "keep\n");
+    let first = repo.commit_all("first");
+
+    repo.write("dir/nested/new.txt", "new\n");
+    repo.commit_all("second");
+
+    repo.rit(&["checkout", &first]);
+    assert!(repo.exists("keep.txt"));
+    assert!(!repo.exists("dir"));
+    assert!(repo.rit(&["status", "-s"]).is_empty());
+
+    repo.rit(&["checkout", "main"]);
+    assert_eq!(repo.read("dir/nested/new.txt"), "new\n");
+}
+
+#[test]
+fn checkout_refuses_to_clobber_local_changes() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "one\n");
+    repo.write("b.txt", "b\n");
+    repo.commit_all("first");
+    repo.rit(&["branch", "other"]);
+    repo.write("a.txt", "two\n");
+    repo.commit_all("second");
+
+    repo.write("a.txt", "local\n");
+    let err = repo.rit_fails(&["switch", "other"]);
+    assert!(err.contains("would be overwritten by checkout"));
+    assert!(err.contains("a.txt"));
+    assert_eq!(repo.read("a.txt"), "local\n");
+    assert_eq!(repo.head(), repo.read(".rit/refs/heads/main").trim());
+
+    // Changes to files that are the same on both branches come along
+    repo.write("a.txt", "two\n");
+    repo.write("b.txt", "edited\n");
+    repo.rit(&["switch", "other"]);
+    assert_eq!(repo.read("a.txt"), "one\n");
+    assert_eq!(repo.read("b.txt"), "edited\n");
+    assert_eq!(repo.rit(&["status", "-s"]), " M b.txt\n");
+}
+
+#[test]
+fn checkout_refuses_to_overwrite_untracked_files() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("first");
+    repo.rit(&["branch", "other"]);
+    repo.write("new.txt", "tracked\n");
+    repo.commit_all("second");
+
+    repo.rit(&["switch", "other"]);
+    repo.write("new.txt", "mine\n");
+
+    let err = repo.rit_fails(&["switch", "main"]);
+    assert!(err.contains("untracked working tree files would be overwritten"));
+    assert_eq!(repo.read("new.txt"), "mine\n");
+}
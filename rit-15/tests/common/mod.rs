@@ -0,0 +1,148 @@
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// A throwaway repository driven through the rit binary
+pub struct TestRepo {
+    dir: TempDir,
+}
+
+impl TestRepo {
+    /// Creates an empty directory and runs `rit init` in it
+    pub fn new() -> TestRepo {
+        let repo = TestRepo::empty();
+        repo.rit(&["init"]);
+        repo
+    }
+
+    /// A directory without a repository
+    pub fn empty() -> TestRepo {
+        TestRepo {
+            dir: tempfile::tempdir().unwrap(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_rit-15"));
+        command.args(args).current_dir(self.path());
+        // rit takes git's identity overrides too, don't pick up whoever runs the tests
+        for field in ["NAME", "EMAIL", "DATE"] {
+            command.env_remove(format!("GIT_AUTHOR_{}", field));
+            command.env_remove(format!("GIT_COMMITTER_{}", field));
+        }
+        command
+    }
+
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    /// Runs rit and returns stdout, panicking with stderr if it fails
+    pub fn rit(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        assert!(
+            output.status.success(),
+            "rit {:?} failed:\n{}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Runs rit expecting failure and returns stderr
+    pub fn rit_fails(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        assert!(
+            !output.status.success(),
+            "rit {:?} unexpectedly succeeded:\n{}",
+            args,
+            String::from_utf8_lossy(&output.stdout)
+        );
+        String::from_utf8(output.stderr).unwrap()
+    }
+
+    pub fn file(&self, name: &str) -> PathBuf {
+        self.path().join(name)
+    }
+
+    pub fn write(&self, name: &str, content: &str) {
+        let path = self.file(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, content).unwrap();
+    }
+
+    pub fn read(&self, name: &str) -> String {
+        fs::read_to_string(self.file(name)).unwrap()
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.file(name).exists()
+    }
+
+    /// Runs git on the repository's `.rit` and returns stdout, panicking with stderr if it fails
+    pub fn git(&self, args: &[&str]) -> String {
+        let mut command = git_command(self.path(), &["--git-dir=.rit", "--work-tree=."]);
+        command.args(args);
+        String::from_utf8(run_git(&mut command)).unwrap()
+    }
+
+    /// Stages everything and commits, returning the new commit hash
+    pub fn commit_all(&self, message: &str) -> String {
+        self.rit(&["add", "."]);
+        self.rit(&["commit", "-m", message]);
+        self.head()
+    }
+
+    /// The commit HEAD resolves to
+    pub fn head(&self) -> String {
+        let head = fs::read_to_string(self.file(".rit/HEAD")).unwrap();
+        match head.trim().strip_prefix("ref: ") {
+            Some(ref_name) => self.read(&format!(".rit/{}", ref_name)).trim().to_string(),
+            None => head.trim().to_string(),
+        }
+    }
+}
+
+/// Whether git is installed. Tests comparing against git return early without it, after
+/// saying so on stderr: written there directly, so the harness doesn't swallow it.
+pub fn git_available() -> bool {
+    if Command::new("git").arg("--version").output().is_ok() {
+        return true;
+    }
+    let test = std::thread::current().name().unwrap_or("test").to_string();
+    let _ = writeln!(std::io::stderr(), "skipping {}: git is not installed", test);
+    false
+}
+
+/// git run in `dir`, committing as a fixed identity and ignoring the system config
+pub fn git_command(dir: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new("git");
+    command.args(args).current_dir(dir).env("GIT_CONFIG_NOSYSTEM", "1");
+    for role in ["AUTHOR", "COMMITTER"] {
+        command.env(format!("GIT_{}_NAME", role), "Git");
+        command.env(format!("GIT_{}_EMAIL", role), "git@example.com");
+    }
+    command
+}
+
+/// Runs a git command and returns stdout, panicking with stderr if it fails
+pub fn run_git(command: &mut Command) -> Vec<u8> {
+    let output = command.output().unwrap();
+    assert!(output.status.success(), "{:?} failed:\n{}", command, String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+/// Runs git in `dir` and returns stdout, panicking with stderr if it fails
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    String::from_utf8(run_git(&mut git_command(dir, args))).unwrap()
+}
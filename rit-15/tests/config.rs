@@ -0,0 +1,142 @@
+mod common;
+
+use common::TestRepo;
+use std::path::Path;
+
+/// Runs rit with HOME pointing at `home`, so ~/.ritconfig is a test file
+fn rit_at_home(repo: &TestRepo, home: &Path, args: &[&str], env: &[(&str, &str)]) -> String {
+    let output = repo.command(args).env("HOME", home).envs(env.iter().copied()).output().unwrap();
+    assert!(
+        output.status.success(),
+        "rit {:?} failed:\n{}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn head_commit(repo: &TestRepo) -> String {
+    repo.rit(&["cat-file", "-p", &repo.head()])
+}
+
+#[test]
+fn repository_config_overrides_global() {
+    let repo = TestRepo::new();
+    let home = TestRepo::empty();
+
+    rit_at_home(&repo, home.path(), &["config", "--global", "user.name", "Global Name"], &[]);
+    rit_at_home(&repo, home.path(), &["config", "--global", "user.email", "global@example.com"], &[]);
+    assert!(home.read(".ritconfig").contains("[user]\n\tname = Global Name\n"));
+    assert_eq!(rit_at_home(&repo, home.path(), &["config", "user.name"], &[]), "Global Name\n");
+
+    rit_at_home(&repo, home.path(), &["config", "user.name", "Repo Name"], &[]);
+    assert_eq!(rit_at_home(&repo, home.path(), &["config", "user.name"], &[]), "Repo Name\n");
+    assert_eq!(
+        rit_at_home(&repo, home.path(), &["config", "--global", "user.name"], &[]),
+        "Global Name\n"
+    );
+
+    let list = rit_at_home(&repo, home.path(), &["config", "--list"], &[]);
+    assert_eq!(
+        list,
+        "user.name=Global Name\nuser.email=global@example.com\nuser.name=Repo Name\n"
+    );
+
+    repo.write("a.txt", "a\n");
+    rit_at_home(&repo, home.path(), &["add", "a.txt"], &[]);
+    rit_at_home(&repo, home.path(), &["commit", "-m", "identity"], &[]);
+    let commit = head_commit(&repo);
+    assert!(commit.contains("\nauthor Repo Name <global@example.com> "));
+    assert!(commit.contains("\ncommitter Repo Name <global@example.com> "));
+
+    rit_at_home(&repo, home.path(), &["config", "--unset", "user.name"], &[]);
+    assert_eq!(rit_at_home(&repo, home.path(), &["config", "user.name"], &[]), "Global Name\n");
+    let output = repo.command(&["config", "--unset", "user.name"]).env("HOME", home.path()).output().unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn environment_overrides_identity_and_date() {
+    let repo = TestRepo::new();
+    let home = TestRepo::empty();
+    repo.rit(&["config", "user.name", "Config Name"]);
+
+    repo.write("a.txt", "a\n");
+    repo.rit(&["add", "a.txt"]);
+    rit_at_home(
+        &repo,
+        home.path(),
+        &["commit", "-m", "from env"],
+        &[
+            ("RIT_AUTHOR_NAME", "A U Thor"),
+            ("RIT_AUTHOR_EMAIL", "author@example.com"),
+            ("RIT_AUTHOR_DATE", "1700000000 +0100"),
+            ("RIT_COMMITTER_DATE", "2024-01-15T12:00:00-05:00"),
+        ],
+    );
+
+    let commit = head_commit(&repo);
+    assert!(commit.contains("\nauthor A U Thor <author@example.com> 1700000000 +0100\n"));
+    assert!(commit.contains("\ncommitter Config Name <rit@example.com> 1705338000 -0500\n"));
+    assert!(repo.rit(&["show"]).contains("Date:   Tue Nov 14 23:13:20 2023 +0100"));
+
+    repo.write("a.txt", "b\n");
+    repo.rit(&["add", "a.txt"]);
+    let output = repo
+        .command(&["commit", "-m", "bad date"])
+        .env("RIT_AUTHOR_DATE", "last tuesday")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid date format"));
+}
+
+#[test]
+fn git_identity_variables_are_honoured() {
+    let repo = TestRepo::new();
+    let home = TestRepo::empty();
+    repo.write("a.txt", "a\n");
+    repo.rit(&["add", "a.txt"]);
+    rit_at_home(
+        &repo,
+        home.path(),
+        &["commit", "-m", "from git env"],
+        &[
+            ("GIT_AUTHOR_NAME", "Git Author"),
+            ("GIT_AUTHOR_EMAIL", "git-author@example.com"),
+            ("GIT_AUTHOR_DATE", "1700000000 +0100"),
+            ("GIT_COMMITTER_NAME", "Git Committer"),
+            ("GIT_COMMITTER_DATE", "1700000001 -0500"),
+            ("RIT_COMMITTER_NAME", "Rit Committer"),
+        ],
+    );
+
+    // The RIT_ variables win where both are set
+    let commit = head_commit(&repo);
+    assert!(commit.contains("\nauthor Git Author <git-author@example.com> 1700000000 +0100\n"));
+    assert!(commit.contains("\ncommitter Rit Committer <rit@example.com> 1700000001 -0500\n"));
+}
+
+#[test]
+fn commits_record_the_local_timezone() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.rit(&["add", "a.txt"]);
+
+    let home = TestRepo::empty();
+
+    // A POSIX rule works even without zoneinfo files installed
+    rit_at_home(&repo, home.path(), &["commit", "-m", "local time"], &[("TZ", "IST-5:30")]);
+    assert!(head_commit(&repo).contains(" +0530\ncommitter "));
+
+    repo.write("a.txt", "b\n");
+    repo.rit(&["add", "a.txt"]);
+    rit_at_home(
+        &repo,
+        home.path(),
+        &["commit", "-m", "winter"],
+        &[("TZ", "CET-1CEST,M3.5.0,M10.5.0/3"), ("RIT_AUTHOR_DATE", "2024-01-15T12:00:00")],
+    );
+    // Noon local time in winter is 11:00 UTC
+    assert!(head_commit(&repo).contains("author Rit User <rit@example.com> 1705316400 +0100\n"));
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn diff_worktree_against_index() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "one\ntwo\nthree\n");
+    repo.commit_all("first");
+
+    repo.write("a.txt", "one\n2\nthree\n");
+    let out = repo.rit(&["diff"]);
+    assert!(out.starts_with("diff --git a/a.txt b/a.txt\nindex "));
+    assert!(out.contains("--- a/a.txt\n+++ b/a.txt\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n"));
+
+    // Once staged, the change moves from `diff` to `diff --cached`
+    repo.rit(&["add", "a.txt"]);
+    assert_eq!(repo.rit(&["diff"]), "");
+    assert!(repo.rit(&["diff", "--cached"]).contains("-two\n+2\n"));
+}
+
+#[test]
+fn diff_between_commits_and_context() {
+    let repo = TestRepo::new();
+    let lines: Vec<String> = (1..=20).map(|i| format!("line {}\n", i)).collect();
+    repo.write("a.txt", &lines.concat());
+    let first = repo.commit_all("first");
+
+    let mut changed = lines.clone();
+    changed[9] = "changed\n".to_string();
+    repo.write("a.txt", &changed.concat());
+    repo.write("new.txt", "new\n");
+    let second = repo.commit_all("second");
+
+    let out = repo.rit(&["diff", &first, &second]);
+    assert!(out.contains("@@ -7,7 +7,7 @@\n"));
+    assert!(out.contains("diff --git a/new.txt b/new.txt\nnew file mode 100644\n"));
+    assert!(out.contains("--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1 @@\n+new\n"));
+
+    let out = repo.rit(&["diff", "-U1", &first, &second]);
+    assert!(out.contains("@@ -9,3 +9,3 @@\n line 9\n-line 10\n+changed\n line 11\n"));
+}
+
+#[test]
+fn show_prints_header_and_patch() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("first");
+    repo.write("a.txt", "b\n");
+    let second = repo.commit_all("second change");
+
+    let out = repo.rit(&["show"]);
+    assert!(out.contains(&format!("commit {}", second)));
+    assert!(out.contains("Author: "));
+    assert!(out.contains("    second change\n"));
+    assert!(out.contains("-a\n+b\n"));
+}
+
+#[test]
+fn binary_files_are_not_printed() {
+    let repo = TestRepo::new();
+    std::fs::write(repo.file("blob.bin"), [0u8, 159, 146, 150]).unwrap();
+    repo.commit_all("binary");
+
+    let out = repo.rit(&["show"]);
+    assert!(out.contains("Binary files /dev/null and b/blob.bin differ\n"));
+}
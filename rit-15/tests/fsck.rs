@@ -0,0 +1,126 @@
+mod common;
+
+use common::TestRepo;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use sha1::{Digest, Sha1};
+use std::io::Write;
+
+fn object_path(hash: &str) -> String {
+    format!(".rit/objects/{}/{}", &hash[..2], &hash[2..])
+}
+
+/// Stores a loose object as is, without any of rit's checks
+fn write_raw_object(repo: &TestRepo, kind: &str, body: &[u8]) -> String {
+    let mut data = format!("{} {}\0", kind, body.len()).into_bytes();
+    data.extend_from_slice(body);
+    let hash = hex::encode(Sha1::digest(&data));
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&data).unwrap();
+    let path = repo.file(&object_path(&hash));
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, encoder.finish().unwrap()).unwrap();
+    hash
+}
+
+fn tree_hash(repo: &TestRepo) -> String {
+    repo.rit(&["log", "-n", "1", "--format=%T"]).trim().to_string()
+}
+
+#[test]
+fn clean_repository_and_dangling_objects() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("first");
+    assert_eq!(repo.rit(&["fsck"]), "");
+
+    // A blob that was written but never added
+    repo.write("loose.txt", "loose\n");
+    repo.rit(&["hash-object", "-w", "loose.txt"]);
+    let blob = repo.rit(&["hash-object", "loose.txt"]);
+    let blob = blob.trim();
+    std::fs::remove_file(repo.file("loose.txt")).unwrap();
+    assert_eq!(repo.rit(&["fsck"]), format!("dangling blob {}\n", blob));
+
+    // A commit that was reset away is kept alive by the reflog
+    repo.write("a.txt", "b\n");
+    repo.commit_all("second");
+    repo.rit(&["reset", "--hard", "HEAD~1"]);
+    assert_eq!(repo.rit(&["fsck"]), format!("dangling blob {}\n", blob));
+}
+
+#[test]
+fn corrupt_objects() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.write("b.txt", "b\n");
+    repo.commit_all("first");
+    let a = repo.rit(&["hash-object", "a.txt"]).trim().to_string();
+    let b = repo.rit(&["hash-object", "b.txt"]).trim().to_string();
+
+    // An object whose content is another object's
+    std::fs::copy(repo.file(&object_path(&b)), repo.file(&object_path(&a))).unwrap();
+    let err = repo.rit_fails(&["fsck"]);
+    assert!(err.contains(&format!("error: hash mismatch for {}: the content hashes to {}", a, b)), "{}", err);
+    assert!(err.contains("fsck found 1 problem(s)"));
+
+    // Garbage instead of zlib data is reported, and other commands fail cleanly
+    std::fs::write(repo.file(&object_path(&a)), "not zlib").unwrap();
+    let err = repo.rit_fails(&["fsck"]);
+    assert!(err.contains(&format!("error: object {} is unreadable: Object {} is corrupt", a, a)), "{}", err);
+    let output = repo.run(&["cat-file", "-p", &a]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let tree = tree_hash(&repo);
+    std::fs::write(repo.file(&object_path(&tree)), "not zlib").unwrap();
+    let output = repo.run(&["show"]);
+    assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let head = repo.head();
+    std::fs::write(repo.file(&object_path(&head)), "not zlib").unwrap();
+    let output = repo.run(&["log"]);
+    assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn missing_objects() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("first");
+    let tree = tree_hash(&repo);
+    let blob = repo.rit(&["hash-object", "a.txt"]).trim().to_string();
+
+    std::fs::remove_file(repo.file(&object_path(&blob))).unwrap();
+    let err = repo.rit_fails(&["fsck"]);
+    assert!(err.contains(&format!("error: missing blob {} (referenced from tree {})", blob, tree)), "{}", err);
+    assert!(err.contains(&format!("error: missing blob {} (referenced from index entry a.txt)", blob)));
+
+    // A branch pointing nowhere
+    repo.write(".rit/refs/heads/gone", "1234567890123456789012345678901234567890\n");
+    let err = repo.rit_fails(&["fsck"]);
+    assert!(err.contains("missing object 1234567890123456789012345678901234567890 (referenced from refs/heads/gone)"));
+}
+
+#[test]
+fn malformed_objects() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("first");
+    let blob = repo.rit(&["hash-object", "a.txt"]).trim().to_string();
+
+    let mut body = b"100644 b\0".to_vec();
+    body.extend(hex::decode(&blob).unwrap());
+    body.extend(b"100644 a\0");
+    body.extend(hex::decode(&blob).unwrap());
+    let unsorted = write_raw_object(&repo, "tree", &body);
+
+    let truncated = write_raw_object(&repo, "tree", b"100644 a\0\x01\x02");
+    let commit = write_raw_object(&repo, "commit", format!("tree {}\n\nno author\n", unsorted).as_bytes());
+
+    let err = repo.rit_fails(&["fsck"]);
+    assert!(err.contains(&format!("error: error in tree {}: entries not sorted properly", unsorted)), "{}", err);
+    assert!(err.contains(&format!("error: error in tree {}: entry 'a' is truncated", truncated)));
+    assert!(err.contains(&format!("error: error in commit {}: missing 'author' line", commit)));
+    assert!(err.contains("fsck found 3 problem(s)"));
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::{TestRepo, git, git_available};
+use std::fs;
+
+fn loose_objects(repo: &TestRepo) -> usize {
+    fs::read_dir(repo.file(".rit/objects"))
+        .unwrap()
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.file_name().len() == 2)
+        .map(|entry| fs::read_dir(entry.path()).unwrap().count())
+        .sum()
+}
+
+fn pack_files(repo: &TestRepo) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(repo.file(".rit/objects/pack"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Three commits of a growing file, plus a branch at the first one
+fn history() -> (TestRepo, String) {
+    let repo = TestRepo::new();
+    let mut content = String::new();
+    let mut first = String::new();
+
+    for i in 0..3 {
+        for line in 0..100 {
+            content.push_str(&format!("commit {} line {}\n", i, line));
+        }
+        repo.write("big.txt", &content);
+        repo.write("small.txt", &format!("{}\n", i));
+        let hash = repo.commit_all(&format!("commit {}", i));
+        if i == 0 {
+            first = hash;
+        }
+    }
+    repo.rit(&["branch", "old", &first]);
+    (repo, first)
+}
+
+#[test]
+fn gc_packs_everything_and_objects_stay_readable() {
+    let (repo, first) = history();
+    let big = repo.read("big.txt");
+
+    let out = repo.rit(&["gc"]);
+    assert!(out.contains("Packed"));
+    assert_eq!(loose_objects(&repo), 0);
+
+    let packs = pack_files(&repo);
+    assert_eq!(packs.len(), 2);
+    assert!(packs[0].starts_with("pack-") && packs[0].ends_with(".idx"));
+
+    assert!(repo.rit(&["log"]).contains("commit 0"));
+    assert!(repo.rit(&["cat-file", "-p", &first]).contains("commit 0"));
+    assert!(repo.rit(&["status", "-s"]).is_empty());
+
+    repo.rit(&["switch", "old"]);
+    assert_eq!(repo.read("small.txt"), "0\n");
+    repo.rit(&["switch", "main"]);
+    assert_eq!(repo.read("big.txt"), big);
+
+    // New objects are written loose next to the pack, a second gc folds them in
+    repo.write("small.txt", "after gc\n");
+    repo.commit_all("after gc");
+    assert!(loose_objects(&repo) > 0);
+    repo.rit(&["gc"]);
+    assert_eq!(loose_objects(&repo), 0);
+    assert_eq!(pack_files(&repo).len(), 2);
+    assert!(repo.rit(&["log"]).contains("after gc"));
+}
+
+#[test]
+fn gc_prunes_unreachable_objects() {
+    let (repo, _) = history();
+    repo.write("stray.txt", "never committed\n");
+    repo.rit(&["hash-object", "-w", "stray.txt"]);
+    let stray = repo.rit(&["hash-object", "stray.txt"]);
+
+    let out = repo.rit(&["gc"]);
+    assert!(out.contains("Pruned 1 unreachable objects"));
+    repo.rit_fails(&["cat-file", "-p", stray.trim()]);
+
+    // Staged but uncommitted content is kept
+    repo.rit(&["add", "stray.txt"]);
+    repo.rit(&["gc"]);
+    assert_eq!(repo.rit(&["cat-file", "-p", stray.trim()]), "never committed\n");
+}
+
+#[test]
+fn git_accepts_the_pack() {
+    if !git_available() {
+        return;
+    }
+
+    let (repo, _) = history();
+    repo.rit(&["gc"]);
+
+    let idx = pack_files(&repo).into_iter().find(|name| name.ends_with(".idx")).unwrap();
+    let verified = git(repo.path(), &["verify-pack", "-v", &format!(".rit/objects/pack/{}", idx)]);
+    assert!(verified.contains("chain length = 1"));
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::TestRepo;
+
+fn staged(repo: &TestRepo) -> Vec<String> {
+    let mut files: Vec<String> = repo
+        .rit(&["status", "--short"])
+        .lines()
+        .filter_map(|line| line.strip_prefix("A  "))
+        .map(str::to_string)
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn add_and_status_follow_ignore_files() {
+    let repo = TestRepo::new();
+    repo.write(
+        ".ritignore",
+        "# build output\n/target\n*.log\n!keep.log\nbuild/\ndocs/**/*.tmp\n",
+    );
+    repo.write("src/main.rs", "fn main() {}\n");
+    repo.write("target/debug/app", "binary\n");
+    repo.write("src/target/data.txt", "not the top-level target\n");
+    repo.write("debug.log", "noise\n");
+    repo.write("logs/deep/trace.log", "noise\n");
+    repo.write("keep.log", "wanted\n");
+    repo.write("build/out.o", "obj\n");
+    repo.write("build.txt", "a file, not the build directory\n");
+    repo.write("docs/a/b/scratch.tmp", "tmp\n");
+    repo.write("docs/readme.md", "docs\n");
+    repo.write("my.github.txt", "not .git\n");
+    repo.write("sub/.ritignore", "*.txt\n!important.txt\n");
+    repo.write("sub/notes.txt", "ignored by the nested file\n");
+    repo.write("sub/important.txt", "re-included\n");
+    repo.write("sub/code.rs", "kept\n");
+
+    let status = repo.rit(&["status", "--short"]);
+    assert!(!status.contains("target/debug"), "{}", status);
+    assert!(!status.contains("build/"), "{}", status);
+
+    repo.rit(&["add", "."]);
+    assert_eq!(
+        staged(&repo),
+        [
+            ".ritignore",
+            "build.txt",
+            "docs/readme.md",
+            "keep.log",
+            "my.github.txt",
+            "src/main.rs",
+            "src/target/data.txt",
+            "sub/.ritignore",
+            "sub/code.rs",
+            "sub/important.txt",
+        ]
+    );
+    assert_eq!(repo.rit(&["status", "--short"]).lines().filter(|l| l.starts_with("??")).count(), 0);
+}
+
+#[test]
+fn explicit_and_tracked_ignored_files() {
+    let repo = TestRepo::new();
+    repo.write("tracked.log", "v1\n");
+    repo.commit_all("before the ignore file");
+    repo.write(".ritignore", "*.log\n");
+    repo.write("new.log", "new\n");
+
+    // Naming an ignored file needs -f
+    let err = repo.rit_fails(&["add", "new.log"]);
+    assert!(err.contains("ignored by one of your .ritignore files"));
+    assert!(err.contains("new.log"));
+    repo.rit(&["add", "-f", "new.log"]);
+
+    // Files already tracked stay visible even though they now match a pattern
+    repo.write("tracked.log", "v2\n");
+    assert!(repo.rit(&["status", "--short"]).contains(" M tracked.log"));
+    repo.rit(&["add", "."]);
+    assert!(repo.rit(&["status", "--short"]).contains("M  tracked.log"));
+
+    // A directory pattern can't be undone for files inside it
+    repo.write(".ritignore", "cache/\n!cache/keep.txt\n");
+    repo.write("cache/keep.txt", "still ignored\n");
+    repo.rit(&["add", "."]);
+    assert!(!repo.rit(&["status", "--short"]).contains("cache"));
+
+    // -f on a directory adds ignored files, but never the repository itself
+    repo.rit(&["add", "-f", "."]);
+    let status = repo.rit(&["status", "--short"]);
+    assert!(status.contains("A  cache/keep.txt"), "{}", status);
+    assert!(!status.contains(".rit/"), "{}", status);
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use common::{TestRepo, git_available};
+use std::fs;
+
+#[test]
+fn index_is_in_dirc_format() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.rit(&["add", "a.txt"]);
+
+    let data = fs::read(repo.file(".rit/index")).unwrap();
+    assert_eq!(&data[..4], b"DIRC");
+    assert_eq!(&data[4..12], &[0, 0, 0, 2, 0, 0, 0, 1]);
+}
+
+#[test]
+fn git_can_read_the_index() {
+    if !git_available() {
+        return;
+    }
+
+    let repo = TestRepo::new();
+    repo.write("a.txt", "hello\n");
+    repo.write("dir/b.txt", "b\n");
+    repo.rit(&["add", "."]);
+
+    let listing = repo.git(&["ls-files", "--stage"]);
+    let hash = repo.rit(&["hash-object", "a.txt"]);
+    assert_eq!(listing.lines().count(), 2);
+    assert!(listing.contains(&format!("100644 {} 0\ta.txt", hash.trim())));
+    assert!(listing.contains("\tdir/b.txt"));
+}
+
+#[test]
+fn same_size_change_right_after_add_is_noticed() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "one\n");
+    repo.commit_all("first");
+
+    // Same size and most likely the same mtime second as the staged version
+    repo.write("a.txt", "two\n");
+    repo.rit(&["add", "a.txt"]);
+    repo.write("a.txt", "six\n");
+
+    assert_eq!(repo.rit(&["status", "-s"]), "MM a.txt\n");
+    assert_eq!(repo.rit(&["status", "-s"]), "MM a.txt\n");
+
+    repo.rit(&["add", "a.txt"]);
+    assert_eq!(repo.rit(&["status", "-s"]), "M  a.txt\n");
+}
+
+#[test]
+fn adding_many_files() {
+    let repo = TestRepo::new();
+    for i in 0..200 {
+        repo.write(&format!("files/{:03}.txt", i), &format!("{}\n", i));
+    }
+    repo.commit_all("many");
+
+    assert!(repo.rit(&["status", "-s"]).is_empty());
+    // Nothing changed, so a second add stages nothing
+    assert!(repo.rit(&["add", "."]).is_empty());
+}
+
+#[test]
+fn unmerged_entries_survive_a_rewrite() {
+    if !git_available() {
+        return;
+    }
+    let repo = TestRepo::new();
+    repo.write("a.txt", "base\n");
+    repo.commit_all("base");
+    repo.rit(&["branch", "other"]);
+    repo.write("a.txt", "ours\n");
+    repo.commit_all("ours");
+    repo.rit(&["switch", "other"]);
+    repo.write("a.txt", "theirs\n");
+    repo.commit_all("theirs");
+    repo.rit(&["switch", "main"]);
+
+    // git leaves a.txt as stages 1 to 3
+    let mut merge = common::git_command(repo.path(), &["--git-dir=.rit", "--work-tree=.", "merge", "other"]);
+    assert!(!merge.output().unwrap().status.success());
+    let unmerged = repo.git(&["ls-files", "-u"]);
+    assert_eq!(unmerged.lines().count(), 3);
+
+    // Staging something else rewrites the index without touching them
+    repo.write("b.txt", "b\n");
+    repo.rit(&["add", "b.txt"]);
+    assert_eq!(repo.git(&["ls-files", "-u"]), unmerged);
+    assert!(!repo.exists(".rit/index.lock"));
+
+    // Staging the file itself resolves it
+    repo.write("a.txt", "both\n");
+    repo.rit(&["add", "a.txt"]);
+    assert_eq!(repo.git(&["ls-files", "-u"]), "");
+    assert_eq!(repo.git(&["status", "--short", "--untracked-files=no"]), "M  a.txt\nA  b.txt\n");
+}
+
+#[test]
+fn a_held_lock_stops_index_writes() {
+    let repo = TestRepo::new();
+    repo.write(".rit/index.lock", "");
+    repo.write("a.txt", "a\n");
+    assert!(repo.rit_fails(&["add", "a.txt"]).contains("index.lock': File exists"));
+    assert!(!repo.exists(".rit/index"));
+}
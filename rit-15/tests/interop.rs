@@ -0,0 +1,210 @@
+mod common;
+
+use common::{TestRepo, git_available, git_command, run_git};
+use std::fs::Permissions;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+// Both tools sign as the same people at the same times, so their commits can be compared
+const IDENTITY: [(&str, &str); 6] = [
+    ("NAME", "Ada Lovelace"),
+    ("EMAIL", "ada@example.com"),
+    ("DATE", "1700000000 +0100"),
+    ("NAME", "Charles Babbage"),
+    ("EMAIL", "charles@example.com"),
+    ("DATE", "1700003600 -0500"),
+];
+
+fn identity(command: &mut Command, prefix: &str) {
+    for (i, (key, value)) in IDENTITY.iter().enumerate() {
+        let role = if i < 3 { "AUTHOR" } else { "COMMITTER" };
+        command.env(format!("{}_{}_{}", prefix, role, key), value);
+    }
+}
+
+fn git_bytes(repo: &TestRepo, args: &[&str]) -> Vec<u8> {
+    let mut command = git_command(repo.path(), args);
+    identity(&mut command, "GIT");
+    run_git(&mut command)
+}
+
+fn git(repo: &TestRepo, args: &[&str]) -> String {
+    String::from_utf8(git_bytes(repo, args)).unwrap()
+}
+
+/// Runs rit on the repository's `.git`
+fn rit_bytes(repo: &TestRepo, args: &[&str]) -> Vec<u8> {
+    let mut command = repo.command(&["--git-dir=.git"]);
+    command.args(args);
+    identity(&mut command, "RIT");
+    let output = command.output().unwrap();
+    assert!(output.status.success(), "rit {:?} failed:\n{}", args, String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+fn rit(repo: &TestRepo, args: &[&str]) -> String {
+    String::from_utf8(rit_bytes(repo, args)).unwrap()
+}
+
+// rit colors the commit lines of its log, git doesn't when writing to a pipe
+fn strip_colors(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('\x1b') {
+        out.push_str(&rest[..start]);
+        let end = rest[start..].find('m').unwrap();
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A git repository with nested directories, an executable, a symlink, names that sort
+/// differently as files and directories, a merge and an annotated tag
+fn git_repo() -> TestRepo {
+    let repo = TestRepo::empty();
+    git(&repo, &["init", "-q", "-b", "main"]);
+    repo.write("a.txt", "a\n");
+    repo.write("a.b", "a dot b\n");
+    repo.write("a/inner.txt", "inner\n");
+    repo.write("dir/sub/deep.txt", "deep\n");
+    repo.write("run.sh", "#!/bin/sh\necho run\n");
+    std::fs::set_permissions(repo.file("run.sh"), Permissions::from_mode(0o755)).unwrap();
+    std::os::unix::fs::symlink("a.txt", repo.file("link")).unwrap();
+    git(&repo, &["add", "-A"]);
+    git(&repo, &["commit", "-q", "-m", "first"]);
+
+    git(&repo, &["switch", "-q", "-c", "topic"]);
+    repo.write("topic.txt", "topic\n");
+    git(&repo, &["add", "topic.txt"]);
+    git(&repo, &["commit", "-q", "-m", "topic work\n\nwith a body\nof two lines"]);
+
+    git(&repo, &["switch", "-q", "main"]);
+    repo.write("a.txt", "a\nmore\n");
+    git(&repo, &["commit", "-q", "-a", "-m", "second"]);
+    git(&repo, &["merge", "-q", "--no-ff", "-m", "merge topic", "topic"]);
+    git(&repo, &["tag", "-a", "v1", "-m", "release\n\nnotes"]);
+    repo
+}
+
+#[test]
+fn rit_reads_git_repositories() {
+    if !git_available() {
+        return;
+    }
+    let repo = git_repo();
+
+    let format = "%H %T %P%n%an <%ae> %at%n%cn <%ce> %ct%n%s%n%b%n--";
+    let format = format!("--format={}", format);
+    assert_eq!(rit(&repo, &["log", &format]), git(&repo, &["log", &format]));
+    assert_eq!(strip_colors(&rit(&repo, &["log"])), git(&repo, &["log"]));
+    assert_eq!(rit(&repo, &["status", "--short"]), "");
+
+    // Every object reads back exactly as git shows it
+    let mut objects: Vec<String> = git(&repo, &["rev-list", "--objects", "--all"])
+        .lines()
+        .map(|line| line.split(' ').next().unwrap().to_string())
+        .collect();
+    objects.push(git(&repo, &["rev-parse", "v1"]).trim().to_string());
+    assert!(objects.len() > 15);
+    for object in &objects {
+        assert_eq!(rit_bytes(&repo, &["cat-file", "-p", object]), git_bytes(&repo, &["cat-file", "-p", object]));
+    }
+}
+
+#[test]
+fn rit_writes_the_objects_git_would() {
+    if !git_available() {
+        return;
+    }
+    let repo = git_repo();
+
+    // The same index gives the same tree
+    assert_eq!(rit(&repo, &["write-tree"]), git(&repo, &["write-tree"]));
+
+    // An index rit wrote is one git reads, and the trees still agree
+    repo.write("a/inner.txt", "changed\n");
+    repo.write("new dir/file", "new\n");
+    rit(&repo, &["add", "a/inner.txt"]);
+    rit(&repo, &["add", "new dir/file"]);
+    assert_eq!(git(&repo, &["status", "--short", "--untracked-files=no"]), "M  a/inner.txt\nA  \"new dir/file\"\n");
+    let tree = rit(&repo, &["write-tree"]);
+    assert_eq!(tree, git(&repo, &["write-tree"]));
+
+    // A commit rit makes is byte for byte the one git makes from the same tree
+    let parent = git(&repo, &["rev-parse", "HEAD"]);
+    rit(&repo, &["commit", "-m", "third"]);
+    let commit = git(&repo, &["rev-parse", "HEAD"]);
+    let expected = git(&repo, &["commit-tree", tree.trim(), "-p", parent.trim(), "-m", "third"]);
+    assert_eq!(commit, expected);
+    assert_eq!(git(&repo, &["log", "-n", "1", "--format=%s"]), "third\n");
+    assert_eq!(git(&repo, &["status", "--short", "--untracked-files=no"]), "");
+    git(&repo, &["fsck", "--strict", "--no-dangling"]);
+
+    // commit-tree too, and both sides read the result the same way
+    let by_rit = rit(&repo, &["commit-tree", tree.trim(), "-p", parent.trim(), "-m", "third"]);
+    assert_eq!(by_rit, expected);
+    let commit = commit.trim();
+    assert_eq!(rit_bytes(&repo, &["cat-file", "-p", commit]), git_bytes(&repo, &["cat-file", "-p", commit]));
+}
+
+#[test]
+fn packed_refs_are_read() {
+    if !git_available() {
+        return;
+    }
+    let repo = git_repo();
+    git(&repo, &["branch", "old", "HEAD~1"]);
+    git(&repo, &["pack-refs", "--all"]);
+    assert!(!repo.exists(".git/refs/heads/main"));
+
+    assert_eq!(rit(&repo, &["log", "--format=%H"]), git(&repo, &["log", "--format=%H"]));
+    assert_eq!(rit(&repo, &["cat-file", "-p", "v1"]), git(&repo, &["cat-file", "-p", "v1"]));
+    assert_eq!(rit(&repo, &["log", "-n", "1", "--format=%s", "topic"]), "topic work\n");
+    assert!(rit(&repo, &["status"]).starts_with("On branch main\n"));
+    assert_eq!(rit(&repo, &["tag"]), "v1\n");
+    assert_eq!(rit(&repo, &["branch"]), "* \x1b[32mmain\x1b[0m\n  old\n  topic\n");
+
+    // A commit writes the branch file, which wins over the packed line
+    repo.write("c.txt", "c\n");
+    rit(&repo, &["add", "c.txt"]);
+    rit(&repo, &["commit", "-m", "third"]);
+    assert_eq!(git(&repo, &["log", "-n", "1", "--format=%s", "main"]), "third\n");
+
+    // Deleting a packed branch drops its line
+    rit(&repo, &["branch", "-D", "old"]);
+    assert!(!repo.read(".git/packed-refs").contains("refs/heads/old"));
+    assert_eq!(git(&repo, &["branch", "--format=%(refname:short)"]), "main\ntopic\n");
+    git(&repo, &["fsck", "--strict", "--no-dangling"]);
+}
+
+#[test]
+fn rit_dir_comes_from_the_environment() {
+    if !git_available() {
+        return;
+    }
+
+    // Any directory can hold the repository
+    let repo = TestRepo::empty();
+    let output = repo.command(&["init"]).env("RIT_DIR", "meta").output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "Initialized empty Rit  repository in meta\n");
+    assert!(repo.exists("meta/HEAD"));
+    assert!(!repo.exists(".rit"));
+    repo.write("a.txt", "a\n");
+    let status = repo.command(&["status", "--short"]).env("RIT_DIR", "meta").output().unwrap();
+    assert_eq!(String::from_utf8(status.stdout).unwrap(), "?? a.txt\n");
+
+    // Remotes are still served from their own .rit
+    let origin = TestRepo::new();
+    origin.write("a.txt", "a\n");
+    origin.commit_all("first");
+
+    let repo = git_repo();
+    let url = origin.path().to_str().unwrap();
+    for args in [vec!["config", "remote.origin.url", url], vec!["fetch", "origin"]] {
+        let output = repo.command(&args).env("RIT_DIR", ".git").output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+    assert_eq!(git(&repo, &["rev-parse", "refs/remotes/origin/main"]).trim(), origin.head());
+    git(&repo, &["fsck", "--strict", "--no-dangling"]);
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use common::{TestRepo, git, git_available};
+use rit::{Head, Object, ObjectDatabase, ObjectFormat, Repository, Tree};
+
+#[test]
+fn discovers_the_repository_from_a_subdirectory() {
+    let repo = TestRepo::new();
+    repo.write("src/deep/file.txt", "x\n");
+
+    let found = Repository::discover(repo.file("src/deep")).unwrap();
+    assert_eq!(found.work_tree().canonicalize().unwrap(), repo.path().canonicalize().unwrap());
+    assert_eq!(found.format(), ObjectFormat::Sha1);
+    assert_eq!(found.head().unwrap(), Head::Branch("refs/heads/main".to_string()));
+    assert_eq!(found.head_commit().unwrap(), None);
+
+    let outside = TestRepo::empty();
+    let err = Repository::discover(outside.path()).err().unwrap();
+    assert!(err.to_string().contains("not a rit repository"));
+    assert!(Repository::open(outside.path()).is_err());
+}
+
+#[test]
+fn reads_and_writes_typed_objects() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.write("dir/b.txt", "b\n");
+    repo.commit_all("first");
+    let rit = Repository::open(repo.path()).unwrap();
+
+    let head = rit.head_commit().unwrap().unwrap();
+    assert_eq!(head, repo.head());
+    let Object::Commit(commit) = rit.objects().read(&head).unwrap() else {
+        panic!("HEAD is not a commit");
+    };
+    assert_eq!(commit.message, b"first\n");
+
+    let Object::Tree(tree) = rit.objects().read(&commit.tree).unwrap() else {
+        panic!("the commit's tree is not a tree");
+    };
+    let names: Vec<&str> = tree.entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["a.txt", "dir"]);
+
+    // Objects written through the library are what the CLI would write
+    let blob = rit.objects().write_raw("blob", b"a\n").unwrap();
+    assert_eq!(blob, tree.entries[0].hash);
+    let mut child = commit.clone();
+    child.parents = vec![head.clone()];
+    child.message = b"second\n".to_vec();
+    let child_hash = rit.objects().write(&Object::Commit(child)).unwrap();
+    rit.set_ref("refs/heads/main", &child_hash).unwrap();
+    assert_eq!(repo.rit(&["log", "--format=%s"]), "second\nfirst\n");
+
+    let empty = rit.objects().write(&Object::Tree(Tree::default())).unwrap();
+    assert_eq!(empty, "4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+    assert!(rit.objects().contains(&empty));
+}
+
+#[test]
+fn refs_api() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("first");
+    repo.rit(&["branch", "feature/x"]);
+    repo.rit(&["gc"]);
+    let rit = Repository::discover(repo.path()).unwrap();
+    let head = repo.head();
+
+    assert_eq!(
+        rit.refs("refs/heads").unwrap(),
+        [("feature/x".to_string(), head.clone()), ("main".to_string(), head.clone())]
+    );
+    // Packed objects are found too
+    assert!(matches!(rit.objects().read(&head).unwrap(), Object::Commit(_)));
+
+    rit.set_ref("refs/tags/v1", &head).unwrap();
+    assert_eq!(repo.rit(&["tag"]), "v1\n");
+    rit.delete_ref("refs/heads/feature/x").unwrap();
+    assert!(!repo.exists(".rit/refs/heads/feature"));
+    assert_eq!(rit.read_ref("refs/heads/feature/x").unwrap(), None);
+
+    rit.set_head_branch("refs/heads/other").unwrap();
+    assert_eq!(rit.head_commit().unwrap(), None);
+}
+
+#[test]
+fn opens_a_repository_kept_elsewhere() {
+    if !git_available() {
+        return;
+    }
+    let repo = TestRepo::empty();
+    git(repo.path(), &["init", "-q", "-b", "main"]);
+    repo.write("a.txt", "a\n");
+    git(repo.path(), &["add", "a.txt"]);
+    git(repo.path(), &["commit", "-q", "-m", "from git"]);
+    let head = git(repo.path(), &["rev-parse", "HEAD"]).trim().to_string();
+
+    let rit = Repository::open_with_rit_dir(repo.path(), repo.file(".git")).unwrap();
+    assert_eq!(rit.rit_dir(), repo.file(".git"));
+    assert_eq!(rit.head_commit().unwrap(), Some(head.clone()));
+    let Object::Commit(commit) = rit.objects().read(&head).unwrap() else {
+        panic!("HEAD is not a commit");
+    };
+    assert_eq!(commit.message, b"from git\n");
+
+    // Its refs are written there too, not into a .rit that doesn't exist
+    rit.set_ref("refs/heads/copy", &head).unwrap();
+    assert_eq!(git(repo.path(), &["rev-parse", "copy"]).trim(), head);
+    assert!(!repo.exists(".rit"));
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use common::TestRepo;
+
+/// Commits everything with a fixed date and author, returning the new commit
+fn commit_at(repo: &TestRepo, message: &str, date: i64, author: &str) -> String {
+    repo.rit(&["add", "."]);
+    let date = format!("{} +0000", date);
+    let output = repo
+        .command(&["commit", "-m", message])
+        .env("RIT_AUTHOR_NAME", author)
+        .env("RIT_AUTHOR_EMAIL", format!("{}@example.com", author.to_lowercase()))
+        .env("RIT_AUTHOR_DATE", &date)
+        .env("RIT_COMMITTER_DATE", &date)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    repo.head()
+}
+
+/// main: base - m1 - m2 - merge, side: base - s1 - s2 (merged)
+fn history() -> TestRepo {
+    let repo = TestRepo::new();
+    repo.write("README", "base\n");
+    commit_at(&repo, "base", 1_000, "Alice");
+
+    repo.rit(&["switch", "-c", "side"]);
+    repo.write("side/a.txt", "1\n");
+    commit_at(&repo, "s1", 2_000, "Bob");
+    repo.write("side/a.txt", "2\n");
+    commit_at(&repo, "s2", 4_000, "Bob");
+
+    repo.rit(&["switch", "main"]);
+    repo.write("main.txt", "1\n");
+    commit_at(&repo, "m1", 3_000, "Alice");
+    repo.write("main.txt", "2\n");
+    commit_at(&repo, "m2", 5_000, "Alice");
+
+    repo.rit(&["merge", "side"]);
+    // Record the merge at a known date too
+    let merge_tree = repo.rit(&["write-tree"]);
+    let merge = repo
+        .command(&["commit-tree", merge_tree.trim(), "-p", "HEAD^", "-p", "side", "-m", "merge side"])
+        .env("RIT_AUTHOR_DATE", "6000 +0000")
+        .env("RIT_COMMITTER_DATE", "6000 +0000")
+        .output()
+        .unwrap();
+    repo.rit(&["update-ref", "refs/heads/main", String::from_utf8(merge.stdout).unwrap().trim()]);
+    repo
+}
+
+fn subjects(repo: &TestRepo, args: &[&str]) -> Vec<String> {
+    let mut command = vec!["log", "--format=%s"];
+    command.extend_from_slice(args);
+    repo.rit(&command).lines().map(str::to_string).collect()
+}
+
+#[test]
+fn walks_merges_in_date_order() {
+    let repo = history();
+    assert_eq!(subjects(&repo, &[]), ["merge side", "m2", "s2", "m1", "s1", "base"]);
+    assert_eq!(subjects(&repo, &["-n", "2"]), ["merge side", "m2"]);
+    assert_eq!(subjects(&repo, &["side"]), ["s2", "s1", "base"]);
+    assert_eq!(subjects(&repo, &["HEAD^2", "HEAD^1"]), ["m2", "s2", "m1", "s1", "base"]);
+
+    // A parent is never shown before its children, whatever the clocks say
+    repo.write("late.txt", "skewed clock\n");
+    commit_at(&repo, "from the past", 500, "Alice");
+    assert_eq!(subjects(&repo, &["-n", "2"]), ["from the past", "merge side"]);
+}
+
+#[test]
+fn filters() {
+    let repo = history();
+    assert_eq!(subjects(&repo, &["--author", "Bob"]), ["s2", "s1"]);
+    assert_eq!(subjects(&repo, &["--author", "alice@example"]), ["m2", "m1", "base"]);
+    assert_eq!(subjects(&repo, &["--since", "@3000 +0000"]), ["merge side", "m2", "s2", "m1"]);
+    assert_eq!(subjects(&repo, &["--until", "@3000 +0000"]), ["m1", "s1", "base"]);
+    assert_eq!(subjects(&repo, &["--since", "1970-01-01T00:50:00Z", "--until", "@4000"]), ["s2", "m1"]);
+    assert!(subjects(&repo, &["--since", "2 weeks ago"]).is_empty());
+
+    // Only commits that changed the paths; the merge took side/ as it was on side
+    assert_eq!(subjects(&repo, &["--", "side"]), ["s2", "s1"]);
+    assert_eq!(subjects(&repo, &["--", "main.txt", "README"]), ["m2", "m1", "base"]);
+    assert!(subjects(&repo, &["--", "nothing"]).is_empty());
+
+    repo.rit_fails(&["log", "--since", "someday"]);
+}
+
+#[test]
+fn formats() {
+    let repo = history();
+    let head = repo.head();
+    let oneline = repo.rit(&["log", "--oneline", "-n", "1"]);
+    assert_eq!(oneline, format!("{} merge side\n", &head[..7]));
+    assert_eq!(repo.rit(&["log", "--format=oneline", "-n1"]), oneline);
+
+    let out = repo.rit(&["log", "-n", "1", "--format", "format:%H%n%an <%ae> %at%n%P%n%%%x"]);
+    let parents = repo.rit(&["log", "-n", "1", "--format=%p"]);
+    assert_eq!(parents.split_whitespace().count(), 2);
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], head);
+    assert_eq!(lines[1], "Rit User <rit@example.com> 6000");
+    assert_eq!(lines[2].split(' ').map(|p| &p[..7]).collect::<Vec<_>>().join(" "), parents.trim());
+    assert_eq!(lines[3], "%%x");
+
+    // The default format matches git's "medium"
+    let medium = repo.rit(&["log", "-n", "2", "HEAD^1"]);
+    assert!(medium.contains("Author: Alice <alice@example.com>\nDate:   Thu Jan 1 01:23:20 1970 +0000\n\n    m2\n\n"));
+    assert!(medium.contains("commit "));
+    let merge = repo.rit(&["log", "-n", "1"]);
+    assert!(merge.contains("\nMerge: "));
+}
+
+#[test]
+fn graph() {
+    let repo = history();
+    let out = repo.rit(&["log", "--graph", "--format=%s"]);
+    assert_eq!(out, "* merge side\n|\\\n* | m2\n| * s2\n* | m1\n| * s1\n|/\n* base\n");
+
+    // Filtered commits are skipped without breaking the lines
+    let out = repo.rit(&["log", "--graph", "--oneline", "--author", "Bob"]);
+    assert_eq!(out.lines().count(), 2);
+    assert!(out.lines().all(|line| line.starts_with("* ")));
+}
+
+#[test]
+fn max_count_stops_the_walk_early() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "1\n");
+    let first = commit_at(&repo, "first", 1_000, "Alice");
+    for (i, date) in [2_000, 3_000].into_iter().enumerate() {
+        repo.write("a.txt", &format!("{}\n", i + 2));
+        commit_at(&repo, &format!("commit {}", i + 2), date, "Alice");
+    }
+
+    // Without the root commit only a walk reading the whole history fails
+    std::fs::remove_file(repo.file(&format!(".rit/objects/{}/{}", &first[..2], &first[2..]))).unwrap();
+    assert_eq!(repo.rit(&["log", "-n", "1", "--format=%s"]), "commit 3\n");
+    repo.rit_fails(&["log", "--format=%s"]);
+}
@@ -0,0 +1,192 @@
+mod common;
+
+use common::TestRepo;
+
+/// main and feature both branch off a commit with a.txt = "one\ntwo\nthree\n"
+fn diverged() -> (TestRepo, String) {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "one\ntwo\nthree\n");
+    repo.write("b.txt", "b\n");
+    let base = repo.commit_all("base");
+    repo.rit(&["branch", "feature"]);
+    (repo, base)
+}
+
+fn parents(repo: &TestRepo, commit: &str) -> Vec<String> {
+    repo.rit(&["cat-file", "-p", commit])
+        .lines()
+        .filter_map(|line| line.strip_prefix("parent "))
+        .map(|hash| hash.to_string())
+        .collect()
+}
+
+#[test]
+fn fast_forwards_when_head_is_an_ancestor() {
+    let (repo, base) = diverged();
+    repo.rit(&["switch", "feature"]);
+    repo.write("a.txt", "one\ntwo\nthree\nfour\n");
+    let tip = repo.commit_all("add four");
+    repo.rit(&["switch", "main"]);
+
+    let out = repo.rit(&["merge", "feature"]);
+    assert!(out.contains("Fast-forward"));
+    assert_eq!(repo.head(), tip);
+    assert_eq!(repo.read("a.txt"), "one\ntwo\nthree\nfour\n");
+    assert!(repo.rit(&["status", "-s"]).is_empty());
+
+    // Merging an ancestor does nothing
+    let out = repo.rit(&["merge", &base]);
+    assert!(out.contains("Already up to date."));
+    assert_eq!(repo.head(), tip);
+}
+
+#[test]
+fn clean_three_way_merge_creates_two_parent_commit() {
+    let (repo, _) = diverged();
+    repo.rit(&["switch", "feature"]);
+    repo.write("a.txt", "one\ntwo\nTHREE\n");
+    repo.write("b.txt", "feature\n");
+    let theirs = repo.commit_all("feature work");
+
+    repo.rit(&["switch", "main"]);
+    repo.write("a.txt", "ONE\ntwo\nthree\n");
+    let ours = repo.commit_all("main work");
+
+    let out = repo.rit(&["merge", "feature"]);
+    assert!(out.contains("Merge made by the 'three-way' strategy."));
+
+    let merge = repo.head();
+    assert_eq!(parents(&repo, &merge), vec![ours, theirs]);
+    assert_eq!(repo.read("a.txt"), "ONE\ntwo\nTHREE\n");
+    assert_eq!(repo.read("b.txt"), "feature\n");
+    assert!(repo.rit(&["status", "-s"]).is_empty());
+}
+
+#[test]
+fn conflicts_leave_markers_and_block_commit_until_resolved() {
+    let (repo, _) = diverged();
+    repo.rit(&["switch", "feature"]);
+    repo.write("a.txt", "one\ntheirs\nthree\n");
+    let theirs = repo.commit_all("feature work");
+
+    repo.rit(&["switch", "main"]);
+    repo.write("a.txt", "one\nours\nthree\n");
+    let ours = repo.commit_all("main work");
+
+    let out = repo.rit(&["merge", "feature"]);
+    assert!(out.contains("CONFLICT (content): Merge conflict in a.txt"));
+    assert_eq!(
+        repo.read("a.txt"),
+        "one\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> feature\nthree\n"
+    );
+    assert_eq!(repo.read(".rit/MERGE_HEAD").trim(), theirs);
+    assert_eq!(repo.head(), ours);
+    assert_eq!(repo.rit(&["status", "-s"]), "UU a.txt\n");
+
+    let err = repo.rit_fails(&["commit", "-m", "merge"]);
+    assert!(err.contains("unmerged files"));
+    repo.rit_fails(&["merge", "feature"]);
+
+    repo.write("a.txt", "one\nboth\nthree\n");
+    repo.rit(&["add", "a.txt"]);
+    repo.rit(&["commit", "-m", "Merge branch 'feature'"]);
+
+    assert_eq!(parents(&repo, &repo.head()), vec![ours, theirs]);
+    assert!(!repo.exists(".rit/MERGE_HEAD"));
+    assert!(repo.rit(&["status", "-s"]).is_empty());
+}
+
+#[test]
+fn abort_restores_head() {
+    let (repo, _) = diverged();
+    repo.rit(&["switch", "feature"]);
+    repo.write("a.txt", "one\ntheirs\nthree\n");
+    repo.write("b.txt", "feature\n");
+    repo.commit_all("feature work");
+
+    repo.rit(&["switch", "main"]);
+    repo.write("a.txt", "one\nours\nthree\n");
+    let ours = repo.commit_all("main work");
+
+    repo.rit(&["merge", "feature"]);
+    assert_eq!(repo.read("b.txt"), "feature\n");
+
+    repo.rit(&["merge", "--abort"]);
+    assert_eq!(repo.read("a.txt"), "one\nours\nthree\n");
+    assert_eq!(repo.read("b.txt"), "b\n");
+    assert!(!repo.exists(".rit/MERGE_HEAD"));
+    assert_eq!(repo.head(), ours);
+    assert!(repo.rit(&["status", "-s"]).is_empty());
+
+    repo.rit_fails(&["merge", "--abort"]);
+}
+
+#[test]
+fn refuses_to_merge_with_local_changes() {
+    let (repo, _) = diverged();
+    repo.rit(&["switch", "feature"]);
+    repo.write("a.txt", "changed\n");
+    repo.commit_all("feature work");
+    repo.rit(&["switch", "main"]);
+
+    repo.write("a.txt", "dirty\n");
+    repo.rit_fails(&["merge", "feature"]);
+    assert_eq!(repo.read("a.txt"), "dirty\n");
+}
+
+#[test]
+fn unrelated_histories_need_a_flag() {
+    let (repo, _) = diverged();
+    repo.write("c.txt", "c\n");
+    repo.rit(&["add", "c.txt"]);
+    let tree = repo.rit(&["write-tree"]);
+    let root = repo.rit(&["commit-tree", tree.trim(), "-m", "another root"]);
+    repo.write(".rit/refs/heads/other", &root);
+    repo.rit(&["reset", "--hard", "HEAD"]);
+
+    let err = repo.rit_fails(&["merge", "other"]);
+    assert!(err.contains("refusing to merge unrelated histories"), "{}", err);
+    assert!(!repo.exists("c.txt"));
+
+    repo.rit(&["merge", "--allow-unrelated-histories", "other"]);
+    assert_eq!(repo.read("c.txt"), "c\n");
+    assert_eq!(parents(&repo, &repo.head()).len(), 2);
+}
+
+#[test]
+fn conflicts_are_recorded_as_index_stages() {
+    let (repo, _) = diverged();
+    repo.rit(&["switch", "feature"]);
+    repo.write("a.txt", "one\ntheirs\nthree\n");
+    repo.rit(&["rm", "b.txt"]);
+    repo.commit_all("feature work");
+    repo.rit(&["switch", "main"]);
+    repo.write("a.txt", "one\nours\nthree\n");
+    repo.write("b.txt", "b changed\n");
+    repo.commit_all("main work");
+
+    repo.rit(&["merge", "feature"]);
+    assert_eq!(repo.rit(&["status", "-s"]), "UU a.txt\nUU b.txt\n");
+    if common::git_available() {
+        // a.txt has all three stages, b.txt no stage 3 as feature deleted it
+        let stages: Vec<String> = repo
+            .git(&["ls-files", "-u"])
+            .lines()
+            .map(|line| {
+                let (info, path) = line.split_once('\t').unwrap();
+                format!("{} {}", &info[info.len() - 1..], path)
+            })
+            .collect();
+        assert_eq!(stages, ["1 a.txt", "2 a.txt", "3 a.txt", "1 b.txt", "2 b.txt"]);
+        assert_eq!(repo.git(&["status", "--short", "--untracked-files=no"]), "UU a.txt\nUD b.txt\n");
+    }
+
+    repo.write("a.txt", "one\nboth\nthree\n");
+    repo.rit(&["add", "a.txt"]);
+    assert!(repo.rit_fails(&["commit", "-m", "merge"]).contains("unmerged files: b.txt"));
+    repo.rit(&["add", "b.txt"]);
+    repo.rit(&["commit", "-m", "merge"]);
+    if common::git_available() {
+        assert_eq!(repo.git(&["ls-files", "-u"]), "");
+    }
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use common::TestRepo;
+
+fn subjects(repo: &TestRepo, rev: &str) -> Vec<String> {
+    repo.rit(&["log", "--format=%s", rev]).lines().map(str::to_string).collect()
+}
+
+fn hash(repo: &TestRepo, rev: &str) -> String {
+    repo.rit(&["log", "-n", "1", "--format=%H", rev]).trim().to_string()
+}
+
+/// main: base - m1, topic: base - t1 - t2, checked out on topic. With `conflicting`,
+/// t1 and m1 change the same line of a.txt.
+fn diverged(conflicting: bool) -> TestRepo {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "1\n2\n3\n");
+    repo.commit_all("base");
+
+    repo.rit(&["switch", "-c", "topic"]);
+    repo.write("a.txt", "1\n2\ntopic\n");
+    repo.commit_all("t1");
+    repo.write("t2.txt", "t2\n");
+    repo.commit_all("t2");
+
+    repo.rit(&["switch", "main"]);
+    repo.write("a.txt", if conflicting { "1\n2\nmain\n" } else { "main\n2\n3\n" });
+    repo.commit_all("m1");
+    repo.rit(&["switch", "topic"]);
+    repo
+}
+
+#[test]
+fn cherry_pick_keeps_author_and_message() {
+    let repo = diverged(false);
+    let t1 = hash(&repo, "topic~1");
+    repo.rit(&["switch", "main"]);
+
+    let output = repo.command(&["cherry-pick", &t1]).env("RIT_COMMITTER_NAME", "Picker").output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(subjects(&repo, "HEAD"), ["t1", "m1", "base"]);
+    assert_eq!(repo.read("a.txt"), "main\n2\ntopic\n");
+    assert_eq!(repo.rit(&["log", "-n", "1", "--format=%an / %cn"]), "Rit User / Picker\n");
+    assert!(repo.rit(&["reflog"]).contains("HEAD@{0}: cherry-pick: t1\n"));
+
+    // Picking it again changes nothing
+    assert!(repo.rit_fails(&["cherry-pick", &t1]).contains("now empty"));
+    assert!(repo.rit_fails(&["cherry-pick", "--continue"]).contains("no cherry-pick in progress"));
+}
+
+#[test]
+fn cherry_pick_conflicts() {
+    let repo = diverged(true);
+    repo.rit(&["switch", "main"]);
+    let m1 = repo.head();
+
+    let out = repo.rit(&["cherry-pick", "topic~1"]);
+    assert!(out.contains("CONFLICT (content): Merge conflict in a.txt"));
+    let t1 = hash(&repo, "topic~1");
+    assert_eq!(
+        repo.read("a.txt"),
+        format!("1\n2\n<<<<<<< HEAD\nmain\n=======\ntopic\n>>>>>>> {} (t1)\n", &t1[..7])
+    );
+    assert!(repo.rit(&["status"]).contains("You are currently cherry-picking commit"));
+    assert!(repo.rit_fails(&["cherry-pick", "--continue"]).contains("unmerged files: a.txt"));
+
+    // --abort goes back to HEAD
+    repo.rit(&["cherry-pick", "--abort"]);
+    assert_eq!(repo.head(), m1);
+    assert_eq!(repo.read("a.txt"), "1\n2\nmain\n");
+    assert_eq!(repo.rit(&["status", "--short"]), "");
+
+    // --continue commits the resolution with the original message
+    repo.rit(&["cherry-pick", "topic~1"]);
+    repo.write("a.txt", "1\n2\nboth\n");
+    repo.rit(&["add", "a.txt"]);
+    repo.rit(&["cherry-pick", "--continue"]);
+    assert_eq!(subjects(&repo, "HEAD"), ["t1", "m1", "base"]);
+    assert!(!repo.exists(".rit/CHERRY_PICK_HEAD"));
+    assert_eq!(repo.rit(&["status", "--short"]), "");
+}
+
+#[test]
+fn rebase_replays_unique_commits() {
+    let repo = diverged(false);
+    let m1 = hash(&repo, "main");
+
+    let out = repo.rit(&["rebase", "main"]);
+    assert_eq!(out, "Successfully rebased and updated refs/heads/topic.\n");
+    assert_eq!(subjects(&repo, "HEAD"), ["t2", "t1", "m1", "base"]);
+    assert_eq!(hash(&repo, "HEAD~2"), m1);
+    assert_eq!(repo.read("a.txt"), "main\n2\ntopic\n");
+    assert_eq!(repo.read(".rit/HEAD"), "ref: refs/heads/topic\n");
+    assert!(!repo.exists(".rit/rebase-merge"));
+
+    // The old tip is still in the reflog
+    assert!(repo.rit(&["reflog", "topic"]).contains("topic@{0}: rebase (finish): refs/heads/topic onto"));
+    assert_eq!(subjects(&repo, "topic@{1}"), ["t2", "t1", "base"]);
+
+    assert_eq!(repo.rit(&["rebase", "main"]), "Current branch topic is up to date.\n");
+
+    // A branch that is only behind is fast-forwarded
+    repo.rit(&["switch", "main"]);
+    repo.rit(&["rebase", "topic"]);
+    assert_eq!(repo.head(), hash(&repo, "topic"));
+}
+
+#[test]
+fn rebase_stops_on_conflicts() {
+    let repo = diverged(true);
+    let orig = repo.head();
+
+    let out = repo.rit(&["rebase", "main"]);
+    assert!(out.contains("CONFLICT (content): Merge conflict in a.txt"));
+    assert!(out.contains("could not apply"));
+    assert!(repo.exists(".rit/rebase-merge/git-rebase-todo"));
+    assert!(repo.rit(&["status"]).contains("rebase in progress; onto"));
+    assert!(repo.rit_fails(&["rebase", "main"]).contains("already a rebase-merge directory"));
+    assert!(repo.rit_fails(&["rebase", "--continue"]).contains("unmerged files"));
+
+    // --abort restores the branch as it was
+    repo.rit(&["rebase", "--abort"]);
+    assert_eq!(repo.head(), orig);
+    assert_eq!(repo.read(".rit/HEAD"), "ref: refs/heads/topic\n");
+    assert_eq!(repo.read("a.txt"), "1\n2\ntopic\n");
+    assert_eq!(repo.rit(&["status", "--short"]), "");
+
+    // --continue after resolving
+    repo.rit(&["rebase", "main"]);
+    repo.write("a.txt", "1\n2\nmain and topic\n");
+    repo.rit(&["add", "a.txt"]);
+    assert!(repo.rit(&["rebase", "--continue"]).contains("Successfully rebased"));
+    assert_eq!(subjects(&repo, "HEAD"), ["t2", "t1", "m1", "base"]);
+    assert_eq!(repo.read("a.txt"), "1\n2\nmain and topic\n");
+
+    // --skip drops the conflicting commit
+    repo.rit(&["reset", "--hard", &orig]);
+    repo.rit(&["rebase", "main"]);
+    repo.rit(&["rebase", "--skip"]);
+    assert_eq!(subjects(&repo, "HEAD"), ["t2", "m1", "base"]);
+    assert_eq!(repo.read("a.txt"), "1\n2\nmain\n");
+    assert_eq!(repo.read("t2.txt"), "t2\n");
+
+    assert!(repo.rit_fails(&["rebase", "--skip"]).contains("No rebase in progress?"));
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use common::TestRepo;
+
+/// The commit a revision resolves to
+fn resolve(repo: &TestRepo, rev: &str) -> String {
+    repo.rit(&["log", "-n", "1", "--format=%H", rev]).trim().to_string()
+}
+
+#[test]
+fn logs_every_move_of_head_and_branches() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "1\n");
+    let first = repo.commit_all("first");
+    repo.write("a.txt", "2\n");
+    let second = repo.commit_all("second");
+    repo.rit(&["branch", "topic"]);
+    repo.rit(&["checkout", "topic"]);
+    repo.rit(&["reset", "--hard", "HEAD~1"]);
+
+    let out = repo.rit(&["reflog"]);
+    assert_eq!(
+        out,
+        format!(
+            "{f} HEAD@{{0}}: reset: moving to HEAD~1\n\
+             {s} HEAD@{{1}}: checkout: moving from main to topic\n\
+             {s} HEAD@{{2}}: commit: second\n\
+             {f} HEAD@{{3}}: commit (initial): first\n",
+            f = &first[..7],
+            s = &second[..7]
+        )
+    );
+    assert_eq!(
+        repo.rit(&["reflog", "topic"]),
+        format!("{} topic@{{0}}: reset: moving to HEAD~1\n{} topic@{{1}}: branch: Created from HEAD\n", &first[..7], &second[..7])
+    );
+
+    // The raw log is git's format
+    let log = repo.read(".rit/logs/refs/heads/main");
+    let line = log.lines().next().unwrap();
+    assert!(line.starts_with(&format!("{} {} Rit User <rit@example.com> ", "0".repeat(40), first)));
+    assert!(line.ends_with("\tcommit (initial): first"));
+
+    assert_eq!(resolve(&repo, "HEAD@{0}"), first);
+    assert_eq!(resolve(&repo, "HEAD@{2}"), second);
+    assert_eq!(resolve(&repo, "topic@{1}"), second);
+    assert_eq!(resolve(&repo, "@{1}"), second);
+    assert_eq!(resolve(&repo, "main@{0}~1"), first);
+    assert!(repo.rit_fails(&["log", "main@{5}"]).contains("log for 'refs/heads/main' only has 2 entries"));
+
+    // Tags are not logged
+    repo.rit(&["tag", "v1"]);
+    assert!(!repo.exists(".rit/logs/refs/tags"));
+}
+
+#[test]
+fn recovers_from_a_bad_reset() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "1\n");
+    repo.commit_all("first");
+    repo.write("precious.txt", "do not lose me\n");
+    let lost = repo.commit_all("precious");
+
+    repo.rit(&["reset", "--hard", "HEAD~1"]);
+    assert!(!repo.exists("precious.txt"));
+
+    // gc keeps what the reflog still points at
+    repo.rit(&["gc"]);
+    repo.rit(&["reset", "--hard", "HEAD@{1}"]);
+    assert_eq!(repo.head(), lost);
+    assert_eq!(repo.read("precious.txt"), "do not lose me\n");
+}
+
+#[test]
+fn logs_follow_renamed_and_deleted_branches() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "1\n");
+    let first = repo.commit_all("first");
+    repo.rit(&["branch", "old/name"]);
+
+    repo.rit(&["branch", "-m", "old/name", "new"]);
+    assert!(!repo.exists(".rit/logs/refs/heads/old"));
+    assert_eq!(
+        repo.rit(&["reflog", "new"]),
+        format!(
+            "{h} new@{{0}}: Branch: renamed refs/heads/old/name to refs/heads/new\n\
+             {h} new@{{1}}: branch: Created from HEAD\n",
+            h = &first[..7]
+        )
+    );
+
+    repo.rit(&["branch", "-d", "new"]);
+    assert!(!repo.exists(".rit/logs/refs/heads/new"));
+
+    // Detached commits are logged on HEAD only
+    repo.rit(&["checkout", &first]);
+    repo.write("a.txt", "2\n");
+    let detached = repo.commit_all("detached");
+    assert_eq!(resolve(&repo, "HEAD@{0}"), detached);
+    assert_eq!(resolve(&repo, "main@{0}"), first);
+}
+
+#[test]
+fn garbled_lines_are_skipped() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "1\n");
+    let first = repo.commit_all("first");
+
+    let log = repo.read(".rit/logs/HEAD");
+    repo.write(".rit/logs/HEAD", &format!("{}abc\nabc def\tshort ids\n", log));
+    assert_eq!(repo.rit(&["reflog"]), format!("{} HEAD@{{0}}: commit (initial): first\n", &first[..7]));
+    assert_eq!(resolve(&repo, "HEAD@{0}"), first);
+    repo.rit(&["gc"]);
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use common::{TestRepo, git, git_available};
+
+/// A repository with two commits on main and a side branch
+fn origin() -> TestRepo {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "one\n");
+    repo.write("dir/b.txt", "b\n");
+    repo.commit_all("first");
+    repo.write("a.txt", "one\ntwo\n");
+    repo.commit_all("second");
+    repo.rit(&["branch", "side"]);
+    repo
+}
+
+fn clone_of(origin: &TestRepo) -> TestRepo {
+    let clone = TestRepo::empty();
+    let out = clone.rit(&["clone", origin.path().to_str().unwrap(), "."]);
+    assert!(out.contains("Cloning into '.'..."));
+    clone
+}
+
+#[test]
+fn clone_copies_history_and_checks_out_the_default_branch() {
+    let origin = origin();
+    let clone = clone_of(&origin);
+
+    assert_eq!(clone.head(), origin.head());
+    assert_eq!(clone.read("a.txt"), "one\ntwo\n");
+    assert_eq!(clone.read("dir/b.txt"), "b\n");
+    assert!(clone.rit(&["status", "-s"]).is_empty());
+    assert!(clone.rit(&["log"]).contains("first"));
+    assert_eq!(clone.read(".rit/refs/remotes/origin/side").trim(), origin.head());
+    assert!(clone.read(".rit/config").contains(&format!("url = {}", origin.path().display())));
+
+    // Nothing new to fetch
+    assert_eq!(clone.rit(&["fetch"]), "");
+}
+
+#[test]
+fn clone_refuses_a_non_empty_directory() {
+    let origin = origin();
+    let target = TestRepo::empty();
+    target.write("keep.txt", "mine\n");
+
+    let err = target.rit_fails(&["clone", origin.path().to_str().unwrap(), "."]);
+    assert!(err.contains("already exists and is not an empty directory"));
+    assert!(!target.exists(".rit"));
+
+    let url = format!("file://{}", target.path().join("missing").display());
+    target.rit_fails(&["clone", &url, "copy"]);
+    assert!(!target.exists("copy"));
+}
+
+#[test]
+fn fetch_updates_remote_tracking_branches() {
+    let origin = origin();
+    let clone = clone_of(&origin);
+    let before = clone.head();
+
+    origin.write("a.txt", "one\ntwo\nthree\n");
+    let third = origin.commit_all("third");
+
+    let out = clone.rit(&["fetch", "origin"]);
+    assert!(out.starts_with("From "));
+    assert!(out.contains(&format!("{}..{}", &before[..7], &third[..7])));
+    assert!(out.contains("main -> origin/main"));
+
+    // Only the remote-tracking ref moved, merging brings the work in
+    assert_eq!(clone.head(), before);
+    clone.rit(&["merge", "origin/main"]);
+    assert_eq!(clone.head(), third);
+    assert_eq!(clone.read("a.txt"), "one\ntwo\nthree\n");
+}
+
+#[test]
+fn push_updates_a_branch_that_is_not_checked_out() {
+    let origin = origin();
+    let clone = clone_of(&origin);
+
+    clone.rit(&["switch", "-c", "side", "origin/side"]);
+    clone.write("c.txt", "pushed\n");
+    let pushed = clone.commit_all("on side");
+
+    let out = clone.rit(&["push", "origin", "side"]);
+    assert!(out.contains("side -> side"));
+    assert_eq!(origin.read(".rit/refs/heads/side").trim(), pushed);
+    assert_eq!(clone.read(".rit/refs/remotes/origin/side").trim(), pushed);
+
+    // The pushed objects arrived as a pack the origin can read
+    origin.rit(&["switch", "side"]);
+    assert_eq!(origin.read("c.txt"), "pushed\n");
+
+    assert!(clone.rit(&["push", "origin", "side"]).contains("Everything up-to-date"));
+}
+
+#[test]
+fn push_refuses_the_checked_out_branch() {
+    let origin = origin();
+    let clone = clone_of(&origin);
+    clone.write("a.txt", "changed\n");
+    clone.commit_all("change");
+
+    let err = clone.rit_fails(&["push"]);
+    assert!(err.contains("[remote rejected] main -> main (branch is currently checked out)"));
+    assert_ne!(origin.head(), clone.head());
+}
+
+#[test]
+fn push_rejects_non_fast_forward_unless_forced() {
+    let origin = origin();
+    let clone = clone_of(&origin);
+    origin.rit(&["switch", "side"]);
+
+    let other = clone_of(&origin);
+    other.rit(&["switch", "-c", "feature"]);
+    other.write("a.txt", "theirs\n");
+    other.commit_all("theirs");
+    other.rit(&["push", "origin", "feature"]);
+
+    clone.rit(&["switch", "-c", "feature"]);
+    clone.write("a.txt", "ours\n");
+    let ours = clone.commit_all("ours");
+
+    let err = clone.rit_fails(&["push", "origin", "feature"]);
+    assert!(err.contains("[rejected]        feature -> feature (fetch first)"));
+
+    clone.rit(&["fetch"]);
+    let err = clone.rit_fails(&["push", "origin", "feature"]);
+    assert!(err.contains("(non-fast-forward)"));
+
+    let out = clone.rit(&["push", "--force", "origin", "feature"]);
+    assert!(out.contains("(forced update)"));
+    assert_eq!(origin.read(".rit/refs/heads/feature").trim(), ours);
+}
+
+#[test]
+fn clone_from_git_and_push_to_a_bare_git_repository() {
+    if !git_available() {
+        return;
+    }
+
+    let source = TestRepo::empty();
+    git(source.path(), &["init", "-q", "-b", "main"]);
+    source.write("a.txt", "from git\n");
+    source.write("sub/b.txt", "b\n");
+    git(source.path(), &["add", "."]);
+    git(source.path(), &["commit", "-q", "-m", "git commit"]);
+    git(source.path(), &["tag", "-a", "v1", "-m", "annotated"]);
+
+    let clone = clone_of(&source);
+    assert_eq!(clone.read("a.txt"), "from git\n");
+    assert_eq!(clone.head(), git(source.path(), &["rev-parse", "HEAD"]).trim());
+    assert!(clone.exists(".rit/refs/tags/v1"));
+    clone.rit(&["gc"]);
+
+    let bare = TestRepo::empty();
+    git(bare.path(), &["init", "-q", "--bare", "-b", "main"]);
+    let pusher = clone_of(&bare);
+    pusher.write("a.txt", "from rit\n");
+    pusher.write("sub/b.txt", "b\n");
+    pusher.commit_all("rit commit");
+    pusher.write("a.txt", "from rit\nagain\n");
+    let second = pusher.commit_all("second rit commit");
+
+    let out = pusher.rit(&["push"]);
+    assert!(out.contains("[new branch]"));
+    assert_eq!(git(bare.path(), &["rev-parse", "main"]).trim(), second);
+    git(bare.path(), &["fsck", "--strict"]);
+    assert_eq!(git(bare.path(), &["show", "main:a.txt"]), "from rit\nagain\n");
+}
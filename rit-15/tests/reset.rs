@@ -0,0 +1,151 @@
+mod common;
+
+use common::TestRepo;
+
+fn three_commits() -> (TestRepo, Vec<String>) {
+    let repo = TestRepo::new();
+    let mut commits = Vec::new();
+    for i in 1..=3 {
+        repo.write("a.txt", &format!("{}\n", i));
+        repo.write(&format!("file{}.txt", i), "x\n");
+        commits.push(repo.commit_all(&format!("c{}", i)));
+    }
+    (repo, commits)
+}
+
+#[test]
+fn reset_modes() {
+    let (repo, commits) = three_commits();
+
+    // --soft keeps c3's changes staged
+    repo.rit(&["reset", "--soft", "HEAD~1"]);
+    assert_eq!(repo.head(), commits[1]);
+    assert_eq!(repo.rit(&["status", "--short"]), "M  a.txt\nA  file3.txt\n");
+
+    // --mixed (the default) unstages them but leaves the files alone
+    repo.rit(&["reset", &commits[2]]);
+    let out = repo.rit(&["reset", "HEAD~1"]);
+    assert_eq!(out, "Unstaged changes after reset:\nM\ta.txt\n");
+    assert_eq!(repo.head(), commits[1]);
+    assert_eq!(repo.rit(&["status", "--short"]), " M a.txt\n?? file3.txt\n");
+    assert_eq!(repo.read("a.txt"), "3\n");
+
+    // --hard discards tracked changes but keeps untracked files
+    repo.write("scratch.txt", "mine\n");
+    repo.rit(&["add", "."]);
+    repo.write("untracked.txt", "keep\n");
+    let out = repo.rit(&["reset", "--hard", &commits[0]]);
+    assert_eq!(out, format!("HEAD is now at {} c1\n", &commits[0][..7]));
+    assert_eq!(repo.read(".rit/refs/heads/main").trim(), commits[0]);
+    assert_eq!(repo.read("a.txt"), "1\n");
+    assert!(!repo.exists("file2.txt"));
+    assert!(!repo.exists("file3.txt"));
+    assert!(!repo.exists("scratch.txt"));
+    assert_eq!(repo.read("untracked.txt"), "keep\n");
+    assert_eq!(repo.rit(&["status", "--short"]), "?? untracked.txt\n");
+
+    // Back to where we were
+    repo.rit(&["reset", "--hard", &commits[2]]);
+    assert_eq!(repo.read("a.txt"), "3\n");
+    assert!(repo.exists("file3.txt"));
+
+    repo.rit_fails(&["reset", "--soft", "--hard", "HEAD"]);
+}
+
+#[test]
+fn reset_hard_aborts_a_merge() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "base\n");
+    repo.commit_all("base");
+    repo.rit(&["switch", "-c", "other"]);
+    repo.write("a.txt", "theirs\n");
+    repo.commit_all("theirs");
+    repo.rit(&["switch", "main"]);
+    repo.write("a.txt", "ours\n");
+    let ours = repo.commit_all("ours");
+
+    assert!(repo.rit(&["merge", "other"]).contains("CONFLICT"));
+    assert!(repo.exists(".rit/MERGE_HEAD"));
+    assert!(repo.rit_fails(&["reset", "--soft"]).contains("in the middle of a merge"));
+
+    repo.rit(&["reset", "--hard"]);
+    assert!(!repo.exists(".rit/MERGE_HEAD"));
+    assert_eq!(repo.head(), ours);
+    assert_eq!(repo.read("a.txt"), "ours\n");
+    assert_eq!(repo.rit(&["status", "--short"]), "");
+}
+
+#[test]
+fn restore_worktree_and_index() {
+    let (repo, commits) = three_commits();
+    repo.write("src/lib.rs", "v1\n");
+    repo.write("src/main.rs", "v1\n");
+    repo.commit_all("src");
+
+    repo.write("a.txt", "edited\n");
+    repo.write("src/lib.rs", "edited\n");
+    std::fs::remove_file(repo.file("src/main.rs")).unwrap();
+    repo.rit(&["restore", "src"]);
+    assert_eq!(repo.read("src/lib.rs"), "v1\n");
+    assert_eq!(repo.read("src/main.rs"), "v1\n");
+    assert_eq!(repo.read("a.txt"), "edited\n");
+
+    // --staged unstages without touching the file
+    repo.rit(&["add", "a.txt"]);
+    repo.write("new.txt", "new\n");
+    repo.rit(&["add", "new.txt"]);
+    repo.rit(&["restore", "--staged", "a.txt", "new.txt"]);
+    assert_eq!(repo.rit(&["status", "--short"]), " M a.txt\n?? new.txt\n");
+    assert_eq!(repo.read("a.txt"), "edited\n");
+
+    // Without --staged the index version wins
+    repo.rit(&["restore", "a.txt"]);
+    assert_eq!(repo.read("a.txt"), "3\n");
+
+    // --source takes files from any commit
+    repo.rit(&["restore", "--source", &commits[0], "a.txt"]);
+    assert_eq!(repo.read("a.txt"), "1\n");
+    repo.rit(&["restore", "--staged", "--source", "HEAD~3", "a.txt"]);
+    assert_eq!(repo.rit(&["status", "--short"]), "M  a.txt\n?? new.txt\n");
+
+    let err = repo.rit_fails(&["restore", "missing.txt"]);
+    assert!(err.contains("pathspec 'missing.txt' did not match any file(s) known to rit"));
+}
+
+#[test]
+fn rm_files_and_directories() {
+    let (repo, _) = three_commits();
+    repo.write("dir/one.txt", "1\n");
+    repo.write("dir/sub/two.txt", "2\n");
+    repo.commit_all("dir");
+
+    assert_eq!(repo.rit(&["rm", "file1.txt"]), "rm 'file1.txt'\n");
+    assert!(!repo.exists("file1.txt"));
+    assert_eq!(repo.rit(&["status", "--short"]), "D  file1.txt\n");
+
+    repo.rit(&["rm", "--cached", "file2.txt"]);
+    assert!(repo.exists("file2.txt"));
+    assert_eq!(repo.rit(&["status", "--short"]), "D  file1.txt\nD  file2.txt\n?? file2.txt\n");
+
+    assert!(repo.rit_fails(&["rm", "dir"]).contains("not removing 'dir' recursively without -r"));
+    assert_eq!(repo.rit(&["rm", "-r", "dir"]), "rm 'dir/one.txt'\nrm 'dir/sub/two.txt'\n");
+    assert!(!repo.exists("dir"));
+
+    // Content that would be lost needs -f
+    repo.write("a.txt", "local\n");
+    assert!(repo.rit_fails(&["rm", "a.txt"]).contains("has local modifications"));
+    repo.rit(&["add", "a.txt"]);
+    assert!(repo.rit_fails(&["rm", "a.txt"]).contains("has changes staged in the index"));
+    repo.rit(&["rm", "--cached", "a.txt"]);
+    assert_eq!(repo.read("a.txt"), "local\n");
+
+    repo.write("file3.txt", "staged\n");
+    repo.rit(&["add", "file3.txt"]);
+    repo.write("file3.txt", "on disk\n");
+    let err = repo.rit_fails(&["rm", "--cached", "file3.txt"]);
+    assert!(err.contains("has staged content different from both the file and the HEAD"));
+    repo.rit(&["rm", "-f", "file3.txt"]);
+    assert!(!repo.exists("file3.txt"));
+
+    assert!(repo.rit_fails(&["rm", "nope.txt"]).contains("did not match any file(s)"));
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use common::TestRepo;
+
+/// main: c1 - c2 - c3 - merge(c3, side), side: c1 - s1
+fn history() -> (TestRepo, Vec<String>) {
+    let repo = TestRepo::new();
+    let mut commits = Vec::new();
+    for i in 1..=3 {
+        repo.write("a.txt", &format!("{}\n", i));
+        commits.push(repo.commit_all(&format!("c{}", i)));
+    }
+
+    repo.rit(&["branch", "side", &commits[0]]);
+    repo.rit(&["switch", "side"]);
+    repo.write("side.txt", "side\n");
+    commits.push(repo.commit_all("s1"));
+    repo.rit(&["switch", "main"]);
+
+    // A merge commit with the side branch as second parent, built with plumbing
+    let merge = repo.rit(&["commit-tree", "HEAD^{tree}", "-p", "HEAD", "-p", "side", "-m", "merge"]);
+    repo.rit(&["update-ref", "refs/heads/main", merge.trim()]);
+    commits.push(merge.trim().to_string());
+    (repo, commits)
+}
+
+fn resolve(repo: &TestRepo, rev: &str) -> String {
+    repo.rit(&["update-ref", "refs/heads/probe", rev]);
+    repo.read(".rit/refs/heads/probe").trim().to_string()
+}
+
+#[test]
+fn relative_revisions() {
+    let (repo, commits) = history();
+    let [c1, c2, c3, s1, merge] = &commits[..] else { unreachable!() };
+
+    assert_eq!(resolve(&repo, "HEAD"), *merge);
+    assert_eq!(resolve(&repo, "@"), *merge);
+    assert_eq!(resolve(&repo, "HEAD^"), *c3);
+    assert_eq!(resolve(&repo, "main^1"), *c3);
+    assert_eq!(resolve(&repo, "main^2"), *s1);
+    assert_eq!(resolve(&repo, "HEAD~2"), *c2);
+    assert_eq!(resolve(&repo, "HEAD~3"), *c1);
+    assert_eq!(resolve(&repo, "main^2~1"), *c1);
+    assert_eq!(resolve(&repo, "HEAD^0"), *merge);
+
+    let err = repo.rit_fails(&["log", "HEAD~4"]);
+    assert!(err.contains("not a valid object name: 'HEAD~4'"));
+    repo.rit_fails(&["log", "main^3"]);
+
+    assert!(repo.rit(&["cat-file", "-p", "HEAD~1"]).contains("\n\nc3\n"));
+}
+
+#[test]
+fn abbreviated_hashes() {
+    let (repo, commits) = history();
+    let c2 = &commits[1];
+
+    assert!(repo.rit(&["cat-file", "-p", &c2[..7]]).contains("\n\nc2\n"));
+    assert!(repo.rit(&["log", &c2[..4]]).contains(&format!("commit {}", c2)));
+    repo.rit(&["checkout", &c2[..10]]);
+    assert_eq!(repo.head(), *c2);
+    assert_eq!(repo.read("a.txt"), "2\n");
+
+    // Too short or not hex at all is an error, never a panic
+    for rev in ["ab", "a", "", "zzzz", "é"] {
+        let err = repo.rit_fails(&["cat-file", "-p", rev]);
+        assert!(!err.contains("panicked"), "{}", err);
+    }
+
+    // Two objects sharing a prefix
+    repo.write(".rit/objects/ab/cd000000000000000000000000000000000000", "");
+    repo.write(".rit/objects/ab/cd111111111111111111111111111111111111", "");
+    let err = repo.rit_fails(&["cat-file", "-p", "abcd"]);
+    assert!(err.contains("short object ID abcd is ambiguous"));
+    assert!(err.contains("abcd000000000000000000000000000000000000"));
+}
+
+#[test]
+fn previous_branch() {
+    let (repo, commits) = history();
+
+    repo.rit(&["switch", "side"]);
+    repo.rit(&["switch", "main"]);
+    assert_eq!(resolve(&repo, "@{-1}"), commits[3]);
+    assert_eq!(resolve(&repo, "@{-1}~1"), commits[0]);
+
+    let out = repo.rit(&["checkout", "@{-1}"]);
+    assert!(out.contains("Switched to 'side'"));
+    assert_eq!(repo.read(".rit/HEAD"), "ref: refs/heads/side\n");
+
+    repo.rit(&["switch", "-"]);
+    assert_eq!(repo.read(".rit/HEAD"), "ref: refs/heads/main\n");
+    assert_eq!(resolve(&repo, "@{-2}"), commits[4]);
+
+    let err = repo.rit_fails(&["checkout", "@{-9}"]);
+    assert!(err.contains("@{-9} is not a valid revision"));
+}
+
+#[test]
+fn tags_and_peeling() {
+    let (repo, commits) = history();
+    repo.rit(&["update-ref", "refs/tags/v1", "HEAD~2"]);
+
+    assert_eq!(resolve(&repo, "v1"), commits[1]);
+    assert_eq!(resolve(&repo, "tags/v1~1"), commits[0]);
+    repo.rit(&["checkout", "v1"]);
+    assert_eq!(repo.head(), commits[1]);
+    assert_eq!(repo.read(".rit/HEAD").trim(), commits[1]);
+
+    let tree = repo.rit(&["write-tree"]);
+    assert_eq!(resolve(&repo, "v1^{tree}"), tree.trim());
+    assert_eq!(resolve(&repo, "v1^{commit}"), commits[1]);
+    let err = repo.rit_fails(&["cat-file", "-p", "v1^{tree}^{commit}"]);
+    assert!(err.contains("expected commit type, but the object dereferences to tree type"));
+
+    // Remote-tracking branches and branches in full
+    repo.rit(&["update-ref", "refs/remotes/origin/main", "main"]);
+    assert_eq!(resolve(&repo, "origin/main"), commits[4]);
+    assert_eq!(resolve(&repo, "refs/heads/side"), commits[3]);
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use common::{TestRepo, git_available};
+
+fn sha256_repo() -> TestRepo {
+    let repo = TestRepo::empty();
+    repo.rit(&["init", "--object-format=sha256"]);
+    repo
+}
+
+#[test]
+fn objects_are_named_by_sha256() {
+    let repo = sha256_repo();
+    assert!(repo.read(".rit/config").contains("objectformat = sha256"));
+
+    repo.write("a.txt", "hello\n");
+    // sha256 of "blob 6\0hello\n", as git hash-object computes it
+    assert_eq!(
+        repo.rit(&["hash-object", "a.txt"]),
+        "2cf8d83d9ee29543b34a87727421fdecb7e3f3a183d337639025de576db9ebb4\n"
+    );
+
+    repo.write("dir/b.txt", "b\n");
+    repo.commit_all("first");
+    let first = repo.head();
+    assert_eq!(first.len(), 64);
+    repo.write("a.txt", "changed\n");
+    repo.commit_all("second");
+
+    assert_eq!(repo.rit(&["log", "--format=%s", "HEAD~1"]), "first\n");
+    assert_eq!(repo.rit(&["log", "-n", "1", "--format=%H", &first[..10]]), format!("{}\n", first));
+    assert!(repo.rit(&["diff", "HEAD~1", "HEAD"]).contains("-hello\n+changed\n"));
+    assert_eq!(repo.rit(&["status", "--short"]), "");
+
+    // Packs, and reading back from them
+    repo.rit(&["gc"]);
+    assert_eq!(repo.rit(&["log", "--format=%s"]), "second\nfirst\n");
+    assert_eq!(repo.rit(&["fsck"]), "");
+    repo.rit(&["checkout", &first]);
+    assert_eq!(repo.read("a.txt"), "hello\n");
+
+    // A SHA-1 name is not an object name here
+    assert!(repo.rit_fails(&["cat-file", "-p", &"ab".repeat(20)]).contains("not a valid object name"));
+}
+
+#[test]
+fn unknown_formats_are_refused() {
+    let repo = TestRepo::empty();
+    assert!(repo.rit_fails(&["init", "--object-format=md5"]).contains("unknown object format 'md5'"));
+
+    let repo = TestRepo::new();
+    repo.write(".rit/config", "[extensions]\n\tobjectformat = sha512\n");
+    assert!(repo.rit_fails(&["status"]).contains("unknown object format 'sha512'"));
+}
+
+#[test]
+fn clone_keeps_the_object_format() {
+    let origin = sha256_repo();
+    origin.write("a.txt", "a\n");
+    origin.commit_all("first");
+
+    let clone = TestRepo::empty();
+    clone.rit(&["clone", origin.path().to_str().unwrap(), "."]);
+    assert!(clone.read(".rit/config").contains("objectformat = sha256"));
+    assert_eq!(clone.head(), origin.head());
+
+    // A SHA-1 repository can't fetch from it
+    let other = TestRepo::new();
+    other.rit(&["config", "remote.origin.url", origin.path().to_str().unwrap()]);
+    assert!(other.rit_fails(&["fetch", "origin"]).contains("the remote uses the sha256 object format"));
+}
+
+#[test]
+fn git_reads_rit_sha256_repositories() {
+    if !git_available() {
+        return;
+    }
+    let repo = sha256_repo();
+    repo.write("a.txt", "a\n");
+    repo.write("dir/b.txt", "b\n");
+    repo.commit_all("first");
+    repo.write("a.txt", "a2\n");
+    repo.commit_all("second");
+
+    repo.git(&["fsck", "--strict"]);
+    assert_eq!(repo.git(&["rev-parse", "--show-object-format"]), "sha256\n");
+    assert_eq!(repo.git(&["log", "--format=%H %s"]), repo.rit(&["log", "--format=%H %s"]));
+    assert_eq!(repo.git(&["status", "--short", "--untracked-files=no"]), "");
+
+    repo.rit(&["gc"]);
+    repo.git(&["fsck", "--strict"]);
+}
+
+#[test]
+fn rit_reads_git_sha256_repositories() {
+    if !git_available() {
+        return;
+    }
+    let repo = TestRepo::empty();
+    repo.git(&["init", "-q", "--object-format=sha256", "-b", "main"]);
+    repo.write("a.txt", "a\n");
+    repo.write("dir/b.txt", "b\n");
+    repo.git(&["add", "a.txt", "dir"]);
+    repo.git(&["commit", "-q", "-m", "first"]);
+    repo.write("a.txt", "a2\n");
+    repo.git(&["commit", "-q", "-a", "-m", "second"]);
+    repo.git(&["repack", "-q", "-a", "-d"]);
+
+    assert_eq!(repo.rit(&["log", "--format=%H %s"]), repo.git(&["log", "--format=%H %s"]));
+    assert_eq!(repo.rit(&["status", "--short"]), "");
+    assert_eq!(repo.rit(&["fsck"]), "");
+
+    repo.write("c.txt", "c\n");
+    repo.rit(&["add", "c.txt"]);
+    repo.rit(&["commit", "-m", "third"]);
+    repo.git(&["fsck", "--strict"]);
+    assert_eq!(repo.git(&["log", "-n", "1", "--format=%s"]), "third\n");
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use common::{TestRepo, git_available};
+
+fn repo_with_changes() -> TestRepo {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "1\n2\n3\n");
+    repo.write("b.txt", "b\n");
+    repo.commit_all("base");
+
+    repo.write("a.txt", "1\nTWO\n3\n");
+    repo.write("c.txt", "staged\n");
+    repo.rit(&["add", "c.txt"]);
+    std::fs::remove_file(repo.file("b.txt")).unwrap();
+    repo.write("untracked.txt", "mine\n");
+    repo
+}
+
+#[test]
+fn stash_and_pop() {
+    let repo = repo_with_changes();
+    let head = repo.head();
+
+    let out = repo.rit(&["stash"]);
+    assert_eq!(out, format!("Saved working directory and index state WIP on main: {} base\n", &head[..7]));
+    assert_eq!(repo.rit(&["status", "--short"]), "?? untracked.txt\n");
+    assert_eq!(repo.read("a.txt"), "1\n2\n3\n");
+    assert_eq!(repo.read("b.txt"), "b\n");
+    assert!(!repo.exists("c.txt"));
+    assert_eq!(repo.head(), head);
+
+    assert_eq!(repo.rit(&["stash", "list"]), format!("stash@{{0}}: WIP on main: {} base\n", &head[..7]));
+    assert_eq!(
+        repo.rit(&["stash", "show"]),
+        " a.txt | 2 +-\n b.txt | 1 -\n c.txt | 1 +\n 3 files changed, 2 insertions(+), 2 deletions(-)\n"
+    );
+    assert!(repo.rit(&["stash", "show", "-p"]).contains("-2\n+TWO\n"));
+
+    // Git's layout: the working tree commit has HEAD and the index commit as parents
+    let parents = repo.rit(&["log", "-n", "1", "--format=%P", "stash"]);
+    let parents: Vec<&str> = parents.split_whitespace().collect();
+    assert_eq!(parents.len(), 2);
+    assert_eq!(parents[0], head);
+    assert_eq!(repo.rit(&["log", "-n", "1", "--format=%s", "stash^2"]), format!("index on main: {} base\n", &head[..7]));
+
+    let out = repo.rit(&["stash", "pop"]);
+    assert!(out.contains("Dropped stash@{0} ("));
+    assert_eq!(repo.rit(&["status", "--short"]), " M a.txt\n D b.txt\nA  c.txt\n?? untracked.txt\n");
+    assert_eq!(repo.rit(&["stash", "list"]), "");
+    assert!(!repo.exists(".rit/refs/stash"));
+    assert!(repo.rit_fails(&["stash", "pop"]).contains("No stash entries found."));
+
+    // Nothing to do on a clean tree
+    repo.rit(&["reset", "--hard"]);
+    assert_eq!(repo.rit(&["stash"]), "No local changes to save\n");
+}
+
+#[test]
+fn untracked_files_and_several_stashes() {
+    let repo = repo_with_changes();
+    repo.rit(&["stash", "-u", "-m", "first"]);
+    assert!(!repo.exists("untracked.txt"));
+    assert_eq!(repo.rit(&["status", "--short"]), "");
+    assert_eq!(repo.rit(&["log", "-n", "1", "--format=%P", "stash"]).split_whitespace().count(), 3);
+
+    repo.write("a.txt", "second\n");
+    repo.rit(&["stash", "push", "-m", "second"]);
+    assert_eq!(repo.rit(&["stash", "list"]), "stash@{0}: On main: second\nstash@{1}: On main: first\n");
+
+    // Older stashes can be applied and dropped by position
+    repo.rit(&["stash", "apply", "stash@{1}"]);
+    assert_eq!(repo.read("untracked.txt"), "mine\n");
+    assert_eq!(repo.read("a.txt"), "1\nTWO\n3\n");
+    assert!(repo.rit_fails(&["stash", "apply", "1"]).contains("untracked.txt already exists"));
+
+    assert!(repo.rit(&["stash", "drop", "1"]).starts_with("Dropped stash@{1} ("));
+    assert_eq!(repo.rit(&["stash", "list"]), "stash@{0}: On main: second\n");
+    repo.rit(&["stash", "drop"]);
+    assert_eq!(repo.rit(&["stash", "list"]), "");
+    assert!(repo.rit_fails(&["stash", "drop"]).contains("No stash entries found."));
+}
+
+#[test]
+fn drop_skips_garbled_log_lines() {
+    let repo = repo_with_changes();
+    repo.rit(&["stash", "-u", "-m", "first"]);
+    repo.write("a.txt", "second\n");
+    repo.rit(&["stash", "push", "-m", "second"]);
+    repo.write("a.txt", "third\n");
+    repo.rit(&["stash", "push", "-m", "third"]);
+
+    // A garbled line logged after stash@{1}, so it comes first when counting from the newest
+    let log = repo.read(".rit/logs/refs/stash");
+    let lines: Vec<&str> = log.lines().collect();
+    repo.write(".rit/logs/refs/stash", &format!("{}\n{}\ngarbled\n{}\n", lines[0], lines[1], lines[2]));
+    assert_eq!(
+        repo.rit(&["stash", "list"]),
+        "stash@{0}: On main: third\nstash@{1}: On main: second\nstash@{2}: On main: first\n"
+    );
+
+    repo.rit(&["stash", "drop", "stash@{1}"]);
+    assert_eq!(repo.rit(&["stash", "list"]), "stash@{0}: On main: third\nstash@{1}: On main: first\n");
+    assert!(repo.read(".rit/logs/refs/stash").contains("\ngarbled\n"));
+    repo.rit(&["stash", "apply", "stash@{1}"]);
+    assert_eq!(repo.read("untracked.txt"), "mine\n");
+}
+
+#[test]
+fn conflicting_pop_keeps_the_stash() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "1\n2\n3\n");
+    repo.commit_all("base");
+    repo.write("a.txt", "1\nstashed\n3\n");
+    repo.rit(&["stash"]);
+
+    repo.write("a.txt", "1\ncommitted\n3\n");
+    repo.commit_all("change");
+
+    // Unsaved edits to the same file are never overwritten
+    repo.write("a.txt", "1\nlocal\n3\n");
+    assert!(repo.rit_fails(&["stash", "pop"]).contains("Your local changes"));
+    repo.rit(&["reset", "--hard"]);
+
+    let out = repo.rit(&["stash", "pop"]);
+    assert!(out.contains("CONFLICT (content): Merge conflict in a.txt"));
+    assert!(out.contains("The stash entry is kept"));
+    assert_eq!(
+        repo.read("a.txt"),
+        "1\n<<<<<<< Updated upstream\ncommitted\n=======\nstashed\n>>>>>>> Stashed changes\n3\n"
+    );
+    assert_eq!(repo.rit(&["status", "--short"]), "UU a.txt\n");
+    assert_eq!(repo.rit(&["stash", "list"]).lines().count(), 1);
+}
+
+#[test]
+fn git_reads_rit_stashes() {
+    if !git_available() {
+        return;
+    }
+    let repo = repo_with_changes();
+    repo.rit(&["stash", "-u"]);
+
+    assert_eq!(repo.git(&["stash", "list"]), repo.rit(&["stash", "list"]));
+    repo.git(&["stash", "pop", "--index"]);
+    assert_eq!(repo.git(&["status", "--short", "--untracked-files=no"]), " M a.txt\n D b.txt\nA  c.txt\n");
+    assert_eq!(repo.read("untracked.txt"), "mine\n");
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn short_status_reports_every_kind_of_change() {
+    let repo = TestRepo::new();
+    repo.write("kept.txt", "same\n");
+    repo.write("modified.txt", "v1\n");
+    repo.write("deleted.txt", "gone soon\n");
+    repo.write("staged_then_edited.txt", "v1\n");
+    repo.commit_all("first");
+
+    repo.write("modified.txt", "v2\n");
+    std::fs::remove_file(repo.file("deleted.txt")).unwrap();
+    repo.write("staged_then_edited.txt", "v2\n");
+    repo.rit(&["add", "staged_then_edited.txt"]);
+    repo.write("staged_then_edited.txt", "v3\n");
+    repo.write("new.txt", "new\n");
+    repo.rit(&["add", "new.txt"]);
+    repo.write("untracked.txt", "?\n");
+
+    let out = repo.rit(&["status", "--short"]);
+    assert_eq!(
+        out,
+        " D deleted.txt\n\
+         \x20M modified.txt\n\
+         A  new.txt\n\
+         MM staged_then_edited.txt\n\
+         ?? untracked.txt\n"
+    );
+}
+
+#[test]
+fn long_status_on_clean_and_unborn_repos() {
+    let repo = TestRepo::new();
+
+    let out = repo.rit(&["status"]);
+    assert!(out.contains("On branch main"));
+    assert!(out.contains("No commits yet"));
+
+    repo.write("a.txt", "a\n");
+    repo.rit(&["add", "a.txt"]);
+    let out = repo.rit(&["status"]);
+    assert!(out.contains("Changes to be committed:"));
+    assert!(out.contains("new file:   a.txt"));
+
+    repo.commit_all("first");
+    let out = repo.rit(&["status"]);
+    assert!(out.contains("nothing to commit, working tree clean"));
+}
+
+#[test]
+fn untracked_directories_are_collapsed() {
+    let repo = TestRepo::new();
+    repo.write("src/main.rs", "fn main() {}\n");
+    repo.rit(&["add", "src/main.rs"]);
+
+    repo.write("src/extra.rs", "\n");
+    repo.write("docs/a.md", "a\n");
+    repo.write("docs/deep/b.md", "b\n");
+
+    let out = repo.rit(&["status", "-s"]);
+    assert_eq!(out, "A  src/main.rs\n?? docs/\n?? src/extra.rs\n");
+}
+
+#[test]
+fn non_utf8_names_are_reported() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let repo = TestRepo::new();
+    std::fs::write(repo.path().join(OsStr::from_bytes(b"bad\xffname")), "x\n").unwrap();
+
+    let err = repo.rit_fails(&["status"]);
+    assert!(err.contains("path bad\u{fffd}name is not valid UTF-8"), "{}", err);
+}
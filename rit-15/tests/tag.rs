@@ -0,0 +1,89 @@
+mod common;
+
+use common::{TestRepo, git_available};
+
+fn repo_with_commits() -> (TestRepo, String, String) {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "one\n");
+    let first = repo.commit_all("first");
+    repo.write("a.txt", "two\n");
+    let second = repo.commit_all("second");
+    (repo, first, second)
+}
+
+#[test]
+fn lightweight_tags() {
+    let (repo, first, second) = repo_with_commits();
+
+    repo.rit(&["tag", "v2"]);
+    repo.rit(&["tag", "v1", "HEAD~1"]);
+    assert_eq!(repo.read(".rit/refs/tags/v2"), format!("{}\n", second));
+    assert_eq!(repo.read(".rit/refs/tags/v1"), format!("{}\n", first));
+    assert_eq!(repo.rit(&["tag"]), "v1\nv2\n");
+
+    let err = repo.rit_fails(&["tag", "v1"]);
+    assert!(err.contains("tag 'v1' already exists"));
+    let out = repo.rit(&["tag", "-f", "v1"]);
+    assert!(out.contains(&format!("Updated tag 'v1' (was {})", &first[..7])));
+    assert_eq!(repo.read(".rit/refs/tags/v1"), format!("{}\n", second));
+
+    let out = repo.rit(&["tag", "-d", "v1"]);
+    assert!(out.contains(&format!("Deleted tag 'v1' (was {})", &second[..7])));
+    assert!(!repo.exists(".rit/refs/tags/v1"));
+    assert!(repo.rit_fails(&["tag", "-d", "v1"]).contains("tag 'v1' not found"));
+
+    assert!(repo.rit_fails(&["tag", "bad..name"]).contains("not a valid tag name"));
+}
+
+#[test]
+fn annotated_tags() {
+    let (repo, first, _) = repo_with_commits();
+
+    let output = repo
+        .command(&["tag", "-a", "v1.0", "-m", "first release", &first[..7]])
+        .env("RIT_COMMITTER_NAME", "Tag Ger")
+        .env("RIT_COMMITTER_EMAIL", "tagger@example.com")
+        .env("RIT_COMMITTER_DATE", "1700000000 +0100")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let tag = repo.read(".rit/refs/tags/v1.0").trim().to_string();
+    assert_ne!(tag, first);
+    assert_eq!(
+        repo.rit(&["cat-file", "-p", "v1.0"]),
+        format!(
+            "object {}\ntype commit\ntag v1.0\ntagger Tag Ger <tagger@example.com> 1700000000 +0100\n\nfirst release\n",
+            first
+        )
+    );
+
+    // Revisions see through the tag object to the commit
+    repo.rit(&["checkout", "v1.0"]);
+    assert_eq!(repo.head(), first);
+    assert!(repo.rit(&["log", "v1.0"]).contains(&format!("commit {}", first)));
+    assert_eq!(repo.rit(&["cat-file", "-p", "v1.0^{}"]), repo.rit(&["cat-file", "-p", &first]));
+
+    // Tagging a tag records its type
+    repo.rit(&["tag", "-m", "nested", "outer", "v1.0"]);
+    assert!(repo.rit(&["cat-file", "-p", "outer"]).starts_with(&format!("object {}\ntype tag\n", tag)));
+
+    // gc keeps tag objects that refs point at
+    repo.rit(&["tag", "-d", "outer"]);
+    repo.rit(&["gc"]);
+    assert!(repo.rit(&["cat-file", "-p", &tag]).contains("first release"));
+}
+
+#[test]
+fn git_reads_annotated_tags() {
+    if !git_available() {
+        return;
+    }
+    let (repo, first, _) = repo_with_commits();
+    repo.rit(&["tag", "-m", "release\n\nwith a body", "v1", &first]);
+
+    repo.git(&["fsck", "--strict"]);
+    assert_eq!(repo.git(&["rev-parse", "v1^{commit}"]).trim(), first);
+    assert_eq!(repo.git(&["cat-file", "-t", "v1"]).trim(), "tag");
+    assert_eq!(repo.git(&["tag", "-l", "-n9"]).split_whitespace().collect::<Vec<_>>(), ["v1", "release", "with", "a", "body"]);
+}